@@ -0,0 +1,60 @@
+//! Headless compute: squares numbers in a storage buffer on the compute queue of a device
+//! created with `Vulkan::new_compute_only` and reads them back, no window or surface needed.
+//!
+//! `cargo run --example compute`
+
+use chunklands_rs::game::{work_groups, ComputeInit, Vulkan, DEFAULT_FENCE_TIMEOUT};
+use inline_spirv::include_spirv;
+
+/// `local_size_x` of `square_comp.glsl`
+const LOCAL_SIZE: u32 = 64;
+/// not a multiple of `LOCAL_SIZE`, the last work group is partial
+const COUNT: u32 = 1000;
+
+fn main() {
+    env_logger::builder()
+        .format_timestamp_millis()
+        .format_module_path(false)
+        .init();
+
+    let glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
+    let vulkan = Vulkan::new_compute_only(ComputeInit {
+        debug: cfg!(debug_assertions),
+        glfw: &glfw,
+        req_ext: &vec![],
+        req_layers: &vec![],
+        fence_timeout: DEFAULT_FENCE_TIMEOUT,
+        validation_features: Default::default(),
+    })
+    .unwrap();
+
+    // count followed by the values, see `square_comp.glsl`
+    let data: Vec<u32> = std::iter::once(COUNT).chain(0..COUNT).collect();
+    let buffer = vulkan.create_storage_buffer(&data).unwrap();
+    let pipeline = vulkan
+        .create_compute_pipeline(include_spirv!("shader/square_comp.glsl", glsl, comp), 1)
+        .unwrap();
+
+    vulkan
+        .dispatch(
+            &pipeline,
+            &[&buffer],
+            [work_groups(COUNT, LOCAL_SIZE), 1, 1],
+        )
+        .unwrap();
+    let result: Vec<u32> = vulkan.read_storage_buffer(&buffer).unwrap();
+
+    for (value, squared) in (0..COUNT).zip(&result[1..]) {
+        assert_eq!(*squared, value * value, "square of {}", value);
+    }
+    println!(
+        "squared {} values on the compute queue, last {}",
+        COUNT, result[COUNT as usize]
+    );
+
+    vulkan.destroy_compute_pipeline(pipeline);
+    vulkan.destroy_storage_buffer(buffer);
+
+    assert_eq!(vulkan.validation_error_count(), 0, "validation errors");
+    vulkan.destroy().unwrap();
+}
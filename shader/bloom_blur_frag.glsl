@@ -0,0 +1,27 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D bloomColor;
+
+layout(push_constant) uniform Blur {
+    // one texel along the blur direction, in UV
+    vec2 texelStep;
+} blur;
+
+layout(location = 0) in vec2 fragUV;
+layout(location = 0) out vec4 outColor;
+
+// 9 tap gaussian, run once horizontally and once vertically
+const float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+    vec3 color = texture(bloomColor, fragUV).rgb * weights[0];
+
+    for (int i = 1; i < 5; i++) {
+        vec2 offset = blur.texelStep * float(i);
+        color += texture(bloomColor, fragUV + offset).rgb * weights[i];
+        color += texture(bloomColor, fragUV - offset).rgb * weights[i];
+    }
+
+    outColor = vec4(color, 1.0);
+}
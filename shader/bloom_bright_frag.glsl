@@ -0,0 +1,21 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// rendered at half the scene resolution, the linear sampler averages 2x2 scene pixels
+layout(set = 0, binding = 0) uniform sampler2D hdrColor;
+
+layout(push_constant) uniform Bright {
+    float threshold;
+} bright;
+
+layout(location = 0) in vec2 fragUV;
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec3 color = texture(hdrColor, fragUV).rgb;
+    float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+
+    // keeps the hue, only the part above the threshold glows
+    float brightPart = max(luminance - bright.threshold, 0.0) / max(luminance, 0.0001);
+    outColor = vec4(color * brightPart, 1.0);
+}
@@ -0,0 +1,18 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// one block texture per layer
+layout(set = 1, binding = 0) uniform sampler2DArray textures;
+
+layout(location = 0) in vec3 fragNormal;
+layout(location = 1) in vec2 fragUV;
+layout(location = 2) flat in uint fragTexId;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    // fixed light from above, so faces of a block are told apart
+    float light = 0.6 + 0.4 * max(dot(normalize(fragNormal), normalize(vec3(0.3, 1.0, 0.5))), 0.0);
+    vec4 color = texture(textures, vec3(fragUV, float(fragTexId)));
+    outColor = vec4(color.rgb * light, color.a);
+}
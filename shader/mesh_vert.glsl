@@ -0,0 +1,28 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// shared with the scene pipeline
+layout(set = 0, binding = 0) uniform SceneUniforms {
+    mat4 mvp;
+} scene;
+
+layout(push_constant) uniform MeshConstants {
+    mat4 model;
+} constants;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inUV;
+layout(location = 3) in uint inTexId;
+
+layout(location = 0) out vec3 fragNormal;
+layout(location = 1) out vec2 fragUV;
+layout(location = 2) flat out uint fragTexId;
+
+void main() {
+    gl_Position = scene.mvp * constants.model * vec4(inPosition, 1.0);
+    // without non-uniform scale, which would need the inverse transpose
+    fragNormal = mat3(constants.model) * inNormal;
+    fragUV = inUV;
+    fragTexId = inTexId;
+}
@@ -0,0 +1,16 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in  vec3 inPosition;
+layout(location = 1) in  vec3 inColor;
+layout(location = 2) in  float inSize;
+
+layout(location = 0) out vec3 fragColor;
+
+
+void main() {
+    gl_Position = vec4(inPosition, 1.0);
+    // undefined for point topology, if not written
+    gl_PointSize = inSize;
+    fragColor = inColor;
+}
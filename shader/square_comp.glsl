@@ -0,0 +1,17 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(local_size_x = 64) in;
+
+// count followed by the values squared in place
+layout(set = 0, binding = 0) buffer Values {
+    uint count;
+    uint values[];
+};
+
+void main() {
+    uint index = gl_GlobalInvocationID.x;
+    if (index < count) {
+        values[index] *= values[index];
+    }
+}
@@ -0,0 +1,40 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D hdrColor;
+// blurred bright areas at a lower resolution, the HDR color itself without bloom
+layout(set = 0, binding = 1) uniform sampler2D bloomColor;
+
+layout(push_constant) uniform Tonemap {
+    float exposure;
+    // 0: Reinhard, 1: ACES
+    uint operator;
+    // 0 without bloom
+    float bloomIntensity;
+} tonemap;
+
+layout(location = 0) in vec2 fragUV;
+layout(location = 0) out vec4 outColor;
+
+vec3 reinhard(vec3 color) {
+    return color / (1.0 + color);
+}
+
+// Krzysztof Narkowicz, "ACES Filmic Tone Mapping Curve"
+vec3 aces(vec3 color) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0, 1.0);
+}
+
+void main() {
+    vec3 bloom = texture(bloomColor, fragUV).rgb * tonemap.bloomIntensity;
+    vec3 color = (texture(hdrColor, fragUV).rgb + bloom) * tonemap.exposure;
+
+    // the sRGB swapchain format does the gamma encoding
+    outColor = vec4(tonemap.operator == 0 ? reinhard(color) : aces(color), 1.0);
+}
@@ -1,6 +1,14 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
 
+layout(set = 0, binding = 0) uniform SceneUniforms {
+    mat4 mvp;
+} scene;
+
+layout(push_constant) uniform SceneConstants {
+    mat4 model;
+} constants;
+
 layout(location = 0) in  vec2 inPosition;
 layout(location = 1) in  vec3 inColor;
 
@@ -8,6 +16,6 @@ layout(location = 0) out vec3 fragColor;
 
 
 void main() {
-    gl_Position = vec4(inPosition, 0.0, 1.0);
+    gl_Position = scene.mvp * constants.model * vec4(inPosition, 0.0, 1.0);
     fragColor = inColor;
 }
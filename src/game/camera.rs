@@ -0,0 +1,337 @@
+use std::f32::consts::PI;
+
+use glm::{IVec3, Mat4, Vec2, Vec3, Vec4};
+
+/// distance kept to solid blocks, so the near plane never clips into a wall
+const COLLISION_MARGIN: f32 = 0.2;
+
+/// narrower is a telescope, wider distorts the screen edges like a fisheye
+const MIN_FOV: f32 = 30.0 * PI / 180.0;
+const MAX_FOV: f32 = 110.0 * PI / 180.0;
+const DEFAULT_FOV: f32 = 70.0 * PI / 180.0;
+
+/// vertical field of view per mouse wheel notch
+const ZOOM_STEP: f32 = 5.0 * PI / 180.0;
+/// seconds a zoom takes, short enough to still feel direct
+const ZOOM_DURATION: f32 = 0.15;
+
+/// First person camera in block space, one block is 1.0 and block `(x, y, z)` spans
+/// `x..x + 1.0` and so on.
+pub struct Camera {
+    pub position: Vec3,
+    /// radians around the y axis
+    pub yaw: f32,
+    /// radians, positive looks up
+    pub pitch: f32,
+    fov: f32,
+    zoom: Option<Zoom>,
+}
+
+/// Field of view on its way from `from` to `to`.
+struct Zoom {
+    from: f32,
+    to: f32,
+    elapsed: f32,
+}
+
+impl Camera {
+    pub fn new(position: Vec3) -> Self {
+        Self {
+            position,
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: DEFAULT_FOV,
+            zoom: None,
+        }
+    }
+
+    /// Vertical field of view in radians, while zooming the current in-between value.
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    /// Jumps to `fov` radians, clamped to 30°..=110°, and stops a running zoom.
+    pub fn set_fov(&mut self, fov: f32) {
+        self.fov = clamp_fov(fov);
+        self.zoom = None;
+    }
+
+    /// Smoothly changes the field of view to `fov` radians (clamped like `set_fov`) over a short
+    /// duration, advanced by `update`.
+    pub fn zoom_to(&mut self, fov: f32) {
+        self.zoom = Some(Zoom {
+            from: self.fov,
+            to: clamp_fov(fov),
+            elapsed: 0.0,
+        });
+    }
+
+    /// Zooms in for a positive `scroll_y` (wheel up), see `Input::scroll_delta`. Several notches
+    /// in a row add up to the target of a running zoom.
+    pub fn zoom_by_scroll(&mut self, scroll_y: f64) {
+        if scroll_y == 0.0 {
+            return;
+        }
+
+        let target = self.zoom.as_ref().map(|zoom| zoom.to).unwrap_or(self.fov);
+        self.zoom_to(target - scroll_y as f32 * ZOOM_STEP);
+    }
+
+    /// Advances a running zoom by `frame_time` seconds.
+    pub fn update(&mut self, frame_time: f64) {
+        if let Some(zoom) = &mut self.zoom {
+            zoom.elapsed += frame_time.max(0.0) as f32;
+
+            let t = (zoom.elapsed / ZOOM_DURATION).min(1.0);
+            // smoothstep, eases in and out
+            let t = t * t * (3.0 - 2.0 * t);
+            self.fov = zoom.from + (zoom.to - zoom.from) * t;
+
+            if zoom.elapsed >= ZOOM_DURATION {
+                self.zoom = None;
+            }
+        }
+    }
+
+    /// Perspective projection with the current field of view, for Vulkan clip space: y points
+    /// down and depth is `0.0` at `near` and `1.0` at `far`. The camera looks along -z.
+    pub fn projection(&self, aspect: f32, near: f32, far: f32) -> Mat4 {
+        perspective(self.fov, aspect, near, far)
+    }
+
+    /// Moves by `delta`, but stops in front of the first block `is_solid` reports. Axes are
+    /// resolved one after another, so blocked movement into a wall still slides along it.
+    pub fn move_with_collision(&mut self, delta: Vec3, is_solid: impl Fn(IVec3) -> bool) {
+        for axis in 0..3 {
+            let mut remaining = delta[axis];
+
+            while remaining != 0.0 {
+                // at most a block at a time, a fast camera must not tunnel through thin walls
+                let step = remaining.max(-1.0).min(1.0);
+                remaining -= step;
+
+                let target = self.position[axis] + step;
+                let target_block = (target + COLLISION_MARGIN * step.signum()).floor() as i32;
+
+                // with the margin the step can reach past the next block, check every block on
+                // the way
+                let mut block = block_at(&self.position);
+                let mut blocked = false;
+                while block[axis] != target_block {
+                    block[axis] += step.signum() as i32;
+
+                    if is_solid(block) {
+                        blocked = true;
+                        break;
+                    }
+                }
+
+                if blocked {
+                    let face = if step > 0.0 {
+                        block[axis] as f32 - COLLISION_MARGIN
+                    } else {
+                        block[axis] as f32 + 1.0 + COLLISION_MARGIN
+                    };
+
+                    // already closer than the margin (e.g. spawned there), don't get pushed back
+                    if (face - self.position[axis]) * step > 0.0 {
+                        self.position[axis] = face;
+                    }
+                    break;
+                }
+
+                self.position[axis] = target;
+            }
+        }
+    }
+}
+
+/// Block containing `position`.
+pub fn block_at(position: &Vec3) -> IVec3 {
+    IVec3::new(
+        position.x.floor() as i32,
+        position.y.floor() as i32,
+        position.z.floor() as i32,
+    )
+}
+
+fn clamp_fov(fov: f32) -> f32 {
+    fov.max(MIN_FOV).min(MAX_FOV)
+}
+
+/// See `Camera::projection`, `fov` is vertical and in radians.
+pub fn perspective(fov: f32, aspect: f32, near: f32, far: f32) -> Mat4 {
+    let f = 1.0 / (fov / 2.0).tan();
+
+    Mat4::new(
+        Vec4::new(f / aspect, 0.0, 0.0, 0.0),
+        Vec4::new(0.0, -f, 0.0, 0.0),
+        Vec4::new(0.0, 0.0, far / (near - far), -1.0),
+        Vec4::new(0.0, 0.0, near * far / (near - far), 0.0),
+    )
+}
+
+/// World position seen at `pixel` of a viewport of `viewport_size` pixels, with the `depth` of
+/// the depth buffer there, e.g. from `Vulkan::read_depth_at` for picking. `view_projection` is
+/// the matrix the scene was rendered with, `pixel` is relative to the viewport's top left.
+pub fn unproject(view_projection: &Mat4, pixel: Vec2, viewport_size: Vec2, depth: f32) -> Vec3 {
+    let ndc = Vec4::new(
+        pixel.x / viewport_size.x * 2.0 - 1.0,
+        pixel.y / viewport_size.y * 2.0 - 1.0,
+        depth,
+        1.0,
+    );
+
+    let world = glm::inverse(view_projection) * ndc;
+    Vec3::new(world.x / world.w, world.y / world.w, world.z / world.w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        for axis in 0..3 {
+            assert!(
+                (actual[axis] - expected[axis]).abs() < 1e-5,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn block_at_floors_negative_coordinates() {
+        assert_eq!(block_at(&Vec3::new(-0.5, 0.0, 1.9)), IVec3::new(-1, 0, 1));
+        assert_eq!(block_at(&Vec3::new(-1.0, -1.5, 2.0)), IVec3::new(-1, -2, 2));
+    }
+
+    #[test]
+    fn moves_freely_without_blocks() {
+        let mut camera = Camera::new(Vec3::new(0.5, 0.5, 0.5));
+        camera.move_with_collision(Vec3::new(2.5, -3.25, 0.0), |_| false);
+
+        assert_close(camera.position, Vec3::new(3.0, -2.75, 0.5));
+    }
+
+    #[test]
+    fn stops_in_front_of_a_wall() {
+        let mut camera = Camera::new(Vec3::new(1.5, 0.5, 0.5));
+        camera.move_with_collision(Vec3::new(5.0, 0.0, 0.0), |block| block.x == 3);
+        assert_close(camera.position, Vec3::new(3.0 - COLLISION_MARGIN, 0.5, 0.5));
+
+        let mut camera = Camera::new(Vec3::new(2.5, 0.5, 0.5));
+        camera.move_with_collision(Vec3::new(-5.0, 0.0, 0.0), |block| block.x == 0);
+        assert_close(camera.position, Vec3::new(1.0 + COLLISION_MARGIN, 0.5, 0.5));
+    }
+
+    #[test]
+    fn slides_along_a_wall() {
+        let mut camera = Camera::new(Vec3::new(1.5, 0.5, 0.5));
+        camera.move_with_collision(Vec3::new(5.0, 0.0, 1.0), |block| block.x == 3);
+
+        assert_close(camera.position, Vec3::new(3.0 - COLLISION_MARGIN, 0.5, 1.5));
+    }
+
+    #[test]
+    fn is_not_pushed_back_inside_the_margin() {
+        let mut camera = Camera::new(Vec3::new(2.9, 0.5, 0.5));
+        camera.move_with_collision(Vec3::new(1.0, 0.0, 0.0), |block| block.x == 3);
+
+        assert_close(camera.position, Vec3::new(2.9, 0.5, 0.5));
+    }
+
+    #[test]
+    fn fov_is_clamped() {
+        let mut camera = Camera::new(Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(camera.fov(), DEFAULT_FOV);
+
+        camera.set_fov(PI);
+        assert_eq!(camera.fov(), MAX_FOV);
+        camera.set_fov(0.0);
+        assert_eq!(camera.fov(), MIN_FOV);
+    }
+
+    #[test]
+    fn zoom_eases_to_the_target() {
+        let mut camera = Camera::new(Vec3::new(0.0, 0.0, 0.0));
+        camera.zoom_by_scroll(1.0);
+        camera.zoom_by_scroll(1.0);
+        let target = DEFAULT_FOV - 2.0 * ZOOM_STEP;
+
+        camera.update(ZOOM_DURATION as f64 / 2.0);
+        assert!((camera.fov() - (DEFAULT_FOV + target) / 2.0).abs() < 1e-5);
+
+        camera.update(ZOOM_DURATION as f64);
+        assert!((camera.fov() - target).abs() < 1e-6);
+
+        // done, nothing changes anymore
+        let fov = camera.fov();
+        camera.update(1.0);
+        assert_eq!(camera.fov(), fov);
+    }
+
+    #[test]
+    fn set_fov_stops_a_zoom() {
+        let mut camera = Camera::new(Vec3::new(0.0, 0.0, 0.0));
+        camera.zoom_to(MIN_FOV);
+        camera.set_fov(MAX_FOV);
+        camera.update(1.0);
+
+        assert_eq!(camera.fov(), MAX_FOV);
+    }
+
+    #[test]
+    fn perspective_maps_to_vulkan_clip_space() {
+        let (near, far) = (0.5, 100.0);
+        let projection = perspective(PI / 2.0, 2.0, near, far);
+
+        let clip = projection * Vec4::new(1.0, 1.0, -near, 1.0);
+        assert!((clip.z / clip.w).abs() < 1e-6);
+        // 90° vertical, half as wide as high per unit of aspect
+        assert!((clip.x / clip.w - 1.0).abs() < 1e-5);
+        // y points down
+        assert!((clip.y / clip.w + 2.0).abs() < 1e-5);
+
+        let clip = projection * Vec4::new(0.0, 0.0, -far, 1.0);
+        assert!((clip.z / clip.w - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn unprojects_a_projected_point() {
+        let (width, height) = (800.0, 600.0);
+        let view_projection = perspective(PI / 2.0, width / height, 0.1, 100.0);
+        let point = Vec3::new(1.5, -0.5, -5.0);
+
+        let clip = view_projection * Vec4::new(point.x, point.y, point.z, 1.0);
+        let pixel = Vec2::new(
+            (clip.x / clip.w + 1.0) / 2.0 * width,
+            (clip.y / clip.w + 1.0) / 2.0 * height,
+        );
+        let depth = clip.z / clip.w;
+
+        let unprojected = unproject(&view_projection, pixel, Vec2::new(width, height), depth);
+        for axis in 0..3 {
+            assert!(
+                (unprojected[axis] - point[axis]).abs() < 1e-3,
+                "{:?}",
+                unprojected
+            );
+        }
+    }
+
+    #[test]
+    fn unprojects_the_center_at_depth_zero_onto_the_near_plane() {
+        let near = 0.5;
+        let view_projection = perspective(PI / 2.0, 1.0, near, 100.0);
+
+        let unprojected = unproject(
+            &view_projection,
+            Vec2::new(50.0, 50.0),
+            Vec2::new(100.0, 100.0),
+            0.0,
+        );
+        assert_close(unprojected, Vec3::new(0.0, 0.0, -near));
+    }
+}
@@ -0,0 +1,112 @@
+/// weight of the newest frame time in the moving average
+const SMOOTHING: f64 = 0.1;
+/// frame times within this fraction around the target don't change the scale
+const TOLERANCE: f64 = 0.1;
+/// render scale changes in steps, every change rebuilds the swapchain
+const STEP: f32 = 0.05;
+/// frames to wait after a change, until the moving average has caught up with its effect
+const COOLDOWN_FRAMES: u32 = 30;
+
+/// Nudges the render scale to keep the frame time near a target.
+pub struct DynamicResolution {
+    target_frame_time: f64,
+    min_scale: f32,
+    max_scale: f32,
+    scale: f32,
+    average_frame_time: Option<f64>,
+    cooldown: u32,
+}
+
+impl DynamicResolution {
+    /// Starts at `max_scale`, `target_frame_time` is in seconds (e.g. `1.0 / 60.0`).
+    pub fn new(target_frame_time: f64, min_scale: f32, max_scale: f32) -> Self {
+        Self {
+            target_frame_time,
+            min_scale,
+            max_scale,
+            scale: max_scale,
+            average_frame_time: None,
+            cooldown: 0,
+        }
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub fn target_frame_time(&self) -> f64 {
+        self.target_frame_time
+    }
+
+    /// Feeds the time of the last frame in seconds, returns the new scale if it changed.
+    pub fn update(&mut self, frame_time: f64) -> Option<f32> {
+        let average = match self.average_frame_time {
+            Some(average) => average + (frame_time - average) * SMOOTHING,
+            None => frame_time,
+        };
+        self.average_frame_time = Some(average);
+
+        if self.cooldown > 0 {
+            self.cooldown -= 1;
+            return None;
+        }
+
+        let ratio = average / self.target_frame_time;
+        let scale = if ratio > 1.0 + TOLERANCE {
+            self.scale - STEP
+        } else if ratio < 1.0 - TOLERANCE {
+            self.scale + STEP
+        } else {
+            return None;
+        };
+
+        let scale = scale.max(self.min_scale).min(self.max_scale);
+        if (scale - self.scale).abs() < f32::EPSILON {
+            return None;
+        }
+
+        self.scale = scale;
+        self.cooldown = COOLDOWN_FRAMES;
+
+        Some(scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: f64 = 1.0 / 60.0;
+
+    #[test]
+    fn keeps_the_scale_near_the_target() {
+        let mut dynamic_resolution = DynamicResolution::new(TARGET, 0.5, 1.0);
+
+        assert_eq!(dynamic_resolution.update(TARGET * 1.05), None);
+        assert_eq!(dynamic_resolution.scale(), 1.0);
+    }
+
+    #[test]
+    fn slow_frames_lower_the_scale_then_cool_down() {
+        let mut dynamic_resolution = DynamicResolution::new(TARGET, 0.5, 1.0);
+
+        assert_eq!(dynamic_resolution.update(TARGET * 2.0), Some(1.0 - STEP));
+        for _ in 0..COOLDOWN_FRAMES {
+            assert_eq!(dynamic_resolution.update(TARGET * 2.0), None);
+        }
+        assert!(dynamic_resolution.update(TARGET * 2.0).is_some());
+    }
+
+    #[test]
+    fn stays_within_min_and_max() {
+        let mut dynamic_resolution = DynamicResolution::new(TARGET, 0.9, 1.0);
+        for _ in 0..200 {
+            dynamic_resolution.update(TARGET * 2.0);
+        }
+        assert!((dynamic_resolution.scale() - 0.9).abs() < 1e-6);
+
+        let mut dynamic_resolution = DynamicResolution::new(TARGET, 0.5, 1.0);
+        assert_eq!(dynamic_resolution.update(TARGET / 2.0), None);
+        assert_eq!(dynamic_resolution.scale(), 1.0);
+    }
+}
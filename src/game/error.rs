@@ -3,4 +3,7 @@ pub type Result<T> = std::result::Result<T, GameError>;
 #[derive(Debug)]
 pub enum GameError {
     VulkanError(String),
+    DeviceHang(String),
+    IconError(String),
+    WindowError(String),
 }
@@ -0,0 +1,126 @@
+/// simulation steps per frame at most, a long stall (e.g. a breakpoint) must not be caught up
+/// step by step
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
+/// Runs the simulation in fixed steps, independent of the frame rate. Rendering interpolates
+/// between the last two simulation states by `alpha`.
+pub struct FixedTimestep {
+    step: f64,
+    accumulator: f64,
+}
+
+impl FixedTimestep {
+    /// `step` in seconds, e.g. `1.0 / 60.0`.
+    pub fn new(step: f64) -> Self {
+        Self {
+            step,
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    /// Adds the time of the last frame in seconds, returns how many simulation steps are due.
+    pub fn advance(&mut self, frame_time: f64) -> u32 {
+        self.accumulator += frame_time.max(0.0);
+
+        let steps = (self.accumulator / self.step).floor();
+        if steps > MAX_STEPS_PER_FRAME as f64 {
+            // drop the backlog, the simulation slows down instead of spiraling
+            self.accumulator %= self.step;
+            return MAX_STEPS_PER_FRAME;
+        }
+
+        self.accumulator -= steps * self.step;
+        steps as u32
+    }
+
+    /// How far rendering is between the previous (0.0) and the current (1.0) simulation state,
+    /// in `0.0..1.0`.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step) as f32
+    }
+}
+
+pub fn lerp_vec3(from: glm::Vec3, to: glm::Vec3, alpha: f32) -> glm::Vec3 {
+    glm::Vec3::new(
+        from.x + (to.x - from.x) * alpha,
+        from.y + (to.y - from.y) * alpha,
+        from.z + (to.z - from.z) * alpha,
+    )
+}
+
+/// Normalized lerp of unit quaternions stored as `(x, y, z, w)`, along the shorter arc. Close
+/// enough to slerp for the small rotations between two simulation steps.
+pub fn nlerp_quat(from: glm::Vec4, to: glm::Vec4, alpha: f32) -> glm::Vec4 {
+    let dot = from.x * to.x + from.y * to.y + from.z * to.z + from.w * to.w;
+    // q and -q are the same rotation
+    let sign = if dot < 0.0 { -1.0 } else { 1.0 };
+
+    let x = from.x + (to.x * sign - from.x) * alpha;
+    let y = from.y + (to.y * sign - from.y) * alpha;
+    let z = from.z + (to.z * sign - from.z) * alpha;
+    let w = from.w + (to.w * sign - from.w) * alpha;
+
+    let length = (x * x + y * y + z * z + w * w).sqrt();
+    if length <= f32::EPSILON {
+        return to;
+    }
+
+    glm::Vec4::new(x / length, y / length, z / length, w / length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance_accumulates_partial_steps() {
+        let mut timestep = FixedTimestep::new(0.25);
+
+        assert_eq!(timestep.advance(0.125), 0);
+        assert_eq!(timestep.alpha(), 0.5);
+        assert_eq!(timestep.advance(0.5), 2);
+        assert_eq!(timestep.alpha(), 0.5);
+        assert_eq!(timestep.advance(-1.0), 0);
+        assert_eq!(timestep.alpha(), 0.5);
+    }
+
+    #[test]
+    fn advance_drops_a_long_stall() {
+        let mut timestep = FixedTimestep::new(0.25);
+
+        assert_eq!(timestep.advance(100.125), MAX_STEPS_PER_FRAME);
+        assert_eq!(timestep.alpha(), 0.5);
+        assert_eq!(timestep.advance(0.0), 0);
+    }
+
+    #[test]
+    fn lerp_between_states() {
+        let from = glm::Vec3::new(0.0, 2.0, -4.0);
+        let to = glm::Vec3::new(4.0, 2.0, 0.0);
+
+        assert_eq!(lerp_vec3(from, to, 0.0), from);
+        assert_eq!(lerp_vec3(from, to, 0.25), glm::Vec3::new(1.0, 2.0, -3.0));
+        assert_eq!(lerp_vec3(from, to, 1.0), to);
+    }
+
+    #[test]
+    fn nlerp_takes_the_shorter_arc() {
+        let identity = glm::Vec4::new(0.0, 0.0, 0.0, 1.0);
+        let negated = glm::Vec4::new(0.0, 0.0, 0.0, -1.0);
+
+        // same rotation, must not pass through zero
+        assert_eq!(nlerp_quat(identity, negated, 0.5), identity);
+
+        let half = 0.5f32.sqrt();
+        let quarter_turn = glm::Vec4::new(0.0, half, 0.0, half);
+        let q = nlerp_quat(identity, quarter_turn, 0.5);
+        let length = (q.x * q.x + q.y * q.y + q.z * q.z + q.w * q.w).sqrt();
+
+        assert!((length - 1.0).abs() < 1e-6);
+        assert!((q.y - q.w * (22.5f32.to_radians().tan())).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+
+/// Frame times collected over a run, e.g. a benchmark.
+pub struct FrameStats {
+    frame_times: Vec<f64>,
+}
+
+/// Aggregated frame times in seconds.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStatsSummary {
+    pub frames: usize,
+    pub avg: f64,
+    pub min: f64,
+    pub max: f64,
+    /// 99% of the frames were at least this fast
+    pub p99: f64,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self {
+            frame_times: Vec::new(),
+        }
+    }
+
+    /// Adds the time of a frame in seconds.
+    pub fn record(&mut self, frame_time: f64) {
+        self.frame_times.push(frame_time);
+    }
+
+    /// `None` before the first frame.
+    pub fn summary(&self) -> Option<FrameStatsSummary> {
+        if self.frame_times.is_empty() {
+            return None;
+        }
+
+        let mut sorted = self.frame_times.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let frames = sorted.len();
+        // nearest rank
+        let p99_index = ((frames as f64 * 0.99).ceil() as usize).max(1) - 1;
+
+        Some(FrameStatsSummary {
+            frames,
+            avg: sorted.iter().sum::<f64>() / frames as f64,
+            min: sorted[0],
+            max: sorted[frames - 1],
+            p99: sorted[p99_index],
+        })
+    }
+}
+
+impl fmt::Display for FrameStatsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} frames: avg {:.3} ms ({:.1} fps), min {:.3} ms, max {:.3} ms, p99 {:.3} ms",
+            self.frames,
+            self.avg * 1000.0,
+            1.0 / self.avg,
+            self.min * 1000.0,
+            self.max * 1000.0,
+            self.p99 * 1000.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_summary_without_frames() {
+        assert!(FrameStats::new().summary().is_none());
+    }
+
+    #[test]
+    fn summary_of_unordered_frames() {
+        let mut stats = FrameStats::new();
+        for ms in (1..=100).rev() {
+            stats.record(ms as f64 / 1000.0);
+        }
+
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.frames, 100);
+        assert!((summary.avg - 0.0505).abs() < 1e-9);
+        assert_eq!(summary.min, 0.001);
+        assert_eq!(summary.max, 0.1);
+        assert_eq!(summary.p99, 0.099);
+    }
+
+    #[test]
+    fn p99_of_a_single_frame_is_that_frame() {
+        let mut stats = FrameStats::new();
+        stats.record(0.016);
+
+        assert_eq!(stats.summary().unwrap().p99, 0.016);
+    }
+}
@@ -0,0 +1,293 @@
+use log::info;
+
+/// glfw knows the mouse buttons 1 to 8
+const MOUSE_BUTTON_COUNT: usize = 8;
+
+/// glfw maps standard controllers to 15 buttons, A to DpadLeft
+const GAMEPAD_BUTTON_COUNT: usize = 15;
+
+/// slots searched for a gamepad, couch play rarely needs more
+const GAMEPAD_SLOTS: [glfw::JoystickId; 4] = [
+    glfw::JoystickId::Joystick1,
+    glfw::JoystickId::Joystick2,
+    glfw::JoystickId::Joystick3,
+    glfw::JoystickId::Joystick4,
+];
+
+/// stick deflection below this is treated as rest position, worn sticks rarely report 0.0
+pub const DEFAULT_DEAD_ZONE: f32 = 0.15;
+
+/// Input state collected from window events, queried once per frame.
+pub struct Input {
+    last_cursor_pos: Option<(f64, f64)>,
+    cursor_delta: (f64, f64),
+    scroll_delta: (f64, f64),
+    mouse_buttons: [bool; MOUSE_BUTTON_COUNT],
+    gamepad: Option<Gamepad>,
+    dead_zone: f32,
+}
+
+/// State of the gamepad in use, with the standard mapping: left stick moves, right stick looks.
+struct Gamepad {
+    id: glfw::JoystickId,
+    move_axis: (f32, f32),
+    look_axis: (f32, f32),
+    buttons: [bool; GAMEPAD_BUTTON_COUNT],
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self {
+            last_cursor_pos: None,
+            cursor_delta: (0.0, 0.0),
+            scroll_delta: (0.0, 0.0),
+            mouse_buttons: [false; MOUSE_BUTTON_COUNT],
+            gamepad: None,
+            dead_zone: DEFAULT_DEAD_ZONE,
+        }
+    }
+
+    /// Resets per frame state, called after a frame is rendered.
+    pub fn begin_frame(&mut self) {
+        self.cursor_delta = (0.0, 0.0);
+        self.scroll_delta = (0.0, 0.0);
+    }
+
+    pub fn on_cursor_pos(&mut self, x: f64, y: f64) {
+        if let Some((last_x, last_y)) = self.last_cursor_pos {
+            self.cursor_delta.0 += x - last_x;
+            self.cursor_delta.1 += y - last_y;
+        }
+
+        self.last_cursor_pos = Some((x, y));
+    }
+
+    /// Forgets the last cursor position, so the next cursor event produces no delta.
+    pub fn reset_cursor(&mut self) {
+        self.last_cursor_pos = None;
+        self.cursor_delta = (0.0, 0.0);
+    }
+
+    /// Cursor movement in screen coordinates since the last frame.
+    pub fn cursor_delta(&self) -> (f64, f64) {
+        self.cursor_delta
+    }
+
+    /// A frame can see several scroll events, they add up.
+    pub fn on_scroll(&mut self, x: f64, y: f64) {
+        self.scroll_delta.0 += x;
+        self.scroll_delta.1 += y;
+    }
+
+    pub fn on_mouse_button(&mut self, button: glfw::MouseButton, action: glfw::Action) {
+        match action {
+            glfw::Action::Press => self.mouse_buttons[button as usize] = true,
+            glfw::Action::Release => self.mouse_buttons[button as usize] = false,
+            glfw::Action::Repeat => {}
+        }
+    }
+
+    /// Scroll offset since the last frame, `y` is the usual mouse wheel.
+    pub fn scroll_delta(&self) -> (f64, f64) {
+        self.scroll_delta
+    }
+
+    /// Whether `button` is held down.
+    pub fn mouse_button(&self, button: glfw::MouseButton) -> bool {
+        self.mouse_buttons[button as usize]
+    }
+
+    /// Reads the gamepad state, glfw has no gamepad events. Picks up the first connected
+    /// gamepad and lets go of it when it disconnects.
+    pub fn poll_gamepad(&mut self, glfw: &glfw::Glfw) {
+        if let Some(gamepad) = &self.gamepad {
+            if !glfw.get_joystick(gamepad.id).is_present() {
+                info!("gamepad {:?} disconnected", gamepad.id);
+                self.gamepad = None;
+            }
+        }
+
+        if self.gamepad.is_none() {
+            self.gamepad = GAMEPAD_SLOTS
+                .iter()
+                .find(|id| glfw.get_joystick(**id).is_gamepad())
+                .map(|id| {
+                    info!(
+                        "gamepad {:?} connected: {:?}",
+                        id,
+                        glfw.get_joystick(*id).get_gamepad_name()
+                    );
+
+                    Gamepad {
+                        id: *id,
+                        move_axis: (0.0, 0.0),
+                        look_axis: (0.0, 0.0),
+                        buttons: [false; GAMEPAD_BUTTON_COUNT],
+                    }
+                });
+        }
+
+        let dead_zone = self.dead_zone;
+        if let Some(gamepad) = &mut self.gamepad {
+            match glfw.get_joystick(gamepad.id).get_gamepad_state() {
+                Some(state) => gamepad.update(&state, dead_zone),
+                None => self.gamepad = None,
+            }
+        }
+    }
+
+    pub fn set_dead_zone(&mut self, dead_zone: f32) {
+        self.dead_zone = dead_zone;
+    }
+
+    pub fn has_gamepad(&self) -> bool {
+        self.gamepad.is_some()
+    }
+
+    /// Left stick, in `-1.0..=1.0` per axis with the dead zone applied. `y` is positive downwards.
+    pub fn move_axis(&self) -> (f32, f32) {
+        self.gamepad
+            .as_ref()
+            .map(|gamepad| gamepad.move_axis)
+            .unwrap_or((0.0, 0.0))
+    }
+
+    /// Right stick, in `-1.0..=1.0` per axis with the dead zone applied. `y` is positive downwards.
+    pub fn look_axis(&self) -> (f32, f32) {
+        self.gamepad
+            .as_ref()
+            .map(|gamepad| gamepad.look_axis)
+            .unwrap_or((0.0, 0.0))
+    }
+
+    /// Whether `button` of the gamepad is held down.
+    pub fn gamepad_button(&self, button: glfw::GamepadButton) -> bool {
+        self.gamepad
+            .as_ref()
+            .map(|gamepad| gamepad.buttons[button as usize])
+            .unwrap_or(false)
+    }
+}
+
+impl Gamepad {
+    fn update(&mut self, state: &glfw::GamepadState, dead_zone: f32) {
+        self.move_axis = apply_dead_zone(
+            (
+                state.get_axis(glfw::GamepadAxis::AxisLeftX),
+                state.get_axis(glfw::GamepadAxis::AxisLeftY),
+            ),
+            dead_zone,
+        );
+
+        self.look_axis = apply_dead_zone(
+            (
+                state.get_axis(glfw::GamepadAxis::AxisRightX),
+                state.get_axis(glfw::GamepadAxis::AxisRightY),
+            ),
+            dead_zone,
+        );
+
+        for (index, pressed) in self.buttons.iter_mut().enumerate() {
+            *pressed = gamepad_button(index)
+                .map(|button| state.get_button_state(button) == glfw::Action::Press)
+                .unwrap_or(false);
+        }
+    }
+}
+
+fn gamepad_button(index: usize) -> Option<glfw::GamepadButton> {
+    use glfw::GamepadButton::*;
+
+    [
+        ButtonA,
+        ButtonB,
+        ButtonX,
+        ButtonY,
+        ButtonLeftBumper,
+        ButtonRightBumper,
+        ButtonBack,
+        ButtonStart,
+        ButtonGuide,
+        ButtonLeftThumb,
+        ButtonRightThumb,
+        ButtonDpadUp,
+        ButtonDpadRight,
+        ButtonDpadDown,
+        ButtonDpadLeft,
+    ]
+    .get(index)
+    .cloned()
+}
+
+/// Radial dead zone: deflection within `dead_zone` is zero, beyond it is rescaled to start at
+/// zero again, so there is no jump at the edge of the dead zone. Result length is at most 1.0.
+pub fn apply_dead_zone((x, y): (f32, f32), dead_zone: f32) -> (f32, f32) {
+    let length = (x * x + y * y).sqrt();
+    if length <= dead_zone || dead_zone >= 1.0 {
+        return (0.0, 0.0);
+    }
+
+    let scaled = ((length - dead_zone) / (1.0 - dead_zone)).min(1.0);
+
+    (x / length * scaled, y / length * scaled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close((x, y): (f32, f32), (expected_x, expected_y): (f32, f32)) {
+        assert!(
+            (x - expected_x).abs() < 1e-5 && (y - expected_y).abs() < 1e-5,
+            "({}, {}) != ({}, {})",
+            x,
+            y,
+            expected_x,
+            expected_y
+        );
+    }
+
+    #[test]
+    fn dead_zone_swallows_small_deflection() {
+        assert_close(apply_dead_zone((0.1, -0.1), 0.15), (0.0, 0.0));
+    }
+
+    #[test]
+    fn dead_zone_rescales_beyond_its_edge() {
+        assert_close(apply_dead_zone((0.575, 0.0), 0.15), (0.5, 0.0));
+        assert_close(apply_dead_zone((0.0, -1.0), 0.15), (0.0, -1.0));
+    }
+
+    #[test]
+    fn dead_zone_result_is_at_most_unit_length() {
+        let half_sqrt2 = std::f32::consts::FRAC_1_SQRT_2;
+
+        assert_close(apply_dead_zone((1.0, 1.0), 0.15), (half_sqrt2, half_sqrt2));
+    }
+
+    #[test]
+    fn full_dead_zone_is_always_zero() {
+        assert_close(apply_dead_zone((1.0, 0.0), 1.0), (0.0, 0.0));
+    }
+
+    #[test]
+    fn gamepad_buttons_by_index() {
+        assert!(matches!(
+            gamepad_button(glfw::GamepadButton::ButtonA as usize),
+            Some(glfw::GamepadButton::ButtonA)
+        ));
+        assert!(matches!(
+            gamepad_button(glfw::GamepadButton::ButtonDpadLeft as usize),
+            Some(glfw::GamepadButton::ButtonDpadLeft)
+        ));
+        assert!(gamepad_button(GAMEPAD_BUTTON_COUNT).is_none());
+    }
+
+    #[test]
+    fn no_gamepad_reads_as_rest() {
+        let input = Input::new();
+
+        assert_eq!(input.move_axis(), (0.0, 0.0));
+        assert!(!input.gamepad_button(glfw::GamepadButton::ButtonA));
+    }
+}
@@ -1,87 +1,523 @@
+mod camera;
+mod dynamic_resolution;
 mod error;
+mod fixed_timestep;
+mod frame_stats;
+mod input;
 mod vulkan;
 
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
 use glfw::WindowEvent;
 
+pub use camera::{block_at, perspective, unproject, Camera};
+pub use dynamic_resolution::DynamicResolution;
 use error::{GameError, Result};
-use log::debug;
-use vulkan::{Vulkan, VulkanInit};
+pub use fixed_timestep::{lerp_vec3, nlerp_quat, FixedTimestep};
+pub use frame_stats::{FrameStats, FrameStatsSummary};
+use input::Input;
+use log::{debug, info, warn};
+use vk_sys as vk;
+use vulkan::Error as VulkanError;
+pub use vulkan::{
+    textured_cube, triangle_grid, work_groups, ComputeInit, ComputePipeline, FrameOutcome,
+    PowerPreference, PresentMode, StorageBuffer, ValidationFeatures, Vulkan, VulkanInit,
+    DEFAULT_FENCE_TIMEOUT,
+};
+
+/// seconds to wait for events per loop iteration while rendering is paused
+const PAUSED_EVENT_TIMEOUT: f64 = 0.1;
 
 pub struct GameInit {
     pub debug: bool,
+    /// closes the window on Escape, disable to handle Escape yourself (e.g. for a pause menu)
+    pub escape_closes: bool,
+    pub unfocused: Unfocused,
+    /// initial window size in screen coordinates
+    pub width: u32,
+    pub height: u32,
+    pub title: String,
+    /// the user can resize the window (default), otherwise it keeps `width` x `height`
+    pub resizable: bool,
+    pub window_position: WindowPosition,
+    /// applied before the window is created, e.g. `Decorated(false)` or `Floating(true)`. The
+    /// client API stays `NoApi` as Vulkan requires, `ClientApi` hints are rejected.
+    pub window_hints: Vec<glfw::WindowHint>,
+    /// benchmark: renders this many frames, even if the window is closed or unfocused, prints
+    /// frame time stats and returns from `make_loop`
+    pub bench_frames: Option<u64>,
+    /// stress test: draws this many triangles instead of the scene triangle, the benchmark then
+    /// reports triangles per second
+    pub stress_triangles: Option<u32>,
+    /// renders on the GPU whose name contains this (case-insensitive), if there is one
+    pub device_name_filter: Option<String>,
+    /// discrete or integrated GPU, if there are both
+    pub power_preference: PowerPreference,
+    /// integrated GPUs are candidates like any other (default), otherwise only
+    /// `device_name_filter` picks one
+    pub allow_integrated: bool,
+    /// presentation modes in the order of preference, the first supported one is used and FIFO
+    /// if there is none. E.g. `[PRESENT_MODE_IMMEDIATE_KHR]` to benchmark without vsync or
+    /// `[PRESENT_MODE_FIFO_KHR]` to save power. Default MAILBOX, then FIFO.
+    pub present_mode_preference: Vec<vk::PresentModeKHR>,
+    /// keeps the pipeline cache in this file, which speeds up the next start
+    pub pipeline_cache_path: Option<PathBuf>,
+    /// smoke test: closes the window and returns from `make_loop` after this wall clock time,
+    /// however many frames were rendered (also ends a benchmark early)
+    pub run_for: Option<Duration>,
+}
+
+impl Default for GameInit {
+    fn default() -> Self {
+        Self {
+            debug: false,
+            escape_closes: true,
+            unfocused: Unfocused::Render,
+            width: 640,
+            height: 480,
+            title: "Vulkan Rust".to_owned(),
+            resizable: true,
+            window_position: WindowPosition::Default,
+            window_hints: Vec::new(),
+            bench_frames: None,
+            stress_triangles: None,
+            device_name_filter: None,
+            power_preference: PowerPreference::default(),
+            allow_integrated: true,
+            present_mode_preference: PresentMode::default().preference(),
+            pipeline_cache_path: None,
+            run_for: None,
+        }
+    }
+}
+
+/// Where the window shows up initially.
+#[derive(Debug, Clone, Copy)]
+pub enum WindowPosition {
+    /// wherever the window system puts it
+    Default,
+    /// centered on the primary monitor
+    Centered,
+    /// top left corner in screen coordinates
+    At(i32, i32),
+}
+
+/// What to do while the window has no focus. Events are handled in any case.
+#[derive(Debug, Clone, Copy)]
+pub enum Unfocused {
+    /// render at full speed
+    Render,
+    /// render at most this many frames per second, has to be positive
+    Throttle(f64),
+    /// don't render at all
+    Pause,
+}
+
+impl Unfocused {
+    /// A throttle needs a finite, positive frame rate, otherwise the frame interval is
+    /// infinite or negative.
+    fn check(&self) -> Result<()> {
+        match *self {
+            Unfocused::Throttle(fps) if !(fps.is_finite() && fps > 0.0) => Err(
+                GameError::WindowError(format!("unfocused throttle of {} fps", fps)),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Seconds to wait for events before the next frame is due, `None` if it is due now.
+    fn wait_secs(&self, since_last_frame: f64) -> Option<f64> {
+        match *self {
+            Unfocused::Render => None,
+            Unfocused::Throttle(fps) => {
+                let remaining = 1.0 / fps - since_last_frame;
+                if remaining > 0.0 {
+                    Some(remaining)
+                } else {
+                    None
+                }
+            }
+            Unfocused::Pause => Some(PAUSED_EVENT_TIMEOUT),
+        }
+    }
 }
 
 pub struct Game {
     debug: bool,
+    escape_closes: bool,
     glfw: glfw::Glfw,
     vulkan: Option<Vulkan>,
     window: glfw::Window,
     window_events: std::sync::mpsc::Receiver<(f64, WindowEvent)>,
+    frame_number: u64,
+    start_time: f64,
+    input: Input,
+    cursor_captured: bool,
+    unfocused: Unfocused,
+    focused: bool,
+    last_frame_time: f64,
+    dynamic_resolution: Option<DynamicResolution>,
+    bench_frames: Option<u64>,
+    stress_triangles: Option<u32>,
+    run_for: Option<Duration>,
+    frame_stats: FrameStats,
 }
 
 impl Game {
     pub fn new(init: GameInit) -> Result<Self> {
+        init.unfocused.check()?;
+
         let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
 
-        glfw.window_hint(glfw::WindowHint::Visible(true));
+        // an explicit `Resizable` in `window_hints` wins
+        glfw.window_hint(glfw::WindowHint::Resizable(init.resizable));
+
+        let mut visible = true;
+        for hint in &init.window_hints {
+            match *hint {
+                glfw::WindowHint::ClientApi(_) => {
+                    return Err(GameError::WindowError(format!(
+                        "window hint {:?} not allowed, vulkan needs the client API NoApi",
+                        hint
+                    )));
+                }
+                // applied by showing the window or not
+                glfw::WindowHint::Visible(hint_visible) => visible = hint_visible,
+                _ => glfw.window_hint(*hint),
+            }
+        }
+
+        // shown once it is positioned
+        glfw.window_hint(glfw::WindowHint::Visible(false));
         glfw.window_hint(glfw::WindowHint::ClientApi(glfw::ClientApiHint::NoApi));
 
+        if init.width == 0 || init.height == 0 {
+            return Err(GameError::WindowError(format!(
+                "window size {}x{} has no area",
+                init.width, init.height
+            )));
+        }
+
         let (mut window, window_events) = glfw
-            .create_window(640, 480, "Vulkan Rust", glfw::WindowMode::Windowed)
+            .create_window(
+                init.width,
+                init.height,
+                &init.title,
+                glfw::WindowMode::Windowed,
+            )
             .expect("Failed to create GLFW window.");
 
+        match init.window_position {
+            WindowPosition::Default => {}
+            WindowPosition::Centered => match centered_position(&mut glfw, &window) {
+                Some((x, y)) => window.set_pos(x, y),
+                None => warn!("cannot center the window, no primary monitor or video mode"),
+            },
+            WindowPosition::At(x, y) => window.set_pos(x, y),
+        }
+        if visible {
+            window.show();
+        }
+
         assert!(glfw.vulkan_supported());
         let required_extensions = glfw.get_required_instance_extensions().unwrap();
         debug!("GLFW required vulkan extensions: {:?}", required_extensions);
 
-        let vulkan = Vulkan::new(VulkanInit {
+        let mut vulkan = Vulkan::new(VulkanInit {
             debug: init.debug,
             window: &mut window,
             req_ext: &required_extensions,
             req_layers: &vec![],
+            fence_timeout: DEFAULT_FENCE_TIMEOUT,
+            queue_priorities: vec![1.0],
+            sparse_binding: false,
+            validation_features: Default::default(),
+            device_name_filter: init.device_name_filter,
+            power_preference: init.power_preference,
+            allow_integrated: init.allow_integrated,
+            pipeline_cache_path: init.pipeline_cache_path,
         })
         .map_err(|e| GameError::VulkanError(format!("vulkan init failed: {}", e)))?;
 
+        vulkan
+            .set_present_mode_preference(init.present_mode_preference)
+            .map_err(|e| GameError::VulkanError(format!("set present mode failed: {}", e)))?;
+
+        if let Some(count) = init.stress_triangles {
+            vulkan
+                .set_scene_vertices(triangle_grid(count))
+                .map_err(|e| GameError::VulkanError(format!("stress triangles failed: {}", e)))?;
+        }
+
+        let start_time = glfw.get_time();
+        let focused = window.is_focused();
+
         Ok(Self {
             debug: init.debug,
+            escape_closes: init.escape_closes,
             glfw,
             vulkan: Some(vulkan),
             window,
             window_events,
+            frame_number: 0,
+            start_time,
+            input: Input::new(),
+            cursor_captured: false,
+            unfocused: init.unfocused,
+            focused,
+            last_frame_time: start_time,
+            dynamic_resolution: None,
+            bench_frames: init.bench_frames,
+            stress_triangles: init.stress_triangles,
+            run_for: init.run_for,
+            frame_stats: FrameStats::new(),
         })
     }
 
-    pub fn make_loop(&mut self) {
+    /// Number of frames presented so far, frames skipped e.g. while minimized are not counted.
+    pub fn frame_number(&self) -> u64 {
+        self.frame_number
+    }
+
+    /// Seconds since the game loop started.
+    pub fn elapsed_secs(&self) -> f64 {
+        self.glfw.get_time() - self.start_time
+    }
+
+    pub fn input(&self) -> &Input {
+        &self.input
+    }
+
+    /// Captures and hides the cursor for camera control, or releases it e.g. when a menu opens.
+    pub fn set_cursor_captured(&mut self, captured: bool) {
+        if captured == self.cursor_captured {
+            return;
+        }
+
+        self.window.set_cursor_mode(if captured {
+            glfw::CursorMode::Disabled
+        } else {
+            glfw::CursorMode::Normal
+        });
+
+        // the cursor jumps when the mode changes, that must not show up as movement
+        self.input.reset_cursor();
+        self.cursor_captured = captured;
+    }
+
+    pub fn is_cursor_captured(&self) -> bool {
+        self.cursor_captured
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Sets the window and taskbar icon from image files, ideally the same icon in several sizes
+    /// (e.g. 16x16, 32x32, 48x48), the platform picks the best fitting one.
+    pub fn set_window_icon<P: AsRef<Path>>(&mut self, paths: &[P]) -> Result<()> {
+        let icons = paths
+            .iter()
+            .map(|path| {
+                let image = image::open(path)
+                    .map_err(|e| {
+                        GameError::IconError(format!(
+                            "cannot load icon {}: {}",
+                            path.as_ref().display(),
+                            e
+                        ))
+                    })?
+                    .into_rgba8();
+
+                Ok(glfw::PixelImage {
+                    width: image.width(),
+                    height: image.height(),
+                    pixels: image
+                        .pixels()
+                        .map(|pixel| u32::from_le_bytes(pixel.0))
+                        .collect(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.window.set_icon_from_pixels(icons);
+
+        Ok(())
+    }
+
+    /// Adjusts the render scale to the frame time, `None` keeps the current render scale.
+    pub fn set_dynamic_resolution(
+        &mut self,
+        dynamic_resolution: Option<DynamicResolution>,
+    ) -> Result<()> {
+        if let (Some(dynamic_resolution), Some(vulkan)) = (&dynamic_resolution, &mut self.vulkan) {
+            vulkan
+                .set_render_scale(Some(dynamic_resolution.scale()))
+                .map_err(|e| GameError::VulkanError(format!("set render scale failed: {}", e)))?;
+        }
+
+        self.dynamic_resolution = dynamic_resolution;
+
+        Ok(())
+    }
+
+    pub fn dynamic_resolution(&self) -> Option<&DynamicResolution> {
+        self.dynamic_resolution.as_ref()
+    }
+
+    /// Times of the presented frames so far.
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.frame_stats
+    }
+
+    pub fn make_loop(&mut self) -> Result<()> {
         let vulkan = self.vulkan.as_mut().unwrap();
 
         self.window.set_key_polling(true);
         self.window.set_framebuffer_size_polling(true);
+        self.window.set_cursor_pos_polling(true);
+        self.window.set_mouse_button_polling(true);
+        self.window.set_scroll_polling(true);
+        self.window.set_focus_polling(true);
+
+        self.start_time = self.glfw.get_time();
 
-        while !self.window.should_close() {
-            self.glfw.poll_events();
+        // a benchmark ignores closing the window, `vulkan` borrows `self` mutably
+        while match self.bench_frames {
+            Some(bench_frames) => self.frame_number < bench_frames,
+            None => !self.window.should_close(),
+        } {
+            if let Some(run_for) = self.run_for {
+                if self.glfw.get_time() - self.start_time >= run_for.as_secs_f64() {
+                    info!("ran for {:?}, closing", run_for);
+                    self.window.set_should_close(true);
+                    break;
+                }
+            }
+
+            let wait = if self.focused || self.bench_frames.is_some() {
+                None
+            } else {
+                self.unfocused
+                    .wait_secs(self.glfw.get_time() - self.last_frame_time)
+            };
+
+            match wait {
+                // blocks without spinning, but wakes up for events
+                Some(secs) => self.glfw.wait_events_timeout(secs),
+                None => self.glfw.poll_events(),
+            }
+            self.input.poll_gamepad(&self.glfw);
 
-            for (_, event) in glfw::flush_messages(&self.window_events) {
+            let events: Vec<_> = glfw::flush_messages(&self.window_events)
+                .map(|(_, event)| event)
+                .collect();
+            let resized = framebuffer_resized(&events);
+
+            for event in events {
                 match event {
-                    glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
+                    glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _)
+                        if self.escape_closes =>
+                    {
                         self.window.set_should_close(true);
                     }
 
-                    glfw::WindowEvent::FramebufferSize(_, _) => {
-                        vulkan.on_framebuffer_changed().unwrap();
+                    glfw::WindowEvent::CursorPos(x, y) => {
+                        self.input.on_cursor_pos(x, y);
+                    }
+
+                    glfw::WindowEvent::MouseButton(button, action, _) => {
+                        self.input.on_mouse_button(button, action);
+                    }
+
+                    glfw::WindowEvent::Scroll(x, y) => {
+                        self.input.on_scroll(x, y);
+                    }
+
+                    glfw::WindowEvent::Focus(focused) => {
+                        self.focused = focused;
                     }
 
                     _ => {}
                 }
             }
 
+            if resized {
+                vulkan.on_framebuffer_changed().map_err(|e| {
+                    GameError::VulkanError(format!("framebuffer change failed: {}", e))
+                })?;
+            }
+
+            if wait.is_some() {
+                continue;
+            }
+
             let start = self.glfw.get_time();
-            vulkan.draw_frame(&self.window).unwrap();
+            match vulkan.draw_frame(&self.window) {
+                Ok(FrameOutcome::Presented) => {
+                    self.frame_number = vulkan.frame_count();
+
+                    // CPU side frame time, vulkan has no GPU timestamps (yet)
+                    let frame_time = start - self.last_frame_time;
+                    self.frame_stats.record(frame_time);
+                    if let Some(scale) = self
+                        .dynamic_resolution
+                        .as_mut()
+                        .and_then(|dynamic_resolution| dynamic_resolution.update(frame_time))
+                    {
+                        vulkan.set_render_scale(Some(scale)).map_err(|e| {
+                            GameError::VulkanError(format!("set render scale failed: {}", e))
+                        })?;
+                    }
+                }
+                Ok(FrameOutcome::ZeroExtent) => {
+                    // minimized, nothing to render until a restore resizes the framebuffer
+                    self.glfw.wait_events_timeout(PAUSED_EVENT_TIMEOUT);
+                }
+                Ok(outcome) => {
+                    debug!("frame not presented: {:?}", outcome);
+                }
+                Err(err) if err.is_timeout() => {
+                    warn!("skipping frame: {}", err);
+                }
+                Err(VulkanError::DeviceHang(report)) => {
+                    return Err(GameError::DeviceHang(format!("{}", report)));
+                }
+                Err(err) => {
+                    return Err(GameError::VulkanError(format!(
+                        "draw frame failed: {}",
+                        err
+                    )));
+                }
+            }
             let end = self.glfw.get_time();
+            self.last_frame_time = start;
+            self.input.begin_frame();
 
             debug!("diff: {}", end - start)
         }
 
         vulkan.wait_idle().unwrap();
+
+        if self.bench_frames.is_some() {
+            match self.frame_stats.summary() {
+                Some(summary) => {
+                    println!("benchmark: {}", summary);
+                    if let Some(count) = self.stress_triangles {
+                        println!(
+                            "benchmark: {:.0} triangles per second",
+                            count as f64 / summary.avg
+                        );
+                    }
+                }
+                None => println!("benchmark: no frames presented"),
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -90,3 +526,61 @@ impl Drop for Game {
         self.vulkan.take().map(|vulkan| vulkan.destroy());
     }
 }
+
+/// Whether the framebuffer size changed. A drag resize delivers many sizes per loop iteration,
+/// they lead to a single `on_framebuffer_changed`, only the latest size matters.
+fn framebuffer_resized(events: &[glfw::WindowEvent]) -> bool {
+    events
+        .iter()
+        .any(|event| matches!(event, glfw::WindowEvent::FramebufferSize(_, _)))
+}
+
+/// Window position centering it on the primary monitor, `None` if the monitor cannot be queried.
+fn centered_position(glfw: &mut glfw::Glfw, window: &glfw::Window) -> Option<(i32, i32)> {
+    let (width, height) = window.get_size();
+
+    glfw.with_primary_monitor(|_, monitor| {
+        let monitor = monitor?;
+        let mode = monitor.get_video_mode()?;
+        let (monitor_x, monitor_y) = monitor.get_pos();
+
+        Some((
+            monitor_x + (mode.width as i32 - width) / 2,
+            monitor_y + (mode.height as i32 - height) / 2,
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framebuffer_sizes_are_coalesced() {
+        let events = [
+            glfw::WindowEvent::FramebufferSize(800, 600),
+            glfw::WindowEvent::CursorPos(1.0, 2.0),
+            glfw::WindowEvent::FramebufferSize(810, 600),
+            glfw::WindowEvent::FramebufferSize(820, 610),
+        ];
+        assert!(framebuffer_resized(&events));
+
+        assert!(!framebuffer_resized(&[
+            glfw::WindowEvent::Focus(true),
+            glfw::WindowEvent::Scroll(0.0, 1.0),
+        ]));
+        assert!(!framebuffer_resized(&[]));
+    }
+
+    #[test]
+    fn throttle_needs_a_positive_frame_rate() {
+        assert!(Unfocused::Throttle(10.0).check().is_ok());
+        assert!(Unfocused::Render.check().is_ok());
+        assert!(Unfocused::Pause.check().is_ok());
+
+        assert!(Unfocused::Throttle(0.0).check().is_err());
+        assert!(Unfocused::Throttle(-5.0).check().is_err());
+        assert!(Unfocused::Throttle(f64::NAN).check().is_err());
+        assert!(Unfocused::Throttle(f64::INFINITY).check().is_err());
+    }
+}
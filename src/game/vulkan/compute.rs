@@ -0,0 +1,336 @@
+//! Compute pipelines over host visible storage buffers, dispatched on the compute queue, e.g.
+//! for offline chunk generation with `Vulkan::new_compute_only`.
+
+use std::{mem::size_of, ptr, time::Instant};
+
+use super::{
+    error::{to_other, to_vulkan, Error},
+    handle,
+    pipeline::{create_shader_module, entry_point_name, DEFAULT_ENTRY_POINT},
+    swapchain::{create_host_visible_buffer, write_host_visible_memory},
+    DescriptorWriter, Result, Vulkan,
+};
+use log::debug;
+use vk_sys as vk;
+
+/// Pipeline of a compute shader whose set 0 has a storage buffer at each binding
+/// `0..storage_buffer_count`.
+pub struct ComputePipeline {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    shader_module: vk::ShaderModule,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    /// rewritten by every dispatch, which waits for it
+    descriptor_set: vk::DescriptorSet,
+    storage_buffer_count: u32,
+}
+
+/// Storage buffer in host visible memory, filled at creation and read back after dispatches.
+pub struct StorageBuffer {
+    pub buffer: vk::Buffer,
+    pub size: vk::DeviceSize,
+    memory: vk::DeviceMemory,
+    coherent: bool,
+}
+
+impl Vulkan {
+    /// `shader` is SPIR-V with a `main` entry point, e.g. from `include_spirv!`.
+    pub fn create_compute_pipeline(
+        &self,
+        shader: &[u32],
+        storage_buffer_count: u32,
+    ) -> Result<ComputePipeline> {
+        let ctx = &self.ctx;
+        let entry_point = entry_point_name(DEFAULT_ENTRY_POINT)?;
+
+        let bindings: Vec<_> = (0..storage_buffer_count)
+            .map(|binding| vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptorType: vk::DESCRIPTOR_TYPE_STORAGE_BUFFER,
+                descriptorCount: 1,
+                stageFlags: vk::SHADER_STAGE_COMPUTE_BIT,
+                pImmutableSamplers: ptr::null(),
+            })
+            .collect();
+
+        let descriptor_set_layout = handle::descriptor_set_layout(
+            ctx,
+            unsafe {
+                ctx.dp.create_descriptor_set_layout(
+                    ctx.device,
+                    &vk::DescriptorSetLayoutCreateInfo {
+                        sType: vk::STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+                        pNext: ptr::null(),
+                        flags: 0,
+                        bindingCount: bindings.len() as u32,
+                        pBindings: bindings.as_ptr(),
+                    },
+                )
+            }
+            .map_err(to_vulkan)?,
+        );
+
+        // a pool size of 0 is invalid
+        let pool_size = vk::DescriptorPoolSize {
+            ty: vk::DESCRIPTOR_TYPE_STORAGE_BUFFER,
+            descriptorCount: storage_buffer_count.max(1),
+        };
+
+        let descriptor_pool = handle::descriptor_pool(
+            ctx,
+            unsafe {
+                ctx.dp.create_descriptor_pool(
+                    ctx.device,
+                    &vk::DescriptorPoolCreateInfo {
+                        sType: vk::STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO,
+                        pNext: ptr::null(),
+                        flags: 0,
+                        maxSets: 1,
+                        poolSizeCount: 1,
+                        pPoolSizes: &pool_size,
+                    },
+                )
+            }
+            .map_err(to_vulkan)?,
+        );
+
+        let descriptor_set = unsafe {
+            ctx.dp.allocate_descriptor_sets(
+                ctx.device,
+                &vk::DescriptorSetAllocateInfo {
+                    sType: vk::STRUCTURE_TYPE_DESCRIPTOR_SET_ALLOCATE_INFO,
+                    pNext: ptr::null(),
+                    descriptorPool: *descriptor_pool,
+                    descriptorSetCount: 1,
+                    pSetLayouts: &*descriptor_set_layout,
+                },
+            )
+        }
+        .map_err(to_vulkan)?[0];
+
+        let layout = handle::pipeline_layout(
+            ctx,
+            unsafe {
+                ctx.dp.create_pipeline_layout(
+                    ctx.device,
+                    &vk::PipelineLayoutCreateInfo {
+                        sType: vk::STRUCTURE_TYPE_PIPELINE_LAYOUT_CREATE_INFO,
+                        pNext: ptr::null(),
+                        flags: 0,
+                        setLayoutCount: 1,
+                        pSetLayouts: &*descriptor_set_layout,
+                        pushConstantRangeCount: 0,
+                        pPushConstantRanges: ptr::null(),
+                    },
+                )
+            }
+            .map_err(to_vulkan)?,
+        );
+
+        let shader_module =
+            handle::shader_module(ctx, create_shader_module(&ctx.dp, ctx.device, shader)?);
+
+        let pipeline_info = vk::ComputePipelineCreateInfo {
+            sType: vk::STRUCTURE_TYPE_COMPUTE_PIPELINE_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            stage: vk::PipelineShaderStageCreateInfo {
+                sType: vk::STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
+                pNext: ptr::null(),
+                flags: 0,
+                stage: vk::SHADER_STAGE_COMPUTE_BIT,
+                module: *shader_module,
+                pName: entry_point.as_ptr(),
+                pSpecializationInfo: ptr::null(),
+            },
+            layout: *layout,
+            basePipelineHandle: vk::NULL_HANDLE,
+            basePipelineIndex: -1,
+        };
+
+        let start = Instant::now();
+        let pipeline = unsafe {
+            ctx.dp
+                .create_compute_pipelines(ctx.device, ctx.pipeline_cache, &[pipeline_info])
+        }
+        .map_err(to_vulkan)?[0];
+        debug!("compute pipeline created in {:?}", start.elapsed());
+
+        Ok(ComputePipeline {
+            pipeline,
+            layout: layout.release(),
+            shader_module: shader_module.release(),
+            descriptor_set_layout: descriptor_set_layout.release(),
+            descriptor_pool: descriptor_pool.release(),
+            descriptor_set,
+            storage_buffer_count,
+        })
+    }
+
+    /// The pipeline must not be in use, `dispatch` waits for it.
+    pub fn destroy_compute_pipeline(&self, pipeline: ComputePipeline) {
+        let ctx = &self.ctx;
+
+        ctx.dp.destroy_pipeline(ctx.device, pipeline.pipeline);
+        ctx.dp.destroy_pipeline_layout(ctx.device, pipeline.layout);
+        ctx.dp
+            .destroy_shader_module(ctx.device, pipeline.shader_module);
+        ctx.dp
+            .destroy_descriptor_pool(ctx.device, pipeline.descriptor_pool);
+        ctx.dp
+            .destroy_descriptor_set_layout(ctx.device, pipeline.descriptor_set_layout);
+    }
+
+    /// Storage buffer with `data`, e.g. the input of a dispatch or zeros for its output.
+    pub fn create_storage_buffer<T>(&self, data: &[T]) -> Result<StorageBuffer> {
+        if data.is_empty() {
+            return Err(to_other("a storage buffer needs data"));
+        }
+
+        let size = (size_of::<T>() * data.len()) as vk::DeviceSize;
+        let (buffer, memory, coherent) =
+            create_host_visible_buffer(&self.ctx, size, vk::BUFFER_USAGE_STORAGE_BUFFER_BIT)?;
+
+        write_host_visible_memory(&self.ctx, *memory, coherent, data)?;
+
+        Ok(StorageBuffer {
+            buffer: buffer.release(),
+            size,
+            memory: memory.release(),
+            coherent,
+        })
+    }
+
+    /// Content of `buffer` as `T`s, after the dispatches writing it returned.
+    pub fn read_storage_buffer<T: Copy>(&self, buffer: &StorageBuffer) -> Result<Vec<T>> {
+        let ctx = &self.ctx;
+        let count = buffer.size as usize / size_of::<T>();
+
+        if !buffer.coherent {
+            ctx.invalidate_mapped_range(buffer.memory, 0, buffer.size)?;
+        }
+
+        let data = ctx
+            .dp
+            .map_memory(ctx.device, buffer.memory, 0, buffer.size, 0)
+            .map_err(to_vulkan)?;
+
+        let mut elements = Vec::with_capacity(count);
+        unsafe {
+            // count of elements, not bytes
+            ptr::copy_nonoverlapping(data as *const T, elements.as_mut_ptr(), count);
+            elements.set_len(count);
+        }
+
+        ctx.dp.unmap_memory(ctx.device, buffer.memory);
+
+        Ok(elements)
+    }
+
+    pub fn destroy_storage_buffer(&self, buffer: StorageBuffer) {
+        self.ctx.dp.destroy_buffer(self.ctx.device, buffer.buffer);
+        self.ctx.dp.free_memory(self.ctx.device, buffer.memory);
+    }
+
+    /// Runs `group_count` work groups of `pipeline` on `compute_queue` with `buffers` bound in
+    /// order, and waits for them. Their writes are visible to `read_storage_buffer` afterwards.
+    pub fn dispatch(
+        &self,
+        pipeline: &ComputePipeline,
+        buffers: &[&StorageBuffer],
+        group_count: [u32; 3],
+    ) -> Result<()> {
+        let ctx = &self.ctx;
+
+        if buffers.len() != pipeline.storage_buffer_count as usize {
+            return Err(Error::Other(format!(
+                "{} storage buffers for a pipeline with {}",
+                buffers.len(),
+                pipeline.storage_buffer_count
+            )));
+        }
+
+        // one time command buffers come from the command pool of the graphics family
+        if ctx.queue_family_indices.compute != ctx.queue_family_indices.graphics {
+            return Err(to_other(
+                "the compute queue family has no command pool, it differs from the graphics one",
+            ));
+        }
+
+        let mut writer = DescriptorWriter::default();
+        for (binding, buffer) in buffers.iter().enumerate() {
+            writer.write_buffer_of_type(
+                pipeline.descriptor_set,
+                binding as u32,
+                vk::DESCRIPTOR_TYPE_STORAGE_BUFFER,
+                buffer.buffer,
+                vk::WHOLE_SIZE,
+            );
+        }
+        writer.flush(ctx);
+
+        ctx.submit_one_time_to(ctx.queue_families.compute_queue, |command_buffer| {
+            ctx.dp.cmd_bind_pipeline(
+                command_buffer,
+                vk::PIPELINE_BIND_POINT_COMPUTE,
+                pipeline.pipeline,
+            );
+            ctx.dp.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PIPELINE_BIND_POINT_COMPUTE,
+                pipeline.layout,
+                0,
+                &[pipeline.descriptor_set],
+                &[],
+            );
+
+            let [x, y, z] = group_count;
+            ctx.dp.cmd_dispatch(command_buffer, x, y, z);
+
+            let host_barriers: Vec<_> = buffers
+                .iter()
+                .map(|buffer| vk::BufferMemoryBarrier {
+                    sType: vk::STRUCTURE_TYPE_BUFFER_MEMORY_BARRIER,
+                    pNext: ptr::null(),
+                    srcAccessMask: vk::ACCESS_SHADER_WRITE_BIT,
+                    dstAccessMask: vk::ACCESS_HOST_READ_BIT,
+                    srcQueueFamilyIndex: vk::QUEUE_FAMILY_IGNORED,
+                    dstQueueFamilyIndex: vk::QUEUE_FAMILY_IGNORED,
+                    buffer: buffer.buffer,
+                    offset: 0,
+                    size: vk::WHOLE_SIZE,
+                })
+                .collect();
+
+            ctx.dp.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PIPELINE_STAGE_COMPUTE_SHADER_BIT,
+                vk::PIPELINE_STAGE_HOST_BIT,
+                0,
+                &[],
+                &host_barriers,
+                &[],
+            );
+        })
+    }
+}
+
+/// Work groups of `local_size` invocations covering `invocations`, the last one may be partial.
+pub fn work_groups(invocations: u32, local_size: u32) -> u32 {
+    (invocations + local_size - 1) / local_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn work_groups_cover_all_invocations() {
+        assert_eq!(work_groups(0, 64), 0);
+        assert_eq!(work_groups(1, 64), 1);
+        assert_eq!(work_groups(64, 64), 1);
+        assert_eq!(work_groups(65, 64), 2);
+        assert_eq!(work_groups(1000, 1), 1000);
+    }
+}
@@ -1,6 +1,10 @@
-use super::util::copy_extent_2d;
-use super::{error::to_vulkan, Context};
-use super::{Result, SwapchainContext};
+use super::util::{atom_range, copy_rect_2d};
+use super::{
+    error::{classify, to_vulkan, Error, Status},
+    handle, Context,
+};
+use super::{Pipeline, Result, SwapchainContext};
+use std::time::Duration;
 use vk_sys as vk;
 
 impl Context {
@@ -39,27 +43,163 @@ impl Context {
         }
     }
 
+    /// Acquires the next image of `swapchain`, `semaphore` is signaled once it can be rendered
+    /// to. `Status::Suboptimal` still comes with an acquired image, which `vulkanic` would drop
+    /// together with its index, so this calls the unwrapped function.
+    pub fn acquire_next_image(
+        &self,
+        swapchain: vk::SwapchainKHR,
+        semaphore: vk::Semaphore,
+    ) -> Result<(u32, Status)> {
+        let mut image_index = 0;
+        let result = unsafe {
+            self.raw_dp.AcquireNextImageKHR(
+                self.device,
+                swapchain,
+                u64::MAX,
+                semaphore,
+                vk::NULL_HANDLE,
+                &mut image_index,
+            )
+        };
+
+        let status = classify(if result == vk::SUCCESS {
+            Ok(())
+        } else {
+            Err(result)
+        })?;
+
+        Ok((image_index, status))
+    }
+
+    /// Records commands with `record` and submits them to the graphics queue, returns when they
+    /// finished. For uploads and other work outside of frames, not for every frame.
+    pub fn submit_one_time<F>(&self, record: F) -> Result<()>
+    where
+        F: FnOnce(vk::CommandBuffer),
+    {
+        self.submit_one_time_to(self.queue_families.graphics_queue, record)
+    }
+
+    /// Like `submit_one_time`, to `queue` of the graphics family, e.g. the compute queue when
+    /// both families are the same.
+    pub fn submit_one_time_to<F>(&self, queue: vk::Queue, record: F) -> Result<()>
+    where
+        F: FnOnce(vk::CommandBuffer),
+    {
+        let command_buffer = handle::command_buffer(self, self.allocate_primary_command_buffer()?);
+
+        unsafe {
+            self.dp.begin_command_buffer(
+                *command_buffer,
+                &vk::CommandBufferBeginInfo {
+                    sType: vk::STRUCTURE_TYPE_COMMAND_BUFFER_BEGIN_INFO,
+                    pNext: std::ptr::null(),
+                    flags: vk::COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT,
+                    pInheritanceInfo: std::ptr::null(),
+                },
+            )
+        }
+        .map_err(to_vulkan)?;
+
+        record(*command_buffer);
+
+        self.dp
+            .end_command_buffer(*command_buffer)
+            .map_err(to_vulkan)?;
+
+        let command_buffers = [*command_buffer];
+        let submit_info = vk::SubmitInfo {
+            sType: vk::STRUCTURE_TYPE_SUBMIT_INFO,
+            pNext: std::ptr::null(),
+            waitSemaphoreCount: 0,
+            pWaitSemaphores: std::ptr::null(),
+            pWaitDstStageMask: std::ptr::null(),
+            commandBufferCount: command_buffers.len() as u32,
+            pCommandBuffers: command_buffers.as_ptr(),
+            signalSemaphoreCount: 0,
+            pSignalSemaphores: std::ptr::null(),
+        };
+
+        unsafe { self.dp.queue_submit(queue, &[submit_info], vk::NULL_HANDLE) }
+            .map_err(to_vulkan)?;
+
+        self.dp.queue_wait_idle(queue).map_err(to_vulkan)
+    }
+
+    /// Copies the first `size` bytes of `src` to the start of `dst`, which need `TRANSFER_SRC`
+    /// and `TRANSFER_DST` usage.
+    pub fn copy_buffer(
+        &self,
+        src: vk::Buffer,
+        dst: vk::Buffer,
+        size: vk::DeviceSize,
+    ) -> Result<()> {
+        self.submit_one_time(|command_buffer| {
+            let region = vk::BufferCopy {
+                srcOffset: 0,
+                dstOffset: 0,
+                size,
+            };
+
+            self.dp.cmd_copy_buffer(command_buffer, src, dst, &[region]);
+        })
+    }
+
+    /// Copies tightly packed texels from the start of `src` into mip level 0, layer 0 of the
+    /// color `image`. The image has to be in `TRANSFER_DST_OPTIMAL` layout already, it stays in
+    /// it.
+    pub fn copy_buffer_to_image(
+        &self,
+        src: vk::Buffer,
+        image: vk::Image,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        self.submit_one_time(|command_buffer| {
+            let region = vk::BufferImageCopy {
+                bufferOffset: 0,
+                // 0 is tightly packed
+                bufferRowLength: 0,
+                bufferImageHeight: 0,
+                imageSubresource: vk::ImageSubresourceLayers {
+                    aspectMask: vk::IMAGE_ASPECT_COLOR_BIT,
+                    mipLevel: 0,
+                    baseArrayLayer: 0,
+                    layerCount: 1,
+                },
+                imageOffset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                imageExtent: vk::Extent3D {
+                    width,
+                    height,
+                    depth: 1,
+                },
+            };
+
+            self.dp.cmd_copy_buffer_to_image(
+                command_buffer,
+                src,
+                image,
+                vk::IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                &[region],
+            );
+        })
+    }
+
     pub fn begin_render_pass(
         &self,
         sc_ctx: &SwapchainContext,
         command_buffer: vk::CommandBuffer,
         framebuffer: vk::Framebuffer,
     ) {
-        let clear_values = [vk::ClearValue {
-            color: vk::ClearColorValue {
-                float32: [0.0, 0.0, 0.0, 0.0],
-            },
-        }];
+        let clear_values = &sc_ctx.clear_values;
 
         let info = vk::RenderPassBeginInfo {
             sType: vk::STRUCTURE_TYPE_RENDER_PASS_BEGIN_INFO,
             pNext: std::ptr::null(),
             renderPass: sc_ctx.render_pass,
             framebuffer,
-            renderArea: vk::Rect2D {
-                offset: vk::Offset2D { x: 0, y: 0 },
-                extent: copy_extent_2d(&sc_ctx.extent),
-            },
+            renderArea: copy_rect_2d(&sc_ctx.render_area),
             clearValueCount: clear_values.len() as u32,
             pClearValues: clear_values.as_ptr(),
         };
@@ -70,14 +210,119 @@ impl Context {
         };
     }
 
-    pub fn cmd_bind_pipeline(&self, sc_ctx: &SwapchainContext, command_buffer: vk::CommandBuffer) {
+    /// Sets the dynamic viewport and scissor, so the scene fits into `area` and nothing is drawn
+    /// outside of it.
+    pub fn cmd_set_viewport(&self, command_buffer: vk::CommandBuffer, area: &vk::Rect2D) {
+        let viewport = vk::Viewport {
+            x: area.offset.x as f32,
+            y: area.offset.y as f32,
+            width: area.extent.width as f32,
+            height: area.extent.height as f32,
+            minDepth: 0.0,
+            maxDepth: 1.0,
+        };
+
+        self.dp.cmd_set_viewport(command_buffer, 0, &[viewport]);
+        self.dp
+            .cmd_set_scissor(command_buffer, 0, &[copy_rect_2d(area)]);
+    }
+
+    pub fn cmd_bind_pipeline(&self, pipeline: &Pipeline, command_buffer: vk::CommandBuffer) {
         self.dp.cmd_bind_pipeline(
             command_buffer,
             vk::PIPELINE_BIND_POINT_GRAPHICS,
-            sc_ctx.pipeline,
+            pipeline.pipeline,
         );
     }
 
+    /// Binds `(buffer, offset)` pairs to the bindings of a `VertexLayout`, in order.
+    pub fn cmd_bind_vertex_buffers(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffers: &[(vk::Buffer, vk::DeviceSize)],
+    ) {
+        let (buffers, offsets): (Vec<_>, Vec<_>) = buffers.iter().cloned().unzip();
+
+        self.dp
+            .cmd_bind_vertex_buffers(command_buffer, 0, &buffers, &offsets);
+    }
+
+    /// Pushes `data` to the start of the push constant range of `layout` used by `stage_flags`.
+    /// Fails instead of recording if it exceeds `maxPushConstantsSize` or isn't a multiple of 4
+    /// bytes.
+    pub fn cmd_push_constants(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        stage_flags: vk::ShaderStageFlags,
+        data: &[u8],
+    ) -> Result<()> {
+        if data.len() > self.limits.maxPushConstantsSize as usize || data.len() % 4 != 0 {
+            return Err(Error::Other(format!(
+                "cannot push {} bytes of constants, the device limit is {} and the size must be a \
+                 multiple of 4",
+                data.len(),
+                self.limits.maxPushConstantsSize
+            )));
+        }
+
+        self.dp
+            .cmd_push_constants(command_buffer, layout, stage_flags, 0, data);
+
+        Ok(())
+    }
+
+    /// Makes host writes to mapped, non-coherent `memory` visible to the device. The range is
+    /// widened to `nonCoherentAtomSize`, the allocation must be a multiple of it.
+    pub fn flush_mapped_range(
+        &self,
+        memory: vk::DeviceMemory,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> Result<()> {
+        self.dp
+            .flush_mapped_memory_ranges(self.device, &[self.mapped_range(memory, offset, size)])
+            .map_err(to_vulkan)
+    }
+
+    /// Makes device writes to mapped, non-coherent `memory` visible to the host, after the
+    /// writing commands finished. Rounded like `flush_mapped_range`.
+    pub fn invalidate_mapped_range(
+        &self,
+        memory: vk::DeviceMemory,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> Result<()> {
+        self.dp
+            .invalidate_mapped_memory_ranges(
+                self.device,
+                &[self.mapped_range(memory, offset, size)],
+            )
+            .map_err(to_vulkan)
+    }
+
+    fn mapped_range(
+        &self,
+        memory: vk::DeviceMemory,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> vk::MappedMemoryRange {
+        let (offset, size) = atom_range(offset, size, self.limits.nonCoherentAtomSize);
+
+        vk::MappedMemoryRange {
+            sType: vk::STRUCTURE_TYPE_MAPPED_MEMORY_RANGE,
+            pNext: std::ptr::null(),
+            memory,
+            offset,
+            size,
+        }
+    }
+
+    /// Covers the viewport, for pipelines from `create_fullscreen_pipeline`.
+    pub fn cmd_draw_fullscreen_triangle(&self, command_buffer: vk::CommandBuffer) {
+        self.dp.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+
     pub fn create_semaphore(&self) -> Result<vk::Semaphore> {
         unsafe {
             self.dp.create_semaphore(
@@ -100,6 +345,15 @@ impl Context {
         self.dp.destroy_fence(self.device, fence);
     }
 
+    pub fn wait_for_fence(&self, fence: vk::Fence, timeout: Duration) -> Result<Status> {
+        let timeout = timeout.as_nanos().min(u64::MAX as u128) as u64;
+
+        classify(
+            self.dp
+                .wait_for_fences(self.device, &[fence], true, timeout),
+        )
+    }
+
     pub fn create_signaled_fence(&self) -> Result<vk::Fence> {
         unsafe {
             self.dp.create_fence(
@@ -0,0 +1,203 @@
+//! Messages of the debug messenger (`VK_EXT_debug_utils`), mostly from the validation layer.
+
+use std::{
+    borrow::Cow,
+    ffi::{c_void, CStr},
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use log::{log, log_enabled, Level};
+use vk_sys as vk;
+
+/// counts of the debug messenger, the messenger exists before `Vulkan` does, so there is no user
+/// data to count them in
+pub(super) static VALIDATION_STATS: ValidationStats = ValidationStats::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum MessageSeverity {
+    Verbose,
+    Info,
+    Warning,
+    Error,
+}
+
+impl MessageSeverity {
+    /// The most severe bit of `flags`.
+    fn from_flags(flags: vk::DebugUtilsMessageSeverityFlagBitsEXT) -> Option<Self> {
+        if flags & vk::DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT != 0 {
+            Some(Self::Error)
+        } else if flags & vk::DEBUG_UTILS_MESSAGE_SEVERITY_WARNING_BIT_EXT != 0 {
+            Some(Self::Warning)
+        } else if flags & vk::DEBUG_UTILS_MESSAGE_SEVERITY_INFO_BIT_EXT != 0 {
+            Some(Self::Info)
+        } else if flags & vk::DEBUG_UTILS_MESSAGE_SEVERITY_VERBOSE_BIT_EXT != 0 {
+            Some(Self::Verbose)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum MessageType {
+    General,
+    Validation,
+    Performance,
+}
+
+impl MessageType {
+    /// The most specific bit of `flags`, `General` without any.
+    fn from_flags(flags: vk::DebugUtilsMessageTypeFlagsEXT) -> Self {
+        if flags & vk::DEBUG_UTILS_MESSAGE_TYPE_VALIDATION_BIT_EXT != 0 {
+            Self::Validation
+        } else if flags & vk::DEBUG_UTILS_MESSAGE_TYPE_PERFORMANCE_BIT_EXT != 0 {
+            Self::Performance
+        } else {
+            Self::General
+        }
+    }
+}
+
+impl fmt::Display for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::General => "general",
+            Self::Validation => "validation",
+            Self::Performance => "performance",
+        })
+    }
+}
+
+pub(super) struct ValidationStats {
+    errors: AtomicU32,
+}
+
+impl ValidationStats {
+    const fn new() -> Self {
+        Self {
+            errors: AtomicU32::new(0),
+        }
+    }
+
+    pub(super) fn errors(&self) -> u32 {
+        self.errors.load(Ordering::Relaxed)
+    }
+}
+
+/// `Trace` for messages without a known severity bit.
+pub(super) fn log_level(severity: Option<MessageSeverity>) -> Level {
+    match severity {
+        Some(MessageSeverity::Error) => Level::Error,
+        Some(MessageSeverity::Warning) => Level::Warn,
+        Some(MessageSeverity::Info) => Level::Info,
+        Some(MessageSeverity::Verbose) => Level::Debug,
+        None => Level::Trace,
+    }
+}
+
+/// Counts and logs a message, everything the callback does besides reading its arguments.
+pub(super) fn on_message(
+    severity: Option<MessageSeverity>,
+    message_type: MessageType,
+    message: &str,
+    stats: &ValidationStats,
+) {
+    if severity == Some(MessageSeverity::Error) {
+        stats.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    log!(
+        target: "vulkan",
+        log_level(severity),
+        "vulkan | {} | {}",
+        message_type,
+        message
+    );
+}
+
+/// `pfnUserCallback` of the debug messenger, only turns its arguments into Rust types for
+/// `on_message`.
+pub(super) extern "system" fn debugger_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagBitsEXT,
+    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    _user_data: *mut c_void,
+) -> vk::Bool32 {
+    let severity = MessageSeverity::from_flags(message_severity);
+
+    // verbose validation sends thousands of messages per second, most of them filtered out, those
+    // aren't even read
+    let message = if log_enabled!(target: "vulkan", log_level(severity)) {
+        // the layer passes valid callback data with a null-terminated message
+        unsafe { CStr::from_ptr((*callback_data).pMessage) }.to_string_lossy()
+    } else {
+        Cow::Borrowed("")
+    };
+
+    on_message(
+        severity,
+        MessageType::from_flags(message_type),
+        &message,
+        &VALIDATION_STATS,
+    );
+
+    vk::FALSE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_severe_bit_wins() {
+        assert_eq!(
+            MessageSeverity::from_flags(
+                vk::DEBUG_UTILS_MESSAGE_SEVERITY_WARNING_BIT_EXT
+                    | vk::DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT
+            ),
+            Some(MessageSeverity::Error)
+        );
+        assert_eq!(
+            MessageSeverity::from_flags(vk::DEBUG_UTILS_MESSAGE_SEVERITY_VERBOSE_BIT_EXT),
+            Some(MessageSeverity::Verbose)
+        );
+        assert_eq!(MessageSeverity::from_flags(0), None);
+        assert_eq!(log_level(None), Level::Trace);
+        assert_eq!(log_level(Some(MessageSeverity::Warning)), Level::Warn);
+    }
+
+    #[test]
+    fn most_specific_type_wins() {
+        assert_eq!(
+            MessageType::from_flags(
+                vk::DEBUG_UTILS_MESSAGE_TYPE_GENERAL_BIT_EXT
+                    | vk::DEBUG_UTILS_MESSAGE_TYPE_PERFORMANCE_BIT_EXT
+            ),
+            MessageType::Performance
+        );
+        assert_eq!(MessageType::from_flags(0), MessageType::General);
+    }
+
+    #[test]
+    fn only_errors_are_counted() {
+        let stats = ValidationStats::new();
+
+        on_message(
+            Some(MessageSeverity::Warning),
+            MessageType::Validation,
+            "warning",
+            &stats,
+        );
+        on_message(None, MessageType::General, "unknown", &stats);
+        assert_eq!(stats.errors(), 0);
+
+        on_message(
+            Some(MessageSeverity::Error),
+            MessageType::Validation,
+            "error",
+            &stats,
+        );
+        assert_eq!(stats.errors(), 1);
+    }
+}
@@ -0,0 +1,388 @@
+//! Depth buffers and their formats, which unlike color formats have no format every device
+//! supports.
+
+use std::ptr;
+
+use super::{
+    error::to_vulkan,
+    handle,
+    swapchain::{create_host_visible_buffer, find_memory_type},
+    Context, Error, Result,
+};
+use vk_sys as vk;
+
+/// in order of preference, stencil formats only as fallback
+pub const DEPTH_FORMAT_CANDIDATES: [vk::Format; 3] = [
+    vk::FORMAT_D32_SFLOAT,
+    vk::FORMAT_D32_SFLOAT_S8_UINT,
+    vk::FORMAT_D24_UNORM_S8_UINT,
+];
+
+/// First of `candidates` usable as depth attachment with optimal tiling.
+pub fn find_supported_depth_format(ctx: &Context, candidates: &[vk::Format]) -> Result<vk::Format> {
+    candidates
+        .iter()
+        .find(|format| {
+            let properties = ctx
+                .ip
+                .get_physical_device_format_properties(ctx.physical_device, **format);
+
+            properties.optimalTilingFeatures & vk::FORMAT_FEATURE_DEPTH_STENCIL_ATTACHMENT_BIT != 0
+        })
+        .cloned()
+        .ok_or_else(|| {
+            Error::Other(format!(
+                "none of the depth formats {:?} is supported",
+                candidates
+            ))
+        })
+}
+
+/// Device local depth attachment, cleared when the render pass begins and stored at its end, so
+/// `read_texel` can copy from it after the frame.
+pub struct DepthBuffer {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub image_view: vk::ImageView,
+}
+
+impl DepthBuffer {
+    pub fn new(ctx: &Context, extent: &vk::Extent2D, format: vk::Format) -> Result<Self> {
+        let info = vk::ImageCreateInfo {
+            sType: vk::STRUCTURE_TYPE_IMAGE_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            imageType: vk::IMAGE_TYPE_2D,
+            format,
+            extent: vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            mipLevels: 1,
+            arrayLayers: 1,
+            samples: vk::SAMPLE_COUNT_1_BIT,
+            tiling: vk::IMAGE_TILING_OPTIMAL,
+            usage: vk::IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT | vk::IMAGE_USAGE_TRANSFER_SRC_BIT,
+            sharingMode: vk::SHARING_MODE_EXCLUSIVE,
+            queueFamilyIndexCount: 0,
+            pQueueFamilyIndices: ptr::null(),
+            initialLayout: vk::IMAGE_LAYOUT_UNDEFINED,
+        };
+
+        let image = handle::image(
+            ctx,
+            unsafe { ctx.dp.create_image(ctx.device, &info) }.map_err(to_vulkan)?,
+        );
+
+        let memory_requirements = ctx.dp.get_image_memory_requirements(ctx.device, *image);
+        let allocate_info = vk::MemoryAllocateInfo {
+            sType: vk::STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
+            pNext: ptr::null(),
+            allocationSize: memory_requirements.size,
+            memoryTypeIndex: find_memory_type(
+                ctx,
+                memory_requirements.memoryTypeBits,
+                vk::MEMORY_PROPERTY_DEVICE_LOCAL_BIT,
+            )?,
+        };
+
+        let memory = handle::memory(
+            ctx,
+            unsafe { ctx.dp.allocate_memory(ctx.device, &allocate_info) }.map_err(to_vulkan)?,
+        );
+
+        ctx.dp
+            .bind_image_memory(ctx.device, *image, *memory, 0)
+            .map_err(to_vulkan)?;
+
+        let view_info = vk::ImageViewCreateInfo {
+            sType: vk::STRUCTURE_TYPE_IMAGE_VIEW_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            image: *image,
+            viewType: vk::IMAGE_VIEW_TYPE_2D,
+            format,
+            components: vk::ComponentMapping {
+                r: vk::COMPONENT_SWIZZLE_IDENTITY,
+                g: vk::COMPONENT_SWIZZLE_IDENTITY,
+                b: vk::COMPONENT_SWIZZLE_IDENTITY,
+                a: vk::COMPONENT_SWIZZLE_IDENTITY,
+            },
+            subresourceRange: depth_subresource_range(format),
+        };
+
+        let image_view =
+            unsafe { ctx.dp.create_image_view(ctx.device, &view_info) }.map_err(to_vulkan)?;
+
+        Ok(Self {
+            image: image.release(),
+            memory: memory.release(),
+            image_view,
+        })
+    }
+
+    /// Depth at texel `(x, y)`, waits for it. Submitted to the graphics queue after the frames,
+    /// so it sees the depth the last of them stored. The image must have been rendered, it is
+    /// expected in `DEPTH_STENCIL_ATTACHMENT_OPTIMAL` and left in it. Depth formats are often
+    /// neither linearly filterable nor blittable, the texel is copied as is and decoded on the
+    /// host.
+    pub fn read_texel(&self, ctx: &Context, format: vk::Format, x: u32, y: u32) -> Result<f32> {
+        let size = depth_texel_size(format)?;
+        let (buffer, memory, coherent) =
+            create_host_visible_buffer(ctx, size, vk::BUFFER_USAGE_TRANSFER_DST_BIT)?;
+
+        ctx.submit_one_time(|command_buffer| {
+            let to_transfer_barrier = vk::ImageMemoryBarrier {
+                sType: vk::STRUCTURE_TYPE_IMAGE_MEMORY_BARRIER,
+                pNext: ptr::null(),
+                srcAccessMask: vk::ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+                dstAccessMask: vk::ACCESS_TRANSFER_READ_BIT,
+                oldLayout: vk::IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                newLayout: vk::IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                srcQueueFamilyIndex: vk::QUEUE_FAMILY_IGNORED,
+                dstQueueFamilyIndex: vk::QUEUE_FAMILY_IGNORED,
+                image: self.image,
+                subresourceRange: depth_subresource_range(format),
+            };
+
+            ctx.dp.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT,
+                vk::PIPELINE_STAGE_TRANSFER_BIT,
+                0,
+                &[],
+                &[],
+                &[to_transfer_barrier],
+            );
+
+            let region = vk::BufferImageCopy {
+                bufferOffset: 0,
+                bufferRowLength: 0,
+                bufferImageHeight: 0,
+                // only the depth of depth stencil formats
+                imageSubresource: vk::ImageSubresourceLayers {
+                    aspectMask: vk::IMAGE_ASPECT_DEPTH_BIT,
+                    mipLevel: 0,
+                    baseArrayLayer: 0,
+                    layerCount: 1,
+                },
+                imageOffset: vk::Offset3D {
+                    x: x as i32,
+                    y: y as i32,
+                    z: 0,
+                },
+                imageExtent: vk::Extent3D {
+                    width: 1,
+                    height: 1,
+                    depth: 1,
+                },
+            };
+
+            ctx.dp.cmd_copy_image_to_buffer(
+                command_buffer,
+                self.image,
+                vk::IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                *buffer,
+                &[region],
+            );
+
+            // back to the layout the render pass leaves it in, so reading twice between frames
+            // starts from the same one
+            let to_attachment_barrier = vk::ImageMemoryBarrier {
+                sType: vk::STRUCTURE_TYPE_IMAGE_MEMORY_BARRIER,
+                pNext: ptr::null(),
+                srcAccessMask: 0,
+                dstAccessMask: vk::ACCESS_DEPTH_STENCIL_ATTACHMENT_READ_BIT
+                    | vk::ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+                oldLayout: vk::IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+                newLayout: vk::IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                srcQueueFamilyIndex: vk::QUEUE_FAMILY_IGNORED,
+                dstQueueFamilyIndex: vk::QUEUE_FAMILY_IGNORED,
+                image: self.image,
+                subresourceRange: depth_subresource_range(format),
+            };
+
+            let host_barrier = vk::BufferMemoryBarrier {
+                sType: vk::STRUCTURE_TYPE_BUFFER_MEMORY_BARRIER,
+                pNext: ptr::null(),
+                srcAccessMask: vk::ACCESS_TRANSFER_WRITE_BIT,
+                dstAccessMask: vk::ACCESS_HOST_READ_BIT,
+                srcQueueFamilyIndex: vk::QUEUE_FAMILY_IGNORED,
+                dstQueueFamilyIndex: vk::QUEUE_FAMILY_IGNORED,
+                buffer: *buffer,
+                offset: 0,
+                size,
+            };
+
+            ctx.dp.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PIPELINE_STAGE_TRANSFER_BIT,
+                vk::PIPELINE_STAGE_HOST_BIT | vk::PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT,
+                0,
+                &[],
+                &[host_barrier],
+                &[to_attachment_barrier],
+            );
+        })?;
+
+        if !coherent {
+            ctx.invalidate_mapped_range(*memory, 0, size)?;
+        }
+
+        let mut texel = [0u8; 4];
+        let data = ctx
+            .dp
+            .map_memory(ctx.device, *memory, 0, size, 0)
+            .map_err(to_vulkan)?;
+        unsafe { ptr::copy_nonoverlapping(data as *const u8, texel.as_mut_ptr(), size as usize) };
+        ctx.dp.unmap_memory(ctx.device, *memory);
+
+        decode_depth(format, &texel[..size as usize])
+    }
+
+    pub fn destroy(&self, ctx: &Context) {
+        ctx.dp.destroy_image_view(ctx.device, self.image_view);
+        ctx.dp.destroy_image(ctx.device, self.image);
+        ctx.dp.free_memory(ctx.device, self.memory);
+    }
+}
+
+pub fn is_depth_format(format: vk::Format) -> bool {
+    match format {
+        vk::FORMAT_D16_UNORM
+        | vk::FORMAT_X8_D24_UNORM_PACK32
+        | vk::FORMAT_D32_SFLOAT
+        | vk::FORMAT_S8_UINT
+        | vk::FORMAT_D16_UNORM_S8_UINT
+        | vk::FORMAT_D24_UNORM_S8_UINT
+        | vk::FORMAT_D32_SFLOAT_S8_UINT => true,
+        _ => false,
+    }
+}
+
+/// Whether views and barriers of `format` need the stencil aspect besides the depth aspect.
+pub fn has_stencil_component(format: vk::Format) -> bool {
+    match format {
+        vk::FORMAT_S8_UINT
+        | vk::FORMAT_D16_UNORM_S8_UINT
+        | vk::FORMAT_D24_UNORM_S8_UINT
+        | vk::FORMAT_D32_SFLOAT_S8_UINT => true,
+        _ => false,
+    }
+}
+
+/// Aspect mask covering all components of a depth `format`.
+pub fn depth_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    if has_stencil_component(format) {
+        vk::IMAGE_ASPECT_DEPTH_BIT | vk::IMAGE_ASPECT_STENCIL_BIT
+    } else {
+        vk::IMAGE_ASPECT_DEPTH_BIT
+    }
+}
+
+fn depth_subresource_range(format: vk::Format) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        aspectMask: depth_aspect_mask(format),
+        baseMipLevel: 0,
+        levelCount: 1,
+        baseArrayLayer: 0,
+        layerCount: 1,
+    }
+}
+
+/// Bytes per texel of the depth aspect copied to a buffer, without the stencil. 24 bit depth
+/// takes 32 bits.
+pub fn depth_texel_size(format: vk::Format) -> Result<vk::DeviceSize> {
+    match format {
+        vk::FORMAT_D16_UNORM | vk::FORMAT_D16_UNORM_S8_UINT => Ok(2),
+        vk::FORMAT_X8_D24_UNORM_PACK32
+        | vk::FORMAT_D24_UNORM_S8_UINT
+        | vk::FORMAT_D32_SFLOAT
+        | vk::FORMAT_D32_SFLOAT_S8_UINT => Ok(4),
+        _ => Err(Error::Other(format!("format {} has no depth", format))),
+    }
+}
+
+/// Depth of a texel copied to a buffer, `depth_texel_size(format)` bytes in the byte order of
+/// the host. Normalized formats are mapped to `0.0..=1.0`.
+pub fn decode_depth(format: vk::Format, bytes: &[u8]) -> Result<f32> {
+    match (format, bytes) {
+        (vk::FORMAT_D16_UNORM, &[a, b]) | (vk::FORMAT_D16_UNORM_S8_UINT, &[a, b]) => {
+            Ok(u16::from_ne_bytes([a, b]) as f32 / u16::MAX as f32)
+        }
+        // the upper 8 bits are undefined
+        (vk::FORMAT_X8_D24_UNORM_PACK32, &[a, b, c, d])
+        | (vk::FORMAT_D24_UNORM_S8_UINT, &[a, b, c, d]) => {
+            Ok((u32::from_ne_bytes([a, b, c, d]) & D24_MAX) as f32 / D24_MAX as f32)
+        }
+        (vk::FORMAT_D32_SFLOAT, &[a, b, c, d]) | (vk::FORMAT_D32_SFLOAT_S8_UINT, &[a, b, c, d]) => {
+            Ok(f32::from_ne_bytes([a, b, c, d]))
+        }
+        _ => Err(Error::Other(format!(
+            "{} bytes are no depth texel of format {}",
+            bytes.len(),
+            format
+        ))),
+    }
+}
+
+const D24_MAX: u32 = 0x00ff_ffff;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depth_formats() {
+        assert!(is_depth_format(vk::FORMAT_D32_SFLOAT));
+        assert!(is_depth_format(vk::FORMAT_D24_UNORM_S8_UINT));
+        assert!(!is_depth_format(vk::FORMAT_B8G8R8A8_SRGB));
+        assert!(!is_depth_format(vk::FORMAT_R16G16B16A16_SFLOAT));
+    }
+
+    #[test]
+    fn depth_texel_sizes() {
+        assert_eq!(depth_texel_size(vk::FORMAT_D16_UNORM).unwrap(), 2);
+        assert_eq!(depth_texel_size(vk::FORMAT_D24_UNORM_S8_UINT).unwrap(), 4);
+        assert_eq!(depth_texel_size(vk::FORMAT_D32_SFLOAT_S8_UINT).unwrap(), 4);
+        assert!(depth_texel_size(vk::FORMAT_B8G8R8A8_SRGB).is_err());
+    }
+
+    #[test]
+    fn decodes_float_depth() {
+        let bytes = 0.25f32.to_ne_bytes();
+
+        assert_eq!(decode_depth(vk::FORMAT_D32_SFLOAT, &bytes).unwrap(), 0.25);
+        assert_eq!(
+            decode_depth(vk::FORMAT_D32_SFLOAT_S8_UINT, &bytes).unwrap(),
+            0.25
+        );
+    }
+
+    #[test]
+    fn decodes_normalized_depth() {
+        assert_eq!(
+            decode_depth(vk::FORMAT_D16_UNORM, &u16::MAX.to_ne_bytes()).unwrap(),
+            1.0
+        );
+
+        // garbage in the undefined upper bits
+        let bytes = (0xab00_0000 | D24_MAX).to_ne_bytes();
+        assert_eq!(
+            decode_depth(vk::FORMAT_D24_UNORM_S8_UINT, &bytes).unwrap(),
+            1.0
+        );
+        let bytes = 0xab00_0000u32.to_ne_bytes();
+        assert_eq!(
+            decode_depth(vk::FORMAT_X8_D24_UNORM_PACK32, &bytes).unwrap(),
+            0.0
+        );
+    }
+
+    #[test]
+    fn texel_size_must_match_the_format() {
+        assert!(decode_depth(vk::FORMAT_D32_SFLOAT, &[0, 0]).is_err());
+        assert!(decode_depth(vk::FORMAT_S8_UINT, &[0]).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Descriptor writes collected and applied with a single `update_descriptor_sets`.
+
+use std::ptr;
+
+use super::Context;
+use vk_sys as vk;
+
+/// Accumulates descriptor writes of any number of sets, e.g. uniforms, textures and shadow maps
+/// of a frame, until `flush`.
+#[derive(Default)]
+pub struct DescriptorWriter {
+    writes: Vec<Write>,
+}
+
+struct Write {
+    set: vk::DescriptorSet,
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    info: Info,
+}
+
+enum Info {
+    Buffer(vk::DescriptorBufferInfo),
+    Image(vk::DescriptorImageInfo),
+}
+
+impl DescriptorWriter {
+    /// `range` bytes of `buffer` from its start as uniform buffer, `vk::WHOLE_SIZE` for all.
+    pub fn write_buffer(
+        &mut self,
+        set: vk::DescriptorSet,
+        binding: u32,
+        buffer: vk::Buffer,
+        range: vk::DeviceSize,
+    ) -> &mut Self {
+        self.write_buffer_of_type(
+            set,
+            binding,
+            vk::DESCRIPTOR_TYPE_UNIFORM_BUFFER,
+            buffer,
+            range,
+        )
+    }
+
+    /// Like `write_buffer`, for `UNIFORM_BUFFER_DYNAMIC`, `STORAGE_BUFFER` and the like.
+    pub fn write_buffer_of_type(
+        &mut self,
+        set: vk::DescriptorSet,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        buffer: vk::Buffer,
+        range: vk::DeviceSize,
+    ) -> &mut Self {
+        self.writes.push(Write {
+            set,
+            binding,
+            descriptor_type,
+            info: Info::Buffer(vk::DescriptorBufferInfo {
+                buffer,
+                offset: 0,
+                range,
+            }),
+        });
+
+        self
+    }
+
+    /// `view` sampled with `sampler` as combined image sampler, in `SHADER_READ_ONLY_OPTIMAL`
+    /// layout.
+    pub fn write_image(
+        &mut self,
+        set: vk::DescriptorSet,
+        binding: u32,
+        view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) -> &mut Self {
+        self.writes.push(Write {
+            set,
+            binding,
+            descriptor_type: vk::DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+            info: Info::Image(vk::DescriptorImageInfo {
+                sampler,
+                imageView: view,
+                imageLayout: vk::IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+            }),
+        });
+
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Applies all writes in one `update_descriptor_sets` and starts over. The sets must not be
+    /// in use by pending command buffers.
+    pub fn flush(&mut self, ctx: &Context) {
+        if self.writes.is_empty() {
+            return;
+        }
+
+        let writes = self.write_descriptor_sets();
+        unsafe { ctx.dp.update_descriptor_sets(ctx.device, &writes, &[]) };
+
+        self.writes.clear();
+    }
+
+    /// One `vk::WriteDescriptorSet` per write, pointing into `self`, which has to outlive the
+    /// update.
+    fn write_descriptor_sets(&self) -> Vec<vk::WriteDescriptorSet> {
+        self.writes
+            .iter()
+            .map(|write| {
+                let (buffer_info, image_info) = match &write.info {
+                    Info::Buffer(info) => (info as *const _, ptr::null()),
+                    Info::Image(info) => (ptr::null(), info as *const _),
+                };
+
+                vk::WriteDescriptorSet {
+                    sType: vk::STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
+                    pNext: ptr::null(),
+                    dstSet: write.set,
+                    dstBinding: write.binding,
+                    dstArrayElement: 0,
+                    descriptorCount: 1,
+                    descriptorType: write.descriptor_type,
+                    pImageInfo: image_info,
+                    pBufferInfo: buffer_info,
+                    pTexelBufferView: ptr::null(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_write_per_descriptor() {
+        let (set, buffer, view, sampler): (
+            vk::DescriptorSet,
+            vk::Buffer,
+            vk::ImageView,
+            vk::Sampler,
+        ) = (1, 2, 3, 4);
+
+        let mut writer = DescriptorWriter::default();
+        assert!(writer.is_empty());
+        writer
+            .write_buffer(set, 0, buffer, 64)
+            .write_image(set, 1, view, sampler)
+            .write_buffer_of_type(set, 2, vk::DESCRIPTOR_TYPE_STORAGE_BUFFER, buffer, 16);
+        assert_eq!(writer.len(), 3);
+
+        let writes = writer.write_descriptor_sets();
+        let layout: Vec<_> = writes
+            .iter()
+            .map(|write| (write.dstSet, write.dstBinding, write.descriptorType))
+            .collect();
+        assert_eq!(
+            layout,
+            [
+                (set, 0, vk::DESCRIPTOR_TYPE_UNIFORM_BUFFER),
+                (set, 1, vk::DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER),
+                (set, 2, vk::DESCRIPTOR_TYPE_STORAGE_BUFFER),
+            ]
+        );
+
+        assert!(writes[0].pImageInfo.is_null());
+        assert_eq!(unsafe { (*writes[0].pBufferInfo).range }, 64);
+        assert!(writes[1].pBufferInfo.is_null());
+        let image_info = unsafe { &*writes[1].pImageInfo };
+        assert_eq!((image_info.imageView, image_info.sampler), (view, sampler));
+        assert_eq!(
+            image_info.imageLayout,
+            vk::IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use std::fmt;
+use std::{fmt, time::Duration};
 use vk_sys as vk;
 
 pub fn maybe_vulkan_error(error_code: u32) -> Result<()> {
@@ -17,11 +17,172 @@ pub fn to_vulkan(error_result: vk::Result) -> Error {
     Error::VulkanError(error_result)
 }
 
+/// For `map_err`, wraps an error with the init stage it occurred in.
+pub fn in_stage(stage: Stage) -> impl FnOnce(Error) -> Error {
+    move |err| Error::Init {
+        stage,
+        source: Box::new(err),
+    }
+}
+
+/// `vulkanic` reports every code except `SUCCESS` as `Err`, though some of them are no errors
+/// but just a different kind of success.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Status {
+    Success,
+    Timeout,
+    NotReady,
+    Suboptimal,
+}
+
+pub fn classify(result: std::result::Result<(), vk::Result>) -> Result<Status> {
+    match result {
+        Ok(()) => Ok(Status::Success),
+        Err(vk::TIMEOUT) => Ok(Status::Timeout),
+        Err(vk::NOT_READY) => Ok(Status::NotReady),
+        Err(vk::SUBOPTIMAL_KHR) => Ok(Status::Suboptimal),
+        Err(error_code) => Err(to_vulkan(error_code)),
+    }
+}
+
 pub enum Error {
     VulkanError(u32),
+    DeviceHang(HangReport),
+    /// none of the physical devices can be used, with the reasons per device
+    NoSuitableDevice(DeviceReport),
+    /// `Vulkan::new` or `Vulkan::new_compute_only` failed
+    Init {
+        stage: Stage,
+        source: Box<Error>,
+    },
     Other(String),
 }
 
+/// Step of the Vulkan init. The swapchain is not part of it, it is created with the first frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Instance,
+    DebugMessenger,
+    Surface,
+    PhysicalDevice,
+    QueueFamilies,
+    Device,
+    CommandPool,
+    FramesInFlight,
+}
+
+/// Created when the device got lost after fences already timed out, which usually means the
+/// driver reset a hanging GPU (TDR). `VK_EXT_device_fault` is not queried, so there is no fault
+/// address or vendor info, only the last submit.
+#[derive(Debug)]
+pub struct HangReport {
+    /// frames presented before the hang
+    pub frame: u64,
+    pub frame_in_flight: usize,
+    /// fence timeouts in a row before the device got lost
+    pub fence_timeouts: u32,
+    pub since_last_frame: Duration,
+    /// frame count and swapchain image index of the last queue submit
+    pub last_submit: Option<(u64, u32)>,
+}
+
+impl fmt::Display for HangReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "device lost after {} fence timeout(s) at frame {} (frame in flight {}), last frame presented {:?} ago",
+            self.fence_timeouts, self.frame, self.frame_in_flight, self.since_last_frame
+        )?;
+
+        match self.last_submit {
+            Some((frame, image_index)) => write!(
+                f,
+                ", last submitted frame {} to swapchain image {}",
+                frame, image_index
+            ),
+            None => write!(f, ", nothing submitted"),
+        }
+    }
+}
+
+/// Why the enumerated physical devices were rejected, for bug reports of failed inits.
+#[derive(Debug, Default)]
+pub struct DeviceReport {
+    pub devices: Vec<DeviceRejection>,
+}
+
+#[derive(Debug)]
+pub struct DeviceRejection {
+    pub name: String,
+    pub reasons: Vec<RejectionReason>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RejectionReason {
+    /// an unknown device type, or an integrated GPU without `allow_integrated`
+    DeviceType(vk::PhysicalDeviceType),
+    MissingExtension(String),
+    /// no queue family can present to the window surface
+    NoPresentSupport,
+}
+
+impl fmt::Display for DeviceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.devices.is_empty() {
+            return write!(
+                f,
+                "no Vulkan capable device found, is a GPU driver with Vulkan support installed?"
+            );
+        }
+
+        write!(f, "none of {} device(s) is suitable", self.devices.len())?;
+        for device in &self.devices {
+            write!(f, "\n  {}:", device.name)?;
+            for reason in &device.reasons {
+                write!(f, "\n    - {}", reason)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectionReason::DeviceType(device_type) => {
+                write!(
+                    f,
+                    "device type {} not allowed",
+                    device_type_name(*device_type)
+                )
+            }
+            RejectionReason::MissingExtension(extension) => {
+                write!(f, "missing extension {}", extension)
+            }
+            RejectionReason::NoPresentSupport => write!(f, "cannot present to the window surface"),
+        }
+    }
+}
+
+pub fn device_type_name(device_type: vk::PhysicalDeviceType) -> &'static str {
+    match device_type {
+        vk::PHYSICAL_DEVICE_TYPE_OTHER => "other",
+        vk::PHYSICAL_DEVICE_TYPE_INTEGRATED_GPU => "integrated GPU",
+        vk::PHYSICAL_DEVICE_TYPE_DISCRETE_GPU => "discrete GPU",
+        vk::PHYSICAL_DEVICE_TYPE_VIRTUAL_GPU => "virtual GPU",
+        vk::PHYSICAL_DEVICE_TYPE_CPU => "CPU",
+        _ => "unknown device type",
+    }
+}
+
+impl Error {
+    /// timeouts are recoverable, the caller may just try again with the next frame
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::VulkanError(vk::TIMEOUT))
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt::Debug::fmt(self, f)
@@ -65,6 +226,15 @@ impl fmt::Debug for Error {
 
                 write!(f, "Vulkan error: {}", name)
             }
+            Error::DeviceHang(report) => {
+                write!(f, "GPU hang: {}", report)
+            }
+            Error::NoSuitableDevice(report) => {
+                write!(f, "No suitable device: {}", report)
+            }
+            Error::Init { stage, source } => {
+                write!(f, "Init failed at {:?}: {}", stage, source)
+            }
             Error::Other(text) => {
                 write!(f, "Other error: {}", text)
             }
@@ -72,6 +242,92 @@ impl fmt::Debug for Error {
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Init { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_success_codes() {
+        assert_eq!(classify(Ok(())).unwrap(), Status::Success);
+        assert_eq!(classify(Err(vk::TIMEOUT)).unwrap(), Status::Timeout);
+        assert_eq!(classify(Err(vk::NOT_READY)).unwrap(), Status::NotReady);
+        assert_eq!(
+            classify(Err(vk::SUBOPTIMAL_KHR)).unwrap(),
+            Status::Suboptimal
+        );
+    }
+
+    #[test]
+    fn classify_errors() {
+        assert!(matches!(
+            classify(Err(vk::ERROR_DEVICE_LOST)),
+            Err(Error::VulkanError(vk::ERROR_DEVICE_LOST))
+        ));
+    }
+
+    #[test]
+    fn empty_device_report() {
+        assert_eq!(
+            DeviceReport::default().to_string(),
+            "no Vulkan capable device found, is a GPU driver with Vulkan support installed?"
+        );
+    }
+
+    #[test]
+    fn device_report_lists_every_reason() {
+        let report = DeviceReport {
+            devices: vec![
+                DeviceRejection {
+                    name: "llvmpipe".to_owned(),
+                    reasons: vec![
+                        RejectionReason::DeviceType(vk::PHYSICAL_DEVICE_TYPE_CPU),
+                        RejectionReason::MissingExtension("VK_KHR_swapchain".to_owned()),
+                    ],
+                },
+                DeviceRejection {
+                    name: "GPU".to_owned(),
+                    reasons: vec![RejectionReason::NoPresentSupport],
+                },
+            ],
+        };
+
+        assert_eq!(
+            report.to_string(),
+            "none of 2 device(s) is suitable\n  \
+             llvmpipe:\n    \
+             - device type CPU not allowed\n    \
+             - missing extension VK_KHR_swapchain\n  \
+             GPU:\n    \
+             - cannot present to the window surface"
+        );
+    }
+
+    #[test]
+    fn hang_report_names_the_last_submit() {
+        let report = HangReport {
+            frame: 12,
+            frame_in_flight: 1,
+            fence_timeouts: 2,
+            since_last_frame: Duration::from_secs(3),
+            last_submit: Some((11, 2)),
+        };
+        assert!(format!("{}", report).ends_with(", last submitted frame 11 to swapchain image 2"));
+
+        let report = HangReport {
+            last_submit: None,
+            ..report
+        };
+        assert!(format!("{}", report).ends_with(", nothing submitted"));
+    }
+}
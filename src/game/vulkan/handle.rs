@@ -0,0 +1,220 @@
+//! Scoped ownership of Vulkan handles while an object is built out of several of them. A handle
+//! is destroyed when its guard is dropped, e.g. by `?` on a later error, unless it was released
+//! to its final owner.
+
+use std::ops::Deref;
+
+use super::{
+    depth::DepthBuffer,
+    post_process::{PostProcessImage, PostProcessPass},
+    render_target::RenderTarget,
+    scene_uniforms::{SceneUniformImage, SceneUniformSets},
+    tonemap::{TonemapImage, TonemapPass},
+    uniform::DynamicUniformBuffer,
+    Context, Pipeline,
+};
+use vk_sys as vk;
+
+pub struct Scoped<'a, T> {
+    ctx: &'a Context,
+    handle: Option<T>,
+    destroy: fn(&Context, T),
+}
+
+impl<'a, T> Scoped<'a, T> {
+    pub fn new(ctx: &'a Context, handle: T, destroy: fn(&Context, T)) -> Self {
+        Self {
+            ctx,
+            handle: Some(handle),
+            destroy,
+        }
+    }
+
+    /// Hands the handle over to the caller, who is responsible to destroy it from now on.
+    pub fn release(mut self) -> T {
+        self.handle.take().unwrap()
+    }
+}
+
+impl<'a, T> Deref for Scoped<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.handle.as_ref().unwrap()
+    }
+}
+
+impl<'a, T> Drop for Scoped<'a, T> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            (self.destroy)(self.ctx, handle);
+        }
+    }
+}
+
+pub fn buffer(ctx: &Context, buffer: vk::Buffer) -> Scoped<vk::Buffer> {
+    Scoped::new(ctx, buffer, |ctx, buffer| {
+        ctx.dp.destroy_buffer(ctx.device, buffer)
+    })
+}
+
+pub fn memory(ctx: &Context, memory: vk::DeviceMemory) -> Scoped<vk::DeviceMemory> {
+    Scoped::new(ctx, memory, |ctx, memory| {
+        ctx.dp.free_memory(ctx.device, memory)
+    })
+}
+
+pub fn image(ctx: &Context, image: vk::Image) -> Scoped<vk::Image> {
+    Scoped::new(ctx, image, |ctx, image| {
+        ctx.dp.destroy_image(ctx.device, image)
+    })
+}
+
+pub fn image_view(ctx: &Context, image_view: vk::ImageView) -> Scoped<vk::ImageView> {
+    Scoped::new(ctx, image_view, |ctx, image_view| {
+        ctx.dp.destroy_image_view(ctx.device, image_view)
+    })
+}
+
+pub fn framebuffer(ctx: &Context, framebuffer: vk::Framebuffer) -> Scoped<vk::Framebuffer> {
+    Scoped::new(ctx, framebuffer, |ctx, framebuffer| {
+        ctx.dp.destroy_framebuffer(ctx.device, framebuffer)
+    })
+}
+
+pub fn command_buffer(
+    ctx: &Context,
+    command_buffer: vk::CommandBuffer,
+) -> Scoped<vk::CommandBuffer> {
+    Scoped::new(ctx, command_buffer, |ctx, command_buffer| {
+        ctx.dp
+            .free_command_buffers(ctx.device, ctx.command_pool, &[command_buffer])
+    })
+}
+
+pub fn render_pass(ctx: &Context, render_pass: vk::RenderPass) -> Scoped<vk::RenderPass> {
+    Scoped::new(ctx, render_pass, |ctx, render_pass| {
+        ctx.dp.destroy_render_pass(ctx.device, render_pass)
+    })
+}
+
+pub fn swapchain(ctx: &Context, swapchain: vk::SwapchainKHR) -> Scoped<vk::SwapchainKHR> {
+    Scoped::new(ctx, swapchain, |ctx, swapchain| {
+        ctx.dp.destroy_swapchain_khr(ctx.device, swapchain)
+    })
+}
+
+pub fn shader_module(ctx: &Context, shader_module: vk::ShaderModule) -> Scoped<vk::ShaderModule> {
+    Scoped::new(ctx, shader_module, |ctx, shader_module| {
+        ctx.dp.destroy_shader_module(ctx.device, shader_module)
+    })
+}
+
+pub fn pipeline_layout(
+    ctx: &Context,
+    pipeline_layout: vk::PipelineLayout,
+) -> Scoped<vk::PipelineLayout> {
+    Scoped::new(ctx, pipeline_layout, |ctx, pipeline_layout| {
+        ctx.dp.destroy_pipeline_layout(ctx.device, pipeline_layout)
+    })
+}
+
+pub fn pipeline(ctx: &Context, pipeline: Pipeline) -> Scoped<Pipeline> {
+    Scoped::new(ctx, pipeline, |ctx, pipeline| pipeline.destroy(ctx))
+}
+
+pub fn render_target(ctx: &Context, render_target: RenderTarget) -> Scoped<RenderTarget> {
+    Scoped::new(ctx, render_target, |ctx, render_target| {
+        render_target.destroy(ctx)
+    })
+}
+
+pub fn depth_buffer(ctx: &Context, depth_buffer: DepthBuffer) -> Scoped<DepthBuffer> {
+    Scoped::new(ctx, depth_buffer, |ctx, depth_buffer| {
+        depth_buffer.destroy(ctx)
+    })
+}
+
+pub fn scene_uniform_sets(
+    ctx: &Context,
+    scene_uniform_sets: SceneUniformSets,
+) -> Scoped<SceneUniformSets> {
+    Scoped::new(ctx, scene_uniform_sets, |ctx, scene_uniform_sets| {
+        scene_uniform_sets.destroy(ctx)
+    })
+}
+
+pub fn dynamic_uniform_buffer(
+    ctx: &Context,
+    dynamic_uniform_buffer: DynamicUniformBuffer,
+) -> Scoped<DynamicUniformBuffer> {
+    Scoped::new(
+        ctx,
+        dynamic_uniform_buffer,
+        |ctx, dynamic_uniform_buffer| dynamic_uniform_buffer.destroy(ctx),
+    )
+}
+
+pub fn scene_uniform_image(
+    ctx: &Context,
+    scene_uniform_image: SceneUniformImage,
+) -> Scoped<SceneUniformImage> {
+    Scoped::new(ctx, scene_uniform_image, |ctx, scene_uniform_image| {
+        scene_uniform_image.destroy(ctx)
+    })
+}
+
+pub fn sampler(ctx: &Context, sampler: vk::Sampler) -> Scoped<vk::Sampler> {
+    Scoped::new(ctx, sampler, |ctx, sampler| {
+        ctx.dp.destroy_sampler(ctx.device, sampler)
+    })
+}
+
+pub fn descriptor_set_layout(
+    ctx: &Context,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+) -> Scoped<vk::DescriptorSetLayout> {
+    Scoped::new(ctx, descriptor_set_layout, |ctx, descriptor_set_layout| {
+        ctx.dp
+            .destroy_descriptor_set_layout(ctx.device, descriptor_set_layout)
+    })
+}
+
+pub fn descriptor_pool(
+    ctx: &Context,
+    descriptor_pool: vk::DescriptorPool,
+) -> Scoped<vk::DescriptorPool> {
+    Scoped::new(ctx, descriptor_pool, |ctx, descriptor_pool| {
+        ctx.dp.destroy_descriptor_pool(ctx.device, descriptor_pool)
+    })
+}
+
+pub fn tonemap_pass(ctx: &Context, tonemap_pass: TonemapPass) -> Scoped<TonemapPass> {
+    Scoped::new(ctx, tonemap_pass, |ctx, tonemap_pass| {
+        tonemap_pass.destroy(ctx)
+    })
+}
+
+pub fn tonemap_image(ctx: &Context, tonemap_image: TonemapImage) -> Scoped<TonemapImage> {
+    Scoped::new(ctx, tonemap_image, |ctx, tonemap_image| {
+        tonemap_image.destroy(ctx)
+    })
+}
+
+pub fn post_process_pass(
+    ctx: &Context,
+    post_process_pass: PostProcessPass,
+) -> Scoped<PostProcessPass> {
+    Scoped::new(ctx, post_process_pass, |ctx, post_process_pass| {
+        post_process_pass.destroy(ctx)
+    })
+}
+
+pub fn post_process_image(
+    ctx: &Context,
+    post_process_image: PostProcessImage,
+) -> Scoped<PostProcessImage> {
+    Scoped::new(ctx, post_process_image, |ctx, post_process_image| {
+        post_process_image.destroy(ctx)
+    })
+}
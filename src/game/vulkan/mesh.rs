@@ -0,0 +1,380 @@
+//! Indexed geometry, e.g. from `primitives` or a chunk mesher.
+
+use std::{cmp::Ordering, mem::size_of, slice};
+
+use super::{
+    handle,
+    swapchain::{
+        create_host_visible_buffer, upload_device_local_buffer, write_host_visible_memory,
+    },
+    vertex::MeshVertex,
+    Context, Error, Result,
+};
+use glm::{Mat4, Vec3, Vec4};
+use vk_sys as vk;
+
+/// Width of the indices in the index buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexType {
+    U16,
+    U32,
+}
+
+impl IndexType {
+    /// `U16` whenever every vertex can be addressed with it, halving the index buffer.
+    pub fn for_vertex_count(vertex_count: usize) -> Self {
+        if vertex_count <= u16::MAX as usize + 1 {
+            IndexType::U16
+        } else {
+            IndexType::U32
+        }
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            IndexType::U16 => size_of::<u16>(),
+            IndexType::U32 => size_of::<u32>(),
+        }
+    }
+
+    pub fn to_vk(self) -> vk::IndexType {
+        match self {
+            IndexType::U16 => vk::INDEX_TYPE_UINT16,
+            IndexType::U32 => vk::INDEX_TYPE_UINT32,
+        }
+    }
+}
+
+pub struct Mesh {
+    vertex_buffer: vk::Buffer,
+    vertex_memory: vk::DeviceMemory,
+    index_buffer: vk::Buffer,
+    index_memory: vk::DeviceMemory,
+    index_type: IndexType,
+    index_count: u32,
+    translucent: bool,
+    /// center of the bounding box in model space, translucent meshes are sorted by it
+    center: Vec3,
+}
+
+impl Mesh {
+    /// Uploads `vertices` and `indices` into host visible buffers, the indices are stored as
+    /// `u16` if the vertex count allows it. Cheap to create, for geometry that changes often.
+    pub fn new<T>(ctx: &Context, vertices: &[T], indices: &[u32]) -> Result<Self> {
+        Self::upload(ctx, vertices, indices, false)
+    }
+
+    /// Like `new`, but copies the geometry through staging buffers into device local ones,
+    /// which are faster to draw from. Waits for the copies.
+    pub fn new_device_local<T>(ctx: &Context, vertices: &[T], indices: &[u32]) -> Result<Self> {
+        Self::upload(ctx, vertices, indices, true)
+    }
+
+    fn upload<T>(
+        ctx: &Context,
+        vertices: &[T],
+        indices: &[u32],
+        device_local: bool,
+    ) -> Result<Self> {
+        check_indices(vertices.len(), indices)?;
+
+        let (vertex_buffer, vertex_memory) = create_buffer(
+            ctx,
+            vertices,
+            vk::BUFFER_USAGE_VERTEX_BUFFER_BIT,
+            device_local,
+        )?;
+
+        let index_type = IndexType::for_vertex_count(vertices.len());
+        let (index_buffer, index_memory) = match index_type {
+            IndexType::U16 => {
+                let indices: Vec<u16> = indices.iter().map(|index| *index as u16).collect();
+                create_buffer(
+                    ctx,
+                    &indices,
+                    vk::BUFFER_USAGE_INDEX_BUFFER_BIT,
+                    device_local,
+                )?
+            }
+            IndexType::U32 => create_buffer(
+                ctx,
+                indices,
+                vk::BUFFER_USAGE_INDEX_BUFFER_BIT,
+                device_local,
+            )?,
+        };
+
+        Ok(Self {
+            vertex_buffer: vertex_buffer.release(),
+            vertex_memory: vertex_memory.release(),
+            index_buffer: index_buffer.release(),
+            index_memory: index_memory.release(),
+            index_type,
+            index_count: indices.len() as u32,
+            translucent: false,
+            center: Vec3::new(0.0, 0.0, 0.0),
+        })
+    }
+
+    /// For tests of the bookkeeping around meshes, there is nothing to draw or destroy.
+    #[cfg(test)]
+    pub(super) fn without_buffers() -> Self {
+        Self {
+            vertex_buffer: vk::NULL_HANDLE,
+            vertex_memory: vk::NULL_HANDLE,
+            index_buffer: vk::NULL_HANDLE,
+            index_memory: vk::NULL_HANDLE,
+            index_type: IndexType::U16,
+            index_count: 0,
+            translucent: false,
+            center: Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn destroy(&self, ctx: &Context) {
+        ctx.dp.destroy_buffer(ctx.device, self.index_buffer);
+        ctx.dp.free_memory(ctx.device, self.index_memory);
+        ctx.dp.destroy_buffer(ctx.device, self.vertex_buffer);
+        ctx.dp.free_memory(ctx.device, self.vertex_memory);
+    }
+
+    /// Water, glass and the like, drawn after the opaque meshes with alpha blending.
+    pub fn set_translucent(&mut self, translucent: bool) {
+        self.translucent = translucent;
+    }
+
+    pub fn translucent(&self) -> bool {
+        self.translucent
+    }
+
+    /// See `aabb_center`, the origin by default.
+    pub fn set_center(&mut self, center: Vec3) {
+        self.center = center;
+    }
+
+    pub fn center(&self) -> Vec3 {
+        self.center
+    }
+
+    pub fn index_type(&self) -> IndexType {
+        self.index_type
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+
+    /// bytes of index data
+    pub fn index_buffer_size(&self) -> vk::DeviceSize {
+        (self.index_type.size() * self.index_count as usize) as vk::DeviceSize
+    }
+
+    /// Binds the vertex buffer to binding 0 and the index buffer, and draws all indices.
+    pub fn cmd_draw(&self, ctx: &Context, command_buffer: vk::CommandBuffer) {
+        ctx.cmd_bind_vertex_buffers(command_buffer, &[(self.vertex_buffer, 0)]);
+        ctx.dp.cmd_bind_index_buffer(
+            command_buffer,
+            self.index_buffer,
+            0,
+            self.index_type.to_vk(),
+        );
+        ctx.dp
+            .cmd_draw_indexed(command_buffer, self.index_count, 1, 0, 0, 0);
+    }
+}
+
+/// A mesh at `model`, as recorded into the command buffers. Keeps the buffers of the mesh, the
+/// mesh has to outlive the command buffers.
+#[derive(Clone, Copy, PartialEq)]
+pub struct MeshDraw {
+    vertex_buffer: vk::Buffer,
+    index_buffer: vk::Buffer,
+    index_type: IndexType,
+    index_count: u32,
+    pub model: Mat4,
+    /// drawn with the blending pipeline, after the opaque draws
+    pub translucent: bool,
+}
+
+impl Mesh {
+    pub fn draw(&self, model: Mat4) -> MeshDraw {
+        MeshDraw {
+            vertex_buffer: self.vertex_buffer,
+            index_buffer: self.index_buffer,
+            index_type: self.index_type,
+            index_count: self.index_count,
+            model,
+            translucent: self.translucent,
+        }
+    }
+
+    /// `center` transformed by `model`, what translucent draws are sorted by.
+    pub fn world_center(&self, model: &Mat4) -> Vec3 {
+        let center = *model * Vec4::new(self.center.x, self.center.y, self.center.z, 1.0);
+        Vec3::new(center.x, center.y, center.z)
+    }
+}
+
+impl MeshDraw {
+    /// Pushes the model matrix into the vertex stage through `layout` and draws all indices.
+    pub fn cmd_draw(
+        &self,
+        ctx: &Context,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+    ) -> Result<()> {
+        let model = unsafe {
+            slice::from_raw_parts(&self.model as *const Mat4 as *const u8, size_of::<Mat4>())
+        };
+        ctx.cmd_push_constants(command_buffer, layout, vk::SHADER_STAGE_VERTEX_BIT, model)?;
+
+        ctx.cmd_bind_vertex_buffers(command_buffer, &[(self.vertex_buffer, 0)]);
+        ctx.dp.cmd_bind_index_buffer(
+            command_buffer,
+            self.index_buffer,
+            0,
+            self.index_type.to_vk(),
+        );
+        ctx.dp
+            .cmd_draw_indexed(command_buffer, self.index_count, 1, 0, 0, 0);
+
+        Ok(())
+    }
+}
+
+/// A mesh needs geometry, and every index has to address one of the `vertex_count` vertices.
+fn check_indices(vertex_count: usize, indices: &[u32]) -> Result<()> {
+    if vertex_count == 0 || indices.is_empty() {
+        return Err(Error::Other("mesh without vertices or indices".to_owned()));
+    }
+
+    if let Some(index) = indices
+        .iter()
+        .find(|index| **index as usize >= vertex_count)
+    {
+        return Err(Error::Other(format!(
+            "index {} out of {} vertices",
+            index, vertex_count
+        )));
+    }
+
+    Ok(())
+}
+
+/// Buffer with `data`, device local ones are filled through a staging buffer.
+fn create_buffer<'a, T>(
+    ctx: &'a Context,
+    data: &[T],
+    usage: vk::BufferUsageFlags,
+    device_local: bool,
+) -> Result<(
+    handle::Scoped<'a, vk::Buffer>,
+    handle::Scoped<'a, vk::DeviceMemory>,
+)> {
+    if device_local {
+        return upload_device_local_buffer(ctx, data, usage);
+    }
+
+    let size = (size_of::<T>() * data.len()) as vk::DeviceSize;
+    let (buffer, memory, coherent) = create_host_visible_buffer(ctx, size, usage)?;
+    write_host_visible_memory(ctx, *memory, coherent, data)?;
+
+    Ok((buffer, memory))
+}
+
+/// Center of the bounding box of `vertices`, the origin without any.
+pub fn aabb_center(vertices: &[MeshVertex]) -> Vec3 {
+    let mut positions = vertices.iter().map(|vertex| vertex.pos);
+    let first = match positions.next() {
+        Some(first) => first,
+        None => return Vec3::new(0.0, 0.0, 0.0),
+    };
+
+    let (min, max) = positions.fold((first, first), |(min, max), pos| {
+        (
+            Vec3::new(min.x.min(pos.x), min.y.min(pos.y), min.z.min(pos.z)),
+            Vec3::new(max.x.max(pos.x), max.y.max(pos.y), max.z.max(pos.z)),
+        )
+    });
+
+    (min + max) * 0.5
+}
+
+/// Order to draw `meshes` in, given with their AABB centers: the opaque ones as they are, then
+/// the translucent ones back to front from `camera`, so blending sees what is behind them.
+pub fn draw_order(camera: &Vec3, meshes: &[(&Mesh, Vec3)]) -> Vec<usize> {
+    let (mut order, mut translucent): (Vec<usize>, Vec<usize>) =
+        (0..meshes.len()).partition(|index| !meshes[*index].0.translucent());
+
+    let distance_squared = |index: &usize| {
+        let offset = meshes[*index].1 - *camera;
+        glm::dot(offset, offset)
+    };
+    translucent.sort_by(|a, b| {
+        distance_squared(b)
+            .partial_cmp(&distance_squared(a))
+            .unwrap_or(Ordering::Equal)
+    });
+
+    order.extend(translucent);
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_type_by_vertex_count() {
+        assert_eq!(IndexType::for_vertex_count(3), IndexType::U16);
+        assert_eq!(IndexType::for_vertex_count(65536), IndexType::U16);
+        assert_eq!(IndexType::for_vertex_count(65537), IndexType::U32);
+
+        assert_eq!(IndexType::U16.size(), 2);
+        assert_eq!(IndexType::U32.size(), 4);
+        assert_eq!(IndexType::U16.to_vk(), vk::INDEX_TYPE_UINT16);
+        assert_eq!(IndexType::U32.to_vk(), vk::INDEX_TYPE_UINT32);
+    }
+
+    #[test]
+    fn indices_must_address_vertices() {
+        assert!(check_indices(3, &[0, 1, 2]).is_ok());
+        assert!(check_indices(3, &[0, 1, 3]).is_err());
+        assert!(check_indices(0, &[0]).is_err());
+        assert!(check_indices(3, &[]).is_err());
+    }
+
+    #[test]
+    fn translucent_meshes_last_back_to_front() {
+        let (opaque, mut translucent) = (Mesh::without_buffers(), Mesh::without_buffers());
+        translucent.set_translucent(true);
+        let camera = Vec3::new(0.0, 0.0, 0.0);
+
+        let meshes = [
+            (&translucent, Vec3::new(0.0, 0.0, -2.0)),
+            (&opaque, Vec3::new(0.0, 0.0, -9.0)),
+            (&translucent, Vec3::new(5.0, 0.0, 0.0)),
+            (&opaque, Vec3::new(0.0, 0.0, -1.0)),
+            (&translucent, Vec3::new(0.0, -1.0, 0.0)),
+        ];
+
+        assert_eq!(draw_order(&camera, &meshes), [1, 3, 2, 0, 4]);
+        assert!(draw_order(&camera, &[]).is_empty());
+    }
+
+    #[test]
+    fn center_of_the_bounding_box() {
+        let vertices: Vec<MeshVertex> = [(-1.0, 0.0, 2.0), (3.0, -2.0, 4.0), (1.0, 6.0, 3.0)]
+            .iter()
+            .map(|&(x, y, z)| MeshVertex {
+                pos: Vec3::new(x, y, z),
+                normal: Vec3::new(0.0, 1.0, 0.0),
+                uv: glm::Vec2::new(0.0, 0.0),
+                tex_id: 0,
+            })
+            .collect();
+
+        assert_eq!(aabb_center(&vertices), Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(aabb_center(&[]), Vec3::new(0.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,170 @@
+//! Meshes uploaded once and referenced by `MeshId` across frames.
+
+use super::{mesh::Mesh, Context, MAX_FRAMES_IN_FLIGHT};
+
+/// Handle of a mesh in `MeshSlab`. Slots are reused, the generation tells a freed mesh from a
+/// later one in the same slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MeshId {
+    index: u32,
+    generation: u32,
+}
+
+struct Slot {
+    generation: u32,
+    mesh: Option<Mesh>,
+}
+
+#[derive(Default)]
+pub struct MeshSlab {
+    slots: Vec<Slot>,
+    /// indices of empty slots
+    free: Vec<u32>,
+    /// freed meshes with the frame count at the time, frames in flight may still draw them
+    pending: Vec<(u64, Mesh)>,
+}
+
+impl MeshSlab {
+    pub fn insert(&mut self, mesh: Mesh) -> MeshId {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.mesh = Some(mesh);
+
+                MeshId {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    mesh: Some(mesh),
+                });
+
+                MeshId {
+                    index: self.slots.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, id: MeshId) -> Option<&Mesh> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.mesh.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: MeshId) -> Option<&mut Mesh> {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.mesh.as_mut())
+    }
+
+    /// Takes the mesh out of the slab, it is destroyed by `destroy_unused` once the frames in
+    /// flight at `frame_count` are done. `false` if `id` was freed already.
+    pub fn remove(&mut self, id: MeshId, frame_count: u64) -> bool {
+        let slot = match self.slots.get_mut(id.index as usize) {
+            Some(slot) if slot.generation == id.generation => slot,
+            _ => return false,
+        };
+
+        match slot.mesh.take() {
+            Some(mesh) => {
+                slot.generation = slot.generation.wrapping_add(1);
+                self.free.push(id.index);
+                self.pending.push((frame_count, mesh));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Destroys freed meshes no frame in flight can draw anymore. `frame_count` counts the
+    /// frames submitted so far, with the fence of the one `MAX_FRAMES_IN_FLIGHT` back waited
+    /// for.
+    pub fn destroy_unused(&mut self, ctx: &Context, frame_count: u64) {
+        for mesh in self.take_unused(frame_count) {
+            mesh.destroy(ctx);
+        }
+    }
+
+    /// The freed meshes `destroy_unused` destroys.
+    fn take_unused(&mut self, frame_count: u64) -> Vec<Mesh> {
+        let mut unused = Vec::new();
+
+        let mut index = 0;
+        while index < self.pending.len() {
+            let (freed_at, _) = &self.pending[index];
+            if frame_count >= freed_at + MAX_FRAMES_IN_FLIGHT as u64 {
+                let (_, mesh) = self.pending.swap_remove(index);
+                unused.push(mesh);
+            } else {
+                index += 1;
+            }
+        }
+
+        unused
+    }
+
+    /// Destroys all meshes, the device has to be idle.
+    pub fn destroy(&mut self, ctx: &Context) {
+        for (_, mesh) in self.pending.drain(..) {
+            mesh.destroy(ctx);
+        }
+
+        for slot in &mut self.slots {
+            if let Some(mesh) = slot.mesh.take() {
+                mesh.destroy(ctx);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_ids_are_stale() {
+        let mut slab = MeshSlab::default();
+        let first = slab.insert(Mesh::without_buffers());
+        let second = slab.insert(Mesh::without_buffers());
+        assert_ne!(first, second);
+        assert!(slab.get(first).is_some());
+
+        assert!(slab.remove(first, 0));
+        assert!(slab.get(first).is_none());
+        assert!(!slab.remove(first, 0));
+        assert!(slab.get(second).is_some());
+    }
+
+    #[test]
+    fn slots_are_reused_with_a_new_generation() {
+        let mut slab = MeshSlab::default();
+        let first = slab.insert(Mesh::without_buffers());
+        slab.remove(first, 0);
+
+        let reused = slab.insert(Mesh::without_buffers());
+        assert_eq!(reused.index, first.index);
+        assert_ne!(reused, first);
+        assert!(slab.get(first).is_none());
+        assert!(slab.get(reused).is_some());
+        assert!(!slab.remove(first, 0));
+    }
+
+    #[test]
+    fn freed_meshes_outlive_the_frames_in_flight() {
+        let mut slab = MeshSlab::default();
+        let id = slab.insert(Mesh::without_buffers());
+        slab.remove(id, 10);
+
+        let in_flight = 10 + MAX_FRAMES_IN_FLIGHT as u64 - 1;
+        assert!(slab.take_unused(in_flight).is_empty());
+        assert_eq!(slab.take_unused(in_flight + 1).len(), 1);
+        assert!(slab.take_unused(in_flight + 2).is_empty());
+    }
+}
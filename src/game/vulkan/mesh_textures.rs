@@ -0,0 +1,376 @@
+//! Block textures of the mesh pipeline, one per layer of a 2D array image, selected by the
+//! `tex_id` of `MeshVertex`.
+
+use std::ptr;
+
+use super::{
+    error::to_vulkan,
+    handle,
+    sampler::{create_sampler, SamplerOptions},
+    swapchain::{create_host_visible_buffer, find_memory_type, write_host_visible_memory},
+    AddressMode, Context, DescriptorWriter, Error, Result,
+};
+use vk_sys as vk;
+
+/// format of the texels passed to `MeshTextures::new`
+pub const MESH_TEXTURE_FORMAT: vk::Format = vk::FORMAT_R8G8B8A8_SRGB;
+
+const BYTES_PER_TEXEL: usize = 4;
+
+pub struct MeshTextures {
+    /// `sampler2DArray` at binding 0, set 1 of the mesh pipeline
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    image_view: vk::ImageView,
+    sampler: vk::Sampler,
+}
+
+impl MeshTextures {
+    /// `texels` are `layers` tightly packed RGBA images of `width` x `height`, one after the
+    /// other. Waits for the upload.
+    pub fn new(ctx: &Context, width: u32, height: u32, layers: u32, texels: &[u8]) -> Result<Self> {
+        check_texels(
+            width,
+            height,
+            layers,
+            texels.len(),
+            ctx.limits.maxImageDimension2D,
+            ctx.limits.maxImageArrayLayers,
+        )?;
+
+        // destroyed at the end of the scope, the copy has been waited for by then
+        let (staging_buffer, staging_memory, coherent) = create_host_visible_buffer(
+            ctx,
+            texels.len() as vk::DeviceSize,
+            vk::BUFFER_USAGE_TRANSFER_SRC_BIT,
+        )?;
+        write_host_visible_memory(ctx, *staging_memory, coherent, texels)?;
+
+        let image_info = vk::ImageCreateInfo {
+            sType: vk::STRUCTURE_TYPE_IMAGE_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            imageType: vk::IMAGE_TYPE_2D,
+            format: MESH_TEXTURE_FORMAT,
+            extent: vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            },
+            mipLevels: 1,
+            arrayLayers: layers,
+            samples: vk::SAMPLE_COUNT_1_BIT,
+            tiling: vk::IMAGE_TILING_OPTIMAL,
+            usage: vk::IMAGE_USAGE_TRANSFER_DST_BIT | vk::IMAGE_USAGE_SAMPLED_BIT,
+            sharingMode: vk::SHARING_MODE_EXCLUSIVE,
+            queueFamilyIndexCount: 0,
+            pQueueFamilyIndices: ptr::null(),
+            initialLayout: vk::IMAGE_LAYOUT_UNDEFINED,
+        };
+
+        let image = handle::image(
+            ctx,
+            unsafe { ctx.dp.create_image(ctx.device, &image_info) }.map_err(to_vulkan)?,
+        );
+
+        let memory_requirements = ctx.dp.get_image_memory_requirements(ctx.device, *image);
+        let allocate_info = vk::MemoryAllocateInfo {
+            sType: vk::STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
+            pNext: ptr::null(),
+            allocationSize: memory_requirements.size,
+            memoryTypeIndex: find_memory_type(
+                ctx,
+                memory_requirements.memoryTypeBits,
+                vk::MEMORY_PROPERTY_DEVICE_LOCAL_BIT,
+            )?,
+        };
+
+        let memory = handle::memory(
+            ctx,
+            unsafe { ctx.dp.allocate_memory(ctx.device, &allocate_info) }.map_err(to_vulkan)?,
+        );
+
+        ctx.dp
+            .bind_image_memory(ctx.device, *image, *memory, 0)
+            .map_err(to_vulkan)?;
+
+        ctx.submit_one_time(|command_buffer| {
+            cmd_transition_layers(
+                ctx,
+                command_buffer,
+                *image,
+                layers,
+                (
+                    vk::IMAGE_LAYOUT_UNDEFINED,
+                    vk::IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                ),
+                (0, vk::ACCESS_TRANSFER_WRITE_BIT),
+                (
+                    vk::PIPELINE_STAGE_TOP_OF_PIPE_BIT,
+                    vk::PIPELINE_STAGE_TRANSFER_BIT,
+                ),
+            );
+
+            // `Context::copy_buffer_to_image` only fills layer 0, the layers follow each other
+            // in the staging buffer
+            let region = vk::BufferImageCopy {
+                bufferOffset: 0,
+                bufferRowLength: 0,
+                bufferImageHeight: 0,
+                imageSubresource: vk::ImageSubresourceLayers {
+                    aspectMask: vk::IMAGE_ASPECT_COLOR_BIT,
+                    mipLevel: 0,
+                    baseArrayLayer: 0,
+                    layerCount: layers,
+                },
+                imageOffset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                imageExtent: vk::Extent3D {
+                    width,
+                    height,
+                    depth: 1,
+                },
+            };
+
+            ctx.dp.cmd_copy_buffer_to_image(
+                command_buffer,
+                *staging_buffer,
+                *image,
+                vk::IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                &[region],
+            );
+
+            cmd_transition_layers(
+                ctx,
+                command_buffer,
+                *image,
+                layers,
+                (
+                    vk::IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                    vk::IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+                ),
+                (vk::ACCESS_TRANSFER_WRITE_BIT, vk::ACCESS_SHADER_READ_BIT),
+                (
+                    vk::PIPELINE_STAGE_TRANSFER_BIT,
+                    vk::PIPELINE_STAGE_FRAGMENT_SHADER_BIT,
+                ),
+            );
+        })?;
+
+        let image_view = handle::image_view(ctx, create_array_view(ctx, *image, layers)?);
+        // block edges clamp, no bleeding from the other side of the texture
+        let sampler = handle::sampler(
+            ctx,
+            create_sampler(
+                ctx,
+                &SamplerOptions::with_address_mode(AddressMode::ClampToEdge),
+            )?,
+        );
+
+        let descriptor_set_layout =
+            handle::descriptor_set_layout(ctx, create_descriptor_set_layout(ctx)?);
+        let descriptor_pool = handle::descriptor_pool(ctx, create_descriptor_pool(ctx)?);
+
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            sType: vk::STRUCTURE_TYPE_DESCRIPTOR_SET_ALLOCATE_INFO,
+            pNext: ptr::null(),
+            descriptorPool: *descriptor_pool,
+            descriptorSetCount: 1,
+            pSetLayouts: &*descriptor_set_layout,
+        };
+
+        let descriptor_set = unsafe { ctx.dp.allocate_descriptor_sets(ctx.device, &allocate_info) }
+            .map_err(to_vulkan)?[0];
+
+        DescriptorWriter::default()
+            .write_image(descriptor_set, 0, *image_view, *sampler)
+            .flush(ctx);
+
+        Ok(Self {
+            descriptor_set_layout: descriptor_set_layout.release(),
+            descriptor_pool: descriptor_pool.release(),
+            descriptor_set,
+            image: image.release(),
+            memory: memory.release(),
+            image_view: image_view.release(),
+            sampler: sampler.release(),
+        })
+    }
+
+    /// A single white layer, meshes are drawn in their lighting only.
+    pub fn white(ctx: &Context) -> Result<Self> {
+        Self::new(ctx, 1, 1, 1, &[u8::MAX; BYTES_PER_TEXEL])
+    }
+
+    /// The descriptor set is freed with the pool.
+    pub fn destroy(&self, ctx: &Context) {
+        ctx.dp
+            .destroy_descriptor_pool(ctx.device, self.descriptor_pool);
+        ctx.dp
+            .destroy_descriptor_set_layout(ctx.device, self.descriptor_set_layout);
+        ctx.dp.destroy_sampler(ctx.device, self.sampler);
+        ctx.dp.destroy_image_view(ctx.device, self.image_view);
+        ctx.dp.destroy_image(ctx.device, self.image);
+        ctx.dp.free_memory(ctx.device, self.memory);
+    }
+}
+
+/// `texel_bytes` has to hold exactly `layers` RGBA images of `width` x `height`, within the
+/// device limits.
+fn check_texels(
+    width: u32,
+    height: u32,
+    layers: u32,
+    texel_bytes: usize,
+    max_dimension: u32,
+    max_layers: u32,
+) -> Result<()> {
+    if width == 0 || height == 0 || layers == 0 {
+        return Err(Error::Other(format!(
+            "{}x{} textures with {} layers are empty",
+            width, height, layers
+        )));
+    }
+
+    if width > max_dimension || height > max_dimension || layers > max_layers {
+        return Err(Error::Other(format!(
+            "{}x{} textures with {} layers exceed the device limits of {}x{} with {} layers",
+            width, height, layers, max_dimension, max_dimension, max_layers
+        )));
+    }
+
+    let expected = width as usize * height as usize * layers as usize * BYTES_PER_TEXEL;
+    if texel_bytes != expected {
+        return Err(Error::Other(format!(
+            "{} bytes of texels, {}x{} textures with {} layers need {}",
+            texel_bytes, width, height, layers, expected
+        )));
+    }
+
+    Ok(())
+}
+
+fn cmd_transition_layers(
+    ctx: &Context,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    layers: u32,
+    (old_layout, new_layout): (vk::ImageLayout, vk::ImageLayout),
+    (src_access_mask, dst_access_mask): (vk::AccessFlags, vk::AccessFlags),
+    (src_stage_mask, dst_stage_mask): (vk::PipelineStageFlags, vk::PipelineStageFlags),
+) {
+    let barrier = vk::ImageMemoryBarrier {
+        sType: vk::STRUCTURE_TYPE_IMAGE_MEMORY_BARRIER,
+        pNext: ptr::null(),
+        srcAccessMask: src_access_mask,
+        dstAccessMask: dst_access_mask,
+        oldLayout: old_layout,
+        newLayout: new_layout,
+        srcQueueFamilyIndex: vk::QUEUE_FAMILY_IGNORED,
+        dstQueueFamilyIndex: vk::QUEUE_FAMILY_IGNORED,
+        image,
+        subresourceRange: vk::ImageSubresourceRange {
+            aspectMask: vk::IMAGE_ASPECT_COLOR_BIT,
+            baseMipLevel: 0,
+            levelCount: 1,
+            baseArrayLayer: 0,
+            layerCount: layers,
+        },
+    };
+
+    ctx.dp.cmd_pipeline_barrier(
+        command_buffer,
+        src_stage_mask,
+        dst_stage_mask,
+        0,
+        &[],
+        &[],
+        &[barrier],
+    );
+}
+
+fn create_array_view(ctx: &Context, image: vk::Image, layers: u32) -> Result<vk::ImageView> {
+    let info = vk::ImageViewCreateInfo {
+        sType: vk::STRUCTURE_TYPE_IMAGE_VIEW_CREATE_INFO,
+        pNext: ptr::null(),
+        flags: 0,
+        image,
+        viewType: vk::IMAGE_VIEW_TYPE_2D_ARRAY,
+        format: MESH_TEXTURE_FORMAT,
+        components: vk::ComponentMapping {
+            r: vk::COMPONENT_SWIZZLE_IDENTITY,
+            g: vk::COMPONENT_SWIZZLE_IDENTITY,
+            b: vk::COMPONENT_SWIZZLE_IDENTITY,
+            a: vk::COMPONENT_SWIZZLE_IDENTITY,
+        },
+        subresourceRange: vk::ImageSubresourceRange {
+            aspectMask: vk::IMAGE_ASPECT_COLOR_BIT,
+            baseMipLevel: 0,
+            levelCount: 1,
+            baseArrayLayer: 0,
+            layerCount: layers,
+        },
+    };
+
+    unsafe { ctx.dp.create_image_view(ctx.device, &info) }.map_err(to_vulkan)
+}
+
+fn create_descriptor_set_layout(ctx: &Context) -> Result<vk::DescriptorSetLayout> {
+    let binding = vk::DescriptorSetLayoutBinding {
+        binding: 0,
+        descriptorType: vk::DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+        descriptorCount: 1,
+        stageFlags: vk::SHADER_STAGE_FRAGMENT_BIT,
+        pImmutableSamplers: ptr::null(),
+    };
+
+    let info = vk::DescriptorSetLayoutCreateInfo {
+        sType: vk::STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+        pNext: ptr::null(),
+        flags: 0,
+        bindingCount: 1,
+        pBindings: &binding,
+    };
+
+    unsafe { ctx.dp.create_descriptor_set_layout(ctx.device, &info) }.map_err(to_vulkan)
+}
+
+/// the single set with the texture array
+fn create_descriptor_pool(ctx: &Context) -> Result<vk::DescriptorPool> {
+    let pool_size = vk::DescriptorPoolSize {
+        ty: vk::DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+        descriptorCount: 1,
+    };
+
+    let info = vk::DescriptorPoolCreateInfo {
+        sType: vk::STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO,
+        pNext: ptr::null(),
+        flags: 0,
+        maxSets: 1,
+        poolSizeCount: 1,
+        pPoolSizes: &pool_size,
+    };
+
+    unsafe { ctx.dp.create_descriptor_pool(ctx.device, &info) }.map_err(to_vulkan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texels_must_fill_every_layer() {
+        assert!(check_texels(16, 16, 3, 16 * 16 * 3 * 4, 4096, 256).is_ok());
+        assert!(check_texels(16, 16, 3, 16 * 16 * 2 * 4, 4096, 256).is_err());
+        assert!(check_texels(0, 16, 1, 0, 4096, 256).is_err());
+        assert!(check_texels(16, 16, 0, 0, 4096, 256).is_err());
+    }
+
+    #[test]
+    fn texture_size_within_device_limits() {
+        assert!(check_texels(8192, 1, 1, 8192 * 4, 4096, 256).is_err());
+        assert!(check_texels(1, 1, 512, 512 * 4, 4096, 256).is_err());
+    }
+}
@@ -12,35 +12,264 @@
 //!      calls.
 //! -
 
+mod compute;
 mod context;
+mod debug;
+mod depth;
+mod descriptor;
 mod error;
+mod handle;
+mod mesh;
+mod mesh_slab;
+mod mesh_textures;
+mod motion;
+mod pipeline;
+mod pipeline_cache;
+mod post_process;
+mod primitives;
+mod render_target;
+mod sampler;
+mod scene_uniforms;
 mod setup;
+mod sparse;
+mod specialization;
+mod submit;
 mod swapchain;
+mod tonemap;
+mod uniform;
 mod util;
 mod version;
 mod vertex;
 
+pub use compute::{work_groups, ComputePipeline, StorageBuffer};
+pub use descriptor::DescriptorWriter;
 use error::Result;
+pub use error::{DeviceRejection, DeviceReport, Error, RejectionReason, Stage};
+pub use mesh::{aabb_center, draw_order, IndexType, Mesh};
+pub use mesh_slab::MeshId;
+use mesh_slab::MeshSlab;
+use mesh_textures::MeshTextures;
+pub use mesh_textures::MESH_TEXTURE_FORMAT;
+pub use motion::{MotionUniforms, ViewProjectionHistory, MOTION_VECTOR_FORMAT};
+pub use pipeline::DepthBias;
+pub use post_process::PostProcess;
+pub use primitives::{cube, grid_plane, quad, textured_cube, triangle_grid};
+pub use render_target::HistoryTargets;
+pub use sampler::{AddressMode, SamplerOptions};
+pub use specialization::{SpecializationConstants, SpecializationValue};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+pub use submit::SubmitBatch;
+pub use swapchain::{
+    ExternalDependency, FrameOutcome, LatencyMode, LoadOp, PresentGoal, PresentMode, PresentTo,
+    RenderedImage, StoreOp,
+};
+pub use tonemap::{Tonemap, TonemapOperator};
+pub use vertex::{MeshVertex, ParticleVertex, Vertex};
 use vulkanic::{DevicePointers, InstancePointers};
 
 use vk_sys as vk;
 
 use self::error::to_vulkan;
+use self::util::copy_physical_device_limits;
 
 pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+pub const DEFAULT_FENCE_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct VulkanInit<'a> {
     pub debug: bool,
     pub window: &'a mut glfw::Window,
     pub req_ext: &'a Vec<String>,
     pub req_layers: &'a Vec<String>,
+    /// how long to wait for a frame's fence before giving up with a `TIMEOUT` error
+    pub fence_timeout: Duration,
+    /// one queue per priority (in `[0, 1]`) is created on the graphics queue family, the first
+    /// one is used for rendering, others are free for e.g. async transfers
+    pub queue_priorities: Vec<f32>,
+    /// enables sparse buffers, if the device supports them
+    pub sparse_binding: bool,
+    /// validation besides the standard one, only with `debug`
+    pub validation_features: ValidationFeatures,
+    /// prefers the device whose name contains this (case-insensitive), e.g. `"nvidia"` on a
+    /// multi-GPU system
+    pub device_name_filter: Option<String>,
+    /// which GPU to use on systems with a discrete and an integrated one
+    pub power_preference: PowerPreference,
+    /// integrated GPUs are considered, otherwise only picked by `device_name_filter`
+    pub allow_integrated: bool,
+    /// loads the pipeline cache from this file and saves it there in `destroy`, so pipelines
+    /// are built faster from the second run on
+    pub pipeline_cache_path: Option<PathBuf>,
+}
+
+/// Initialization for GPU compute only (e.g. offline chunk generation), without window, surface
+/// and swapchain.
+pub struct ComputeInit<'a> {
+    pub debug: bool,
+    /// loads the Vulkan functions, instead of a window
+    pub glfw: &'a glfw::Glfw,
+    pub req_ext: &'a Vec<String>,
+    pub req_layers: &'a Vec<String>,
+    pub fence_timeout: Duration,
+    pub validation_features: ValidationFeatures,
+}
+
+/// Kind of GPU preferred when several are suitable, e.g. on hybrid laptops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerPreference {
+    /// discrete GPUs over integrated ones
+    HighPerformance,
+    /// integrated GPUs over discrete ones, for battery life
+    LowPower,
+}
+
+impl Default for PowerPreference {
+    fn default() -> Self {
+        PowerPreference::HighPerformance
+    }
+}
+
+/// Optional checks of the validation layer (`VK_EXT_validation_features`).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ValidationFeatures {
+    /// Instruments shaders to check e.g. out of bounds descriptor access. Expensive: shaders are
+    /// rewritten at pipeline creation and every submit is checked, expect frame times to
+    /// multiply.
+    pub gpu_assisted: bool,
+    /// warns about API usage that is valid, but slow
+    pub best_practices: bool,
+    /// checks for missing barriers and other hazards between commands
+    pub synchronization: bool,
 }
 
 pub struct Vulkan {
     ctx: Context,
     sc_ctx: Option<Swapchain>,
+    /// settings or the surface changed, the swapchain is recreated before the next frame
+    swapchain_outdated: bool,
+    /// acquire or present reported `ERROR_SURFACE_LOST_KHR`, the surface is recreated before the
+    /// next frame
+    surface_lost: bool,
+    /// surface recreations since the last presented frame
+    surface_recoveries: u32,
+    /// swapchain recreations in a row because a frame was suboptimal
+    suboptimal_recreations: u32,
+    /// swapchains recreated with the previous one as `oldSwapchain`
+    retired_swapchains: u32,
     inflight_frames: Vec<InFlightFrame>,
     current_frame: usize,
+    latency_mode: LatencyMode,
+    /// acquire semaphores of `LatencyMode::AcquireFirst`, used round robin. One more than frames
+    /// in flight, so the one acquired with belongs to a frame whose fence was waited on.
+    acquire_semaphores: Vec<vk::Semaphore>,
+    acquire_semaphore_index: usize,
+    fence_timeout: Duration,
+    frame_count: u64,
+    fence_timeouts: u32,
+    last_frame_at: Instant,
+    /// frame count and swapchain image index of the last queue submit, logged when the device
+    /// gets lost
+    last_submit: Option<(u64, u32)>,
+    particles: Vec<ParticleVertex>,
+    /// drawn by the scene pipeline, indexed by `scene_indices`
+    scene_vertices: Vec<Vertex>,
+    /// triangle list into `scene_vertices`
+    scene_indices: Vec<u32>,
+    /// model view projection of the scene vertices
+    scene_mvp: glm::Mat4,
+    /// replaces `scene_mvp` in the viewport of the same index
+    viewport_mvps: Vec<glm::Mat4>,
+    /// the scene is drawn once per model matrix
+    scene_models: Vec<glm::Mat4>,
+    /// meshes uploaded with `upload_mesh`
+    meshes: MeshSlab,
+    /// meshes drawn each frame with their model matrix, see `set_render_list`
+    render_list: Vec<(MeshId, glm::Mat4)>,
+    /// translucent meshes of the render list are drawn back to front from here
+    camera_position: glm::Vec3,
+    /// the render list changed, the command buffers are recorded again before the next frame
+    command_buffers_outdated: bool,
+    /// texture array of the mesh pipeline, `None` without a window
+    mesh_textures: Option<MeshTextures>,
+    settings: SwapchainSettings,
+    /// presents instead of `queue_present_khr`, if set
+    present_to: Option<PresentTo>,
+    /// the pipeline cache is loaded from and saved to this file
+    pipeline_cache_path: Option<PathBuf>,
+}
+
+/// Settings baked into the swapchain resources and command buffers. Changing them recreates the
+/// swapchain.
+pub struct SwapchainSettings {
+    /// depth bias of the scene pipeline
+    pub depth_bias: Option<DepthBias>,
+    /// minimum fraction of samples shaded individually by the scene pipeline, `None` shades once
+    /// per pixel
+    pub sample_shading: Option<f32>,
+    /// restricts rendering and clearing to a part of the framebuffer, `None` is the full extent
+    pub render_area: Option<vk::Rect2D>,
+    /// the scene is drawn once into each viewport (e.g. split screen), empty draws it once into
+    /// the render area
+    pub viewports: Vec<vk::Rect2D>,
+    /// dependencies on work submitted before the render pass, besides the color output one
+    pub external_dependencies: Vec<ExternalDependency>,
+    /// swapchain images to request, clamped to what the surface supports. 2 is double buffering
+    /// with the least latency, 3 triple buffering. `None` is one more than the minimum.
+    pub desired_image_count: Option<u32>,
+    /// renders the scene at `scale` times the swapchain extent and upscales (or downscales) it
+    /// with linear filtering, `None` renders directly into the swapchain images
+    pub render_scale: Option<f32>,
+    /// renders the scene in HDR and tonemaps it onto the swapchain image
+    pub tonemap: Option<Tonemap>,
+    /// bloom added by the tonemap pass, ignored without `tonemap`
+    pub post_process: Option<PostProcess>,
+    /// what the scene render pass does with the previous content of its color image
+    pub color_load_op: LoadOp,
+    /// whether the scene render pass keeps what it rendered
+    pub color_store_op: StoreOp,
+    /// presentation modes in the order of preference, see `Vulkan::set_present_mode_preference`
+    pub present_mode_preference: Vec<vk::PresentModeKHR>,
+    /// the scene render pass gets a depth buffer, which the scene and particle pipelines test
+    /// against
+    pub depth_test: bool,
+    /// fails swapchain creation if the surface doesn't support `B8G8R8A8_SRGB` with the sRGB color
+    /// space, instead of falling back to its first format
+    pub require_srgb_format: bool,
+    /// debugging aid: the scene pipeline doesn't cull, scene triangles facing away are reported
+    pub winding_check: bool,
+    /// color attachments are cleared to this RGBA color
+    pub clear_color: [f32; 4],
+    /// depth attachments are cleared to this, `None` is the far plane
+    pub clear_depth: Option<f32>,
+    /// usage of the scene vertex buffer besides `VERTEX_BUFFER`
+    pub vertex_buffer_usage: vk::BufferUsageFlags,
+}
+
+impl Default for SwapchainSettings {
+    fn default() -> Self {
+        Self {
+            depth_bias: None,
+            sample_shading: None,
+            render_area: None,
+            viewports: Vec::new(),
+            external_dependencies: Vec::new(),
+            desired_image_count: None,
+            render_scale: None,
+            tonemap: None,
+            post_process: None,
+            color_load_op: LoadOp::default(),
+            color_store_op: StoreOp::default(),
+            present_mode_preference: PresentMode::default().preference(),
+            depth_test: false,
+            require_srgb_format: false,
+            winding_check: false,
+            clear_color: [0.0; 4],
+            clear_depth: None,
+            vertex_buffer_usage: 0,
+        }
+    }
 }
 
 impl Vulkan {
@@ -50,11 +279,215 @@ impl Vulkan {
             .queue_wait_idle(self.ctx.queue_families.present_queue)
             .map_err(to_vulkan)
     }
+
+    /// Copies the first `size` bytes of `src` to `dst` and waits for it, see
+    /// `Context::copy_buffer`.
+    pub fn copy_buffer(
+        &self,
+        src: vk::Buffer,
+        dst: vk::Buffer,
+        size: vk::DeviceSize,
+    ) -> Result<()> {
+        self.ctx.copy_buffer(src, dst, size)
+    }
+
+    /// Uploads tightly packed texels into a color image in `TRANSFER_DST_OPTIMAL` layout and
+    /// waits for it, see `Context::copy_buffer_to_image`.
+    pub fn copy_buffer_to_image(
+        &self,
+        src: vk::Buffer,
+        image: vk::Image,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        self.ctx.copy_buffer_to_image(src, image, width, height)
+    }
+
+    /// Records commands with `record` (e.g. copies or layout transitions) into a one time
+    /// command buffer, submits it to the graphics queue and waits for it, see
+    /// `Context::submit_one_time`.
+    pub fn run_one_time_commands<F>(&self, record: F) -> Result<()>
+    where
+        F: FnOnce(vk::CommandBuffer),
+    {
+        self.ctx.submit_one_time(record)
+    }
+
+    /// Device local buffer with `data`, filled through a staging buffer, e.g. for large static
+    /// geometry. `TRANSFER_DST` is added to `usage`. Waits for the upload. The caller destroys
+    /// the buffer and frees the memory.
+    pub fn create_device_local_buffer<T>(
+        &self,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+    ) -> Result<(vk::Buffer, vk::DeviceMemory)> {
+        let (buffer, memory) = swapchain::upload_device_local_buffer(&self.ctx, data, usage)?;
+
+        Ok((buffer.release(), memory.release()))
+    }
+
+    /// Uploads a mesh into device local memory once, to be drawn by its id in any number of
+    /// frames, see `set_render_list`. Waits for the upload.
+    pub fn upload_mesh(&mut self, vertices: &[MeshVertex], indices: &[u32]) -> Result<MeshId> {
+        let mut mesh = Mesh::new_device_local(&self.ctx, vertices, indices)?;
+        mesh.set_center(aabb_center(vertices));
+
+        Ok(self.meshes.insert(mesh))
+    }
+
+    /// Frees a mesh of `upload_mesh` and takes it off the render list. Frames in flight may
+    /// still draw it, so it is destroyed after they are done.
+    pub fn free_mesh(&mut self, id: MeshId) -> Result<()> {
+        if !self.meshes.remove(id, self.frame_count) {
+            return Err(Error::Other(format!("{:?} was freed already", id)));
+        }
+
+        let render_list_len = self.render_list.len();
+        self.render_list.retain(|(mesh, _)| *mesh != id);
+        if self.render_list.len() != render_list_len {
+            self.command_buffers_outdated = true;
+        }
+
+        Ok(())
+    }
+
+    /// Meshes of `upload_mesh` drawn by the mesh pipeline each frame, each with its model
+    /// matrix. A mesh may be listed several times. The command buffers are recorded again
+    /// before the next frame, the swapchain stays.
+    pub fn set_render_list(&mut self, render_list: Vec<(MeshId, glm::Mat4)>) -> Result<()> {
+        if let Some((id, _)) = render_list
+            .iter()
+            .find(|(id, _)| self.meshes.get(*id).is_none())
+        {
+            return Err(Error::Other(format!("{:?} is no uploaded mesh", id)));
+        }
+
+        self.render_list = render_list;
+        self.command_buffers_outdated = true;
+
+        Ok(())
+    }
+
+    /// Translucent meshes (water, glass) are drawn after the opaque ones with alpha blending and
+    /// without depth writes, back to front from `set_camera_position`.
+    pub fn set_mesh_translucent(&mut self, id: MeshId, translucent: bool) -> Result<()> {
+        let mesh = self
+            .meshes
+            .get_mut(id)
+            .ok_or_else(|| Error::Other(format!("{:?} is no uploaded mesh", id)))?;
+        mesh.set_translucent(translucent);
+
+        if self.render_list.iter().any(|(mesh, _)| *mesh == id) {
+            self.command_buffers_outdated = true;
+        }
+
+        Ok(())
+    }
+
+    /// Eye position in world space, translucent meshes of the render list are sorted by their
+    /// distance to it. The sort is recorded into the command buffers, they are only recorded
+    /// again if the order changed.
+    pub fn set_camera_position(&mut self, position: glm::Vec3) {
+        self.camera_position = position;
+
+        if let Some(swapchain) = &self.sc_ctx {
+            if swapchain.ctx.mesh_draws != self.mesh_draws() {
+                self.command_buffers_outdated = true;
+            }
+        }
+    }
+
+    /// The render list with the buffers of its meshes in `draw_order`, as recorded into the
+    /// command buffers.
+    fn mesh_draws(&self) -> Vec<mesh::MeshDraw> {
+        let listed: Vec<_> = self
+            .render_list
+            .iter()
+            .filter_map(|(id, model)| self.meshes.get(*id).map(|mesh| (mesh, model)))
+            .collect();
+        let centers: Vec<_> = listed
+            .iter()
+            .map(|(mesh, model)| (*mesh, mesh.world_center(model)))
+            .collect();
+
+        draw_order(&self.camera_position, &centers)
+            .into_iter()
+            .map(|index| {
+                let (mesh, model) = listed[index];
+                mesh.draw(*model)
+            })
+            .collect()
+    }
+
+    pub fn mesh(&self, id: MeshId) -> Option<&Mesh> {
+        self.meshes.get(id)
+    }
+
+    /// Limits of the physical device in use, queried once at init, e.g. to check texture sizes
+    /// against `maxImageDimension2D` before allocating, to size push constants
+    /// (`maxPushConstantsSize`) or to budget allocations (`maxMemoryAllocationCount`).
+    pub fn device_limits(&self) -> vk::PhysicalDeviceLimits {
+        copy_physical_device_limits(&self.ctx.limits)
+    }
+
+    /// Queue for compute dispatches, the graphics queue if its family supports compute.
+    pub fn compute_queue(&self) -> vk::Queue {
+        self.ctx.queue_families.compute_queue
+    }
+
+    /// all queues created on the graphics queue family, in order of `VulkanInit::queue_priorities`
+    pub fn graphics_queues(&self) -> &[vk::Queue] {
+        &self.ctx.queue_families.graphics_queues
+    }
+
+    /// Device functions, for extensions this crate doesn't wrap.
+    ///
+    /// # Safety
+    ///
+    /// Handles created by this crate (device, swapchain, buffers, ...) stay owned by it and must
+    /// not be destroyed through these functions, and nothing created through them may still be
+    /// in use once `Vulkan` is destroyed.
+    pub unsafe fn raw_device_pointers(&self) -> &DevicePointers {
+        &self.ctx.dp
+    }
+
+    /// Instance functions, for extensions this crate doesn't wrap.
+    ///
+    /// # Safety
+    ///
+    /// Same as `raw_device_pointers`, the instance and surface stay owned by this crate.
+    pub unsafe fn raw_instance_pointers(&self) -> &InstancePointers {
+        &self.ctx.ip
+    }
+
+    /// # Safety
+    ///
+    /// Owned by this crate, must not be destroyed by the caller, see `raw_device_pointers`.
+    pub unsafe fn raw_device(&self) -> vk::Device {
+        self.ctx.device
+    }
+
+    /// # Safety
+    ///
+    /// Owned by this crate, must not be destroyed by the caller, see `raw_device_pointers`.
+    pub unsafe fn raw_instance(&self) -> vk::Instance {
+        self.ctx.instance
+    }
+
+    /// # Safety
+    ///
+    /// Owned by this crate, see `raw_device_pointers`.
+    pub unsafe fn raw_physical_device(&self) -> vk::PhysicalDevice {
+        self.ctx.physical_device
+    }
 }
 
 struct Context {
     ip: InstancePointers,
     dp: DevicePointers,
+    /// the unwrapped functions, for results `dp` loses (e.g. the image index of a suboptimal
+    /// acquire)
+    raw_dp: vk::DevicePointers,
     instance: vk::Instance,
     debugger: vk::DebugUtilsMessengerEXT,
     physical_device: vk::PhysicalDevice,
@@ -64,38 +497,103 @@ struct Context {
     surface: vk::SurfaceKHR,
     command_pool: vk::CommandPool,
     memory_properties: vk::PhysicalDeviceMemoryProperties,
+    enabled_features: vk::PhysicalDeviceFeatures,
+    /// snapshot of the physical device limits
+    limits: vk::PhysicalDeviceLimits,
+    device_extensions: Vec<String>,
+    /// passed to every pipeline creation, `NULL_HANDLE` if it couldn't be created
+    pipeline_cache: vk::PipelineCache,
 }
 
 #[derive(Debug)]
 pub struct QueueFamilies {
     pub graphics_queue: vk::Queue,
+    pub graphics_queues: Vec<vk::Queue>,
     pub present_queue: vk::Queue,
+    pub compute_queue: vk::Queue,
+    pub sparse_queue: Option<vk::Queue>,
 }
 
 #[derive(Debug)]
 pub struct QueueFamilyIndices {
     pub graphics: u32,
     pub present: u32,
+    pub compute: u32,
+    pub sparse: Option<u32>,
 }
 
 struct SwapchainContext {
     swapchain: vk::SwapchainKHR,
-    pipeline: vk::Pipeline,
-    pipeline_layout: vk::PipelineLayout,
+    pipeline: Pipeline,
+    particle_pipeline: Pipeline,
+    /// draws the opaque `mesh_draws` with the texture array of `mesh_texture_set`
+    mesh_pipeline: Pipeline,
+    /// blends the translucent `mesh_draws` after the opaque ones
+    translucent_mesh_pipeline: Pipeline,
+    mesh_texture_set: vk::DescriptorSet,
+    /// the render list in draw order, replaced when the command buffers are recorded again
+    mesh_draws: Vec<mesh::MeshDraw>,
     render_pass: vk::RenderPass,
-    vertex_shader_module: vk::ShaderModule,
-    fragment_shader_module: vk::ShaderModule,
+    /// one per attachment of `render_pass`, in order
+    clear_values: Vec<vk::ClearValue>,
     vertex_buffer: vk::Buffer,
     vertex_buffer_memory: vk::DeviceMemory,
+    /// writes to non-coherent memory need a flush
+    vertex_buffer_coherent: bool,
+    index_buffer: vk::Buffer,
+    index_buffer_memory: vk::DeviceMemory,
+    index_count: u32,
+    /// model matrices pushed for the draws of the scene
+    models: Vec<glm::Mat4>,
+    particle_buffer: vk::Buffer,
+    particle_buffer_memory: vk::DeviceMemory,
+    particle_count: u32,
     extent: vk::Extent2D,
+    /// extent the scene is rendered at, differs from `extent` with a render scale
+    render_extent: vk::Extent2D,
+    scene_output: SceneOutput,
+    color_load_op: LoadOp,
+    tonemap_pass: Option<tonemap::TonemapPass>,
+    post_process_pass: Option<post_process::PostProcessPass>,
+    scene_uniforms: scene_uniforms::SceneUniformSets,
+    render_area: vk::Rect2D,
+    viewports: Vec<vk::Rect2D>,
     surface_format: vk::SurfaceFormatKHR,
+    /// format of the depth buffer of each image, if the render pass has one
+    depth_format: Option<vk::Format>,
+    present_mode: vk::PresentModeKHR,
+}
+
+/// what the scene render pass renders into
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SceneOutput {
+    /// the swapchain image
+    Swapchain,
+    /// a render target, which is blitted onto the swapchain image (render scale)
+    Blit,
+    /// a float render target, which is tonemapped onto the swapchain image
+    Tonemap,
 }
+
 struct Swapchain {
     images: Vec<SwapchainImage>,
     ctx: SwapchainContext,
 }
 
+struct Pipeline {
+    vertex_shader_module: vk::ShaderModule,
+    fragment_shader_module: vk::ShaderModule,
+    layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
 struct SwapchainImage {
+    image: vk::Image,
+    render_target: Option<render_target::RenderTarget>,
+    tonemap_image: Option<tonemap::TonemapImage>,
+    post_process_image: Option<post_process::PostProcessImage>,
+    depth_buffer: Option<depth::DepthBuffer>,
+    scene_uniforms: scene_uniforms::SceneUniformImage,
     image_view: vk::ImageView,
     framebuffer: vk::Framebuffer,
     command_buffer: vk::CommandBuffer,
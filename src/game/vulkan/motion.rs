@@ -0,0 +1,87 @@
+//! Motion vectors for temporal techniques (TAA, motion blur): shaders project every vertex with
+//! this and the previous frame's view projection, and write the difference of the two clip space
+//! positions into a `MOTION_VECTOR_FORMAT` attachment.
+
+use glm::Mat4;
+use vk_sys as vk;
+
+/// two channels for the screen space delta, half floats are precise enough for sub-pixel motion
+pub const MOTION_VECTOR_FORMAT: vk::Format = vk::FORMAT_R16G16_SFLOAT;
+
+/// Uniform block of shaders writing motion vectors, std140 compatible.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MotionUniforms {
+    pub view_projection: Mat4,
+    pub previous_view_projection: Mat4,
+}
+
+/// Remembers the view projection of the last frame.
+#[derive(Default)]
+pub struct ViewProjectionHistory {
+    current: Option<Mat4>,
+}
+
+impl ViewProjectionHistory {
+    /// Uniforms of the next frame, rendered with `view_projection`. The previous view projection
+    /// is the one passed the last time; on the first frame (or after `reset`) it is
+    /// `view_projection` itself, so there is no motion.
+    pub fn advance(&mut self, view_projection: Mat4) -> MotionUniforms {
+        let previous_view_projection = self.current.unwrap_or(view_projection);
+        self.current = Some(view_projection);
+
+        MotionUniforms {
+            view_projection,
+            previous_view_projection,
+        }
+    }
+
+    /// Forgets the last frame, e.g. after a camera cut, where motion vectors would be bogus.
+    pub fn reset(&mut self) {
+        self.current = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glm::Vec4;
+
+    fn translation(x: f32) -> Mat4 {
+        Mat4::new(
+            Vec4::new(1.0, 0.0, 0.0, 0.0),
+            Vec4::new(0.0, 1.0, 0.0, 0.0),
+            Vec4::new(0.0, 0.0, 1.0, 0.0),
+            Vec4::new(x, 0.0, 0.0, 1.0),
+        )
+    }
+
+    #[test]
+    fn first_frame_has_no_motion() {
+        let uniforms = ViewProjectionHistory::default().advance(translation(1.0));
+
+        assert_eq!(uniforms.previous_view_projection, translation(1.0));
+    }
+
+    #[test]
+    fn previous_is_the_last_view_projection() {
+        let mut history = ViewProjectionHistory::default();
+        history.advance(translation(1.0));
+        let uniforms = history.advance(translation(2.0));
+
+        assert_eq!(uniforms.view_projection, translation(2.0));
+        assert_eq!(uniforms.previous_view_projection, translation(1.0));
+    }
+
+    #[test]
+    fn reset_forgets_the_last_frame() {
+        let mut history = ViewProjectionHistory::default();
+        history.advance(translation(1.0));
+        history.reset();
+
+        assert_eq!(
+            history.advance(translation(2.0)).previous_view_projection,
+            translation(2.0)
+        );
+    }
+}
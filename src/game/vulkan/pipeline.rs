@@ -0,0 +1,562 @@
+use std::{ffi::CString, mem::size_of, time::Instant};
+
+use super::specialization::SpecializationConstants;
+use super::vertex::{MeshVertex, ParticleVertex, Vertex, VertexLayout};
+use super::{
+    error::{to_other, to_vulkan, Error},
+    handle, Context, Pipeline, Result, SwapchainSettings,
+};
+use inline_spirv::include_spirv;
+use log::debug;
+use vk_sys as vk;
+use vulkanic::DevicePointers;
+
+/// entry point of shaders compiled from GLSL
+pub const DEFAULT_ENTRY_POINT: &str = "main";
+
+pub struct PipelineDesc<'a> {
+    pub vert_shader: &'a [u32],
+    /// function of `vert_shader` to run, usually `DEFAULT_ENTRY_POINT`
+    pub vert_entry_point: &'a str,
+    pub frag_shader: &'a [u32],
+    pub frag_entry_point: &'a str,
+    pub vert_specialization: Option<&'a SpecializationConstants>,
+    pub frag_specialization: Option<&'a SpecializationConstants>,
+    pub topology: vk::PrimitiveTopology,
+    pub vertex_layout: &'a VertexLayout,
+    pub depth_bias: Option<&'a DepthBias>,
+    /// `minSampleShading`, needs the `sampleRateShading` feature
+    pub sample_shading: Option<f32>,
+    /// straight alpha blending over what is already in the attachment, for translucent geometry
+    /// drawn back to front. Disables depth writes.
+    pub alpha_blend: bool,
+    /// `CULL_MODE_BACK_BIT` for closed geometry, front faces are clockwise on screen
+    pub cull_mode: vk::CullModeFlags,
+    /// tests against and writes the depth attachment, which the render pass must have
+    pub depth_test: bool,
+    pub set_layouts: &'a [vk::DescriptorSetLayout],
+    pub push_constant_ranges: &'a [vk::PushConstantRange],
+}
+
+/// Polygon offset added to the depth of rasterized fragments, against shadow acne or z-fighting
+/// of coplanar geometry like decals. Only has an effect with a depth attachment.
+///
+/// Typical values for a shadow pass are a `constant_factor` around `1.25` and a `slope_factor`
+/// around `1.75`. Start low and increase until the acne disappears, too much bias detaches
+/// shadows from their casters ("peter panning").
+#[derive(Debug, Clone, Copy)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    /// maximum bias, `0.0` disables clamping, other values need the `depthBiasClamp` feature
+    pub clamp: f32,
+    pub slope_factor: f32,
+}
+
+impl Pipeline {
+    pub fn destroy(&self, ctx: &Context) {
+        ctx.dp.destroy_pipeline(ctx.device, self.pipeline);
+        ctx.dp.destroy_pipeline_layout(ctx.device, self.layout);
+        ctx.dp
+            .destroy_shader_module(ctx.device, self.vertex_shader_module);
+        ctx.dp
+            .destroy_shader_module(ctx.device, self.fragment_shader_module);
+    }
+}
+
+pub fn create_triangle_pipeline(
+    ctx: &Context,
+    render_pass: vk::RenderPass,
+    settings: &SwapchainSettings,
+    scene_set_layout: vk::DescriptorSetLayout,
+) -> Result<Pipeline> {
+    let vertex_layout = Vertex::layout();
+    // model matrix of each draw, see `Vulkan::set_scene_models`
+    let model_push_constant_range = vk::PushConstantRange {
+        stageFlags: vk::SHADER_STAGE_VERTEX_BIT,
+        offset: 0,
+        size: size_of::<[f32; 16]>() as u32,
+    };
+
+    let desc = PipelineDesc {
+        vert_shader: include_spirv!("shader/vert.glsl", glsl, vert),
+        vert_entry_point: DEFAULT_ENTRY_POINT,
+        frag_shader: include_spirv!("shader/frag.glsl", glsl, frag),
+        frag_entry_point: DEFAULT_ENTRY_POINT,
+        vert_specialization: None,
+        frag_specialization: None,
+        topology: vk::PRIMITIVE_TOPOLOGY_TRIANGLE_LIST,
+        vertex_layout: &vertex_layout,
+        depth_bias: settings.depth_bias.as_ref(),
+        sample_shading: settings.sample_shading,
+        alpha_blend: false,
+        // everything is drawn, `Vulkan::set_winding_check` reports what would have been culled
+        cull_mode: if settings.winding_check {
+            vk::CULL_MODE_NONE
+        } else {
+            vk::CULL_MODE_BACK_BIT
+        },
+        depth_test: settings.depth_test,
+        set_layouts: &[scene_set_layout],
+        push_constant_ranges: &[model_push_constant_range],
+    };
+
+    create_graphics_pipeline(ctx, &desc, render_pass)
+}
+
+/// Point sprites, the size of each point is written to `gl_PointSize` by the vertex shader.
+/// Sizes above 1.0 need the `largePoints` device feature, otherwise points are clamped to 1.0.
+/// `depth_test` has to match whether `render_pass` has a depth attachment.
+pub fn create_particle_pipeline(
+    ctx: &Context,
+    render_pass: vk::RenderPass,
+    depth_test: bool,
+) -> Result<Pipeline> {
+    let vertex_layout = ParticleVertex::layout();
+
+    let desc = PipelineDesc {
+        vert_shader: include_spirv!("shader/particle_vert.glsl", glsl, vert),
+        vert_entry_point: DEFAULT_ENTRY_POINT,
+        frag_shader: include_spirv!("shader/particle_frag.glsl", glsl, frag),
+        frag_entry_point: DEFAULT_ENTRY_POINT,
+        vert_specialization: None,
+        frag_specialization: None,
+        topology: vk::PRIMITIVE_TOPOLOGY_POINT_LIST,
+        vertex_layout: &vertex_layout,
+        depth_bias: None,
+        sample_shading: None,
+        alpha_blend: false,
+        cull_mode: vk::CULL_MODE_BACK_BIT,
+        depth_test,
+        set_layouts: &[],
+        push_constant_ranges: &[],
+    };
+
+    create_graphics_pipeline(ctx, &desc, render_pass)
+}
+
+/// Textured `MeshVertex` geometry, drawn with the scene uniforms at set 0 and the model matrix of
+/// each draw as 64 byte push constant, like the scene pipeline. `texture_set_layout` (set 1) has
+/// a `sampler2DArray` at binding 0 whose layers are selected by `tex_id`, see `MeshTextures`.
+///
+/// `translucent` blends with alpha, for meshes like water and glass that are drawn after the
+/// opaque ones (see `mesh::draw_order`). `depth_test` has to match whether `render_pass` has a
+/// depth attachment.
+pub fn create_mesh_pipeline(
+    ctx: &Context,
+    render_pass: vk::RenderPass,
+    scene_set_layout: vk::DescriptorSetLayout,
+    texture_set_layout: vk::DescriptorSetLayout,
+    translucent: bool,
+    depth_test: bool,
+) -> Result<Pipeline> {
+    let vertex_layout = MeshVertex::layout();
+    let push_constant_range = vk::PushConstantRange {
+        stageFlags: vk::SHADER_STAGE_VERTEX_BIT,
+        offset: 0,
+        size: size_of::<[f32; 16]>() as u32,
+    };
+
+    let desc = PipelineDesc {
+        vert_shader: include_spirv!("shader/mesh_vert.glsl", glsl, vert),
+        vert_entry_point: DEFAULT_ENTRY_POINT,
+        frag_shader: include_spirv!("shader/mesh_frag.glsl", glsl, frag),
+        frag_entry_point: DEFAULT_ENTRY_POINT,
+        vert_specialization: None,
+        frag_specialization: None,
+        topology: vk::PRIMITIVE_TOPOLOGY_TRIANGLE_LIST,
+        vertex_layout: &vertex_layout,
+        depth_bias: None,
+        sample_shading: None,
+        alpha_blend: translucent,
+        cull_mode: vk::CULL_MODE_BACK_BIT,
+        depth_test,
+        set_layouts: &[scene_set_layout, texture_set_layout],
+        push_constant_ranges: &[push_constant_range],
+    };
+
+    create_graphics_pipeline(ctx, &desc, render_pass)
+}
+
+/// Pipeline for post-processing passes drawn with `Context::cmd_draw_fullscreen_triangle`. There
+/// is no vertex input, the vertex shader passes the UV (`[0, 1]` across the viewport) to
+/// `frag_shader` at location 0.
+pub fn create_fullscreen_pipeline(
+    ctx: &Context,
+    render_pass: vk::RenderPass,
+    frag_shader: &[u32],
+    set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_ranges: &[vk::PushConstantRange],
+) -> Result<Pipeline> {
+    let vertex_layout = VertexLayout::default();
+
+    let desc = PipelineDesc {
+        vert_shader: include_spirv!("shader/fullscreen_vert.glsl", glsl, vert),
+        vert_entry_point: DEFAULT_ENTRY_POINT,
+        frag_shader,
+        frag_entry_point: DEFAULT_ENTRY_POINT,
+        vert_specialization: None,
+        frag_specialization: None,
+        topology: vk::PRIMITIVE_TOPOLOGY_TRIANGLE_LIST,
+        vertex_layout: &vertex_layout,
+        depth_bias: None,
+        sample_shading: None,
+        alpha_blend: false,
+        cull_mode: vk::CULL_MODE_BACK_BIT,
+        depth_test: false,
+        set_layouts,
+        push_constant_ranges,
+    };
+
+    create_graphics_pipeline(ctx, &desc, render_pass)
+}
+
+/// Fails with the exceeded limit, instead of leaving it to validation or the driver.
+fn check_limits(ctx: &Context, desc: &PipelineDesc) -> Result<()> {
+    let limits = &ctx.limits;
+
+    let push_constants_size = desc
+        .push_constant_ranges
+        .iter()
+        .map(|range| range.offset + range.size)
+        .max()
+        .unwrap_or(0);
+
+    check_limit(
+        "push constants size",
+        push_constants_size,
+        limits.maxPushConstantsSize,
+    )?;
+    check_limit(
+        "vertex input bindings",
+        desc.vertex_layout.bindings.len() as u32,
+        limits.maxVertexInputBindings,
+    )?;
+    check_limit(
+        "vertex input attributes",
+        desc.vertex_layout.attributes.len() as u32,
+        limits.maxVertexInputAttributes,
+    )?;
+    check_limit(
+        "descriptor set layouts",
+        desc.set_layouts.len() as u32,
+        limits.maxBoundDescriptorSets,
+    )?;
+
+    for binding in &desc.vertex_layout.bindings {
+        check_limit(
+            "vertex input binding stride",
+            binding.stride,
+            limits.maxVertexInputBindingStride,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn check_limit(name: &str, value: u32, limit: u32) -> Result<()> {
+    if value > limit {
+        return Err(Error::Other(format!(
+            "{} {} exceeds the device limit {}",
+            name, value, limit
+        )));
+    }
+
+    Ok(())
+}
+
+fn disabled_stencil_op_state() -> vk::StencilOpState {
+    vk::StencilOpState {
+        failOp: vk::STENCIL_OP_KEEP,
+        passOp: vk::STENCIL_OP_KEEP,
+        depthFailOp: vk::STENCIL_OP_KEEP,
+        compareOp: vk::COMPARE_OP_ALWAYS,
+        compareMask: 0,
+        writeMask: 0,
+        reference: 0,
+    }
+}
+
+pub fn entry_point_name(entry_point: &str) -> Result<CString> {
+    CString::new(entry_point)
+        .map_err(|err| to_other(format!("invalid entry point {:?}: {}", entry_point, err)))
+}
+
+fn create_graphics_pipeline(
+    ctx: &Context,
+    desc: &PipelineDesc,
+    render_pass: vk::RenderPass,
+) -> Result<Pipeline> {
+    if let Some(depth_bias) = desc.depth_bias {
+        if depth_bias.clamp != 0.0 && ctx.enabled_features.depthBiasClamp != vk::TRUE {
+            return Err(Error::Other(
+                "depth bias clamp needs the depthBiasClamp feature".to_owned(),
+            ));
+        }
+    }
+
+    check_limits(ctx, desc)?;
+
+    if desc.sample_shading.is_some() && ctx.enabled_features.sampleRateShading != vk::TRUE {
+        return Err(Error::Other(
+            "sample shading needs the sampleRateShading feature".to_owned(),
+        ));
+    }
+
+    let vert_entry_point = entry_point_name(desc.vert_entry_point)?;
+    let frag_entry_point = entry_point_name(desc.frag_entry_point)?;
+
+    let vertex_shader_module = handle::shader_module(
+        ctx,
+        create_shader_module(&ctx.dp, ctx.device, desc.vert_shader)?,
+    );
+    let fragment_shader_module = handle::shader_module(
+        ctx,
+        create_shader_module(&ctx.dp, ctx.device, desc.frag_shader)?,
+    );
+
+    // referenced by the stage infos until the pipeline is created
+    let vert_specialization = desc.vert_specialization.map(SpecializationConstants::info);
+    let frag_specialization = desc.frag_specialization.map(SpecializationConstants::info);
+
+    let vertex_shader_info = vk::PipelineShaderStageCreateInfo {
+        sType: vk::STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
+        pNext: std::ptr::null(),
+        flags: 0,
+        stage: vk::SHADER_STAGE_VERTEX_BIT,
+        module: *vertex_shader_module,
+        pName: vert_entry_point.as_ptr(),
+        pSpecializationInfo: vert_specialization
+            .as_ref()
+            .map_or(std::ptr::null(), |info| info as *const _),
+    };
+
+    let fragment_shader_info = vk::PipelineShaderStageCreateInfo {
+        sType: vk::STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
+        pNext: std::ptr::null(),
+        flags: 0,
+        stage: vk::SHADER_STAGE_FRAGMENT_BIT,
+        module: *fragment_shader_module,
+        pName: frag_entry_point.as_ptr(),
+        pSpecializationInfo: frag_specialization
+            .as_ref()
+            .map_or(std::ptr::null(), |info| info as *const _),
+    };
+
+    let shader_stages = [vertex_shader_info, fragment_shader_info];
+
+    let vert_input_info = vk::PipelineVertexInputStateCreateInfo {
+        sType: vk::STRUCTURE_TYPE_PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO,
+        pNext: std::ptr::null(),
+        flags: 0,
+        vertexBindingDescriptionCount: desc.vertex_layout.bindings.len() as u32,
+        pVertexBindingDescriptions: desc.vertex_layout.bindings.as_ptr(),
+        vertexAttributeDescriptionCount: desc.vertex_layout.attributes.len() as u32,
+        pVertexAttributeDescriptions: desc.vertex_layout.attributes.as_ptr(),
+    };
+
+    let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo {
+        sType: vk::STRUCTURE_TYPE_PIPELINE_INPUT_ASSEMBLY_STATE_CREATE_INFO,
+        pNext: std::ptr::null(),
+        flags: 0,
+        topology: desc.topology,
+        primitiveRestartEnable: vk::FALSE,
+    };
+
+    // viewport and scissor are dynamic, see `Context::cmd_set_viewport`
+    let viewport_state_info = vk::PipelineViewportStateCreateInfo {
+        sType: vk::STRUCTURE_TYPE_PIPELINE_VIEWPORT_STATE_CREATE_INFO,
+        pNext: std::ptr::null(),
+        flags: 0,
+        viewportCount: 1,
+        pViewports: std::ptr::null(),
+        scissorCount: 1,
+        pScissors: std::ptr::null(),
+    };
+
+    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo {
+        sType: vk::STRUCTURE_TYPE_PIPELINE_RASTERIZATION_STATE_CREATE_INFO,
+        pNext: std::ptr::null(),
+        flags: 0,
+        depthClampEnable: vk::FALSE,
+        rasterizerDiscardEnable: vk::FALSE,
+        polygonMode: vk::POLYGON_MODE_FILL,
+        cullMode: desc.cull_mode,
+        frontFace: vk::FRONT_FACE_CLOCKWISE,
+        depthBiasEnable: if desc.depth_bias.is_some() {
+            vk::TRUE
+        } else {
+            vk::FALSE
+        },
+        depthBiasConstantFactor: desc.depth_bias.map_or(0.0, |bias| bias.constant_factor),
+        depthBiasClamp: desc.depth_bias.map_or(0.0, |bias| bias.clamp),
+        depthBiasSlopeFactor: desc.depth_bias.map_or(0.0, |bias| bias.slope_factor),
+        lineWidth: 1.0,
+    };
+
+    let multisample_info = vk::PipelineMultisampleStateCreateInfo {
+        sType: vk::STRUCTURE_TYPE_PIPELINE_MULTISAMPLE_STATE_CREATE_INFO,
+        pNext: std::ptr::null(),
+        flags: 0,
+        rasterizationSamples: vk::SAMPLE_COUNT_1_BIT,
+        sampleShadingEnable: if desc.sample_shading.is_some() {
+            vk::TRUE
+        } else {
+            vk::FALSE
+        },
+        minSampleShading: desc.sample_shading.unwrap_or(1.0),
+        pSampleMask: std::ptr::null(),
+        alphaToCoverageEnable: vk::FALSE,
+        alphaToOneEnable: vk::FALSE,
+    };
+
+    let (blend_enable, src_color_blend_factor, dst_color_blend_factor, dst_alpha_blend_factor) =
+        if desc.alpha_blend {
+            (
+                vk::TRUE,
+                vk::BLEND_FACTOR_SRC_ALPHA,
+                vk::BLEND_FACTOR_ONE_MINUS_SRC_ALPHA,
+                vk::BLEND_FACTOR_ONE_MINUS_SRC_ALPHA,
+            )
+        } else {
+            (
+                vk::FALSE,
+                vk::BLEND_FACTOR_ONE,
+                vk::BLEND_FACTOR_ZERO,
+                vk::BLEND_FACTOR_ZERO,
+            )
+        };
+
+    let color_blend_attach = vk::PipelineColorBlendAttachmentState {
+        blendEnable: blend_enable,
+        srcColorBlendFactor: src_color_blend_factor,
+        dstColorBlendFactor: dst_color_blend_factor,
+        colorBlendOp: vk::BLEND_OP_ADD,
+        srcAlphaBlendFactor: vk::BLEND_FACTOR_ONE,
+        dstAlphaBlendFactor: dst_alpha_blend_factor,
+        alphaBlendOp: vk::BLEND_OP_ADD,
+        colorWriteMask: vk::COLOR_COMPONENT_R_BIT
+            | vk::COLOR_COMPONENT_G_BIT
+            | vk::COLOR_COMPONENT_B_BIT
+            | vk::COLOR_COMPONENT_A_BIT,
+    };
+
+    let color_blend = vk::PipelineColorBlendStateCreateInfo {
+        sType: vk::STRUCTURE_TYPE_PIPELINE_COLOR_BLEND_STATE_CREATE_INFO,
+        pNext: std::ptr::null(),
+        flags: 0,
+        logicOpEnable: vk::FALSE,
+        logicOp: vk::LOGIC_OP_COPY,
+        attachmentCount: 1,
+        pAttachments: &color_blend_attach,
+        blendConstants: [0.0, 0.0, 0.0, 0.0],
+    };
+
+    // less or equal: with everything at the same depth (e.g. the 2D scene), later draws still
+    // win like without depth test. Blended geometry is tested, but doesn't hide what is drawn
+    // behind it later.
+    let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo {
+        sType: vk::STRUCTURE_TYPE_PIPELINE_DEPTH_STENCIL_STATE_CREATE_INFO,
+        pNext: std::ptr::null(),
+        flags: 0,
+        depthTestEnable: vk::TRUE,
+        depthWriteEnable: if desc.alpha_blend {
+            vk::FALSE
+        } else {
+            vk::TRUE
+        },
+        depthCompareOp: vk::COMPARE_OP_LESS_OR_EQUAL,
+        depthBoundsTestEnable: vk::FALSE,
+        stencilTestEnable: vk::FALSE,
+        front: disabled_stencil_op_state(),
+        back: disabled_stencil_op_state(),
+        minDepthBounds: 0.0,
+        maxDepthBounds: 1.0,
+    };
+
+    let dynamic_states = [vk::DYNAMIC_STATE_VIEWPORT, vk::DYNAMIC_STATE_SCISSOR];
+
+    let dynamic_state_info = vk::PipelineDynamicStateCreateInfo {
+        sType: vk::STRUCTURE_TYPE_PIPELINE_DYNAMIC_STATE_CREATE_INFO,
+        pNext: std::ptr::null(),
+        flags: 0,
+        dynamicStateCount: dynamic_states.len() as u32,
+        pDynamicStates: dynamic_states.as_ptr(),
+    };
+
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
+        sType: vk::STRUCTURE_TYPE_PIPELINE_LAYOUT_CREATE_INFO,
+        pNext: std::ptr::null(),
+        flags: 0,
+        setLayoutCount: desc.set_layouts.len() as u32,
+        pSetLayouts: desc.set_layouts.as_ptr(),
+        pushConstantRangeCount: desc.push_constant_ranges.len() as u32,
+        pPushConstantRanges: desc.push_constant_ranges.as_ptr(),
+    };
+
+    let pipeline_layout = handle::pipeline_layout(
+        ctx,
+        unsafe {
+            ctx.dp
+                .create_pipeline_layout(ctx.device, &pipeline_layout_info)
+        }
+        .map_err(to_vulkan)?,
+    );
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo {
+        sType: vk::STRUCTURE_TYPE_GRAPHICS_PIPELINE_CREATE_INFO,
+        pNext: std::ptr::null(),
+        flags: 0,
+        stageCount: shader_stages.len() as u32,
+        pStages: shader_stages.as_ptr(),
+        pVertexInputState: &vert_input_info,
+        pInputAssemblyState: &input_assembly_info,
+        pTessellationState: std::ptr::null(),
+        pViewportState: &viewport_state_info,
+        pRasterizationState: &rasterizer_info,
+        pMultisampleState: &multisample_info,
+        pDepthStencilState: if desc.depth_test {
+            &depth_stencil_info
+        } else {
+            std::ptr::null()
+        },
+        pColorBlendState: &color_blend,
+        pDynamicState: &dynamic_state_info,
+        layout: *pipeline_layout,
+        renderPass: render_pass,
+        subpass: 0,
+        basePipelineHandle: vk::NULL_HANDLE,
+        basePipelineIndex: -1,
+    };
+
+    let start = Instant::now();
+    let pipelines = unsafe {
+        ctx.dp
+            .create_graphics_pipelines(ctx.device, ctx.pipeline_cache, &[pipeline_info])
+    }
+    .map_err(to_vulkan)?;
+    debug!("graphics pipeline created in {:?}", start.elapsed());
+    let pipeline: vk::Pipeline = *pipelines.iter().next().unwrap();
+
+    Ok(Pipeline {
+        vertex_shader_module: vertex_shader_module.release(),
+        fragment_shader_module: fragment_shader_module.release(),
+        layout: pipeline_layout.release(),
+        pipeline,
+    })
+}
+
+pub fn create_shader_module(
+    dp: &DevicePointers,
+    device: vk::Device,
+    code: &[u32],
+) -> Result<vk::ShaderModule> {
+    let info = vk::ShaderModuleCreateInfo {
+        sType: vk::STRUCTURE_TYPE_SHADER_MODULE_CREATE_INFO,
+        pNext: std::ptr::null(),
+        flags: 0,
+        codeSize: code.len() * size_of::<u32>(), // not the len, but the size
+        pCode: code.as_ptr(),
+    };
+
+    unsafe { dp.create_shader_module(device, &info) }.map_err(to_vulkan)
+}
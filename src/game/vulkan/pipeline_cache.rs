@@ -0,0 +1,60 @@
+//! A `vk::PipelineCache` shared by all pipelines, so recreating them with the swapchain (e.g. on
+//! every resize) reuses what the driver compiled before. Optionally kept in a file across runs.
+
+use std::path::Path;
+
+use super::{error::to_vulkan, Context, Result};
+use log::{debug, warn};
+use vk_sys as vk;
+
+/// Creates the cache with the content of `path`, if there is a file. The driver ignores data of
+/// another device or driver version and starts empty.
+pub fn create(ctx: &Context, path: Option<&Path>) -> Result<vk::PipelineCache> {
+    let initial_data = match path {
+        Some(path) => match std::fs::read(path) {
+            Ok(data) => {
+                debug!("pipeline cache {:?}: {} bytes", path, data.len());
+                data
+            }
+            Err(err) => {
+                debug!("pipeline cache {:?} not loaded: {}", path, err);
+                Vec::new()
+            }
+        },
+        None => Vec::new(),
+    };
+
+    unsafe {
+        ctx.dp.create_pipeline_cache(
+            ctx.device,
+            &vk::PipelineCacheCreateInfo {
+                sType: vk::STRUCTURE_TYPE_PIPELINE_CACHE_CREATE_INFO,
+                pNext: std::ptr::null(),
+                flags: 0,
+                initialDataSize: initial_data.len(),
+                pInitialData: initial_data.as_ptr() as *const _,
+            },
+        )
+    }
+    .map_err(to_vulkan)
+}
+
+/// Writes the cache to `path`, a failure only costs the next start some time.
+pub fn save(ctx: &Context, pipeline_cache: vk::PipelineCache, path: &Path) {
+    let data = match ctx
+        .dp
+        .get_pipeline_cache_data(ctx.device, pipeline_cache)
+        .map_err(to_vulkan)
+    {
+        Ok(data) => data,
+        Err(err) => {
+            warn!("pipeline cache not saved: {}", err);
+            return;
+        }
+    };
+
+    match std::fs::write(path, &data) {
+        Ok(()) => debug!("pipeline cache {:?} saved: {} bytes", path, data.len()),
+        Err(err) => warn!("pipeline cache {:?} not saved: {}", path, err),
+    }
+}
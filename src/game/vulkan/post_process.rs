@@ -0,0 +1,514 @@
+//! Bloom: the bright areas of the HDR scene are downsampled into a half resolution target and
+//! blurred, the tonemap pass adds them to the scene so they glow.
+
+use std::{mem::size_of, ptr, slice};
+
+use super::{
+    error::to_vulkan,
+    handle,
+    pipeline::create_fullscreen_pipeline,
+    render_target::{scaled_extent, RenderTarget},
+    swapchain::create_framebuffer,
+    tonemap::HDR_FORMAT,
+    util::{copy_extent_2d, copy_rect_2d},
+    Context, DescriptorWriter, Error, Pipeline, Result,
+};
+use inline_spirv::include_spirv;
+use vk_sys as vk;
+
+/// resolution of the bloom targets relative to the scene
+const BLOOM_SCALE: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PostProcess {
+    /// luminance above which scene colors glow, `1.0` is the brightest LDR white
+    pub threshold: f32,
+    /// the blurred bright areas are added to the scene multiplied by this
+    pub intensity: f32,
+}
+
+impl Default for PostProcess {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            intensity: 0.5,
+        }
+    }
+}
+
+/// Fails for a negative threshold or intensity, e.g. from a config file.
+pub fn check_post_process(post_process: &PostProcess) -> Result<()> {
+    if !(0.0..).contains(&post_process.threshold) || !(0.0..).contains(&post_process.intensity) {
+        return Err(Error::Other(format!(
+            "bloom threshold and intensity must not be negative: {:?}",
+            post_process
+        )));
+    }
+
+    Ok(())
+}
+
+/// layout of the push constants in `shader/bloom_bright_frag.glsl`
+#[repr(C)]
+struct BrightConstants {
+    threshold: f32,
+}
+
+/// layout of the push constants in `shader/bloom_blur_frag.glsl`
+#[repr(C)]
+struct BlurConstants {
+    texel_step: [f32; 2],
+}
+
+impl BlurConstants {
+    fn horizontal(extent: &vk::Extent2D) -> Self {
+        Self {
+            texel_step: [1.0 / extent.width as f32, 0.0],
+        }
+    }
+
+    fn vertical(extent: &vk::Extent2D) -> Self {
+        Self {
+            texel_step: [0.0, 1.0 / extent.height as f32],
+        }
+    }
+}
+
+pub struct PostProcessPass {
+    pub post_process: PostProcess,
+    render_pass: vk::RenderPass,
+    bright_pipeline: Pipeline,
+    blur_pipeline: Pipeline,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    sampler: vk::Sampler,
+}
+
+/// resources of the bloom per swapchain image
+pub struct PostProcessImage {
+    /// the blurred bright areas, sampled by the tonemap pass
+    pub bloom: RenderTarget,
+    /// the horizontally blurred bright areas, blurred vertically back into `bloom`
+    blur: RenderTarget,
+    bloom_framebuffer: vk::Framebuffer,
+    blur_framebuffer: vk::Framebuffer,
+    /// samples the HDR target
+    bright_set: vk::DescriptorSet,
+    /// samples `bloom`
+    horizontal_set: vk::DescriptorSet,
+    /// samples `blur`
+    vertical_set: vk::DescriptorSet,
+}
+
+/// descriptor sets of a `PostProcessImage`
+const SETS_PER_IMAGE: u32 = 3;
+
+impl PostProcessPass {
+    pub fn new(ctx: &Context, post_process: PostProcess, image_count: u32) -> Result<Self> {
+        let sampler = handle::sampler(ctx, create_sampler(ctx)?);
+        let descriptor_set_layout =
+            handle::descriptor_set_layout(ctx, create_descriptor_set_layout(ctx)?);
+        let descriptor_pool =
+            handle::descriptor_pool(ctx, create_descriptor_pool(ctx, image_count)?);
+        let render_pass = handle::render_pass(ctx, create_render_pass(ctx)?);
+
+        let bright_pipeline = handle::pipeline(
+            ctx,
+            create_fullscreen_pipeline(
+                ctx,
+                *render_pass,
+                include_spirv!("shader/bloom_bright_frag.glsl", glsl, frag),
+                &[*descriptor_set_layout],
+                &[fragment_push_constant_range::<BrightConstants>()],
+            )?,
+        );
+
+        let blur_pipeline = create_fullscreen_pipeline(
+            ctx,
+            *render_pass,
+            include_spirv!("shader/bloom_blur_frag.glsl", glsl, frag),
+            &[*descriptor_set_layout],
+            &[fragment_push_constant_range::<BlurConstants>()],
+        )?;
+
+        Ok(Self {
+            post_process,
+            render_pass: render_pass.release(),
+            bright_pipeline: bright_pipeline.release(),
+            blur_pipeline,
+            descriptor_set_layout: descriptor_set_layout.release(),
+            descriptor_pool: descriptor_pool.release(),
+            sampler: sampler.release(),
+        })
+    }
+
+    /// Descriptor sets are freed with the pool, only `PostProcessImage` needs to be destroyed
+    /// before.
+    pub fn destroy(&self, ctx: &Context) {
+        self.bright_pipeline.destroy(ctx);
+        self.blur_pipeline.destroy(ctx);
+        ctx.dp.destroy_render_pass(ctx.device, self.render_pass);
+        ctx.dp
+            .destroy_descriptor_pool(ctx.device, self.descriptor_pool);
+        ctx.dp
+            .destroy_descriptor_set_layout(ctx.device, self.descriptor_set_layout);
+        ctx.dp.destroy_sampler(ctx.device, self.sampler);
+    }
+
+    /// Bloom targets for an `hdr_target` of `render_extent`, which is sampled.
+    pub fn create_image(
+        &self,
+        ctx: &Context,
+        hdr_target: &RenderTarget,
+        render_extent: &vk::Extent2D,
+    ) -> Result<PostProcessImage> {
+        let extent = scaled_extent(render_extent, BLOOM_SCALE, ctx.limits.maxImageDimension2D);
+
+        let bloom = handle::render_target(
+            ctx,
+            RenderTarget::new(ctx, &extent, HDR_FORMAT, vk::IMAGE_USAGE_SAMPLED_BIT)?,
+        );
+        let blur = handle::render_target(
+            ctx,
+            RenderTarget::new(ctx, &extent, HDR_FORMAT, vk::IMAGE_USAGE_SAMPLED_BIT)?,
+        );
+
+        let set_layouts = [self.descriptor_set_layout; SETS_PER_IMAGE as usize];
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            sType: vk::STRUCTURE_TYPE_DESCRIPTOR_SET_ALLOCATE_INFO,
+            pNext: ptr::null(),
+            descriptorPool: self.descriptor_pool,
+            descriptorSetCount: SETS_PER_IMAGE,
+            pSetLayouts: set_layouts.as_ptr(),
+        };
+
+        let sets = unsafe { ctx.dp.allocate_descriptor_sets(ctx.device, &allocate_info) }
+            .map_err(to_vulkan)?;
+        let (bright_set, horizontal_set, vertical_set) = (sets[0], sets[1], sets[2]);
+
+        DescriptorWriter::default()
+            .write_image(bright_set, 0, hdr_target.image_view, self.sampler)
+            .write_image(horizontal_set, 0, bloom.image_view, self.sampler)
+            .write_image(vertical_set, 0, blur.image_view, self.sampler)
+            .flush(ctx);
+
+        let bloom_framebuffer = handle::framebuffer(
+            ctx,
+            create_framebuffer(
+                &ctx.dp,
+                ctx.device,
+                self.render_pass,
+                &[bloom.image_view],
+                &extent,
+            )?,
+        );
+        let blur_framebuffer = create_framebuffer(
+            &ctx.dp,
+            ctx.device,
+            self.render_pass,
+            &[blur.image_view],
+            &extent,
+        )?;
+
+        Ok(PostProcessImage {
+            bloom: bloom.release(),
+            blur: blur.release(),
+            bloom_framebuffer: bloom_framebuffer.release(),
+            blur_framebuffer,
+            bright_set,
+            horizontal_set,
+            vertical_set,
+        })
+    }
+
+    /// Records the bloom render passes, after the scene render pass and before the tonemap
+    /// pass: bright areas into `bloom`, blurred horizontally into `blur` and vertically back.
+    pub fn cmd_bloom(
+        &self,
+        ctx: &Context,
+        command_buffer: vk::CommandBuffer,
+        image: &PostProcessImage,
+    ) {
+        let extent = &image.bloom.extent;
+
+        let bright = BrightConstants {
+            threshold: self.post_process.threshold,
+        };
+        self.cmd_fullscreen_pass(
+            ctx,
+            command_buffer,
+            (&self.bright_pipeline, image.bloom_framebuffer),
+            image.bright_set,
+            extent,
+            as_bytes(&bright),
+        );
+
+        self.cmd_fullscreen_pass(
+            ctx,
+            command_buffer,
+            (&self.blur_pipeline, image.blur_framebuffer),
+            image.horizontal_set,
+            extent,
+            as_bytes(&BlurConstants::horizontal(extent)),
+        );
+
+        self.cmd_fullscreen_pass(
+            ctx,
+            command_buffer,
+            (&self.blur_pipeline, image.bloom_framebuffer),
+            image.vertical_set,
+            extent,
+            as_bytes(&BlurConstants::vertical(extent)),
+        );
+    }
+
+    fn cmd_fullscreen_pass(
+        &self,
+        ctx: &Context,
+        command_buffer: vk::CommandBuffer,
+        (pipeline, framebuffer): (&Pipeline, vk::Framebuffer),
+        descriptor_set: vk::DescriptorSet,
+        extent: &vk::Extent2D,
+        constants: &[u8],
+    ) {
+        let area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: copy_extent_2d(extent),
+        };
+
+        let info = vk::RenderPassBeginInfo {
+            sType: vk::STRUCTURE_TYPE_RENDER_PASS_BEGIN_INFO,
+            pNext: ptr::null(),
+            renderPass: self.render_pass,
+            framebuffer,
+            renderArea: copy_rect_2d(&area),
+            clearValueCount: 0,
+            pClearValues: ptr::null(),
+        };
+
+        unsafe {
+            ctx.dp
+                .cmd_begin_render_pass(command_buffer, &info, vk::SUBPASS_CONTENTS_INLINE)
+        };
+
+        ctx.cmd_set_viewport(command_buffer, &area);
+        ctx.cmd_bind_pipeline(pipeline, command_buffer);
+        ctx.dp.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PIPELINE_BIND_POINT_GRAPHICS,
+            pipeline.layout,
+            0,
+            &[descriptor_set],
+            &[],
+        );
+        ctx.dp.cmd_push_constants(
+            command_buffer,
+            pipeline.layout,
+            vk::SHADER_STAGE_FRAGMENT_BIT,
+            0,
+            constants,
+        );
+
+        ctx.cmd_draw_fullscreen_triangle(command_buffer);
+        ctx.dp.cmd_end_render_pass(command_buffer);
+    }
+}
+
+impl PostProcessImage {
+    pub fn destroy(&self, ctx: &Context) {
+        ctx.dp
+            .destroy_framebuffer(ctx.device, self.bloom_framebuffer);
+        ctx.dp
+            .destroy_framebuffer(ctx.device, self.blur_framebuffer);
+        self.bloom.destroy(ctx);
+        self.blur.destroy(ctx);
+    }
+}
+
+fn fragment_push_constant_range<T>() -> vk::PushConstantRange {
+    vk::PushConstantRange {
+        stageFlags: vk::SHADER_STAGE_FRAGMENT_BIT,
+        offset: 0,
+        size: size_of::<T>() as u32,
+    }
+}
+
+fn as_bytes<T>(constants: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(constants as *const T as *const u8, size_of::<T>()) }
+}
+
+/// Linear, so the bright pass averages the scene pixels it downsamples and the tonemap pass
+/// upsamples the bloom smoothly.
+fn create_sampler(ctx: &Context) -> Result<vk::Sampler> {
+    let info = vk::SamplerCreateInfo {
+        sType: vk::STRUCTURE_TYPE_SAMPLER_CREATE_INFO,
+        pNext: ptr::null(),
+        flags: 0,
+        magFilter: vk::FILTER_LINEAR,
+        minFilter: vk::FILTER_LINEAR,
+        mipmapMode: vk::SAMPLER_MIPMAP_MODE_NEAREST,
+        // the blur must not wrap around the screen edges
+        addressModeU: vk::SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE,
+        addressModeV: vk::SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE,
+        addressModeW: vk::SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE,
+        mipLodBias: 0.0,
+        anisotropyEnable: vk::FALSE,
+        maxAnisotropy: 1.0,
+        compareEnable: vk::FALSE,
+        compareOp: vk::COMPARE_OP_ALWAYS,
+        minLod: 0.0,
+        maxLod: 0.0,
+        borderColor: vk::BORDER_COLOR_FLOAT_TRANSPARENT_BLACK,
+        unnormalizedCoordinates: vk::FALSE,
+    };
+
+    unsafe { ctx.dp.create_sampler(ctx.device, &info) }.map_err(to_vulkan)
+}
+
+fn create_descriptor_set_layout(ctx: &Context) -> Result<vk::DescriptorSetLayout> {
+    let binding = vk::DescriptorSetLayoutBinding {
+        binding: 0,
+        descriptorType: vk::DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+        descriptorCount: 1,
+        stageFlags: vk::SHADER_STAGE_FRAGMENT_BIT,
+        pImmutableSamplers: ptr::null(),
+    };
+
+    let info = vk::DescriptorSetLayoutCreateInfo {
+        sType: vk::STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+        pNext: ptr::null(),
+        flags: 0,
+        bindingCount: 1,
+        pBindings: &binding,
+    };
+
+    unsafe { ctx.dp.create_descriptor_set_layout(ctx.device, &info) }.map_err(to_vulkan)
+}
+
+/// `SETS_PER_IMAGE` sets per swapchain image
+fn create_descriptor_pool(ctx: &Context, image_count: u32) -> Result<vk::DescriptorPool> {
+    let pool_size = vk::DescriptorPoolSize {
+        ty: vk::DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+        descriptorCount: SETS_PER_IMAGE * image_count,
+    };
+
+    let info = vk::DescriptorPoolCreateInfo {
+        sType: vk::STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO,
+        pNext: ptr::null(),
+        flags: 0,
+        maxSets: SETS_PER_IMAGE * image_count,
+        poolSizeCount: 1,
+        pPoolSizes: &pool_size,
+    };
+
+    unsafe { ctx.dp.create_descriptor_pool(ctx.device, &info) }.map_err(to_vulkan)
+}
+
+/// One HDR color attachment, sampled by the next pass. Its previous content is overwritten by
+/// the fullscreen triangle.
+fn create_render_pass(ctx: &Context) -> Result<vk::RenderPass> {
+    let color_attachment_desc = vk::AttachmentDescription {
+        flags: 0,
+        format: HDR_FORMAT,
+        samples: vk::SAMPLE_COUNT_1_BIT,
+        loadOp: vk::ATTACHMENT_LOAD_OP_DONT_CARE,
+        storeOp: vk::ATTACHMENT_STORE_OP_STORE,
+        stencilLoadOp: vk::ATTACHMENT_LOAD_OP_DONT_CARE,
+        stencilStoreOp: vk::ATTACHMENT_STORE_OP_DONT_CARE,
+        initialLayout: vk::IMAGE_LAYOUT_UNDEFINED,
+        finalLayout: vk::IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+    };
+
+    let color_attachment_ref = vk::AttachmentReference {
+        attachment: 0,
+        layout: vk::IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+    };
+
+    let subpass_desc = vk::SubpassDescription {
+        flags: 0,
+        pipelineBindPoint: vk::PIPELINE_BIND_POINT_GRAPHICS,
+        inputAttachmentCount: 0,
+        pInputAttachments: ptr::null(),
+        colorAttachmentCount: 1,
+        pColorAttachments: &color_attachment_ref,
+        pResolveAttachments: ptr::null(),
+        pDepthStencilAttachment: ptr::null(),
+        preserveAttachmentCount: 0,
+        pPreserveAttachments: ptr::null(),
+    };
+
+    let subpass_deps = [
+        // the target was sampled (horizontal blur reads `bloom`) or written by the previous pass
+        vk::SubpassDependency {
+            srcSubpass: vk::SUBPASS_EXTERNAL,
+            dstSubpass: 0,
+            srcStageMask: vk::PIPELINE_STAGE_FRAGMENT_SHADER_BIT
+                | vk::PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
+            dstStageMask: vk::PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
+            srcAccessMask: vk::ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
+            dstAccessMask: vk::ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
+            dependencyFlags: 0,
+        },
+        // the next pass samples what was written
+        vk::SubpassDependency {
+            srcSubpass: 0,
+            dstSubpass: vk::SUBPASS_EXTERNAL,
+            srcStageMask: vk::PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
+            dstStageMask: vk::PIPELINE_STAGE_FRAGMENT_SHADER_BIT,
+            srcAccessMask: vk::ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
+            dstAccessMask: vk::ACCESS_SHADER_READ_BIT,
+            dependencyFlags: 0,
+        },
+    ];
+
+    let render_pass_info = vk::RenderPassCreateInfo {
+        sType: vk::STRUCTURE_TYPE_RENDER_PASS_CREATE_INFO,
+        pNext: ptr::null(),
+        flags: 0,
+        attachmentCount: 1,
+        pAttachments: &color_attachment_desc,
+        subpassCount: 1,
+        pSubpasses: &subpass_desc,
+        dependencyCount: subpass_deps.len() as u32,
+        pDependencies: subpass_deps.as_ptr(),
+    };
+
+    unsafe { ctx.dp.create_render_pass(ctx.device, &render_pass_info) }.map_err(to_vulkan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_match_the_shaders() {
+        assert_eq!(size_of::<BrightConstants>(), 4);
+        assert_eq!(size_of::<BlurConstants>(), 8);
+    }
+
+    #[test]
+    fn blur_steps_one_texel() {
+        let extent = vk::Extent2D {
+            width: 400,
+            height: 200,
+        };
+
+        assert_eq!(BlurConstants::horizontal(&extent).texel_step, [0.0025, 0.0]);
+        assert_eq!(BlurConstants::vertical(&extent).texel_step, [0.0, 0.005]);
+    }
+
+    #[test]
+    fn negative_settings_are_rejected() {
+        assert!(check_post_process(&PostProcess::default()).is_ok());
+        assert!(check_post_process(&PostProcess {
+            threshold: -1.0,
+            intensity: 0.5,
+        })
+        .is_err());
+        assert!(check_post_process(&PostProcess {
+            threshold: 1.0,
+            intensity: f32::NAN,
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,287 @@
+//! Unit sized geometry for testing pipelines and building voxel faces, centered at the origin.
+//!
+//! Faces are counter-clockwise seen from outside in a y-up space, which a projection into the
+//! y-down framebuffer turns into the clockwise front face of the pipelines. `uv` `(0, 0)` is the
+//! top left of a face, seen from outside.
+
+use super::vertex::{MeshVertex, Vertex};
+use glm::{Vec2, Vec3};
+
+/// Quad in the xy plane, facing +z.
+pub fn quad() -> (Vec<MeshVertex>, Vec<u32>) {
+    let mut vertices = Vec::with_capacity(4);
+    let mut indices = Vec::with_capacity(6);
+
+    push_face(
+        &mut vertices,
+        &mut indices,
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        0,
+    );
+
+    (vertices, indices)
+}
+
+/// Cube from -0.5 to 0.5, 4 vertices per face, so every face has its own normal and uvs.
+pub fn cube() -> (Vec<MeshVertex>, Vec<u32>) {
+    textured_cube([0; 6])
+}
+
+/// `cube` with a texture layer per face, in the order +x, -x, +y, -y, +z, -z.
+pub fn textured_cube(tex_ids: [u32; 6]) -> (Vec<MeshVertex>, Vec<u32>) {
+    // (center, u, v) with u x v pointing outwards
+    let faces = [
+        // +x
+        (
+            Vec3::new(0.5, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ),
+        // -x
+        (
+            Vec3::new(-0.5, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ),
+        // +y
+        (
+            Vec3::new(0.0, 0.5, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        ),
+        // -y
+        (
+            Vec3::new(0.0, -0.5, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ),
+        // +z
+        (
+            Vec3::new(0.0, 0.0, 0.5),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ),
+        // -z
+        (
+            Vec3::new(0.0, 0.0, -0.5),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ),
+    ];
+
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+
+    for ((center, u, v), tex_id) in faces.iter().zip(tex_ids.iter()) {
+        push_face(&mut vertices, &mut indices, *center, *u, *v, *tex_id);
+    }
+
+    (vertices, indices)
+}
+
+/// Plane in the xz plane, facing +y, split into `cells` x `cells` quads. `uv` spans the whole
+/// plane once.
+pub fn grid_plane(cells: u32) -> (Vec<MeshVertex>, Vec<u32>) {
+    let cells = cells.max(1);
+    let row = cells + 1;
+
+    let mut vertices = Vec::with_capacity((row * row) as usize);
+    let mut indices = Vec::with_capacity((cells * cells * 6) as usize);
+
+    // rows run along -z, like v of the +y cube face
+    for j in 0..row {
+        for i in 0..row {
+            let s = i as f32 / cells as f32;
+            let t = j as f32 / cells as f32;
+
+            vertices.push(MeshVertex {
+                pos: Vec3::new(s - 0.5, 0.0, 0.5 - t),
+                normal: Vec3::new(0.0, 1.0, 0.0),
+                uv: Vec2::new(s, 1.0 - t),
+                tex_id: 0,
+            });
+        }
+    }
+
+    for j in 0..cells {
+        for i in 0..cells {
+            let a = j * row + i;
+            let d = a + row;
+
+            indices.extend_from_slice(&[a, a + 1, d + 1, a, d + 1, d]);
+        }
+    }
+
+    (vertices, indices)
+}
+
+/// `count` triangles for the scene pipeline (a triangle list), filling the viewport row by row
+/// in a square grid of cells with two triangles each. Colored by position, to tell them apart.
+pub fn triangle_grid(count: u32) -> Vec<Vertex> {
+    let cells = ((count as f32 / 2.0).sqrt().ceil() as u32).max(1);
+    let cell_size = 2.0 / cells as f32;
+
+    let mut vertices = Vec::with_capacity(count as usize * 3);
+
+    'grid: for row in 0..cells {
+        for column in 0..cells {
+            let x0 = -1.0 + column as f32 * cell_size;
+            let y0 = -1.0 + row as f32 * cell_size;
+            let (x1, y1) = (x0 + cell_size, y0 + cell_size);
+            let color = Vec3::new(column as f32 / cells as f32, row as f32 / cells as f32, 0.5);
+
+            // clockwise on screen, y points down
+            for triangle in &[
+                [(x0, y0), (x1, y0), (x1, y1)],
+                [(x0, y0), (x1, y1), (x0, y1)],
+            ] {
+                if vertices.len() == count as usize * 3 {
+                    break 'grid;
+                }
+
+                vertices.extend(triangle.iter().map(|&(x, y)| Vertex {
+                    pos: Vec2::new(x, y),
+                    color,
+                }));
+            }
+        }
+    }
+
+    vertices
+}
+
+/// Unit square around `center`, spanned by the unit vectors `u` and `v`, facing `u x v`.
+fn push_face(
+    vertices: &mut Vec<MeshVertex>,
+    indices: &mut Vec<u32>,
+    center: Vec3,
+    u: Vec3,
+    v: Vec3,
+    tex_id: u32,
+) {
+    let normal = Vec3::new(
+        u.y * v.z - u.z * v.y,
+        u.z * v.x - u.x * v.z,
+        u.x * v.y - u.y * v.x,
+    );
+
+    let first = vertices.len() as u32;
+
+    // counter-clockwise around the normal, starting bottom left
+    for &(s, t) in [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)].iter() {
+        vertices.push(MeshVertex {
+            pos: Vec3::new(
+                center.x + u.x * s + v.x * t,
+                center.y + u.y * s + v.y * t,
+                center.z + u.z * s + v.z * t,
+            ),
+            normal,
+            uv: Vec2::new(s + 0.5, 0.5 - t),
+            tex_id,
+        });
+    }
+
+    indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cross(a: Vec3, b: Vec3) -> Vec3 {
+        Vec3::new(
+            a.y * b.z - a.z * b.y,
+            a.z * b.x - a.x * b.z,
+            a.x * b.y - a.y * b.x,
+        )
+    }
+
+    /// Every triangle winds counter-clockwise around the normal of its vertices.
+    fn assert_counter_clockwise(vertices: &[MeshVertex], indices: &[u32]) {
+        for triangle in indices.chunks(3) {
+            let a = vertices[triangle[0] as usize];
+            let b = vertices[triangle[1] as usize];
+            let c = vertices[triangle[2] as usize];
+
+            let facing = cross(b.pos - a.pos, c.pos - a.pos);
+            assert!(glm::dot(facing, a.normal) > 0.0, "{:?}", triangle);
+        }
+    }
+
+    #[test]
+    fn quad_faces_positive_z() {
+        let (vertices, indices) = quad();
+
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices.len(), 6);
+        assert!(vertices
+            .iter()
+            .all(|vertex| vertex.pos.z == 0.0 && vertex.normal == Vec3::new(0.0, 0.0, 1.0)));
+        assert_counter_clockwise(&vertices, &indices);
+    }
+
+    #[test]
+    fn cube_faces_point_outwards() {
+        let (vertices, indices) = textured_cube([0, 1, 2, 3, 4, 5]);
+
+        assert_eq!(vertices.len(), 24);
+        assert_eq!(indices.len(), 36);
+        for vertex in &vertices {
+            assert!(glm::dot(vertex.pos, vertex.normal) > 0.0);
+            assert!(vertex.uv.x >= 0.0 && vertex.uv.x <= 1.0);
+            assert!(vertex.uv.y >= 0.0 && vertex.uv.y <= 1.0);
+        }
+        assert_counter_clockwise(&vertices, &indices);
+
+        // +x, -x, +y, -y, +z, -z
+        assert_eq!(vertices[0].normal, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(vertices[4 * 3].normal, Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(vertices[4 * 3].tex_id, 3);
+    }
+
+    #[test]
+    fn grid_plane_counts() {
+        let (vertices, indices) = grid_plane(3);
+
+        assert_eq!(vertices.len(), 16);
+        assert_eq!(indices.len(), 3 * 3 * 6);
+        assert!(indices.iter().all(|&index| index < 16));
+        assert!(vertices.iter().all(|vertex| vertex.pos.y == 0.0));
+        assert_counter_clockwise(&vertices, &indices);
+    }
+
+    #[test]
+    fn grid_plane_has_at_least_one_cell() {
+        let (vertices, indices) = grid_plane(0);
+
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices.len(), 6);
+    }
+
+    #[test]
+    fn triangle_grid_has_count_triangles() {
+        for &count in &[1, 2, 5, 8, 1000] {
+            let vertices = triangle_grid(count);
+
+            assert_eq!(vertices.len(), count as usize * 3);
+            assert!(vertices
+                .iter()
+                .all(|vertex| vertex.pos.x.abs() <= 1.0 && vertex.pos.y.abs() <= 1.0));
+        }
+
+        assert!(triangle_grid(0).is_empty());
+    }
+
+    #[test]
+    fn triangle_grid_is_clockwise_on_screen() {
+        for triangle in triangle_grid(18).chunks(3) {
+            let (a, b, c) = (triangle[0].pos, triangle[1].pos, triangle[2].pos);
+
+            // positive with y pointing down
+            let area = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+            assert!(area > 0.0);
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Offscreen color images the scene is rendered into, instead of the swapchain image.
+
+use std::ptr;
+
+use super::{
+    error::to_vulkan, handle, swapchain::create_image_view, swapchain::find_memory_type, Context,
+    Result,
+};
+use vk_sys as vk;
+
+pub struct RenderTarget {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub image_view: vk::ImageView,
+    pub extent: vk::Extent2D,
+}
+
+impl RenderTarget {
+    /// Device local color target, `usage` is added to `COLOR_ATTACHMENT`.
+    pub fn new(
+        ctx: &Context,
+        extent: &vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+    ) -> Result<Self> {
+        let info = vk::ImageCreateInfo {
+            sType: vk::STRUCTURE_TYPE_IMAGE_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            imageType: vk::IMAGE_TYPE_2D,
+            format,
+            extent: vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            mipLevels: 1,
+            arrayLayers: 1,
+            samples: vk::SAMPLE_COUNT_1_BIT,
+            tiling: vk::IMAGE_TILING_OPTIMAL,
+            usage: vk::IMAGE_USAGE_COLOR_ATTACHMENT_BIT | usage,
+            sharingMode: vk::SHARING_MODE_EXCLUSIVE,
+            queueFamilyIndexCount: 0,
+            pQueueFamilyIndices: ptr::null(),
+            initialLayout: vk::IMAGE_LAYOUT_UNDEFINED,
+        };
+
+        let image = handle::image(
+            ctx,
+            unsafe { ctx.dp.create_image(ctx.device, &info) }.map_err(to_vulkan)?,
+        );
+
+        let memory_requirements = ctx.dp.get_image_memory_requirements(ctx.device, *image);
+        let allocate_info = vk::MemoryAllocateInfo {
+            sType: vk::STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
+            pNext: ptr::null(),
+            allocationSize: memory_requirements.size,
+            memoryTypeIndex: find_memory_type(
+                ctx,
+                memory_requirements.memoryTypeBits,
+                vk::MEMORY_PROPERTY_DEVICE_LOCAL_BIT,
+            )?,
+        };
+
+        let memory = handle::memory(
+            ctx,
+            unsafe { ctx.dp.allocate_memory(ctx.device, &allocate_info) }.map_err(to_vulkan)?,
+        );
+
+        ctx.dp
+            .bind_image_memory(ctx.device, *image, *memory, 0)
+            .map_err(to_vulkan)?;
+
+        let image_view = create_image_view(&ctx.dp, ctx.device, *image, format)?;
+
+        Ok(Self {
+            image: image.release(),
+            memory: memory.release(),
+            image_view,
+            extent: vk::Extent2D {
+                width: extent.width,
+                height: extent.height,
+            },
+        })
+    }
+
+    pub fn destroy(&self, ctx: &Context) {
+        ctx.dp.destroy_image_view(ctx.device, self.image_view);
+        ctx.dp.destroy_image(ctx.device, self.image);
+        ctx.dp.free_memory(ctx.device, self.memory);
+    }
+
+    /// Blits the whole target (in `TRANSFER_SRC_OPTIMAL`) onto a swapchain image with linear
+    /// filtering and leaves the swapchain image ready for presentation.
+    pub fn cmd_blit_to_swapchain_image(
+        &self,
+        ctx: &Context,
+        command_buffer: vk::CommandBuffer,
+        swapchain_image: vk::Image,
+        swapchain_extent: &vk::Extent2D,
+    ) {
+        // the previous content is not needed, the blit covers the whole image
+        cmd_transition_color_image(
+            ctx,
+            command_buffer,
+            swapchain_image,
+            (
+                vk::IMAGE_LAYOUT_UNDEFINED,
+                vk::IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+            ),
+            (0, vk::ACCESS_TRANSFER_WRITE_BIT),
+            (
+                vk::PIPELINE_STAGE_TRANSFER_BIT,
+                vk::PIPELINE_STAGE_TRANSFER_BIT,
+            ),
+        );
+
+        let region = vk::ImageBlit {
+            srcSubresource: color_subresource_layers(),
+            srcOffsets: [
+                vk::Offset3D { x: 0, y: 0, z: 0 },
+                vk::Offset3D {
+                    x: self.extent.width as i32,
+                    y: self.extent.height as i32,
+                    z: 1,
+                },
+            ],
+            dstSubresource: color_subresource_layers(),
+            dstOffsets: [
+                vk::Offset3D { x: 0, y: 0, z: 0 },
+                vk::Offset3D {
+                    x: swapchain_extent.width as i32,
+                    y: swapchain_extent.height as i32,
+                    z: 1,
+                },
+            ],
+        };
+
+        ctx.dp.cmd_blit_image(
+            command_buffer,
+            self.image,
+            vk::IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+            swapchain_image,
+            vk::IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+            &[region],
+            vk::FILTER_LINEAR,
+        );
+
+        cmd_transition_color_image(
+            ctx,
+            command_buffer,
+            swapchain_image,
+            (
+                vk::IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+                vk::IMAGE_LAYOUT_PRESENT_SRC_KHR,
+            ),
+            (vk::ACCESS_TRANSFER_WRITE_BIT, 0),
+            (
+                vk::PIPELINE_STAGE_TRANSFER_BIT,
+                vk::PIPELINE_STAGE_BOTTOM_OF_PIPE_BIT,
+            ),
+        );
+    }
+}
+
+/// Two targets for temporal effects (e.g. TAA): one is rendered into while the other holds the
+/// previous frame and is sampled as history. `swap` once per frame turns the current target
+/// into the history of the next frame.
+pub struct HistoryTargets {
+    targets: [RenderTarget; 2],
+    current: usize,
+}
+
+impl HistoryTargets {
+    /// Both targets with `SAMPLED` usage besides `usage`, so either can be the history.
+    pub fn new(
+        ctx: &Context,
+        extent: &vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+    ) -> Result<Self> {
+        let usage = usage | vk::IMAGE_USAGE_SAMPLED_BIT;
+        let first = handle::render_target(ctx, RenderTarget::new(ctx, extent, format, usage)?);
+        let second = RenderTarget::new(ctx, extent, format, usage)?;
+
+        Ok(Self {
+            targets: [first.release(), second],
+            current: 0,
+        })
+    }
+
+    pub fn destroy(&self, ctx: &Context) {
+        for target in &self.targets {
+            target.destroy(ctx);
+        }
+    }
+
+    /// Target of this frame.
+    pub fn current(&self) -> &RenderTarget {
+        &self.targets[self.current]
+    }
+
+    /// Target of the previous frame.
+    pub fn history(&self) -> &RenderTarget {
+        &self.targets[1 - self.current]
+    }
+
+    /// View of the previous frame, to bind as sampled image.
+    pub fn history_view(&self) -> vk::ImageView {
+        self.history().image_view
+    }
+
+    /// Index of the current target, `0` or `1`, e.g. to pick a framebuffer or descriptor set
+    /// prepared per target.
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    /// Called at the end of a frame.
+    pub fn swap(&mut self) {
+        self.current = 1 - self.current;
+    }
+}
+
+/// `(old, new)` layout, `(src, dst)` access and stage masks
+pub fn cmd_transition_color_image(
+    ctx: &Context,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    (old_layout, new_layout): (vk::ImageLayout, vk::ImageLayout),
+    (src_access_mask, dst_access_mask): (vk::AccessFlags, vk::AccessFlags),
+    (src_stage_mask, dst_stage_mask): (vk::PipelineStageFlags, vk::PipelineStageFlags),
+) {
+    let barrier = vk::ImageMemoryBarrier {
+        sType: vk::STRUCTURE_TYPE_IMAGE_MEMORY_BARRIER,
+        pNext: ptr::null(),
+        srcAccessMask: src_access_mask,
+        dstAccessMask: dst_access_mask,
+        oldLayout: old_layout,
+        newLayout: new_layout,
+        srcQueueFamilyIndex: vk::QUEUE_FAMILY_IGNORED,
+        dstQueueFamilyIndex: vk::QUEUE_FAMILY_IGNORED,
+        image,
+        subresourceRange: vk::ImageSubresourceRange {
+            aspectMask: vk::IMAGE_ASPECT_COLOR_BIT,
+            baseMipLevel: 0,
+            levelCount: 1,
+            baseArrayLayer: 0,
+            layerCount: 1,
+        },
+    };
+
+    ctx.dp.cmd_pipeline_barrier(
+        command_buffer,
+        src_stage_mask,
+        dst_stage_mask,
+        0,
+        &[],
+        &[],
+        &[barrier],
+    );
+}
+
+fn color_subresource_layers() -> vk::ImageSubresourceLayers {
+    vk::ImageSubresourceLayers {
+        aspectMask: vk::IMAGE_ASPECT_COLOR_BIT,
+        mipLevel: 0,
+        baseArrayLayer: 0,
+        layerCount: 1,
+    }
+}
+
+/// `extent * scale`, at least 1x1 and at most what images on the device can be
+pub fn scaled_extent(extent: &vk::Extent2D, scale: f32, max_dimension: u32) -> vk::Extent2D {
+    let scale_dimension = |dimension: u32| {
+        ((dimension as f32 * scale).round() as u32)
+            .max(1)
+            .min(max_dimension)
+    };
+
+    vk::Extent2D {
+        width: scale_dimension(extent.width),
+        height: scale_dimension(extent.height),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_scaled(width: u32, height: u32, scale: f32, expected: (u32, u32)) {
+        let scaled = scaled_extent(&vk::Extent2D { width, height }, scale, 16384);
+
+        assert_eq!((scaled.width, scaled.height), expected);
+    }
+
+    #[test]
+    fn scales_and_rounds() {
+        assert_scaled(1920, 1080, 0.5, (960, 540));
+        assert_scaled(1280, 720, 0.667, (854, 480));
+        assert_scaled(800, 600, 1.5, (1200, 900));
+    }
+
+    #[test]
+    fn keeps_at_least_one_pixel() {
+        assert_scaled(640, 480, 0.0001, (1, 1));
+    }
+
+    #[test]
+    fn clamps_to_the_max_dimension() {
+        assert_scaled(8192, 1024, 4.0, (16384, 4096));
+    }
+
+    /// No image, only `image_view` tells the targets apart.
+    fn target(image_view: vk::ImageView) -> RenderTarget {
+        RenderTarget {
+            image: vk::NULL_HANDLE,
+            memory: vk::NULL_HANDLE,
+            image_view,
+            extent: vk::Extent2D {
+                width: 1,
+                height: 1,
+            },
+        }
+    }
+
+    #[test]
+    fn history_is_the_previous_current() {
+        let mut targets = HistoryTargets {
+            targets: [target(1), target(2)],
+            current: 0,
+        };
+        assert_eq!(targets.current().image_view, 1);
+        assert_eq!(targets.history_view(), 2);
+
+        targets.swap();
+        assert_eq!(targets.current_index(), 1);
+        assert_eq!(targets.current().image_view, 2);
+        assert_eq!(targets.history_view(), 1);
+
+        targets.swap();
+        assert_eq!(targets.current_index(), 0);
+        assert_eq!(targets.history().image_view, 2);
+    }
+}
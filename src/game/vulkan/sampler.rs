@@ -0,0 +1,197 @@
+//! Samplers for textures, configured per texture.
+
+use std::ptr;
+
+use super::{error::to_vulkan, Context, Error, Result};
+use log::warn;
+use vk_sys as vk;
+
+const MIRROR_CLAMP_TO_EDGE_EXTENSION: &str = "VK_KHR_sampler_mirror_clamp_to_edge";
+
+/// What a texture coordinate outside of `0.0..=1.0` samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressMode {
+    /// tiling textures
+    Repeat,
+    MirroredRepeat,
+    /// block edges in an atlas, no bleeding from the neighbour tile
+    ClampToEdge,
+    /// needs the `VK_KHR_sampler_mirror_clamp_to_edge` device extension
+    MirrorClampToEdge,
+}
+
+impl AddressMode {
+    pub fn to_vk(self) -> vk::SamplerAddressMode {
+        match self {
+            AddressMode::Repeat => vk::SAMPLER_ADDRESS_MODE_REPEAT,
+            AddressMode::MirroredRepeat => vk::SAMPLER_ADDRESS_MODE_MIRRORED_REPEAT,
+            AddressMode::ClampToEdge => vk::SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE,
+            AddressMode::MirrorClampToEdge => vk::SAMPLER_ADDRESS_MODE_MIRROR_CLAMP_TO_EDGE,
+        }
+    }
+}
+
+impl Default for AddressMode {
+    fn default() -> Self {
+        AddressMode::Repeat
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SamplerOptions {
+    pub address_mode_u: AddressMode,
+    pub address_mode_v: AddressMode,
+    pub address_mode_w: AddressMode,
+    /// sharper textures at oblique angles, clamped to `maxSamplerAnisotropy`. `None` or a device
+    /// without `samplerAnisotropy` samples isotropic.
+    pub max_anisotropy: Option<f32>,
+    /// added to the mip level, negative sharpens, positive softens. At most
+    /// `maxSamplerLodBias` in either direction.
+    pub mip_lod_bias: f32,
+}
+
+impl SamplerOptions {
+    /// Same address mode in all directions.
+    pub fn with_address_mode(address_mode: AddressMode) -> Self {
+        Self {
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            ..Default::default()
+        }
+    }
+
+    fn address_modes(&self) -> [AddressMode; 3] {
+        [
+            self.address_mode_u,
+            self.address_mode_v,
+            self.address_mode_w,
+        ]
+    }
+}
+
+/// Nearest filtering, block textures are pixel art.
+pub fn create_sampler(ctx: &Context, options: &SamplerOptions) -> Result<vk::Sampler> {
+    check_address_modes(options, &ctx.device_extensions)?;
+
+    check_mip_lod_bias(options.mip_lod_bias, ctx.limits.maxSamplerLodBias)?;
+
+    let max_anisotropy = options.max_anisotropy.and_then(|anisotropy| {
+        clamp_anisotropy(
+            anisotropy,
+            ctx.enabled_features.samplerAnisotropy == vk::TRUE,
+            ctx.limits.maxSamplerAnisotropy,
+        )
+    });
+
+    let info = vk::SamplerCreateInfo {
+        sType: vk::STRUCTURE_TYPE_SAMPLER_CREATE_INFO,
+        pNext: ptr::null(),
+        flags: 0,
+        magFilter: vk::FILTER_NEAREST,
+        minFilter: vk::FILTER_NEAREST,
+        mipmapMode: vk::SAMPLER_MIPMAP_MODE_NEAREST,
+        addressModeU: options.address_mode_u.to_vk(),
+        addressModeV: options.address_mode_v.to_vk(),
+        addressModeW: options.address_mode_w.to_vk(),
+        mipLodBias: options.mip_lod_bias,
+        anisotropyEnable: if max_anisotropy.is_some() {
+            vk::TRUE
+        } else {
+            vk::FALSE
+        },
+        maxAnisotropy: max_anisotropy.unwrap_or(1.0),
+        compareEnable: vk::FALSE,
+        compareOp: vk::COMPARE_OP_ALWAYS,
+        minLod: 0.0,
+        maxLod: 0.0,
+        borderColor: vk::BORDER_COLOR_FLOAT_TRANSPARENT_BLACK,
+        unnormalizedCoordinates: vk::FALSE,
+    };
+
+    unsafe { ctx.dp.create_sampler(ctx.device, &info) }.map_err(to_vulkan)
+}
+
+/// `anisotropy` within `1.0..=max_anisotropy`, `None` if the device can't filter anisotropic.
+fn clamp_anisotropy(anisotropy: f32, supported: bool, max_anisotropy: f32) -> Option<f32> {
+    if !supported {
+        warn!("anisotropic filtering requested, but not supported by the device");
+        return None;
+    }
+
+    Some(anisotropy.max(1.0).min(max_anisotropy))
+}
+
+fn check_mip_lod_bias(mip_lod_bias: f32, max_lod_bias: f32) -> Result<()> {
+    if mip_lod_bias.abs() > max_lod_bias {
+        return Err(Error::Other(format!(
+            "mip lod bias {} exceeds the device limit {}",
+            mip_lod_bias, max_lod_bias
+        )));
+    }
+
+    Ok(())
+}
+
+fn check_address_modes(options: &SamplerOptions, device_extensions: &[String]) -> Result<()> {
+    let mirror_clamp_to_edge = options
+        .address_modes()
+        .iter()
+        .any(|mode| *mode == AddressMode::MirrorClampToEdge);
+
+    if mirror_clamp_to_edge
+        && !device_extensions
+            .iter()
+            .any(|extension| extension == MIRROR_CLAMP_TO_EDGE_EXTENSION)
+    {
+        return Err(Error::Other(format!(
+            "address mode MirrorClampToEdge needs the device extension {}",
+            MIRROR_CLAMP_TO_EDGE_EXTENSION
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_address_mode_in_all_directions() {
+        let options = SamplerOptions::with_address_mode(AddressMode::ClampToEdge);
+
+        assert_eq!(options.address_modes(), [AddressMode::ClampToEdge; 3]);
+        assert_eq!(options.max_anisotropy, None);
+    }
+
+    #[test]
+    fn mirror_clamp_to_edge_needs_its_extension() {
+        let options = SamplerOptions {
+            address_mode_v: AddressMode::MirrorClampToEdge,
+            ..Default::default()
+        };
+
+        assert!(check_address_modes(&options, &[]).is_err());
+        assert!(
+            check_address_modes(&options, &[MIRROR_CLAMP_TO_EDGE_EXTENSION.to_owned()]).is_ok()
+        );
+        assert!(check_address_modes(&SamplerOptions::default(), &[]).is_ok());
+    }
+
+    #[test]
+    fn anisotropy_within_device_limit() {
+        assert_eq!(clamp_anisotropy(8.0, true, 16.0), Some(8.0));
+        assert_eq!(clamp_anisotropy(32.0, true, 16.0), Some(16.0));
+        assert_eq!(clamp_anisotropy(0.5, true, 16.0), Some(1.0));
+        assert_eq!(clamp_anisotropy(8.0, false, 16.0), None);
+    }
+
+    #[test]
+    fn mip_lod_bias_within_device_limit() {
+        assert!(check_mip_lod_bias(0.0, 2.0).is_ok());
+        assert!(check_mip_lod_bias(-2.0, 2.0).is_ok());
+        assert!(check_mip_lod_bias(2.5, 2.0).is_err());
+        assert!(check_mip_lod_bias(-2.5, 2.0).is_err());
+    }
+}
@@ -0,0 +1,238 @@
+//! Uniforms of the scene pipeline, e.g. the camera's model view projection. Each swapchain image
+//! has its own buffer, written before its frame is submitted. The buffer holds the uniforms of
+//! every viewport, selected by dynamic offset when the viewport is drawn.
+//!
+//! The buffers are keyed by swapchain image rather than by frame in flight: the command buffers
+//! binding them are recorded once per image, so each image's command buffer binds its own
+//! buffer. `render_frame` waits for the fence of the image's last frame before writing, which
+//! is what keeps a buffer from being overwritten while the GPU reads it. Frames in flight never
+//! share an image, so they never share a buffer.
+
+use std::{mem::size_of, ptr};
+
+use super::{
+    error::to_vulkan, handle, uniform::DynamicUniformBuffer, Context, DescriptorWriter, Result,
+};
+use glm::{Mat4, Vec4};
+use vk_sys as vk;
+
+/// Uniform block of the scene vertex shader at set 0, binding 0, std140 compatible.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SceneUniforms {
+    pub mvp: Mat4,
+}
+
+impl Default for SceneUniforms {
+    /// the scene vertices are drawn as they are
+    fn default() -> Self {
+        Self { mvp: identity() }
+    }
+}
+
+pub struct SceneUniformSets {
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+}
+
+/// Buffer and descriptor set of one swapchain image.
+pub struct SceneUniformImage {
+    /// one element per viewport
+    uniforms: DynamicUniformBuffer,
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+impl SceneUniformSets {
+    pub fn new(ctx: &Context, image_count: u32) -> Result<Self> {
+        let descriptor_set_layout =
+            handle::descriptor_set_layout(ctx, create_descriptor_set_layout(ctx)?);
+        let descriptor_pool =
+            handle::descriptor_pool(ctx, create_descriptor_pool(ctx, image_count)?);
+
+        Ok(Self {
+            descriptor_set_layout: descriptor_set_layout.release(),
+            descriptor_pool: descriptor_pool.release(),
+        })
+    }
+
+    /// Descriptor sets are freed with the pool, only the buffers of `SceneUniformImage` need to
+    /// be destroyed before.
+    pub fn destroy(&self, ctx: &Context) {
+        ctx.dp
+            .destroy_descriptor_pool(ctx.device, self.descriptor_pool);
+        ctx.dp
+            .destroy_descriptor_set_layout(ctx.device, self.descriptor_set_layout);
+    }
+
+    pub fn create_image(&self, ctx: &Context, viewport_count: u32) -> Result<SceneUniformImage> {
+        let uniforms = handle::dynamic_uniform_buffer(
+            ctx,
+            DynamicUniformBuffer::new(ctx, size_of::<SceneUniforms>(), viewport_count)?,
+        );
+
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            sType: vk::STRUCTURE_TYPE_DESCRIPTOR_SET_ALLOCATE_INFO,
+            pNext: ptr::null(),
+            descriptorPool: self.descriptor_pool,
+            descriptorSetCount: 1,
+            pSetLayouts: &self.descriptor_set_layout,
+        };
+
+        let descriptor_set = unsafe { ctx.dp.allocate_descriptor_sets(ctx.device, &allocate_info) }
+            .map_err(to_vulkan)?[0];
+
+        let buffer_info = uniforms.descriptor_buffer_info();
+        DescriptorWriter::default()
+            .write_buffer_of_type(
+                descriptor_set,
+                0,
+                vk::DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC,
+                buffer_info.buffer,
+                buffer_info.range,
+            )
+            .flush(ctx);
+
+        let image = SceneUniformImage {
+            uniforms: uniforms.release(),
+            descriptor_set,
+        };
+        image.write(
+            ctx,
+            &vec![SceneUniforms::default(); viewport_count as usize],
+        )?;
+
+        Ok(image)
+    }
+}
+
+impl SceneUniformImage {
+    /// Uniforms of each viewport, in order. The frame of the image must not be in flight.
+    pub fn write(&self, ctx: &Context, uniforms: &[SceneUniforms]) -> Result<()> {
+        for (index, uniforms) in uniforms.iter().enumerate() {
+            self.uniforms.write(ctx, index as u32, uniforms)?;
+        }
+
+        Ok(())
+    }
+
+    /// Binds the descriptor set at set 0 with the uniforms of viewport `viewport_index`.
+    pub fn cmd_bind(
+        &self,
+        ctx: &Context,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        viewport_index: u32,
+    ) {
+        self.uniforms.cmd_bind(
+            ctx,
+            command_buffer,
+            layout,
+            0,
+            self.descriptor_set,
+            viewport_index,
+        );
+    }
+
+    pub fn destroy(&self, ctx: &Context) {
+        self.uniforms.destroy(ctx);
+    }
+}
+
+/// Uniforms of `viewport_count` viewports: each viewport with a matrix in `viewport_mvps` (e.g.
+/// one camera per player) uses it, the others `scene_mvp`.
+pub fn viewport_uniforms(
+    scene_mvp: &Mat4,
+    viewport_mvps: &[Mat4],
+    viewport_count: usize,
+) -> Vec<SceneUniforms> {
+    (0..viewport_count)
+        .map(|index| SceneUniforms {
+            mvp: *viewport_mvps.get(index).unwrap_or(scene_mvp),
+        })
+        .collect()
+}
+
+pub fn identity() -> Mat4 {
+    Mat4::new(
+        Vec4::new(1.0, 0.0, 0.0, 0.0),
+        Vec4::new(0.0, 1.0, 0.0, 0.0),
+        Vec4::new(0.0, 0.0, 1.0, 0.0),
+        Vec4::new(0.0, 0.0, 0.0, 1.0),
+    )
+}
+
+fn create_descriptor_set_layout(ctx: &Context) -> Result<vk::DescriptorSetLayout> {
+    let binding = vk::DescriptorSetLayoutBinding {
+        binding: 0,
+        descriptorType: vk::DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC,
+        descriptorCount: 1,
+        stageFlags: vk::SHADER_STAGE_VERTEX_BIT,
+        pImmutableSamplers: ptr::null(),
+    };
+
+    let info = vk::DescriptorSetLayoutCreateInfo {
+        sType: vk::STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+        pNext: ptr::null(),
+        flags: 0,
+        bindingCount: 1,
+        pBindings: &binding,
+    };
+
+    unsafe { ctx.dp.create_descriptor_set_layout(ctx.device, &info) }.map_err(to_vulkan)
+}
+
+/// one set with the uniform buffer per swapchain image
+fn create_descriptor_pool(ctx: &Context, image_count: u32) -> Result<vk::DescriptorPool> {
+    let pool_size = vk::DescriptorPoolSize {
+        ty: vk::DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC,
+        descriptorCount: image_count,
+    };
+
+    let info = vk::DescriptorPoolCreateInfo {
+        sType: vk::STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO,
+        pNext: ptr::null(),
+        flags: 0,
+        maxSets: image_count,
+        poolSizeCount: 1,
+        pPoolSizes: &pool_size,
+    };
+
+    unsafe { ctx.dp.create_descriptor_pool(ctx.device, &info) }.map_err(to_vulkan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scale(factor: f32) -> Mat4 {
+        Mat4::new(
+            Vec4::new(factor, 0.0, 0.0, 0.0),
+            Vec4::new(0.0, factor, 0.0, 0.0),
+            Vec4::new(0.0, 0.0, factor, 0.0),
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+        )
+    }
+
+    #[test]
+    fn each_viewport_gets_its_own_transform() {
+        let uniforms = viewport_uniforms(&identity(), &[scale(2.0), scale(3.0)], 2);
+
+        assert_eq!(uniforms.len(), 2);
+        assert_eq!(uniforms[0].mvp, scale(2.0));
+        assert_eq!(uniforms[1].mvp, scale(3.0));
+    }
+
+    #[test]
+    fn viewports_without_transform_use_the_scene_mvp() {
+        let uniforms = viewport_uniforms(&scale(4.0), &[scale(2.0)], 3);
+
+        let mvps: Vec<_> = uniforms.iter().map(|uniforms| uniforms.mvp).collect();
+        assert_eq!(mvps, [scale(2.0), scale(4.0), scale(4.0)]);
+
+        // extra transforms without viewport are ignored
+        assert_eq!(
+            viewport_uniforms(&identity(), &[scale(2.0), scale(3.0)], 1).len(),
+            1
+        );
+    }
+}
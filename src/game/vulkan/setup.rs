@@ -1,18 +1,27 @@
 use super::{
-    error::{maybe_vulkan_error, to_vulkan},
+    debug::{debugger_callback, VALIDATION_STATS},
+    error::{in_stage, maybe_vulkan_error, to_vulkan, Stage},
+    pipeline_cache,
     util::{cchar_to_string, CStrings},
     version::VulkanVersion,
-    QueueFamilies, QueueFamilyIndices, Result, Vulkan, VulkanInit,
+    ComputeInit, QueueFamilies, QueueFamilyIndices, Result, ValidationFeatures, Vulkan, VulkanInit,
 };
 use crate::game::vulkan::{
-    error::{to_other, Error},
-    Context, InFlightFrame, MAX_FRAMES_IN_FLIGHT,
+    error::{device_type_name, to_other, DeviceRejection, DeviceReport, Error, RejectionReason},
+    mesh_textures::MeshTextures,
+    scene_uniforms::identity,
+    swapchain::{sequential_indices, triangle_vertices},
+    Context, InFlightFrame, LatencyMode, MeshSlab, PowerPreference, SwapchainSettings,
+    MAX_FRAMES_IN_FLIGHT,
 };
-use log::{error, info, log, Level};
+use log::{debug, error, info, warn};
 use std::{
     collections::HashSet,
     ffi::{c_void, CString},
-    mem, ptr,
+    mem,
+    path::PathBuf,
+    ptr,
+    time::{Duration, Instant},
 };
 use vk_sys as vk;
 use vulkanic::{DevicePointers, EntryPoints, InstancePointers};
@@ -25,7 +34,14 @@ impl Vulkan {
         })
         .into();
 
-        let instance = Self::create_instance(&ep, init.req_layers, init.req_ext, init.debug)?;
+        let (instance, instance_names) = Self::create_instance(
+            &ep,
+            init.req_layers,
+            init.req_ext,
+            init.debug,
+            &init.validation_features,
+        )
+        .map_err(in_stage(Stage::Instance))?;
         let ip: InstancePointers = vk::InstancePointers::load(|procname| {
             init.window
                 .get_instance_proc_address(instance, procname.to_str().unwrap())
@@ -36,52 +52,417 @@ impl Vulkan {
                 .get_instance_proc_address(instance, procname.to_str().unwrap())
         })
         .into();
+        let raw_dp = vk::DevicePointers::load(|procname| {
+            init.window
+                .get_instance_proc_address(instance, procname.to_str().unwrap())
+        });
 
-        let debugger = if init.debug {
-            Self::create_debug_messenger(&ip, instance)?
-        } else {
-            vk::NULL_HANDLE
+        // everything created from here on is destroyed again if a later step fails
+        let mut handles = InitHandles {
+            instance,
+            debugger: vk::NULL_HANDLE,
+            surface: vk::NULL_HANDLE,
+            device: 0,
+            command_pool: vk::NULL_HANDLE,
         };
 
-        let surface = Self::create_surface(init.window, instance)?;
+        let device_init = match Self::init_device(&init, &ip, &dp, &mut handles) {
+            Ok(device_init) => device_init,
+            Err(err) => {
+                handles.destroy(&ip, &dp);
+                return Err(err);
+            }
+        };
 
-        let req_dev_exts = vec!["VK_KHR_swapchain".to_owned()];
+        let mut vulkan = Self::with_device(
+            ip,
+            dp,
+            raw_dp,
+            &handles,
+            &instance_names,
+            device_init,
+            init.fence_timeout,
+            init.pipeline_cache_path,
+        )?;
+
+        match MeshTextures::white(&vulkan.ctx) {
+            Ok(mesh_textures) => vulkan.mesh_textures = Some(mesh_textures),
+            Err(err) => {
+                if let Err(destroy_err) = vulkan.destroy() {
+                    error!("cleanup after failed init failed: {}", destroy_err);
+                }
 
-        let physical_device = Self::find_physical_device(&ip, instance, &req_dev_exts)?;
-        let queue_family_indices = Self::find_queue_families(&ip, physical_device, surface)?;
+                return Err(err);
+            }
+        }
 
-        let device =
-            Self::create_device(&ip, physical_device, &queue_family_indices, &req_dev_exts)?;
-        let queues = Self::get_device_queue_families(&dp, device, &queue_family_indices);
+        Ok(vulkan)
+    }
 
-        let command_pool = Self::create_command_pool(&dp, device, &queue_family_indices)?;
-        let memory_properties = ip.get_physical_device_memory_properties(physical_device);
+    /// Instance, device and command pool only. There is no swapchain, `draw_frame` fails.
+    pub fn new_compute_only(init: ComputeInit) -> Result<Self> {
+        let ep: EntryPoints = vk::EntryPoints::load(|procname| {
+            init.glfw
+                .get_instance_proc_address_raw(0, procname.to_str().unwrap())
+        })
+        .into();
 
-        let ctx = Context {
+        let (instance, instance_names) = Self::create_instance(
+            &ep,
+            init.req_layers,
+            init.req_ext,
+            init.debug,
+            &init.validation_features,
+        )
+        .map_err(in_stage(Stage::Instance))?;
+        let ip: InstancePointers = vk::InstancePointers::load(|procname| {
+            init.glfw
+                .get_instance_proc_address_raw(instance, procname.to_str().unwrap())
+        })
+        .into();
+        let dp: DevicePointers = vk::DevicePointers::load(|procname| {
+            init.glfw
+                .get_instance_proc_address_raw(instance, procname.to_str().unwrap())
+        })
+        .into();
+        let raw_dp = vk::DevicePointers::load(|procname| {
+            init.glfw
+                .get_instance_proc_address_raw(instance, procname.to_str().unwrap())
+        });
+
+        let mut handles = InitHandles {
             instance,
+            debugger: vk::NULL_HANDLE,
+            surface: vk::NULL_HANDLE,
+            device: 0,
+            command_pool: vk::NULL_HANDLE,
+        };
+
+        let device_init = match Self::init_compute_device(&init, &ip, &dp, &mut handles) {
+            Ok(device_init) => device_init,
+            Err(err) => {
+                handles.destroy(&ip, &dp);
+                return Err(err);
+            }
+        };
+
+        Self::with_device(
             ip,
-            debugger,
             dp,
+            raw_dp,
+            &handles,
+            &instance_names,
+            device_init,
+            init.fence_timeout,
+            None,
+        )
+    }
+
+    /// Takes over the handles created so far and creates the frames in flight.
+    fn with_device(
+        ip: InstancePointers,
+        dp: DevicePointers,
+        raw_dp: vk::DevicePointers,
+        handles: &InitHandles,
+        instance_names: &InstanceNames,
+        device_init: DeviceInit,
+        fence_timeout: Duration,
+        pipeline_cache_path: Option<PathBuf>,
+    ) -> Result<Self> {
+        let ctx = Context {
+            instance: handles.instance,
+            ip,
+            debugger: handles.debugger,
+            dp,
+            raw_dp,
+            physical_device: device_init.physical_device,
+            device: handles.device,
+            queue_family_indices: device_init.queue_family_indices,
+            queue_families: device_init.queue_families,
+            surface: handles.surface,
+            command_pool: handles.command_pool,
+            memory_properties: device_init.memory_properties,
+            enabled_features: device_init.enabled_features,
+            limits: device_init.limits,
+            device_extensions: device_init.extensions,
+            pipeline_cache: vk::NULL_HANDLE,
+        };
+
+        let mut vulkan = Vulkan {
+            ctx,
+            inflight_frames: Vec::with_capacity(MAX_FRAMES_IN_FLIGHT),
+            current_frame: 0,
+            latency_mode: LatencyMode::default(),
+            acquire_semaphores: Vec::with_capacity(MAX_FRAMES_IN_FLIGHT + 1),
+            acquire_semaphore_index: 0,
+            sc_ctx: None,
+            swapchain_outdated: false,
+            surface_lost: false,
+            surface_recoveries: 0,
+            suboptimal_recreations: 0,
+            retired_swapchains: 0,
+            fence_timeout,
+            frame_count: 0,
+            fence_timeouts: 0,
+            last_frame_at: Instant::now(),
+            last_submit: None,
+            particles: Vec::new(),
+            scene_vertices: triangle_vertices(),
+            scene_indices: sequential_indices(3),
+            scene_mvp: identity(),
+            viewport_mvps: Vec::new(),
+            scene_models: vec![identity()],
+            meshes: MeshSlab::default(),
+            render_list: Vec::new(),
+            camera_position: glm::Vec3::new(0.0, 0.0, 0.0),
+            command_buffers_outdated: false,
+            mesh_textures: None,
+            settings: SwapchainSettings::default(),
+            present_to: None,
+            pipeline_cache_path,
+        };
+
+        // only makes pipeline creation faster, it works without
+        match pipeline_cache::create(&vulkan.ctx, vulkan.pipeline_cache_path.as_deref()) {
+            Ok(pipeline_cache) => vulkan.ctx.pipeline_cache = pipeline_cache,
+            Err(err) => warn!("pipeline cache not created: {}", err),
+        }
+
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            match InFlightFrame::new(&vulkan.ctx) {
+                Ok(frame) => vulkan.inflight_frames.push(frame),
+                Err(err) => {
+                    if let Err(destroy_err) = vulkan.destroy() {
+                        error!("cleanup after failed init failed: {}", destroy_err);
+                    }
+
+                    return Err(in_stage(Stage::FramesInFlight)(err));
+                }
+            }
+        }
+
+        for _ in 0..MAX_FRAMES_IN_FLIGHT + 1 {
+            match vulkan.ctx.create_semaphore() {
+                Ok(semaphore) => vulkan.acquire_semaphores.push(semaphore),
+                Err(err) => {
+                    if let Err(destroy_err) = vulkan.destroy() {
+                        error!("cleanup after failed init failed: {}", destroy_err);
+                    }
+
+                    return Err(in_stage(Stage::FramesInFlight)(err));
+                }
+            }
+        }
+
+        vulkan.log_summary(instance_names);
+
+        Ok(vulkan)
+    }
+
+    /// Everything enabled in one block, for bug reports.
+    fn log_summary(&self, instance_names: &InstanceNames) {
+        let properties = self
+            .ctx
+            .ip
+            .get_physical_device_properties(self.ctx.physical_device);
+        let features = &self.ctx.enabled_features;
+
+        let enabled_features: Vec<&str> = [
+            ("sparseBinding", features.sparseBinding),
+            ("largePoints", features.largePoints),
+            ("depthBiasClamp", features.depthBiasClamp),
+            ("sampleRateShading", features.sampleRateShading),
+            ("samplerAnisotropy", features.samplerAnisotropy),
+        ]
+        .iter()
+        .filter(|(_, enabled)| *enabled == vk::TRUE)
+        .map(|(name, _)| *name)
+        .collect();
+
+        info!(
+            "vulkan summary:\n  \
+             device: {} (API {}, driver {:#x})\n  \
+             requested API: {}\n  \
+             instance layers: {:?}\n  \
+             instance extensions: {:?}\n  \
+             device extensions: {:?}\n  \
+             device features: {:?}",
+            cchar_to_string(&properties.deviceName),
+            VulkanVersion::from_compact(properties.apiVersion),
+            properties.driverVersion,
+            API_VERSION,
+            instance_names.layers,
+            instance_names.extensions,
+            self.ctx.device_extensions,
+            enabled_features,
+        );
+    }
+
+    /// Creates the device and everything needed for it. Created handles are recorded in
+    /// `handles`, so the caller can clean up on error.
+    fn init_device(
+        init: &VulkanInit,
+        ip: &InstancePointers,
+        dp: &DevicePointers,
+        handles: &mut InitHandles,
+    ) -> Result<DeviceInit> {
+        let instance = handles.instance;
+
+        if init.debug {
+            handles.debugger = Self::create_debug_messenger(ip, instance)
+                .map_err(in_stage(Stage::DebugMessenger))?;
+        }
+
+        handles.surface =
+            Self::create_surface(init.window, instance).map_err(in_stage(Stage::Surface))?;
+        let surface = handles.surface;
+
+        let req_dev_exts = vec!["VK_KHR_swapchain".to_owned()];
+
+        let physical_device = Self::find_physical_device(
+            ip,
+            instance,
+            &req_dev_exts,
+            init.device_name_filter.as_deref(),
+            init.power_preference,
+            init.allow_integrated,
+            surface,
+        )
+        .map_err(in_stage(Stage::PhysicalDevice))?;
+        let supported_features = ip.get_physical_device_features(physical_device);
+
+        let sparse_binding = init.sparse_binding && supported_features.sparseBinding == vk::TRUE;
+        if init.sparse_binding && !sparse_binding {
+            warn!("sparse binding requested, but not supported by the device");
+        }
+
+        let queue_family_indices =
+            Self::find_queue_families(ip, physical_device, surface, sparse_binding)
+                .map_err(in_stage(Stage::QueueFamilies))?;
+
+        let mut enabled_features: vk::PhysicalDeviceFeatures = unsafe { mem::zeroed() };
+        if queue_family_indices.sparse.is_some() {
+            enabled_features.sparseBinding = vk::TRUE;
+        }
+        // particles bigger than 1px
+        enabled_features.largePoints = supported_features.largePoints;
+        enabled_features.depthBiasClamp = supported_features.depthBiasClamp;
+        enabled_features.sampleRateShading = supported_features.sampleRateShading;
+        enabled_features.samplerAnisotropy = supported_features.samplerAnisotropy;
+
+        handles.device = Self::create_device(
+            ip,
             physical_device,
+            &queue_family_indices,
+            &init.queue_priorities,
+            &enabled_features,
+            &req_dev_exts,
+        )
+        .map_err(in_stage(Stage::Device))?;
+        let device = handles.device;
+
+        let queue_families = Self::get_device_queue_families(
+            dp,
             device,
+            &queue_family_indices,
+            init.queue_priorities.len(),
+        );
+
+        handles.command_pool = Self::create_command_pool(dp, device, &queue_family_indices)
+            .map_err(in_stage(Stage::CommandPool))?;
+        let memory_properties = ip.get_physical_device_memory_properties(physical_device);
+        let limits = ip.get_physical_device_properties(physical_device).limits;
+
+        Ok(DeviceInit {
+            physical_device,
             queue_family_indices,
-            queue_families: queues,
-            surface,
-            command_pool,
+            queue_families,
             memory_properties,
-        };
+            enabled_features,
+            limits,
+            extensions: req_dev_exts,
+        })
+    }
 
-        let mut inflight_frames = Vec::<InFlightFrame>::with_capacity(MAX_FRAMES_IN_FLIGHT);
-        for _ in 0..MAX_FRAMES_IN_FLIGHT {
-            let frame = InFlightFrame::new(&ctx)?;
-            inflight_frames.push(frame);
+    /// `init_device` without surface: a single queue, of a dedicated compute family if there is
+    /// one, serves as graphics and present queue as well.
+    fn init_compute_device(
+        init: &ComputeInit,
+        ip: &InstancePointers,
+        dp: &DevicePointers,
+        handles: &mut InitHandles,
+    ) -> Result<DeviceInit> {
+        let instance = handles.instance;
+
+        if init.debug {
+            handles.debugger = Self::create_debug_messenger(ip, instance)
+                .map_err(in_stage(Stage::DebugMessenger))?;
         }
 
-        Ok(Vulkan {
-            ctx,
-            inflight_frames,
-            current_frame: 0,
-            sc_ctx: None,
+        let req_dev_exts = vec![];
+
+        let physical_device = Self::find_physical_device(
+            ip,
+            instance,
+            &req_dev_exts,
+            None,
+            PowerPreference::HighPerformance,
+            true,
+            vk::NULL_HANDLE,
+        )
+        .map_err(in_stage(Stage::PhysicalDevice))?;
+        let props = ip.get_physical_device_queue_family_properties(physical_device);
+
+        let compute = props
+            .iter()
+            .position(|prop| {
+                prop.queueFlags & vk::QUEUE_COMPUTE_BIT != 0
+                    && prop.queueFlags & vk::QUEUE_GRAPHICS_BIT == 0
+            })
+            .or_else(|| {
+                props
+                    .iter()
+                    .position(|prop| prop.queueFlags & vk::QUEUE_COMPUTE_BIT != 0)
+            })
+            .map(|index| index as u32)
+            .ok_or_else(|| Error::Other("compute queue needed".to_owned()))
+            .map_err(in_stage(Stage::QueueFamilies))?;
+
+        let queue_family_indices = QueueFamilyIndices {
+            graphics: compute,
+            present: compute,
+            compute,
+            sparse: None,
+        };
+
+        let enabled_features: vk::PhysicalDeviceFeatures = unsafe { mem::zeroed() };
+
+        handles.device = Self::create_device(
+            ip,
+            physical_device,
+            &queue_family_indices,
+            &[1.0],
+            &enabled_features,
+            &req_dev_exts,
+        )
+        .map_err(in_stage(Stage::Device))?;
+        let device = handles.device;
+
+        let queue_families = Self::get_device_queue_families(dp, device, &queue_family_indices, 1);
+
+        handles.command_pool = Self::create_command_pool(dp, device, &queue_family_indices)
+            .map_err(in_stage(Stage::CommandPool))?;
+        let memory_properties = ip.get_physical_device_memory_properties(physical_device);
+        let limits = ip.get_physical_device_properties(physical_device).limits;
+
+        Ok(DeviceInit {
+            physical_device,
+            queue_family_indices,
+            queue_families,
+            memory_properties,
+            enabled_features,
+            limits,
+            extensions: req_dev_exts,
         })
     }
 
@@ -90,7 +471,26 @@ impl Vulkan {
             inflight_frame.destroy(&self.ctx);
         }
 
+        for semaphore in self.acquire_semaphores.drain(..) {
+            self.ctx.destroy_semaphore(semaphore);
+        }
+
         self.sc_ctx.take().map(|sc| sc.destroy(&self.ctx));
+        self.meshes.destroy(&self.ctx);
+        if let Some(mesh_textures) = self.mesh_textures.take() {
+            mesh_textures.destroy(&self.ctx);
+        }
+
+        if self.ctx.pipeline_cache != vk::NULL_HANDLE {
+            if let Some(path) = &self.pipeline_cache_path {
+                pipeline_cache::save(&self.ctx, self.ctx.pipeline_cache, path);
+            }
+
+            self.ctx
+                .dp
+                .destroy_pipeline_cache(self.ctx.device, self.ctx.pipeline_cache);
+            self.ctx.pipeline_cache = vk::NULL_HANDLE;
+        }
 
         self.ctx
             .dp
@@ -100,10 +500,12 @@ impl Vulkan {
         self.ctx.dp.destroy_device(self.ctx.device);
         self.ctx.device = 0;
 
-        self.ctx
-            .ip
-            .destroy_surface_khr(self.ctx.instance, self.ctx.surface);
-        self.ctx.surface = vk::NULL_HANDLE;
+        if self.ctx.surface != vk::NULL_HANDLE {
+            self.ctx
+                .ip
+                .destroy_surface_khr(self.ctx.instance, self.ctx.surface);
+            self.ctx.surface = vk::NULL_HANDLE;
+        }
 
         if self.ctx.debugger != vk::NULL_HANDLE {
             self.ctx
@@ -124,7 +526,8 @@ impl Vulkan {
         required_layers: &Vec<String>,
         required_extensions: &Vec<String>,
         debug: bool,
-    ) -> Result<vk::Instance> {
+        validation_features: &ValidationFeatures,
+    ) -> Result<(vk::Instance, InstanceNames)> {
         let app_name = CString::new("chunklands").unwrap();
         let engine_name = CString::new("crankshaft").unwrap();
         let app_info = vk::ApplicationInfo {
@@ -134,10 +537,10 @@ impl Vulkan {
             applicationVersion: VulkanVersion::new(0, 0, 1).get_compact(),
             pEngineName: engine_name.as_ptr(),
             engineVersion: VulkanVersion::new(0, 0, 1).get_compact(),
-            apiVersion: VulkanVersion::new(1, 0, 0).get_compact(),
+            apiVersion: API_VERSION.get_compact(),
         };
 
-        let (layers, extensions) = if debug {
+        let (layer_names, extension_names) = if debug {
             let mut req_dbg_layers = required_layers.clone();
             req_dbg_layers.push("VK_LAYER_KHRONOS_validation".to_owned());
             Self::check_required_layers(ep, &req_dbg_layers)?;
@@ -146,21 +549,36 @@ impl Vulkan {
             req_dbg_ext.push("VK_EXT_debug_utils".to_owned());
             Self::check_required_extensions(ep, &req_dbg_ext)?;
 
-            (
-                CStrings::new(&req_dbg_layers).unwrap(),
-                CStrings::new(&req_dbg_ext).unwrap(),
-            ) // TODO unwrap
+            // provided by the validation layer, so it is not listed without it
+            if !enabled_validation_features(validation_features).is_empty() {
+                req_dbg_ext.push("VK_EXT_validation_features".to_owned());
+            }
+
+            (req_dbg_layers, req_dbg_ext)
         } else {
             Self::check_required_extensions(ep, &required_extensions)?;
 
-            (
-                CStrings::new(&Vec::<String>::new()).unwrap(),
-                CStrings::new(&required_extensions).unwrap(),
-            ) // TODO unwrap
+            (Vec::new(), required_extensions.clone())
         };
 
+        let layers = CStrings::new(&layer_names).unwrap(); // TODO unwrap
+        let extensions = CStrings::new(&extension_names).unwrap(); // TODO unwrap
+
         let mut debug_info = Self::create_debugger_info();
 
+        let enabled_features = enabled_validation_features(validation_features);
+        let validation_features_info = ValidationFeaturesInfo {
+            s_type: STRUCTURE_TYPE_VALIDATION_FEATURES_EXT,
+            p_next: ptr::null(),
+            enabled_validation_feature_count: enabled_features.len() as u32,
+            p_enabled_validation_features: enabled_features.as_ptr(),
+            disabled_validation_feature_count: 0,
+            p_disabled_validation_features: ptr::null(),
+        };
+        if debug && !enabled_features.is_empty() {
+            debug_info.pNext = &validation_features_info as *const _ as *const c_void;
+        }
+
         let instance_info = vk::InstanceCreateInfo {
             sType: vk::STRUCTURE_TYPE_INSTANCE_CREATE_INFO,
             flags: 0,
@@ -176,7 +594,15 @@ impl Vulkan {
             },
         };
 
-        unsafe { ep.create_instance(&instance_info) }.map_err(to_vulkan)
+        let instance = unsafe { ep.create_instance(&instance_info) }.map_err(to_vulkan)?;
+
+        Ok((
+            instance,
+            InstanceNames {
+                layers: layer_names,
+                extensions: extension_names,
+            },
+        ))
     }
 
     fn check_required_layers(ep: &EntryPoints, required_layers: &Vec<String>) -> Result<()> {
@@ -201,7 +627,7 @@ impl Vulkan {
                     let layer_name = cchar_to_string(&layer.layerName);
                     let version = VulkanVersion::from_compact(layer.specVersion);
 
-                    info!("found layer: {}@{}", layer_name, version);
+                    debug!("found layer: {}@{}", layer_name, version);
                 }
             }
         }
@@ -233,7 +659,7 @@ impl Vulkan {
                     let extension_name = cchar_to_string(&ext.extensionName);
                     let version = VulkanVersion::from_compact(ext.specVersion);
 
-                    info!("found extensions: {}@{}", extension_name, version);
+                    debug!("found extensions: {}@{}", extension_name, version);
                 }
             }
         }
@@ -252,52 +678,23 @@ impl Vulkan {
             messageType: vk::DEBUG_UTILS_MESSAGE_TYPE_GENERAL_BIT_EXT
                 | vk::DEBUG_UTILS_MESSAGE_TYPE_VALIDATION_BIT_EXT
                 | vk::DEBUG_UTILS_MESSAGE_TYPE_PERFORMANCE_BIT_EXT,
-            pfnUserCallback: Self::debugger_callback,
+            pfnUserCallback: debugger_callback,
             pUserData: ptr::null_mut(),
             pNext: ptr::null(),
         }
     }
 
-    extern "system" fn debugger_callback(
-        message_severity: vk::DebugUtilsMessageSeverityFlagBitsEXT,
-        _message_type: vk::DebugUtilsMessageTypeFlagsEXT,
-        callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-        _user_data: *mut c_void,
-    ) -> vk::Bool32 {
-        unsafe {
-            let c_msg = std::ffi::CStr::from_ptr((*callback_data).pMessage);
-
-            match c_msg.to_str() {
-                Ok(s) => {
-                    let log_level = if message_severity
-                        & vk::DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT
-                        != 0
-                    {
-                        Level::Error
-                    } else if message_severity & vk::DEBUG_UTILS_MESSAGE_SEVERITY_WARNING_BIT_EXT
-                        != 0
-                    {
-                        Level::Warn
-                    } else if message_severity & vk::DEBUG_UTILS_MESSAGE_SEVERITY_INFO_BIT_EXT != 0
-                    {
-                        Level::Info
-                    } else if message_severity & vk::DEBUG_UTILS_MESSAGE_SEVERITY_VERBOSE_BIT_EXT
-                        != 0
-                    {
-                        Level::Debug
-                    } else {
-                        Level::Trace
-                    };
-
-                    log!(target: "vulkan", log_level, "vulkan | {}", s);
-                }
-                Err(_) => {
-                    error!(target: "vulkan", "vulkan | debug utils cannot read message: {:?}", c_msg);
-                }
-            };
-        };
+    /// Error messages of the validation layer so far, e.g. to fail a run with validation errors.
+    /// Always 0 without `VulkanInit::debug`.
+    pub fn validation_error_count(&self) -> u32 {
+        VALIDATION_STATS.errors()
+    }
 
-        vk::FALSE
+    /// Like `validation_error_count`, but readable after `destroy`. Objects still alive when the
+    /// device is destroyed are reported then, so leak checks read this once `Vulkan` is gone.
+    /// Counts the messages of every `Vulkan` of the process.
+    pub fn process_validation_error_count() -> u32 {
+        VALIDATION_STATS.errors()
     }
 
     fn create_debug_messenger(
@@ -309,6 +706,50 @@ impl Vulkan {
         unsafe { ip.create_debug_utils_messenger_ext(instance, &create_info) }.map_err(to_vulkan)
     }
 
+    /// Replaces a lost surface (e.g. after a display hotplug). The swapchain belongs to the old
+    /// surface and goes with it, the next frame creates a new one.
+    pub(super) fn recreate_surface(&mut self, window: &glfw::Window) -> Result<()> {
+        self.ctx
+            .dp
+            .device_wait_idle(self.ctx.device)
+            .map_err(to_vulkan)?;
+
+        if let Some(swapchain) = self.sc_ctx.take() {
+            swapchain.destroy(&self.ctx)?;
+        }
+
+        // a window can only have one surface at a time
+        if self.ctx.surface != vk::NULL_HANDLE {
+            self.ctx
+                .ip
+                .destroy_surface_khr(self.ctx.instance, self.ctx.surface);
+            self.ctx.surface = vk::NULL_HANDLE;
+        }
+
+        self.ctx.surface = Self::create_surface(window, self.ctx.instance)?;
+
+        let present_supported = self
+            .ctx
+            .ip
+            .get_physical_device_surface_support_khr(
+                self.ctx.physical_device,
+                self.ctx.queue_family_indices.present,
+                self.ctx.surface,
+            )
+            .map_err(to_vulkan)?;
+        if !present_supported {
+            return Err(Error::Other(
+                "present queue family cannot present to the new surface".to_owned(),
+            ));
+        }
+
+        info!("surface recreated");
+        self.surface_lost = false;
+        self.swapchain_outdated = true;
+
+        Ok(())
+    }
+
     fn create_surface(window: &glfw::Window, instance: vk::Instance) -> Result<vk::SurfaceKHR> {
         let mut surface = mem::MaybeUninit::<vk::SurfaceKHR>::uninit();
         let result = window.create_window_surface(instance, std::ptr::null(), surface.as_mut_ptr());
@@ -317,51 +758,162 @@ impl Vulkan {
         Ok(unsafe { surface.assume_init() })
     }
 
+    /// A device with the required extensions whose name contains `device_name_filter`
+    /// (case-insensitive), otherwise the suitable GPU whose type fits `power_preference` best.
+    /// Integrated GPUs are only suitable with `allow_integrated`, unless picked by name.
+    /// Fails with a report of why each device was rejected.
     fn find_physical_device(
         ip: &InstancePointers,
         instance: vk::Instance,
         required_device_extensions: &Vec<String>,
+        device_name_filter: Option<&str>,
+        power_preference: PowerPreference,
+        allow_integrated: bool,
+        surface: vk::SurfaceKHR,
     ) -> Result<vk::PhysicalDevice> {
         let physical_devices = ip.enumerate_physical_devices(instance).map_err(to_vulkan)?;
 
-        let maybe_good_physical_device = {
-            let mut physical_devices_it = physical_devices.iter();
-            loop {
-                match physical_devices_it.next() {
-                    Some(physical_device) => {
-                        let properties = ip.get_physical_device_properties(*physical_device);
-                        let name = cchar_to_string(&properties.deviceName);
-                        info!("found physical device {}", name);
-
-                        if properties.deviceType & vk::PHYSICAL_DEVICE_TYPE_DISCRETE_GPU != 0
-                            && Self::check_physical_device_extensions(
-                                ip,
-                                *physical_device,
-                                required_device_extensions,
-                            )?
-                        {
-                            info!("found device and will use {}", name);
-                            break Some(*physical_device);
-                        }
-
-                        info!("found device {}", name);
-                    }
-                    None => {
-                        break None;
-                    }
+        if let Some(filter) = device_name_filter {
+            let filter = filter.to_lowercase();
+
+            for physical_device in &physical_devices {
+                let properties = ip.get_physical_device_properties(*physical_device);
+                let name = cchar_to_string(&properties.deviceName);
+
+                // any device type will do, the user asked for this one
+                if name.to_lowercase().contains(&filter)
+                    && Self::rejection_reasons(
+                        ip,
+                        *physical_device,
+                        &properties,
+                        required_device_extensions,
+                        true,
+                        surface,
+                    )?
+                    .iter()
+                    .all(|reason| matches!(reason, RejectionReason::DeviceType(_)))
+                {
+                    info!(
+                        "device {} matches filter {:?} and will be used",
+                        name, filter
+                    );
+                    return Ok(*physical_device);
                 }
             }
-        };
 
-        maybe_good_physical_device
-            .ok_or_else(|| to_other(Error::Other("no discrete GPU found".to_owned())))
+            warn!(
+                "no suitable device matches filter {:?}, choosing as without filter",
+                filter
+            );
+        }
+
+        let mut report = DeviceReport::default();
+        // score, device, name, device type
+        let mut best: Option<(u32, vk::PhysicalDevice, String, vk::PhysicalDeviceType)> = None;
+        for physical_device in &physical_devices {
+            let properties = ip.get_physical_device_properties(*physical_device);
+            let name = cchar_to_string(&properties.deviceName);
+            debug!("found physical device {}", name);
+
+            let reasons = Self::rejection_reasons(
+                ip,
+                *physical_device,
+                &properties,
+                required_device_extensions,
+                allow_integrated,
+                surface,
+            )?;
+
+            if !reasons.is_empty() {
+                debug!("rejected device {}", name);
+                report.devices.push(DeviceRejection { name, reasons });
+                continue;
+            }
+
+            let score = device_type_score(properties.deviceType, power_preference);
+            // the first of equally scored devices, as enumerated
+            if best
+                .as_ref()
+                .map_or(true, |(best_score, ..)| score > *best_score)
+            {
+                best = Some((score, *physical_device, name, properties.deviceType));
+            }
+        }
+
+        match best {
+            Some((_, physical_device, name, device_type)) => {
+                if device_type == vk::PHYSICAL_DEVICE_TYPE_CPU {
+                    warn!(
+                        "no GPU found, rendering on the CPU with {} will be slow",
+                        name
+                    );
+                }
+
+                info!(
+                    "found device and will use {}, the {} is preferred for {:?}",
+                    name,
+                    device_type_name(device_type),
+                    power_preference
+                );
+                Ok(physical_device)
+            }
+            None => {
+                info!("{}", report);
+                Err(Error::NoSuitableDevice(report))
+            }
+        }
     }
 
-    fn check_physical_device_extensions(
+    /// Everything that keeps `physical_device` from being used, empty if it is suitable.
+    /// `surface` is `NULL_HANDLE` for compute only.
+    fn rejection_reasons(
+        ip: &InstancePointers,
+        physical_device: vk::PhysicalDevice,
+        properties: &vk::PhysicalDeviceProperties,
+        required_device_extensions: &Vec<String>,
+        allow_integrated: bool,
+        surface: vk::SurfaceKHR,
+    ) -> Result<Vec<RejectionReason>> {
+        let mut reasons = Vec::new();
+
+        // CPU implementations are slow, but better than not starting at all, see
+        // `device_type_score`. Unknown device types may not render at all.
+        if properties.deviceType == vk::PHYSICAL_DEVICE_TYPE_OTHER
+            || (properties.deviceType == vk::PHYSICAL_DEVICE_TYPE_INTEGRATED_GPU
+                && !allow_integrated)
+        {
+            reasons.push(RejectionReason::DeviceType(properties.deviceType));
+        }
+
+        reasons.extend(
+            Self::missing_device_extensions(ip, physical_device, required_device_extensions)?
+                .into_iter()
+                .map(RejectionReason::MissingExtension),
+        );
+
+        if surface != vk::NULL_HANDLE {
+            let family_count = ip
+                .get_physical_device_queue_family_properties(physical_device)
+                .len() as u32;
+            let present_support = (0..family_count).any(|index| {
+                ip.get_physical_device_surface_support_khr(physical_device, index, surface)
+                    .unwrap_or(false)
+            });
+
+            if !present_support {
+                reasons.push(RejectionReason::NoPresentSupport);
+            }
+        }
+
+        Ok(reasons)
+    }
+
+    /// Required extensions `physical_device` doesn't support.
+    fn missing_device_extensions(
         ip: &InstancePointers,
         physical_device: vk::PhysicalDevice,
         req_dev_exts: &Vec<String>,
-    ) -> Result<bool> {
+    ) -> Result<Vec<String>> {
         let props = ip
             .enumerate_device_extension_properties::<&str>(physical_device, None)
             .map_err(to_vulkan)?;
@@ -370,17 +922,21 @@ impl Vulkan {
 
         for prop in &props {
             let ext_name = cchar_to_string(&prop.extensionName);
-            info!("found device extension {}", ext_name);
+            debug!("found device extension {}", ext_name);
             required_device_extensions.remove(&ext_name);
         }
 
-        Ok(required_device_extensions.is_empty())
+        let mut missing: Vec<String> = required_device_extensions.into_iter().cloned().collect();
+        missing.sort();
+
+        Ok(missing)
     }
 
     fn find_queue_families(
         ip: &InstancePointers,
         physical_device: vk::PhysicalDevice,
         surface: vk::SurfaceKHR,
+        sparse_binding: bool,
     ) -> Result<QueueFamilyIndices> {
         let props = ip.get_physical_device_queue_family_properties(physical_device);
 
@@ -401,38 +957,92 @@ impl Vulkan {
             .map(|(index, _)| index as u32)
             .ok_or_else(|| Error::Other("present queue needed".to_owned()))?;
 
-        Ok(QueueFamilyIndices { graphics, present })
+        // a family with graphics and compute exists, if graphics is supported at all
+        let compute = props
+            .get(graphics as usize)
+            .filter(|prop| prop.queueFlags & vk::QUEUE_COMPUTE_BIT != 0)
+            .map(|_| graphics)
+            .or_else(|| {
+                props
+                    .iter()
+                    .position(|prop| prop.queueFlags & vk::QUEUE_COMPUTE_BIT != 0)
+                    .map(|index| index as u32)
+            })
+            .ok_or_else(|| Error::Other("compute queue needed".to_owned()))?;
+
+        let sparse = if sparse_binding {
+            let sparse = props
+                .iter()
+                .enumerate()
+                .find(|(_, prop)| prop.queueFlags & vk::QUEUE_SPARSE_BINDING_BIT != 0)
+                .map(|(index, _)| index as u32);
+
+            if sparse.is_none() {
+                warn!("sparse binding supported, but no queue family for it");
+            }
+
+            sparse
+        } else {
+            None
+        };
+
+        Ok(QueueFamilyIndices {
+            graphics,
+            present,
+            compute,
+            sparse,
+        })
     }
 
     fn create_device(
         ip: &InstancePointers,
         physical_device: vk::PhysicalDevice,
         queue_family_indices: &QueueFamilyIndices,
+        graphics_queue_priorities: &[f32],
+        enabled_features: &vk::PhysicalDeviceFeatures,
         required_device_extensions: &Vec<String>,
     ) -> Result<vk::Device> {
-        let queue_priorities = [1f32];
+        let props = ip.get_physical_device_queue_family_properties(physical_device);
+        let graphics_queue_count = props
+            .get(queue_family_indices.graphics as usize)
+            .map(|prop| prop.queueCount)
+            .unwrap_or(0);
+        Self::check_queue_priorities(graphics_queue_priorities, graphics_queue_count)?;
+
+        let default_queue_priorities = [1f32];
 
         // There may be queues, which are graphics and present as well.
         // Vulkan does not allow to create multiple queues for the same index
         // so we need to dedupe them.
-        let unique_queue_indices: HashSet<u32> =
-            vec![queue_family_indices.graphics, queue_family_indices.present]
-                .drain(..)
-                .collect();
+        let mut unique_queue_indices: HashSet<u32> = vec![
+            queue_family_indices.graphics,
+            queue_family_indices.present,
+            queue_family_indices.compute,
+        ]
+        .drain(..)
+        .collect();
+        unique_queue_indices.extend(queue_family_indices.sparse);
 
         let queue_create_infos: Vec<vk::DeviceQueueCreateInfo> = unique_queue_indices
             .into_iter()
-            .map(|queue_index| vk::DeviceQueueCreateInfo {
-                sType: vk::STRUCTURE_TYPE_DEVICE_QUEUE_CREATE_INFO,
-                pNext: std::ptr::null(),
-                flags: 0,
-                queueFamilyIndex: queue_index as u32,
-                queueCount: 1,
-                pQueuePriorities: queue_priorities.as_ptr(),
+            .map(|queue_index| {
+                let queue_priorities = if queue_index == queue_family_indices.graphics {
+                    graphics_queue_priorities
+                } else {
+                    &default_queue_priorities[..]
+                };
+
+                vk::DeviceQueueCreateInfo {
+                    sType: vk::STRUCTURE_TYPE_DEVICE_QUEUE_CREATE_INFO,
+                    pNext: std::ptr::null(),
+                    flags: 0,
+                    queueFamilyIndex: queue_index as u32,
+                    queueCount: queue_priorities.len() as u32,
+                    pQueuePriorities: queue_priorities.as_ptr(),
+                }
             })
             .collect();
 
-        let enabled_features: vk::PhysicalDeviceFeatures = unsafe { mem::zeroed() };
         let req_dev_exts = CStrings::new(&required_device_extensions).map_err(to_other)?;
 
         let create_info = vk::DeviceCreateInfo {
@@ -445,20 +1055,60 @@ impl Vulkan {
             ppEnabledLayerNames: std::ptr::null(),
             enabledExtensionCount: req_dev_exts.len() as u32,
             ppEnabledExtensionNames: req_dev_exts.as_ptr(),
-            pEnabledFeatures: &enabled_features,
+            pEnabledFeatures: enabled_features,
         };
 
         unsafe { ip.create_device(physical_device, &create_info) }.map_err(to_vulkan)
     }
 
+    fn check_queue_priorities(priorities: &[f32], queue_count: u32) -> Result<()> {
+        if priorities.is_empty() {
+            return Err(Error::Other(
+                "at least one graphics queue priority needed".to_owned(),
+            ));
+        }
+
+        if let Some(priority) = priorities
+            .iter()
+            .find(|priority| !(0.0..=1.0).contains(*priority))
+        {
+            return Err(Error::Other(format!(
+                "queue priority {} not in [0, 1]",
+                priority
+            )));
+        }
+
+        if priorities.len() > queue_count as usize {
+            return Err(Error::Other(format!(
+                "requested {} graphics queues, but queue family only has {}",
+                priorities.len(),
+                queue_count
+            )));
+        }
+
+        Ok(())
+    }
+
     fn get_device_queue_families(
         dp: &DevicePointers,
         device: vk::Device,
         queue_family_indices: &QueueFamilyIndices,
+        graphics_queue_count: usize,
     ) -> QueueFamilies {
+        let graphics_queues: Vec<vk::Queue> = (0..graphics_queue_count as u32)
+            .map(|queue_index| {
+                dp.get_device_queue(device, queue_family_indices.graphics, queue_index)
+            })
+            .collect();
+
         QueueFamilies {
-            graphics_queue: dp.get_device_queue(device, queue_family_indices.graphics, 0),
+            graphics_queue: graphics_queues[0],
+            graphics_queues,
             present_queue: dp.get_device_queue(device, queue_family_indices.present, 0),
+            compute_queue: dp.get_device_queue(device, queue_family_indices.compute, 0),
+            sparse_queue: queue_family_indices
+                .sparse
+                .map(|sparse| dp.get_device_queue(device, sparse, 0)),
         }
     }
 
@@ -477,3 +1127,199 @@ impl Vulkan {
         unsafe { dp.create_command_pool(device, &info) }.map_err(to_vulkan)
     }
 }
+
+const API_VERSION: VulkanVersion = VulkanVersion {
+    major: 1,
+    minor: 0,
+    patch: 0,
+};
+
+// `VK_EXT_validation_features` is newer than vk-sys
+const STRUCTURE_TYPE_VALIDATION_FEATURES_EXT: vk::StructureType = 1000247000;
+const VALIDATION_FEATURE_ENABLE_GPU_ASSISTED_EXT: u32 = 0;
+const VALIDATION_FEATURE_ENABLE_BEST_PRACTICES_EXT: u32 = 2;
+const VALIDATION_FEATURE_ENABLE_SYNCHRONIZATION_VALIDATION_EXT: u32 = 4;
+
+/// `VkValidationFeaturesEXT`
+#[repr(C)]
+struct ValidationFeaturesInfo {
+    s_type: vk::StructureType,
+    p_next: *const c_void,
+    enabled_validation_feature_count: u32,
+    p_enabled_validation_features: *const u32,
+    disabled_validation_feature_count: u32,
+    p_disabled_validation_features: *const u32,
+}
+
+fn enabled_validation_features(features: &ValidationFeatures) -> Vec<u32> {
+    let mut enabled = Vec::new();
+    if features.gpu_assisted {
+        enabled.push(VALIDATION_FEATURE_ENABLE_GPU_ASSISTED_EXT);
+    }
+    if features.best_practices {
+        enabled.push(VALIDATION_FEATURE_ENABLE_BEST_PRACTICES_EXT);
+    }
+    if features.synchronization {
+        enabled.push(VALIDATION_FEATURE_ENABLE_SYNCHRONIZATION_VALIDATION_EXT);
+    }
+
+    enabled
+}
+
+/// Handles created during `Vulkan::new`, before there is a `Context` to destroy them.
+struct InitHandles {
+    instance: vk::Instance,
+    debugger: vk::DebugUtilsMessengerEXT,
+    surface: vk::SurfaceKHR,
+    device: vk::Device,
+    command_pool: vk::CommandPool,
+}
+
+struct DeviceInit {
+    physical_device: vk::PhysicalDevice,
+    queue_family_indices: QueueFamilyIndices,
+    queue_families: QueueFamilies,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    enabled_features: vk::PhysicalDeviceFeatures,
+    limits: vk::PhysicalDeviceLimits,
+    extensions: Vec<String>,
+}
+
+/// layers and extensions the instance was created with
+struct InstanceNames {
+    layers: Vec<String>,
+    extensions: Vec<String>,
+}
+
+impl InitHandles {
+    /// Destroys all created handles in reverse order of creation.
+    fn destroy(&self, ip: &InstancePointers, dp: &DevicePointers) {
+        if self.command_pool != vk::NULL_HANDLE {
+            dp.destroy_command_pool(self.device, self.command_pool);
+        }
+
+        if self.device != 0 {
+            dp.destroy_device(self.device);
+        }
+
+        if self.surface != vk::NULL_HANDLE {
+            ip.destroy_surface_khr(self.instance, self.surface);
+        }
+
+        if self.debugger != vk::NULL_HANDLE {
+            if let Err(err) = ip.destroy_debug_utils_messenger_ext(self.instance, self.debugger) {
+                error!("cannot destroy debug messenger: {}", to_vulkan(err));
+            }
+        }
+
+        ip.destroy_instance(self.instance);
+    }
+}
+
+/// Higher is preferred, GPUs in the order of `power_preference`, then virtual GPUs, then CPUs.
+fn device_type_score(
+    device_type: vk::PhysicalDeviceType,
+    power_preference: PowerPreference,
+) -> u32 {
+    match (device_type, power_preference) {
+        (vk::PHYSICAL_DEVICE_TYPE_DISCRETE_GPU, PowerPreference::HighPerformance) => 3,
+        (vk::PHYSICAL_DEVICE_TYPE_INTEGRATED_GPU, PowerPreference::HighPerformance) => 2,
+        (vk::PHYSICAL_DEVICE_TYPE_INTEGRATED_GPU, PowerPreference::LowPower) => 3,
+        (vk::PHYSICAL_DEVICE_TYPE_DISCRETE_GPU, PowerPreference::LowPower) => 2,
+        (vk::PHYSICAL_DEVICE_TYPE_VIRTUAL_GPU, _) => 1,
+        // last resort, see `rejection_reasons`
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_priorities_fit_the_queue_family() {
+        assert!(Vulkan::check_queue_priorities(&[1.0, 0.0], 2).is_ok());
+        assert!(Vulkan::check_queue_priorities(&[1.0, 0.5, 0.5], 2).is_err());
+        assert!(Vulkan::check_queue_priorities(&[], 2).is_err());
+        assert!(Vulkan::check_queue_priorities(&[1.5], 2).is_err());
+        assert!(Vulkan::check_queue_priorities(&[-0.1], 2).is_err());
+        assert!(Vulkan::check_queue_priorities(&[f32::NAN], 2).is_err());
+    }
+
+    #[test]
+    fn validation_features_by_flag() {
+        assert!(enabled_validation_features(&ValidationFeatures::default()).is_empty());
+
+        let all = ValidationFeatures {
+            gpu_assisted: true,
+            best_practices: true,
+            synchronization: true,
+        };
+        assert_eq!(
+            enabled_validation_features(&all),
+            [
+                VALIDATION_FEATURE_ENABLE_GPU_ASSISTED_EXT,
+                VALIDATION_FEATURE_ENABLE_BEST_PRACTICES_EXT,
+                VALIDATION_FEATURE_ENABLE_SYNCHRONIZATION_VALIDATION_EXT,
+            ]
+        );
+
+        let synchronization = ValidationFeatures {
+            synchronization: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            enabled_validation_features(&synchronization),
+            [VALIDATION_FEATURE_ENABLE_SYNCHRONIZATION_VALIDATION_EXT]
+        );
+    }
+
+    #[test]
+    fn device_type_by_power_preference() {
+        let order = |power_preference| {
+            let mut device_types = vec![
+                vk::PHYSICAL_DEVICE_TYPE_CPU,
+                vk::PHYSICAL_DEVICE_TYPE_VIRTUAL_GPU,
+                vk::PHYSICAL_DEVICE_TYPE_INTEGRATED_GPU,
+                vk::PHYSICAL_DEVICE_TYPE_OTHER,
+                vk::PHYSICAL_DEVICE_TYPE_DISCRETE_GPU,
+            ];
+            device_types.sort_by_key(|device_type| {
+                std::cmp::Reverse(device_type_score(*device_type, power_preference))
+            });
+            device_types.truncate(3);
+            device_types
+        };
+
+        assert_eq!(
+            order(PowerPreference::HighPerformance),
+            [
+                vk::PHYSICAL_DEVICE_TYPE_DISCRETE_GPU,
+                vk::PHYSICAL_DEVICE_TYPE_INTEGRATED_GPU,
+                vk::PHYSICAL_DEVICE_TYPE_VIRTUAL_GPU,
+            ]
+        );
+        assert_eq!(
+            order(PowerPreference::LowPower),
+            [
+                vk::PHYSICAL_DEVICE_TYPE_INTEGRATED_GPU,
+                vk::PHYSICAL_DEVICE_TYPE_DISCRETE_GPU,
+                vk::PHYSICAL_DEVICE_TYPE_VIRTUAL_GPU,
+            ]
+        );
+    }
+
+    #[test]
+    fn cpus_and_unknown_types_are_last_resort() {
+        for &power_preference in &[PowerPreference::HighPerformance, PowerPreference::LowPower] {
+            assert_eq!(
+                device_type_score(vk::PHYSICAL_DEVICE_TYPE_CPU, power_preference),
+                0
+            );
+            assert_eq!(
+                device_type_score(vk::PHYSICAL_DEVICE_TYPE_OTHER, power_preference),
+                0
+            );
+        }
+    }
+}
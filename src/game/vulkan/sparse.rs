@@ -0,0 +1,262 @@
+//! Opt-in sparse buffers (`VulkanInit::sparse_binding`), meant for huge voxel volumes of which
+//! only a part needs to be backed by memory at a time.
+
+use std::{collections::BTreeMap, ptr};
+
+use super::{
+    error::{to_other, to_vulkan, Error},
+    swapchain::find_memory_type,
+    Result, Vulkan,
+};
+use vk_sys as vk;
+
+pub struct SparseBuffer {
+    pub buffer: vk::Buffer,
+    pub size: u64,
+    memory_type_bits: u32,
+    residency: SparseResidency,
+}
+
+/// Bookkeeping of the memory bound to regions of a sparse resource. Regions are aligned to the
+/// resource's page size and never overlap.
+pub struct SparseResidency {
+    page_size: u64,
+    size: u64,
+    regions: BTreeMap<u64, ResidentRegion>,
+}
+
+pub struct ResidentRegion {
+    pub size: u64,
+    pub memory: vk::DeviceMemory,
+}
+
+impl SparseResidency {
+    pub fn new(page_size: u64, size: u64) -> Self {
+        Self {
+            page_size,
+            size,
+            regions: BTreeMap::new(),
+        }
+    }
+
+    pub fn page_size(&self) -> u64 {
+        self.page_size
+    }
+
+    pub fn check_bind(&self, offset: u64, size: u64) -> Result<()> {
+        if size == 0 || offset % self.page_size != 0 || size % self.page_size != 0 {
+            return Err(Error::Other(format!(
+                "sparse region {}+{} not aligned to page size {}",
+                offset, size, self.page_size
+            )));
+        }
+
+        if offset + size > self.size {
+            return Err(Error::Other(format!(
+                "sparse region {}+{} exceeds resource size {}",
+                offset, size, self.size
+            )));
+        }
+
+        let overlaps = self
+            .regions
+            .range(..offset + size)
+            .next_back()
+            .map(|(region_offset, region)| region_offset + region.size > offset)
+            .unwrap_or(false);
+
+        if overlaps {
+            return Err(Error::Other(format!(
+                "sparse region {}+{} overlaps a resident region",
+                offset, size
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn insert(&mut self, offset: u64, region: ResidentRegion) {
+        self.regions.insert(offset, region);
+    }
+
+    pub fn remove(&mut self, offset: u64) -> Option<ResidentRegion> {
+        self.regions.remove(&offset)
+    }
+
+    pub fn is_resident(&self, offset: u64) -> bool {
+        self.regions
+            .range(..=offset)
+            .next_back()
+            .map(|(region_offset, region)| region_offset + region.size > offset)
+            .unwrap_or(false)
+    }
+
+    /// `(offset, size)` of all resident regions in ascending order
+    pub fn resident_regions(&self) -> Vec<(u64, u64)> {
+        self.regions
+            .iter()
+            .map(|(offset, region)| (*offset, region.size))
+            .collect()
+    }
+}
+
+impl Vulkan {
+    pub fn supports_sparse_binding(&self) -> bool {
+        self.ctx.queue_families.sparse_queue.is_some()
+    }
+
+    pub fn create_sparse_buffer(
+        &self,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+    ) -> Result<SparseBuffer> {
+        if !self.supports_sparse_binding() {
+            return Err(to_other("sparse binding not enabled"));
+        }
+
+        let buffer_info = vk::BufferCreateInfo {
+            sType: vk::STRUCTURE_TYPE_BUFFER_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: vk::BUFFER_CREATE_SPARSE_BINDING_BIT,
+            size,
+            usage,
+            sharingMode: vk::SHARING_MODE_EXCLUSIVE,
+            queueFamilyIndexCount: 0,
+            pQueueFamilyIndices: ptr::null(),
+        };
+
+        let buffer = unsafe { self.ctx.dp.create_buffer(self.ctx.device, &buffer_info) }
+            .map_err(to_vulkan)?;
+        let memory_requirements = self
+            .ctx
+            .dp
+            .get_buffer_memory_requirements(self.ctx.device, buffer);
+
+        Ok(SparseBuffer {
+            buffer,
+            size,
+            memory_type_bits: memory_requirements.memoryTypeBits,
+            residency: SparseResidency::new(
+                memory_requirements.alignment,
+                memory_requirements.size,
+            ),
+        })
+    }
+
+    /// Backs `offset..offset + size` of the buffer with newly allocated device local memory.
+    /// Both must be multiples of `SparseBuffer::page_size`.
+    pub fn bind_sparse(&self, buffer: &mut SparseBuffer, offset: u64, size: u64) -> Result<()> {
+        buffer.residency.check_bind(offset, size)?;
+
+        let allocate_info = vk::MemoryAllocateInfo {
+            sType: vk::STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
+            pNext: ptr::null(),
+            allocationSize: size,
+            memoryTypeIndex: find_memory_type(
+                &self.ctx,
+                buffer.memory_type_bits,
+                vk::MEMORY_PROPERTY_DEVICE_LOCAL_BIT,
+            )?,
+        };
+
+        let memory = unsafe { self.ctx.dp.allocate_memory(self.ctx.device, &allocate_info) }
+            .map_err(to_vulkan)?;
+
+        if let Err(err) = self.queue_bind_sparse(buffer.buffer, offset, size, memory) {
+            self.ctx.dp.free_memory(self.ctx.device, memory);
+            return Err(err);
+        }
+
+        buffer
+            .residency
+            .insert(offset, ResidentRegion { size, memory });
+
+        Ok(())
+    }
+
+    /// Releases the memory of a region previously bound at `offset`.
+    pub fn unbind_sparse(&self, buffer: &mut SparseBuffer, offset: u64) -> Result<()> {
+        let region = buffer
+            .residency
+            .remove(offset)
+            .ok_or_else(|| to_other(format!("no resident sparse region at {}", offset)))?;
+
+        self.queue_bind_sparse(buffer.buffer, offset, region.size, vk::NULL_HANDLE)?;
+        self.ctx.dp.free_memory(self.ctx.device, region.memory);
+
+        Ok(())
+    }
+
+    pub fn destroy_sparse_buffer(&self, mut buffer: SparseBuffer) -> Result<()> {
+        for (offset, _) in buffer.residency.resident_regions() {
+            self.unbind_sparse(&mut buffer, offset)?;
+        }
+
+        self.ctx.dp.destroy_buffer(self.ctx.device, buffer.buffer);
+
+        Ok(())
+    }
+
+    fn queue_bind_sparse(
+        &self,
+        buffer: vk::Buffer,
+        offset: u64,
+        size: u64,
+        memory: vk::DeviceMemory,
+    ) -> Result<()> {
+        let sparse_queue = self
+            .ctx
+            .queue_families
+            .sparse_queue
+            .ok_or_else(|| to_other("sparse binding not enabled"))?;
+
+        let bind = vk::SparseMemoryBind {
+            resourceOffset: offset,
+            size,
+            memory,
+            memoryOffset: 0,
+            flags: 0,
+        };
+
+        let buffer_bind = vk::SparseBufferMemoryBindInfo {
+            buffer,
+            bindCount: 1,
+            pBinds: &bind,
+        };
+
+        let bind_info = vk::BindSparseInfo {
+            sType: vk::STRUCTURE_TYPE_BIND_SPARSE_INFO,
+            pNext: ptr::null(),
+            waitSemaphoreCount: 0,
+            pWaitSemaphores: ptr::null(),
+            bufferBindCount: 1,
+            pBufferBinds: &buffer_bind,
+            imageOpaqueBindCount: 0,
+            pImageOpaqueBinds: ptr::null(),
+            imageBindCount: 0,
+            pImageBinds: ptr::null(),
+            signalSemaphoreCount: 0,
+            pSignalSemaphores: ptr::null(),
+        };
+
+        unsafe {
+            self.ctx
+                .dp
+                .queue_bind_sparse(sparse_queue, &[bind_info], vk::NULL_HANDLE)
+        }
+        .map_err(to_vulkan)?;
+
+        // binding is rare and keeping track of in-flight binds is not worth it (yet)
+        self.ctx.dp.queue_wait_idle(sparse_queue).map_err(to_vulkan)
+    }
+}
+
+impl SparseBuffer {
+    pub fn page_size(&self) -> u64 {
+        self.residency.page_size()
+    }
+
+    pub fn residency(&self) -> &SparseResidency {
+        &self.residency
+    }
+}
@@ -0,0 +1,116 @@
+//! Specialization constants, values of `layout(constant_id = ...) const` shader declarations
+//! fixed at pipeline creation, e.g. a chunk size or a feature toggle.
+
+use std::ffi::c_void;
+use vk_sys as vk;
+
+/// Constant values and where they are in the data blob, in the layout `vk::SpecializationInfo`
+/// expects.
+#[derive(Default)]
+pub struct SpecializationConstants {
+    entries: Vec<vk::SpecializationMapEntry>,
+    data: Vec<u8>,
+}
+
+/// Value types of specialization constants, all 4 bytes wide. `bool` is a `VkBool32`.
+pub trait SpecializationValue {
+    fn to_bytes(&self) -> [u8; 4];
+}
+
+impl SpecializationValue for u32 {
+    fn to_bytes(&self) -> [u8; 4] {
+        self.to_ne_bytes()
+    }
+}
+
+impl SpecializationValue for i32 {
+    fn to_bytes(&self) -> [u8; 4] {
+        self.to_ne_bytes()
+    }
+}
+
+impl SpecializationValue for f32 {
+    fn to_bytes(&self) -> [u8; 4] {
+        self.to_bits().to_ne_bytes()
+    }
+}
+
+impl SpecializationValue for bool {
+    fn to_bytes(&self) -> [u8; 4] {
+        let value: vk::Bool32 = if *self { vk::TRUE } else { vk::FALSE };
+        value.to_ne_bytes()
+    }
+}
+
+impl SpecializationConstants {
+    /// Sets `constant_id` to `value`, appended to the data blob.
+    pub fn constant<T: SpecializationValue>(mut self, constant_id: u32, value: T) -> Self {
+        let bytes = value.to_bytes();
+
+        self.entries.push(vk::SpecializationMapEntry {
+            constantID: constant_id,
+            offset: self.data.len() as u32,
+            size: bytes.len(),
+        });
+        self.data.extend_from_slice(&bytes);
+
+        self
+    }
+
+    pub fn entries(&self) -> &[vk::SpecializationMapEntry] {
+        &self.entries
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Points into `self`, which has to outlive the pipeline creation.
+    pub fn info(&self) -> vk::SpecializationInfo {
+        vk::SpecializationInfo {
+            mapEntryCount: self.entries.len() as u32,
+            pMapEntries: self.entries.as_ptr(),
+            dataSize: self.data.len(),
+            pData: self.data.as_ptr() as *const c_void,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_are_packed_in_order() {
+        let constants = SpecializationConstants::default()
+            .constant(3, 16u32)
+            .constant(0, -1i32)
+            .constant(1, 0.5f32)
+            .constant(7, true);
+
+        let layout: Vec<_> = constants
+            .entries()
+            .iter()
+            .map(|entry| (entry.constantID, entry.offset, entry.size))
+            .collect();
+        assert_eq!(layout, [(3, 0, 4), (0, 4, 4), (1, 8, 4), (7, 12, 4)]);
+
+        let data = constants.data();
+        assert_eq!(data.len(), 16);
+        assert_eq!(&data[0..4], &16u32.to_ne_bytes());
+        assert_eq!(&data[4..8], &(-1i32).to_ne_bytes());
+        assert_eq!(&data[8..12], &0.5f32.to_bits().to_ne_bytes());
+        assert_eq!(&data[12..16], &vk::TRUE.to_ne_bytes());
+    }
+
+    #[test]
+    fn info_points_at_the_constants() {
+        let constants = SpecializationConstants::default().constant(0, false);
+        let info = constants.info();
+
+        assert_eq!(info.mapEntryCount, 1);
+        assert_eq!(info.pMapEntries, constants.entries().as_ptr());
+        assert_eq!(info.dataSize, 4);
+        assert_eq!(info.pData as *const u8, constants.data().as_ptr());
+    }
+}
@@ -0,0 +1,152 @@
+//! Several `vk::SubmitInfo`s in one `queue_submit`, e.g. an upload and the frame that reads it.
+//! Every `queue_submit` has a fixed cost in the driver, batches pay it once.
+
+use super::{error::to_vulkan, Context, Result};
+use vk_sys as vk;
+
+/// Submits in order, built like
+/// `SubmitBatch::default().submit(&[upload]).then(uploaded, stage, &[render]).signal(rendered)`.
+/// A semaphore signaled by one submit of the batch may be waited on by a later one.
+#[derive(Default)]
+pub struct SubmitBatch {
+    submits: Vec<Submit>,
+}
+
+#[derive(Default)]
+struct Submit {
+    wait_semaphores: Vec<vk::Semaphore>,
+    /// one per wait semaphore
+    wait_dst_stage_masks: Vec<vk::PipelineStageFlags>,
+    command_buffers: Vec<vk::CommandBuffer>,
+    signal_semaphores: Vec<vk::Semaphore>,
+}
+
+impl SubmitBatch {
+    /// Starts the next submit with `command_buffers`.
+    pub fn submit(mut self, command_buffers: &[vk::CommandBuffer]) -> Self {
+        self.submits.push(Submit {
+            command_buffers: command_buffers.to_vec(),
+            ..Default::default()
+        });
+
+        self
+    }
+
+    /// The last submit waits on `semaphore` before `stage`. Without a submit yet, one without
+    /// command buffers is started.
+    pub fn wait(mut self, semaphore: vk::Semaphore, stage: vk::PipelineStageFlags) -> Self {
+        let submit = self.last_submit();
+        submit.wait_semaphores.push(semaphore);
+        submit.wait_dst_stage_masks.push(stage);
+
+        self
+    }
+
+    /// The last submit signals `semaphore` when its command buffers are done.
+    pub fn signal(mut self, semaphore: vk::Semaphore) -> Self {
+        self.last_submit().signal_semaphores.push(semaphore);
+
+        self
+    }
+
+    /// Chains a submit of `command_buffers` after the last one: the last one signals
+    /// `semaphore`, the new one waits on it before `stage`.
+    pub fn then(
+        self,
+        semaphore: vk::Semaphore,
+        stage: vk::PipelineStageFlags,
+        command_buffers: &[vk::CommandBuffer],
+    ) -> Self {
+        self.signal(semaphore)
+            .submit(command_buffers)
+            .wait(semaphore, stage)
+    }
+
+    pub fn len(&self) -> usize {
+        self.submits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.submits.is_empty()
+    }
+
+    /// One `vk::SubmitInfo` per submit, pointing into `self`, which has to outlive the
+    /// `queue_submit`.
+    pub fn submit_infos(&self) -> Vec<vk::SubmitInfo> {
+        self.submits
+            .iter()
+            .map(|submit| vk::SubmitInfo {
+                sType: vk::STRUCTURE_TYPE_SUBMIT_INFO,
+                pNext: std::ptr::null(),
+                waitSemaphoreCount: submit.wait_semaphores.len() as u32,
+                pWaitSemaphores: submit.wait_semaphores.as_ptr(),
+                pWaitDstStageMask: submit.wait_dst_stage_masks.as_ptr(),
+                commandBufferCount: submit.command_buffers.len() as u32,
+                pCommandBuffers: submit.command_buffers.as_ptr(),
+                signalSemaphoreCount: submit.signal_semaphores.len() as u32,
+                pSignalSemaphores: submit.signal_semaphores.as_ptr(),
+            })
+            .collect()
+    }
+
+    /// Submits the whole batch to `queue`, `fence` (may be `NULL_HANDLE`) is signaled when all
+    /// of it is done.
+    pub fn queue_submit(&self, ctx: &Context, queue: vk::Queue, fence: vk::Fence) -> Result<()> {
+        let submit_infos = self.submit_infos();
+
+        unsafe { ctx.dp.queue_submit(queue, &submit_infos, fence) }.map_err(to_vulkan)
+    }
+
+    fn last_submit(&mut self) -> &mut Submit {
+        if self.submits.is_empty() {
+            self.submits.push(Submit::default());
+        }
+
+        self.submits.last_mut().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn then_chains_through_a_semaphore() {
+        let (upload, render): (vk::CommandBuffer, vk::CommandBuffer) = (1, 2);
+        let (uploaded, rendered): (vk::Semaphore, vk::Semaphore) = (10, 11);
+
+        let batch = SubmitBatch::default()
+            .submit(&[upload])
+            .then(uploaded, vk::PIPELINE_STAGE_VERTEX_INPUT_BIT, &[render])
+            .signal(rendered);
+        assert_eq!(batch.len(), 2);
+
+        let infos = batch.submit_infos();
+        assert_eq!(infos[0].waitSemaphoreCount, 0);
+        assert_eq!(infos[0].commandBufferCount, 1);
+        assert_eq!(infos[0].signalSemaphoreCount, 1);
+        assert_eq!(unsafe { *infos[0].pSignalSemaphores }, uploaded);
+
+        assert_eq!(infos[1].waitSemaphoreCount, 1);
+        assert_eq!(unsafe { *infos[1].pWaitSemaphores }, uploaded);
+        assert_eq!(
+            unsafe { *infos[1].pWaitDstStageMask },
+            vk::PIPELINE_STAGE_VERTEX_INPUT_BIT
+        );
+        assert_eq!(unsafe { *infos[1].pCommandBuffers }, render);
+        assert_eq!(unsafe { *infos[1].pSignalSemaphores }, rendered);
+    }
+
+    #[test]
+    fn wait_without_submit_starts_an_empty_one() {
+        let available: vk::Semaphore = 10;
+
+        let batch = SubmitBatch::default().wait(available, vk::PIPELINE_STAGE_ALL_COMMANDS_BIT);
+        assert_eq!(batch.len(), 1);
+
+        let infos = batch.submit_infos();
+        assert_eq!(infos[0].waitSemaphoreCount, 1);
+        assert_eq!(infos[0].commandBufferCount, 0);
+        assert!(SubmitBatch::default().is_empty());
+    }
+}
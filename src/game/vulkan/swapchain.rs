@@ -1,26 +1,127 @@
-use std::{ffi::CString, mem::size_of, ptr};
+use std::{
+    mem::size_of,
+    ptr, slice,
+    time::{Duration, Instant},
+};
 
-use crate::game::vulkan::vertex::Vertex;
+use crate::game::vulkan::vertex::{ParticleVertex, Vertex};
 
-use super::util::{copy_extent_2d, copy_surface_format_khr};
+use super::depth::{
+    find_supported_depth_format, is_depth_format, DepthBuffer, DEPTH_FORMAT_CANDIDATES,
+};
+use super::handle;
+use super::mesh::MeshDraw;
+use super::mesh_textures::MeshTextures;
+use super::pipeline::{
+    create_mesh_pipeline, create_particle_pipeline, create_triangle_pipeline, DepthBias,
+};
+use super::post_process::{check_post_process, PostProcess, PostProcessImage, PostProcessPass};
+use super::render_target::{cmd_transition_color_image, scaled_extent, RenderTarget};
+use super::scene_uniforms::{viewport_uniforms, SceneUniformImage, SceneUniformSets};
+use super::submit::SubmitBatch;
+use super::tonemap::{Tonemap, TonemapImage, TonemapPass, HDR_FORMAT};
+use super::util::{align_up, copy_extent_2d, copy_rect_2d, copy_surface_format_khr};
 use super::Result;
 use super::{
-    error::{to_other, to_vulkan, Error},
-    Context, InFlightFrame, Swapchain, SwapchainContext, SwapchainImage, Vulkan,
-    MAX_FRAMES_IN_FLIGHT,
+    error::{to_other, to_vulkan, Error, HangReport, Status},
+    Context, InFlightFrame, SceneOutput, Swapchain, SwapchainContext, SwapchainImage,
+    SwapchainSettings, Vulkan, MAX_FRAMES_IN_FLIGHT,
 };
 use glfw::Window;
-use glm::{Vec2, Vec3};
-use inline_spirv::include_spirv;
+use glm::{Mat4, Vec2, Vec3};
+use log::{error, info, warn};
 use vk_sys as vk;
 use vulkanic::DevicePointers;
 
+/// surface recreations in a row without a presented frame, before giving up
+const MAX_SURFACE_RECOVERIES: u32 = 3;
+
+/// swapchain recreations in a row for suboptimal frames, before keeping the suboptimal one. Some
+/// surfaces (e.g. rotated ones) are suboptimal with any swapchain.
+const MAX_SUBOPTIMAL_RECREATIONS: u32 = 3;
+
+/// depth of the far plane, depth attachments are cleared to it unless configured otherwise
+const FAR_DEPTH: f32 = 1.0;
+
+/// back facing share of the scene triangles the winding check warns about, a few are normal
+/// for closed geometry seen from inside
+const BACK_FACING_WARN_FRACTION: f32 = 0.25;
+
 impl Vulkan {
-    pub fn draw_frame(&mut self, window: &glfw::Window) -> Result<()> {
-        if self.sc_ctx.is_none() {
-            self.create_swapchain(window)?;
+    /// A lost device is logged with the last submitted frame and swapchain image, the only hint
+    /// at the faulting work: `VK_EXT_device_fault` is not queried, fault addresses and vendor
+    /// info are not available.
+    pub fn draw_frame(&mut self, window: &glfw::Window) -> Result<FrameOutcome> {
+        if self.ctx.surface == vk::NULL_HANDLE && !self.surface_lost {
+            return Err(to_other("compute only, there is no surface to draw to"));
         }
 
+        match self.render_frame(window) {
+            Ok(outcome) => {
+                self.fence_timeouts = 0;
+                Ok(outcome)
+            }
+            Err(err) if err.is_timeout() => {
+                self.fence_timeouts += 1;
+                Err(err)
+            }
+            Err(Error::VulkanError(vk::ERROR_DEVICE_LOST)) => {
+                match self.last_submit {
+                    Some((frame, image_index)) => error!(
+                        "device lost, last submitted frame {} to swapchain image {}",
+                        frame, image_index
+                    ),
+                    None => error!("device lost before the first submit"),
+                }
+
+                if self.fence_timeouts > 0 {
+                    Err(Error::DeviceHang(HangReport {
+                        frame: self.frame_count,
+                        frame_in_flight: self.current_frame,
+                        fence_timeouts: self.fence_timeouts,
+                        since_last_frame: self.last_frame_at.elapsed(),
+                        last_submit: self.last_submit,
+                    }))
+                } else {
+                    Err(to_vulkan(vk::ERROR_DEVICE_LOST))
+                }
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn render_frame(&mut self, window: &glfw::Window) -> Result<FrameOutcome> {
+        self.meshes.destroy_unused(&self.ctx, self.frame_count);
+
+        if self.surface_lost {
+            if self.surface_recoveries >= MAX_SURFACE_RECOVERIES {
+                return Err(to_vulkan(vk::ERROR_SURFACE_LOST_KHR));
+            }
+
+            self.surface_recoveries += 1;
+            warn!(
+                "surface lost, recreating it (attempt {} of {})",
+                self.surface_recoveries, MAX_SURFACE_RECOVERIES
+            );
+            self.recreate_surface(window)?;
+        }
+
+        if self.sc_ctx.is_none() || self.swapchain_outdated {
+            // minimized, a swapchain cannot have a zero extent
+            let (width, height) = window.get_framebuffer_size();
+            if width == 0 || height == 0 {
+                return Ok(FrameOutcome::ZeroExtent);
+            }
+
+            self.recreate_swapchain(window)?;
+        }
+
+        if self.command_buffers_outdated {
+            self.rerecord_command_buffers()?;
+        }
+
+        let acquire_first = self.latency_mode == LatencyMode::AcquireFirst;
+
         let acquire_result = {
             let swapchain = self.sc_ctx.as_mut().unwrap();
 
@@ -29,38 +130,69 @@ impl Vulkan {
                 .get(self.current_frame)
                 .ok_or_else(|| to_other("invalid current frame"))?;
 
+            // the frame's own semaphore may still be waited on by its last submit, which is only
+            // known to be done after the fence wait
+            let available_semaphore = if acquire_first {
+                self.acquire_semaphores[self.acquire_semaphore_index]
+            } else {
+                wait_for_frame(
+                    &self.ctx,
+                    current_inflight_frame,
+                    self.current_frame,
+                    self.fence_timeout,
+                )?;
+
+                current_inflight_frame.available_semaphore
+            };
+
             self.ctx
-                .dp
-                .wait_for_fences(
-                    self.ctx.device,
-                    &[current_inflight_frame.in_flight_fence],
-                    true,
-                    u64::MAX,
-                )
-                .map_err(to_vulkan)?;
-            self.ctx
-                .dp
-                .acquire_next_image_khr(
-                    self.ctx.device,
-                    swapchain.ctx.swapchain,
-                    u64::MAX,
-                    current_inflight_frame.available_semaphore,
-                    vk::NULL_HANDLE,
-                )
-                .map_err(to_vulkan)
-                .map(|next_image| (next_image, current_inflight_frame))
+                .acquire_next_image(swapchain.ctx.swapchain, available_semaphore)
+                .map(|(next_image, status)| {
+                    (
+                        next_image,
+                        status,
+                        current_inflight_frame,
+                        available_semaphore,
+                    )
+                })
         };
 
-        if let Err(Error::VulkanError(vk::ERROR_OUT_OF_DATE_KHR)) = acquire_result {
-            self.destroy_swapchain()?;
-            return Ok(());
+        match acquire_result {
+            Err(Error::VulkanError(vk::ERROR_OUT_OF_DATE_KHR)) => {
+                self.invalidate_swapchain()?;
+                return Ok(FrameOutcome::SwapchainOutdated);
+            }
+            Err(Error::VulkanError(vk::ERROR_SURFACE_LOST_KHR)) => {
+                self.surface_lost = true;
+                return Ok(FrameOutcome::SurfaceLost);
+            }
+            _ => {}
         }
 
-        let (image_index_index, current_inflight_frame) = acquire_result?;
+        // a suboptimal image is still rendered and presented, the swapchain is recreated after
+        let (image_index_index, acquire_status, current_inflight_frame, available_semaphore) =
+            acquire_result?;
+
+        if acquire_first {
+            // signaled now, the next acquire takes the next one
+            self.acquire_semaphore_index =
+                (self.acquire_semaphore_index + 1) % self.acquire_semaphores.len();
+
+            if let Err(err) = wait_for_frame(
+                &self.ctx,
+                current_inflight_frame,
+                self.current_frame,
+                self.fence_timeout,
+            ) {
+                self.abandon_acquired_image(available_semaphore)?;
+                return Err(err);
+            }
+        }
 
         let swapchain = self.sc_ctx.as_mut().unwrap();
 
         let swapchain_images_len = swapchain.images.len();
+        let viewport_count = swapchain.ctx.viewports.len();
         let swapchain_image = swapchain
             .images
             .get_mut(image_index_index as usize)
@@ -71,150 +203,835 @@ impl Vulkan {
                 ))
             })?;
 
-        if swapchain_image.in_flight_fence != vk::NULL_HANDLE {
+        if swapchain_image.in_flight_fence != vk::NULL_HANDLE
+            && self
+                .ctx
+                .wait_for_fence(swapchain_image.in_flight_fence, self.fence_timeout)?
+                == Status::Timeout
+        {
+            warn!(
+                "swapchain image {} did not finish within {:?} (fence {:#x})",
+                image_index_index, self.fence_timeout, swapchain_image.in_flight_fence
+            );
+            self.abandon_acquired_image(available_semaphore)?;
+            return Err(to_vulkan(vk::TIMEOUT));
+        }
+
+        // the image's last frame is done, its uniforms can be overwritten
+        swapchain_image.scene_uniforms.write(
+            &self.ctx,
+            &viewport_uniforms(&self.scene_mvp, &self.viewport_mvps, viewport_count),
+        )?;
+
+        swapchain_image.in_flight_fence = current_inflight_frame.in_flight_fence;
+
+        let signal_semaphores = [current_inflight_frame.rendered_semaphore];
+
+        let batch = SubmitBatch::default()
+            .submit(&[swapchain_image.command_buffer])
+            // an upscaled scene is blitted onto the swapchain image in the transfer stage
+            .wait(
+                available_semaphore,
+                vk::PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT | vk::PIPELINE_STAGE_TRANSFER_BIT,
+            )
+            .signal(current_inflight_frame.rendered_semaphore);
+
+        self.ctx
+            .dp
+            .reset_fences(self.ctx.device, &[current_inflight_frame.in_flight_fence])
+            .map_err(to_vulkan)?;
+
+        batch.queue_submit(
+            &self.ctx,
+            self.ctx.queue_families.graphics_queue,
+            current_inflight_frame.in_flight_fence,
+        )?;
+        self.last_submit = Some((self.frame_count, image_index_index));
+
+        let present_result = match &mut self.present_to {
+            Some(present_to) => present_to(&RenderedImage {
+                swapchain: swapchain.ctx.swapchain,
+                image_index: image_index_index,
+                image: swapchain_image.image,
+                image_view: swapchain_image.image_view,
+                format: swapchain.ctx.surface_format.format,
+                extent: copy_extent_2d(&swapchain.ctx.extent),
+                rendered_semaphore: current_inflight_frame.rendered_semaphore,
+            }),
+            None => {
+                let swapchains = [swapchain.ctx.swapchain];
+
+                let present_info = vk::PresentInfoKHR {
+                    sType: vk::STRUCTURE_TYPE_PRESENT_INFO_KHR,
+                    pNext: std::ptr::null(),
+                    waitSemaphoreCount: signal_semaphores.len() as u32,
+                    pWaitSemaphores: signal_semaphores.as_ptr(),
+                    swapchainCount: swapchains.len() as u32,
+                    pSwapchains: swapchains.as_ptr(),
+                    pImageIndices: &image_index_index,
+                    pResults: std::ptr::null_mut(),
+                };
+
+                unsafe {
+                    self.ctx
+                        .dp
+                        .queue_present_khr(self.ctx.queue_families.present_queue, &present_info)
+                        .map_err(to_vulkan)
+                }
+            }
+        };
+        let suboptimal = match present_result {
+            Ok(_) => acquire_status == Status::Suboptimal,
+            // presented, but e.g. stretched after a resize
+            Err(Error::VulkanError(vk::SUBOPTIMAL_KHR)) => true,
+            Err(Error::VulkanError(vk::ERROR_OUT_OF_DATE_KHR)) => {
+                self.invalidate_swapchain()?;
+                return Ok(FrameOutcome::SwapchainOutdated);
+            }
+            Err(Error::VulkanError(vk::ERROR_SURFACE_LOST_KHR)) => {
+                self.surface_lost = true;
+                return Ok(FrameOutcome::SurfaceLost);
+            }
+            Err(err) => {
+                return Err(err);
+            }
+        };
+
+        if !suboptimal {
+            self.suboptimal_recreations = 0;
+        } else if self.suboptimal_recreations < MAX_SUBOPTIMAL_RECREATIONS {
+            self.suboptimal_recreations += 1;
+            self.invalidate_swapchain()?;
+        } else if self.suboptimal_recreations == MAX_SUBOPTIMAL_RECREATIONS {
+            self.suboptimal_recreations += 1;
+            warn!(
+                "swapchain still suboptimal after {} recreations, keeping it",
+                MAX_SUBOPTIMAL_RECREATIONS
+            );
+        }
+
+        self.surface_recoveries = 0;
+
+        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+        self.frame_count += 1;
+        self.last_frame_at = Instant::now();
+
+        Ok(FrameOutcome::Presented)
+    }
+
+    /// Order of acquiring the next image and waiting for the frame in flight, takes effect with
+    /// the next frame.
+    pub fn set_latency_mode(&mut self, latency_mode: LatencyMode) {
+        self.latency_mode = latency_mode;
+    }
+
+    /// Number of frames presented, frames dropped because of an outdated swapchain don't count.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Swapchains passed as `oldSwapchain` to the creation of their successor, e.g. on resize,
+    /// so the driver can reuse their resources.
+    pub fn retired_swapchains(&self) -> u32 {
+        self.retired_swapchains
+    }
+
+    /// Frame in flight the next `draw_frame` renders, in `0..MAX_FRAMES_IN_FLIGHT`. Resources
+    /// kept per frame in flight (e.g. descriptor sets of an overlay) can be indexed with it: the
+    /// ones at this index are free once `draw_frame` waited for the frame's fence, which is the
+    /// case inside `PresentTo`. The index advances after the frame is presented.
+    pub fn current_frame_index(&self) -> usize {
+        self.current_frame
+    }
+
+    /// Whether there is a swapchain, which is not known to be outdated. Without one, the next
+    /// `draw_frame` recreates it or skips the frame.
+    pub fn has_swapchain(&self) -> bool {
+        self.sc_ctx.is_some() && !self.swapchain_outdated
+    }
+
+    /// Only marks the swapchain as outdated, so any number of size changes between two frames
+    /// lead to a single recreation, with the framebuffer size at the next `draw_frame`.
+    pub fn on_framebuffer_changed(&mut self) -> Result<()> {
+        // the new size may well be optimal again
+        self.suboptimal_recreations = 0;
+        self.invalidate_swapchain()
+    }
+
+    /// Replaces the scene triangle in place, e.g. to experiment at runtime. Waits for the frames
+    /// in flight, which read the vertex buffer.
+    pub fn set_triangle(&mut self, v0: Vertex, v1: Vertex, v2: Vertex) -> Result<()> {
+        if self.scene_vertices.len() != 3 || self.scene_indices.len() != 3 {
+            return self.set_scene_vertices(vec![v0, v1, v2]);
+        }
+
+        self.scene_vertices = vec![v0, v1, v2];
+
+        if let Some(swapchain) = &self.sc_ctx {
             self.ctx
                 .dp
-                .wait_for_fences(
-                    self.ctx.device,
-                    &[swapchain_image.in_flight_fence],
-                    true,
-                    u64::MAX,
-                )
+                .device_wait_idle(self.ctx.device)
                 .map_err(to_vulkan)?;
+
+            // same buffer handle, the command buffers stay valid
+            write_host_visible_memory(
+                &self.ctx,
+                swapchain.ctx.vertex_buffer_memory,
+                swapchain.ctx.vertex_buffer_coherent,
+                &self.scene_vertices,
+            )?;
         }
 
-        swapchain_image.in_flight_fence = current_inflight_frame.in_flight_fence;
+        Ok(())
+    }
 
-        let command_buffers = [swapchain_image.command_buffer];
+    /// Replaces the scene with a triangle list of any size, e.g. `triangle_grid` for stress
+    /// tests. The vertex buffer is sized for it with the next swapchain.
+    pub fn set_scene_vertices(&mut self, vertices: Vec<Vertex>) -> Result<()> {
+        if vertices.is_empty() || vertices.len() % 3 != 0 {
+            return Err(Error::Other(format!(
+                "{} vertices are no triangle list",
+                vertices.len()
+            )));
+        }
 
-        let wait_dst_stage_mask = [vk::PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT];
+        let indices = sequential_indices(vertices.len());
+        self.set_scene_indexed(vertices, indices)
+    }
 
-        let wait_semaphores = [current_inflight_frame.available_semaphore];
-        let signal_semaphores = [current_inflight_frame.rendered_semaphore];
+    /// Model view projection the scene vertices are transformed with in the vertex shader,
+    /// written into the uniform buffer of each frame before it is submitted. Identity by default.
+    pub fn set_scene_mvp(&mut self, mvp: Mat4) {
+        self.scene_mvp = mvp;
+    }
 
-        let submit_info = vk::SubmitInfo {
-            sType: vk::STRUCTURE_TYPE_SUBMIT_INFO,
-            pNext: std::ptr::null(),
-            waitSemaphoreCount: wait_semaphores.len() as u32,
-            pWaitSemaphores: wait_semaphores.as_ptr(),
-            pWaitDstStageMask: wait_dst_stage_mask.as_ptr(),
-            commandBufferCount: command_buffers.len() as u32,
-            pCommandBuffers: command_buffers.as_ptr(),
-            signalSemaphoreCount: signal_semaphores.len() as u32,
-            pSignalSemaphores: signal_semaphores.as_ptr(),
+    /// Sets the scene MVP like `set_scene_mvp` and writes it into the uniform buffer of swapchain
+    /// image `image_index` right away, instead of before the image's next frame. Waits for the
+    /// image's last frame. Without a swapchain only the scene MVP is set.
+    pub fn update_uniform(&mut self, image_index: u32, mvp: Mat4) -> Result<()> {
+        self.set_scene_mvp(mvp);
+
+        let swapchain = match &self.sc_ctx {
+            Some(swapchain) => swapchain,
+            None => return Ok(()),
         };
 
+        let swapchain_image = swapchain.images.get(image_index as usize).ok_or_else(|| {
+            to_other(format!(
+                "invalid swapchain image index {} of {} images",
+                image_index,
+                swapchain.images.len()
+            ))
+        })?;
+
+        if swapchain_image.in_flight_fence != vk::NULL_HANDLE
+            && self
+                .ctx
+                .wait_for_fence(swapchain_image.in_flight_fence, self.fence_timeout)?
+                == Status::Timeout
+        {
+            return Err(to_vulkan(vk::TIMEOUT));
+        }
+
+        swapchain_image.scene_uniforms.write(
+            &self.ctx,
+            &viewport_uniforms(
+                &self.scene_mvp,
+                &self.viewport_mvps,
+                swapchain.ctx.viewports.len(),
+            ),
+        )
+    }
+
+    /// Model view projection per viewport, in the order of `set_viewports`, e.g. one camera per
+    /// player in split screen. Viewports without one use the scene MVP. Like the scene MVP it is
+    /// written before each frame, the swapchain is not recreated.
+    pub fn set_viewport_mvps(&mut self, mvps: Vec<Mat4>) {
+        self.viewport_mvps = mvps;
+    }
+
+    /// Draws the scene once per model matrix, pushed as push constant before each draw (e.g. the
+    /// same chunk geometry at several positions). `[identity]` by default.
+    pub fn set_scene_models(&mut self, models: Vec<Mat4>) -> Result<()> {
+        if models.is_empty() {
+            return Err(Error::Other(
+                "the scene needs at least one model matrix".to_owned(),
+            ));
+        }
+
+        self.scene_models = models;
+
+        // pushed in the command buffers
+        self.invalidate_swapchain()
+    }
+
+    /// Replaces the textures of the mesh pipeline with `layers` RGBA images (see
+    /// `MESH_TEXTURE_FORMAT`) of `width` x `height`, tightly packed one after the other in
+    /// `texels`. The `tex_id` of a `MeshVertex` selects the layer. A single white layer by
+    /// default. Waits for the device to idle.
+    pub fn set_mesh_textures(
+        &mut self,
+        width: u32,
+        height: u32,
+        layers: u32,
+        texels: &[u8],
+    ) -> Result<()> {
+        let mesh_textures = MeshTextures::new(&self.ctx, width, height, layers, texels)?;
+
+        // bound in the command buffers
         self.ctx
             .dp
-            .reset_fences(self.ctx.device, &[current_inflight_frame.in_flight_fence])
+            .device_wait_idle(self.ctx.device)
             .map_err(to_vulkan)?;
+        if let Some(old_mesh_textures) = self.mesh_textures.replace(mesh_textures) {
+            old_mesh_textures.destroy(&self.ctx);
+        }
 
-        unsafe {
-            self.ctx.dp.queue_submit(
-                self.ctx.queue_families.graphics_queue,
-                &[submit_info],
-                current_inflight_frame.in_flight_fence,
-            )
+        self.invalidate_swapchain()
+    }
+
+    /// Replaces the scene with `vertices` shared by the triangles of `indices` (a triangle list),
+    /// e.g. quads of 4 vertices and 6 indices. The buffers are sized for it with the next
+    /// swapchain.
+    pub fn set_scene_indexed(&mut self, vertices: Vec<Vertex>, indices: Vec<u32>) -> Result<()> {
+        if indices.is_empty() || indices.len() % 3 != 0 {
+            return Err(Error::Other(format!(
+                "{} indices are no triangle list",
+                indices.len()
+            )));
         }
-        .map_err(to_vulkan)?;
 
-        let swapchains = [swapchain.ctx.swapchain];
-
-        let present_info = vk::PresentInfoKHR {
-            sType: vk::STRUCTURE_TYPE_PRESENT_INFO_KHR,
-            pNext: std::ptr::null(),
-            waitSemaphoreCount: signal_semaphores.len() as u32,
-            pWaitSemaphores: signal_semaphores.as_ptr(),
-            swapchainCount: swapchains.len() as u32,
-            pSwapchains: swapchains.as_ptr(),
-            pImageIndices: &image_index_index,
-            pResults: std::ptr::null_mut(),
+        if let Some(index) = indices
+            .iter()
+            .find(|index| **index as usize >= vertices.len())
+        {
+            return Err(Error::Other(format!(
+                "index {} out of range of {} vertices",
+                index,
+                vertices.len()
+            )));
+        }
+
+        self.scene_vertices = vertices;
+        self.scene_indices = indices;
+        if self.settings.winding_check {
+            check_winding(&self.scene_vertices, &self.scene_indices);
+        }
+
+        self.invalidate_swapchain()
+    }
+
+    /// Color the scene is cleared to with `LoadOp::Clear`, RGBA in the color space of the scene
+    /// image. Transparent black by default.
+    pub fn set_clear_color(&mut self, clear_color: [f32; 4]) -> Result<()> {
+        self.settings.clear_color = clear_color;
+        self.invalidate_swapchain()
+    }
+
+    /// Depth the depth attachment is cleared to, `None` is the far plane (`1.0`). Reversed-Z
+    /// clears to `0.0` instead.
+    pub fn set_clear_depth(&mut self, clear_depth: Option<f32>) -> Result<()> {
+        if let Some(depth) = clear_depth {
+            // outside of it needs VK_EXT_depth_range_unrestricted
+            if !(0.0..=1.0).contains(&depth) {
+                return Err(Error::Other(format!(
+                    "clear depth {} not in 0.0..=1.0",
+                    depth
+                )));
+            }
+        }
+
+        self.settings.clear_depth = clear_depth;
+        self.invalidate_swapchain()
+    }
+
+    /// Fails the next swapchain creation instead of falling back to another surface format if
+    /// `B8G8R8A8_SRGB` (sRGB color space) isn't supported. Off by default.
+    pub fn set_require_srgb_format(&mut self, require_srgb_format: bool) -> Result<()> {
+        self.settings.require_srgb_format = require_srgb_format;
+        self.invalidate_swapchain()
+    }
+
+    /// Usage of the scene vertex buffer besides `VERTEX_BUFFER`, e.g. `STORAGE_BUFFER` for
+    /// compute shaders generating geometry. None (`0`) by default.
+    pub fn set_vertex_buffer_usage(&mut self, extra_usage: vk::BufferUsageFlags) -> Result<()> {
+        check_vertex_buffer_usage(extra_usage)?;
+
+        self.settings.vertex_buffer_usage = extra_usage;
+        self.invalidate_swapchain()
+    }
+
+    /// Vertex buffer of the current swapchain, e.g. for a compute shader writing the scene with
+    /// `set_vertex_buffer_usage(STORAGE_BUFFER)`. Replaced with the swapchain.
+    pub fn scene_vertex_buffer(&self) -> Option<vk::Buffer> {
+        self.sc_ctx
+            .as_ref()
+            .map(|swapchain| swapchain.ctx.vertex_buffer)
+    }
+
+    /// Draws the scene without back face culling and warns if many scene triangles would have
+    /// been culled, e.g. because of a y flip somewhere. Meant for debugging.
+    pub fn set_winding_check(&mut self, winding_check: bool) -> Result<()> {
+        if winding_check {
+            check_winding(&self.scene_vertices, &self.scene_indices);
+        }
+
+        self.settings.winding_check = winding_check;
+        self.invalidate_swapchain()
+    }
+
+    /// Replaces all particles drawn as point sprites after the scene.
+    pub fn set_particles(&mut self, particles: Vec<ParticleVertex>) -> Result<()> {
+        self.particles = particles;
+
+        // particles are baked into the command buffers
+        self.invalidate_swapchain()
+    }
+
+    /// Depth buffer for the scene render pass, in the first supported of `D32_SFLOAT`,
+    /// `D32_SFLOAT_S8_UINT` and `D24_UNORM_S8_UINT`, cleared to `clear_depth`. Off by default.
+    pub fn set_depth_test(&mut self, depth_test: bool) -> Result<()> {
+        self.settings.depth_test = depth_test;
+        self.invalidate_swapchain()
+    }
+
+    /// Depth at pixel `(x, y)` of the frame submitted last, e.g. to pick what the cursor points
+    /// at with `unproject`. In framebuffer pixels (not glfw's screen coordinates), scaled to the
+    /// depth buffer with a render scale. `clear_depth` where nothing was drawn. Needs
+    /// `set_depth_test(true)`, waits for the frame to finish.
+    pub fn read_depth_at(&self, x: u32, y: u32) -> Result<f32> {
+        let swapchain = match (&self.sc_ctx, self.last_submit) {
+            (Some(swapchain), Some((_, image_index))) => swapchain
+                .images
+                .get(image_index as usize)
+                // images of a recreated swapchain are not rendered until they get a fence
+                .filter(|image| image.in_flight_fence != vk::NULL_HANDLE)
+                .map(|image| (&swapchain.ctx, image)),
+            _ => None,
         };
+        let (sc_ctx, image) =
+            swapchain.ok_or_else(|| to_other("no frame rendered to read the depth of"))?;
 
-        let present_result = unsafe {
-            self.ctx
-                .dp
-                .queue_present_khr(self.ctx.queue_families.present_queue, &present_info)
-                .map_err(to_vulkan)
+        let (depth_format, depth_buffer) = match (sc_ctx.depth_format, &image.depth_buffer) {
+            (Some(depth_format), Some(depth_buffer)) => (depth_format, depth_buffer),
+            _ => return Err(to_other("no depth buffer, see `set_depth_test`")),
         };
-        match present_result {
-            Ok(_) => {
-                // go on
+
+        let (texel_x, texel_y) = render_texel((x, y), &sc_ctx.extent, &sc_ctx.render_extent)
+            .ok_or_else(|| {
+                Error::Other(format!(
+                    "pixel ({}, {}) outside of the {}x{} swapchain",
+                    x, y, sc_ctx.extent.width, sc_ctx.extent.height
+                ))
+            })?;
+
+        depth_buffer.read_texel(&self.ctx, depth_format, texel_x, texel_y)
+    }
+
+    pub fn set_depth_bias(&mut self, depth_bias: Option<DepthBias>) -> Result<()> {
+        self.settings.depth_bias = depth_bias;
+        self.invalidate_swapchain()
+    }
+
+    /// Shades at least `min_sample_shading * samples` samples per pixel individually, reducing
+    /// aliasing inside of textured surfaces. Needs the `sampleRateShading` feature.
+    pub fn set_sample_shading(&mut self, min_sample_shading: Option<f32>) -> Result<()> {
+        if let Some(min_sample_shading) = min_sample_shading {
+            if !(0.0..=1.0).contains(&min_sample_shading) {
+                return Err(Error::Other(format!(
+                    "invalid min sample shading {}",
+                    min_sample_shading
+                )));
             }
-            Err(Error::VulkanError(vk::ERROR_OUT_OF_DATE_KHR)) => {
-                self.destroy_swapchain()?;
-                return Ok(());
+            if self.ctx.enabled_features.sampleRateShading != vk::TRUE {
+                return Err(Error::Other(
+                    "sample shading needs the sampleRateShading feature".to_owned(),
+                ));
             }
-            Err(err) => {
-                return Err(err);
+        }
+
+        self.settings.sample_shading = min_sample_shading;
+        self.invalidate_swapchain()
+    }
+
+    pub fn set_render_area(&mut self, render_area: Option<vk::Rect2D>) -> Result<()> {
+        self.settings.render_area = render_area;
+        self.invalidate_swapchain()
+    }
+
+    /// Draws the scene once per viewport, e.g. `[left, right]` halves for two player split
+    /// screen, with the MVPs of `set_viewport_mvps`. Viewports must be within the framebuffer,
+    /// they share the depth buffer, so they shouldn't overlap.
+    pub fn set_viewports(&mut self, viewports: Vec<vk::Rect2D>) -> Result<()> {
+        self.settings.viewports = viewports;
+        self.invalidate_swapchain()
+    }
+
+    /// Makes the render pass wait for earlier submitted work, e.g. a compute dispatch writing
+    /// vertices (`ExternalDependency::COMPUTE_TO_VERTEX_INPUT`).
+    pub fn set_external_dependencies(
+        &mut self,
+        dependencies: Vec<ExternalDependency>,
+    ) -> Result<()> {
+        self.settings.external_dependencies = dependencies;
+        self.invalidate_swapchain()
+    }
+
+    /// Renders at a fraction (or multiple) of the framebuffer resolution, e.g. `Some(0.5)` on weak
+    /// GPUs. Render area and viewports are then relative to the scaled extent.
+    pub fn set_render_scale(&mut self, render_scale: Option<f32>) -> Result<()> {
+        if let Some(scale) = render_scale {
+            if !(scale.is_finite() && scale > 0.0) {
+                return Err(Error::Other(format!("invalid render scale {}", scale)));
             }
         }
 
-        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+        self.settings.render_scale = render_scale;
+        self.invalidate_swapchain()
+    }
 
-        Ok(())
+    /// Load and store op of the scene color attachment, `LoadOp::Load` renders offscreen.
+    pub fn set_color_ops(&mut self, load_op: LoadOp, store_op: StoreOp) -> Result<()> {
+        self.settings.color_load_op = load_op;
+        self.settings.color_store_op = store_op;
+        self.invalidate_swapchain()
     }
 
-    pub fn on_framebuffer_changed(&mut self) -> Result<()> {
-        if self.sc_ctx.is_some() {
-            self.destroy_swapchain()?;
+    /// Renders the scene into a float target and tonemaps it, `None` renders in LDR.
+    pub fn set_tonemap(&mut self, tonemap: Option<Tonemap>) -> Result<()> {
+        self.settings.tonemap = tonemap;
+        self.invalidate_swapchain()
+    }
+
+    /// Bloom: scene colors above the threshold are blurred and added by the tonemap pass, so
+    /// it needs `set_tonemap` as well. `None` turns it off.
+    pub fn set_post_process(&mut self, post_process: Option<PostProcess>) -> Result<()> {
+        if let Some(post_process) = &post_process {
+            check_post_process(post_process)?;
+        }
+
+        self.settings.post_process = post_process;
+        self.invalidate_swapchain()
+    }
+
+    /// Hands rendered images to `present_to` instead of presenting them, `None` presents to the
+    /// window surface.
+    pub fn set_present_to(&mut self, present_to: Option<PresentTo>) {
+        self.present_to = present_to;
+    }
+
+    /// Presentation modes in the order of preference, the first one the surface supports is
+    /// used. FIFO if none is, it is always supported.
+    pub fn set_present_mode_preference(
+        &mut self,
+        present_mode_preference: Vec<vk::PresentModeKHR>,
+    ) -> Result<()> {
+        self.settings.present_mode_preference = present_mode_preference;
+        self.invalidate_swapchain()
+    }
+
+    /// Presentation mode, or a goal to choose one by, see `PresentMode::preference`.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> Result<()> {
+        self.set_present_mode_preference(present_mode.preference())
+    }
+
+    /// Mode of the current swapchain, `None` without one.
+    pub fn present_mode(&self) -> Option<vk::PresentModeKHR> {
+        self.sc_ctx
+            .as_ref()
+            .map(|swapchain| swapchain.ctx.present_mode)
+    }
+
+    /// Fewer swapchain images mean fewer frames queued for presentation and less input latency.
+    pub fn set_desired_image_count(&mut self, image_count: Option<u32>) -> Result<()> {
+        self.settings.desired_image_count = image_count;
+        self.invalidate_swapchain()
+    }
+
+    /// Rebuilds the swapchain and everything depending on it with the current settings, after
+    /// all frames in flight finished. The old swapchain is handed to the new one, so the
+    /// presentation engine can reuse its resources.
+    ///
+    /// Setters only mark the swapchain outdated, it is recreated before the next frame.
+    pub fn recreate_swapchain(&mut self, window: &glfw::Window) -> Result<()> {
+        self.ctx
+            .dp
+            .device_wait_idle(self.ctx.device)
+            .map_err(to_vulkan)?;
+
+        let mesh_textures = self
+            .mesh_textures
+            .as_ref()
+            .ok_or_else(|| to_other("a swapchain needs a window"))?;
+
+        let mesh_draws = self.mesh_draws();
+
+        let old_swapchain = self.sc_ctx.take();
+        let old_handle = old_swapchain
+            .as_ref()
+            .map_or(vk::NULL_HANDLE, |old_swapchain| old_swapchain.ctx.swapchain);
+        let swapchain = Swapchain::new(
+            &self.ctx,
+            window,
+            &self.settings,
+            &self.particles,
+            &self.scene_vertices,
+            &self.scene_indices,
+            &self.scene_models,
+            mesh_textures,
+            &mesh_draws,
+            old_handle,
+        );
+
+        // retired by now, whether the new swapchain was created or not
+        if let Some(old_swapchain) = old_swapchain {
+            old_swapchain.destroy(&self.ctx)?;
+        }
+
+        self.sc_ctx = Some(swapchain?);
+        self.swapchain_outdated = false;
+        self.command_buffers_outdated = false;
+        if old_handle != vk::NULL_HANDLE {
+            self.retired_swapchains += 1;
         }
 
         Ok(())
     }
 
-    fn create_swapchain(&mut self, window: &glfw::Window) -> Result<()> {
-        assert!(self.sc_ctx.is_none());
+    /// Records the command buffers of the swapchain images again with the current render list.
+    /// Cheaper than recreating the swapchain, the images and pipelines stay.
+    fn rerecord_command_buffers(&mut self) -> Result<()> {
+        let mesh_draws = self.mesh_draws();
+
+        if let Some(swapchain) = self.sc_ctx.as_mut() {
+            // frames in flight may still execute the command buffers
+            self.ctx
+                .dp
+                .device_wait_idle(self.ctx.device)
+                .map_err(to_vulkan)?;
 
-        self.sc_ctx = Some(Swapchain::new(&self.ctx, window)?);
+            swapchain.ctx.mesh_draws = mesh_draws;
+            for image in &mut swapchain.images {
+                image.rerecord(&self.ctx, &swapchain.ctx)?;
+            }
+        }
+
+        self.command_buffers_outdated = false;
 
         Ok(())
     }
 
-    fn destroy_swapchain(&mut self) -> Result<()> {
-        let swapchain = self.sc_ctx.take().unwrap();
-        swapchain.destroy(&self.ctx)
+    fn invalidate_swapchain(&mut self) -> Result<()> {
+        self.swapchain_outdated = true;
+
+        Ok(())
+    }
+
+    /// Gives up an acquired image without rendering it. The acquire still signals
+    /// `available_semaphore`, an empty submit waits on it, so the next acquire taking it finds
+    /// it unsignaled. The image only comes back with the recreated swapchain, whose recreation
+    /// waits for the device to idle.
+    fn abandon_acquired_image(&mut self, available_semaphore: vk::Semaphore) -> Result<()> {
+        SubmitBatch::default()
+            .wait(available_semaphore, vk::PIPELINE_STAGE_ALL_COMMANDS_BIT)
+            .queue_submit(
+                &self.ctx,
+                self.ctx.queue_families.graphics_queue,
+                vk::NULL_HANDLE,
+            )?;
+
+        self.invalidate_swapchain()
     }
 }
 
 impl Swapchain {
-    fn new(ctx: &Context, window: &glfw::Window) -> Result<Self> {
-        let (swapchain, surface_format, _, extent) = create_swapchain(ctx, window)?;
-        let render_pass = create_render_pass(ctx, &surface_format)?;
+    fn new(
+        ctx: &Context,
+        window: &glfw::Window,
+        settings: &SwapchainSettings,
+        particles: &[ParticleVertex],
+        triangle: &[Vertex],
+        indices: &[u32],
+        models: &[Mat4],
+        mesh_textures: &MeshTextures,
+        mesh_draws: &[MeshDraw],
+        old_swapchain: vk::SwapchainKHR,
+    ) -> Result<Self> {
+        let (swapchain, surface_format, present_mode, extent) =
+            create_swapchain(ctx, window, settings, old_swapchain)?;
+        let swapchain = handle::swapchain(ctx, swapchain);
+
+        let scene_output = scene_output(settings);
+        let scene_format = match scene_output {
+            SceneOutput::Tonemap => HDR_FORMAT,
+            SceneOutput::Swapchain | SceneOutput::Blit => surface_format.format,
+        };
 
-        let (vertex_shader_module, fragment_shader_module, pipeline_layout, pipeline) =
-            create_graphics_pipeline(ctx, &extent, render_pass)?;
+        let render_extent = match settings.render_scale {
+            Some(scale) => scaled_extent(&extent, scale, ctx.limits.maxImageDimension2D),
+            None => copy_extent_2d(&extent),
+        };
+
+        let depth_format = if settings.depth_test {
+            Some(find_supported_depth_format(ctx, &DEPTH_FORMAT_CANDIDATES)?)
+        } else {
+            None
+        };
+
+        let attachments = scene_attachments(scene_format, depth_format, scene_output, settings);
+        let render_pass = handle::render_pass(
+            ctx,
+            create_render_pass(
+                ctx,
+                &attachments,
+                &settings.external_dependencies,
+                scene_output,
+            )?,
+        );
+
+        let render_area = choose_render_area(settings, &render_extent)?;
+        let viewports = choose_viewports(settings, &render_area, &render_extent)?;
 
-        let (vertex_buffer, vertex_buffer_memory) = create_vertex_buffer(ctx)?;
+        let images = ctx
+            .dp
+            .get_swapchain_images_khr(ctx.device, *swapchain)
+            .map_err(to_vulkan)?;
+        info!("swapchain created with {} images", images.len());
+
+        let scene_uniforms =
+            handle::scene_uniform_sets(ctx, SceneUniformSets::new(ctx, images.len() as u32)?);
+
+        let pipeline = handle::pipeline(
+            ctx,
+            create_triangle_pipeline(
+                ctx,
+                *render_pass,
+                settings,
+                scene_uniforms.descriptor_set_layout,
+            )?,
+        );
+        let particle_pipeline = handle::pipeline(
+            ctx,
+            create_particle_pipeline(ctx, *render_pass, settings.depth_test)?,
+        );
+        let mesh_pipeline = handle::pipeline(
+            ctx,
+            create_mesh_pipeline(
+                ctx,
+                *render_pass,
+                scene_uniforms.descriptor_set_layout,
+                mesh_textures.descriptor_set_layout,
+                false,
+                settings.depth_test,
+            )?,
+        );
+        let translucent_mesh_pipeline = handle::pipeline(
+            ctx,
+            create_mesh_pipeline(
+                ctx,
+                *render_pass,
+                scene_uniforms.descriptor_set_layout,
+                mesh_textures.descriptor_set_layout,
+                true,
+                settings.depth_test,
+            )?,
+        );
+
+        let (vertex_buffer, vertex_buffer_memory, vertex_buffer_coherent) =
+            create_vertex_buffer(ctx, triangle, settings.vertex_buffer_usage)?;
+        let (index_buffer, index_buffer_memory) = create_index_buffer(ctx, indices)?;
+        let (particle_buffer, particle_buffer_memory) = if particles.is_empty() {
+            (
+                handle::buffer(ctx, vk::NULL_HANDLE),
+                handle::memory(ctx, vk::NULL_HANDLE),
+            )
+        } else {
+            let (buffer, memory, _) = create_vertex_buffer(ctx, particles, 0)?;
+            (buffer, memory)
+        };
+
+        let post_process_pass = match (settings.post_process, settings.tonemap) {
+            (Some(post_process), Some(_)) => Some(handle::post_process_pass(
+                ctx,
+                PostProcessPass::new(ctx, post_process, images.len() as u32)?,
+            )),
+            (Some(_), None) => {
+                warn!("bloom is added by the tonemap pass, it is off without tonemapping");
+                None
+            }
+            (None, _) => None,
+        };
+
+        let bloom_intensity = post_process_pass
+            .as_ref()
+            .map(|post_process_pass| post_process_pass.post_process.intensity)
+            .unwrap_or(0.0);
+
+        let tonemap_pass = match settings.tonemap {
+            Some(tonemap) => Some(handle::tonemap_pass(
+                ctx,
+                TonemapPass::new(
+                    ctx,
+                    tonemap,
+                    bloom_intensity,
+                    surface_format.format,
+                    images.len() as u32,
+                )?,
+            )),
+            None => None,
+        };
 
         let sc_ctx = SwapchainContext {
-            pipeline,
-            pipeline_layout,
-            render_pass,
-            swapchain,
-            vertex_shader_module,
-            fragment_shader_module,
-            vertex_buffer,
-            vertex_buffer_memory,
+            pipeline: pipeline.release(),
+            particle_pipeline: particle_pipeline.release(),
+            mesh_pipeline: mesh_pipeline.release(),
+            translucent_mesh_pipeline: translucent_mesh_pipeline.release(),
+            mesh_texture_set: mesh_textures.descriptor_set,
+            mesh_draws: mesh_draws.to_vec(),
+            render_pass: render_pass.release(),
+            clear_values: clear_values(&attachments, settings),
+            swapchain: swapchain.release(),
+            vertex_buffer: vertex_buffer.release(),
+            vertex_buffer_memory: vertex_buffer_memory.release(),
+            vertex_buffer_coherent,
+            index_buffer: index_buffer.release(),
+            index_buffer_memory: index_buffer_memory.release(),
+            index_count: indices.len() as u32,
+            models: models.to_vec(),
+            particle_buffer: particle_buffer.release(),
+            particle_buffer_memory: particle_buffer_memory.release(),
+            particle_count: particles.len() as u32,
             extent,
+            render_extent,
+            scene_output,
+            color_load_op: settings.color_load_op,
+            tonemap_pass: tonemap_pass.map(|tonemap_pass| tonemap_pass.release()),
+            post_process_pass: post_process_pass
+                .map(|post_process_pass| post_process_pass.release()),
+            scene_uniforms: scene_uniforms.release(),
+            render_area,
+            viewports,
             surface_format,
+            depth_format,
+            present_mode,
         };
 
-        let images = ctx
-            .dp
-            .get_swapchain_images_khr(ctx.device, swapchain)
-            .map_err(to_vulkan)?;
+        let mut swapchain = Self {
+            images: Vec::with_capacity(images.len()),
+            ctx: sc_ctx,
+        };
 
-        let mut swapchain_images = Vec::<SwapchainImage>::with_capacity(images.len());
         for image in &images {
-            let swapchain_image = SwapchainImage::new(ctx, &sc_ctx, *image)?;
-            swapchain_images.push(swapchain_image);
+            match SwapchainImage::new(ctx, &swapchain.ctx, *image) {
+                Ok(swapchain_image) => swapchain.images.push(swapchain_image),
+                Err(err) => {
+                    swapchain.destroy(ctx)?;
+                    return Err(err);
+                }
+            }
         }
 
-        Ok(Self {
-            images: swapchain_images,
-            ctx: sc_ctx,
-        })
+        Ok(swapchain)
     }
 
     pub fn destroy(self, ctx: &Context) -> Result<()> {
@@ -223,22 +1040,44 @@ impl Swapchain {
         ctx.dp
             .free_memory(ctx.device, self.ctx.vertex_buffer_memory);
         ctx.dp.destroy_buffer(ctx.device, self.ctx.vertex_buffer);
+        ctx.dp.free_memory(ctx.device, self.ctx.index_buffer_memory);
+        ctx.dp.destroy_buffer(ctx.device, self.ctx.index_buffer);
+        ctx.dp
+            .free_memory(ctx.device, self.ctx.particle_buffer_memory);
+        ctx.dp.destroy_buffer(ctx.device, self.ctx.particle_buffer);
 
         for image in &self.images {
             ctx.dp.destroy_framebuffer(ctx.device, image.framebuffer);
             ctx.dp.destroy_image_view(ctx.device, image.image_view);
+            if let Some(render_target) = &image.render_target {
+                render_target.destroy(ctx);
+            }
+            if let Some(tonemap_image) = &image.tonemap_image {
+                tonemap_image.destroy(ctx);
+            }
+            if let Some(post_process_image) = &image.post_process_image {
+                post_process_image.destroy(ctx);
+            }
+            if let Some(depth_buffer) = &image.depth_buffer {
+                depth_buffer.destroy(ctx);
+            }
+            image.scene_uniforms.destroy(ctx);
             ctx.dp
                 .free_command_buffers(ctx.device, ctx.command_pool, &[image.command_buffer]);
         }
 
-        ctx.dp.destroy_pipeline(ctx.device, self.ctx.pipeline);
-        ctx.dp
-            .destroy_pipeline_layout(ctx.device, self.ctx.pipeline_layout);
+        self.ctx.pipeline.destroy(ctx);
+        self.ctx.particle_pipeline.destroy(ctx);
+        self.ctx.mesh_pipeline.destroy(ctx);
+        self.ctx.translucent_mesh_pipeline.destroy(ctx);
+        if let Some(tonemap_pass) = &self.ctx.tonemap_pass {
+            tonemap_pass.destroy(ctx);
+        }
+        if let Some(post_process_pass) = &self.ctx.post_process_pass {
+            post_process_pass.destroy(ctx);
+        }
+        self.ctx.scene_uniforms.destroy(ctx);
         ctx.dp.destroy_render_pass(ctx.device, self.ctx.render_pass);
-        ctx.dp
-            .destroy_shader_module(ctx.device, self.ctx.vertex_shader_module);
-        ctx.dp
-            .destroy_shader_module(ctx.device, self.ctx.fragment_shader_module);
         ctx.dp.destroy_swapchain_khr(ctx.device, self.ctx.swapchain);
 
         Ok(())
@@ -247,32 +1086,174 @@ impl Swapchain {
 
 impl SwapchainImage {
     fn new(ctx: &Context, sc_ctx: &SwapchainContext, image: vk::Image) -> Result<Self> {
-        let image_view =
-            create_image_view(&ctx.dp, ctx.device, image, sc_ctx.surface_format.format)?;
-        let framebuffer = create_framebuffer(
-            &ctx.dp,
-            ctx.device,
-            sc_ctx.render_pass,
-            image_view,
-            &sc_ctx.extent,
-        )?;
-        let command_buffer = create_command_buffer(ctx, sc_ctx, framebuffer)?;
+        let image_view = handle::image_view(
+            ctx,
+            create_image_view(&ctx.dp, ctx.device, image, sc_ctx.surface_format.format)?,
+        );
+        let render_target = match sc_ctx.scene_output {
+            SceneOutput::Swapchain => None,
+            SceneOutput::Blit => Some((
+                sc_ctx.surface_format.format,
+                vk::IMAGE_USAGE_TRANSFER_SRC_BIT,
+            )),
+            SceneOutput::Tonemap => Some((HDR_FORMAT, vk::IMAGE_USAGE_SAMPLED_BIT)),
+        }
+        .map(|(format, usage)| {
+            RenderTarget::new(ctx, &sc_ctx.render_extent, format, usage)
+                .map(|render_target| handle::render_target(ctx, render_target))
+        })
+        .transpose()?;
+
+        // a loading render pass expects the image in its final layout from the previous frame
+        if let (LoadOp::Load, Some(render_target)) = (sc_ctx.color_load_op, &render_target) {
+            ctx.submit_one_time(|command_buffer| {
+                cmd_transition_color_image(
+                    ctx,
+                    command_buffer,
+                    render_target.image,
+                    (
+                        vk::IMAGE_LAYOUT_UNDEFINED,
+                        scene_final_layout(sc_ctx.scene_output),
+                    ),
+                    (0, 0),
+                    (
+                        vk::PIPELINE_STAGE_TOP_OF_PIPE_BIT,
+                        vk::PIPELINE_STAGE_BOTTOM_OF_PIPE_BIT,
+                    ),
+                )
+            })?;
+        }
+
+        let post_process_image = match (&sc_ctx.post_process_pass, &render_target) {
+            (Some(post_process_pass), Some(render_target)) => Some(handle::post_process_image(
+                ctx,
+                post_process_pass.create_image(ctx, render_target, &sc_ctx.render_extent)?,
+            )),
+            _ => None,
+        };
+
+        let tonemap_image = match (&sc_ctx.tonemap_pass, &render_target) {
+            (Some(tonemap_pass), Some(render_target)) => Some(handle::tonemap_image(
+                ctx,
+                tonemap_pass.create_image(
+                    ctx,
+                    render_target,
+                    post_process_image
+                        .as_ref()
+                        .map(|post_process_image| &post_process_image.bloom),
+                    *image_view,
+                    &sc_ctx.extent,
+                )?,
+            )),
+            _ => None,
+        };
+
+        let scene_uniforms = handle::scene_uniform_image(
+            ctx,
+            sc_ctx
+                .scene_uniforms
+                .create_image(ctx, sc_ctx.viewports.len() as u32)?,
+        );
+
+        // one per image, frames in flight must not share it
+        let depth_buffer = sc_ctx
+            .depth_format
+            .map(|format| {
+                DepthBuffer::new(ctx, &sc_ctx.render_extent, format)
+                    .map(|depth_buffer| handle::depth_buffer(ctx, depth_buffer))
+            })
+            .transpose()?;
+
+        // in the order of the render pass attachments
+        let mut attachments = vec![match &render_target {
+            Some(render_target) => render_target.image_view,
+            None => *image_view,
+        }];
+        if let Some(depth_buffer) = &depth_buffer {
+            attachments.push(depth_buffer.image_view);
+        }
+
+        let framebuffer = handle::framebuffer(
+            ctx,
+            create_framebuffer(
+                &ctx.dp,
+                ctx.device,
+                sc_ctx.render_pass,
+                &attachments,
+                &sc_ctx.render_extent,
+            )?,
+        );
+
+        let final_pass = final_pass(
+            sc_ctx,
+            image,
+            render_target.as_deref(),
+            tonemap_image.as_deref(),
+            post_process_image.as_deref(),
+        );
+        let command_buffer =
+            create_command_buffer(ctx, sc_ctx, *framebuffer, &scene_uniforms, final_pass)?;
 
         Ok(Self {
-            framebuffer,
-            image_view,
+            image,
+            render_target: render_target.map(|render_target| render_target.release()),
+            tonemap_image: tonemap_image.map(|tonemap_image| tonemap_image.release()),
+            post_process_image: post_process_image
+                .map(|post_process_image| post_process_image.release()),
+            depth_buffer: depth_buffer.map(|depth_buffer| depth_buffer.release()),
+            scene_uniforms: scene_uniforms.release(),
+            framebuffer: framebuffer.release(),
+            image_view: image_view.release(),
             command_buffer,
             in_flight_fence: vk::NULL_HANDLE,
         })
     }
+
+    /// Replaces the command buffer with a newly recorded one, e.g. for a changed render list.
+    /// The old one must not be executing anymore.
+    fn rerecord(&mut self, ctx: &Context, sc_ctx: &SwapchainContext) -> Result<()> {
+        let final_pass = final_pass(
+            sc_ctx,
+            self.image,
+            self.render_target.as_ref(),
+            self.tonemap_image.as_ref(),
+            self.post_process_image.as_ref(),
+        );
+        let command_buffer = create_command_buffer(
+            ctx,
+            sc_ctx,
+            self.framebuffer,
+            &self.scene_uniforms,
+            final_pass,
+        )?;
+
+        ctx.dp
+            .free_command_buffers(ctx.device, ctx.command_pool, &[self.command_buffer]);
+        self.command_buffer = command_buffer;
+
+        Ok(())
+    }
 }
 
 impl InFlightFrame {
     pub fn new(ctx: &Context) -> Result<Self> {
+        let available_semaphore = ctx.create_semaphore()?;
+
+        let rendered_semaphore = ctx.create_semaphore().map_err(|err| {
+            ctx.destroy_semaphore(available_semaphore);
+            err
+        })?;
+
+        let in_flight_fence = ctx.create_signaled_fence().map_err(|err| {
+            ctx.destroy_semaphore(available_semaphore);
+            ctx.destroy_semaphore(rendered_semaphore);
+            err
+        })?;
+
         Ok(Self {
-            available_semaphore: ctx.create_semaphore()?,
-            rendered_semaphore: ctx.create_semaphore()?,
-            in_flight_fence: ctx.create_signaled_fence()?,
+            available_semaphore,
+            rendered_semaphore,
+            in_flight_fence,
         })
     }
 
@@ -283,23 +1264,326 @@ impl InFlightFrame {
     }
 }
 
-fn create_render_pass(ctx: &Context, format: &vk::SurfaceFormatKHR) -> Result<vk::RenderPass> {
+/// Waits until the last submit of the frame in flight finished, so its resources can be reused.
+fn wait_for_frame(
+    ctx: &Context,
+    frame: &InFlightFrame,
+    frame_index: usize,
+    fence_timeout: Duration,
+) -> Result<()> {
+    if ctx.wait_for_fence(frame.in_flight_fence, fence_timeout)? == Status::Timeout {
+        warn!(
+            "frame in flight {} did not finish within {:?} (fence {:#x})",
+            frame_index, fence_timeout, frame.in_flight_fence
+        );
+        return Err(to_vulkan(vk::TIMEOUT));
+    }
+
+    Ok(())
+}
+
+/// How `draw_frame` paces the CPU.
+///
+/// Waiting for the display itself (`VK_KHR_present_wait`) would pace more precisely, but the
+/// bindings don't know the extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LatencyMode {
+    /// waits for the frame in flight, then acquires. The CPU runs up to `MAX_FRAMES_IN_FLIGHT`
+    /// frames ahead of the GPU, the most throughput.
+    Throughput,
+    /// acquires first, then waits for the frame in flight. With FIFO presentation the acquire
+    /// blocks until the display released an image, so input is sampled closer to when the frame
+    /// shows up, at the cost of less overlap between CPU and GPU.
+    AcquireFirst,
+}
+
+impl Default for LatencyMode {
+    fn default() -> Self {
+        LatencyMode::Throughput
+    }
+}
+
+/// What became of a frame passed to `draw_frame`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameOutcome {
+    /// rendered and presented (or handed to `PresentTo`)
+    Presented,
+    /// dropped, the swapchain is out of date and gets recreated with the next frame
+    SwapchainOutdated,
+    /// skipped without a swapchain, the framebuffer has no area (e.g. minimized window)
+    ZeroExtent,
+    /// dropped, the surface is gone (e.g. display unplugged) and gets recreated with the next
+    /// frame, together with the swapchain
+    SurfaceLost,
+}
+
+/// A rendered swapchain image, handed to `PresentTo` instead of being presented.
+///
+/// Synchronization contract:
+/// - the image is still being rendered, all work reading it has to wait on `rendered_semaphore`.
+///   The semaphore is signaled once per frame, the callback has to queue exactly one wait on it
+///   (e.g. by the compositing submit or `queue_present_khr`), before it returns.
+/// - the image is in `PRESENT_SRC_KHR` layout and still acquired. It has to be presented with
+///   `swapchain` and `image_index` eventually, or the swapchain runs out of images.
+/// - handles are only valid until the swapchain is recreated. A callback result of
+///   `ERROR_OUT_OF_DATE_KHR` recreates it, as it does from `queue_present_khr`. With
+///   `SUBOPTIMAL_KHR` the frame counts as presented and the swapchain is recreated before the
+///   next one.
+pub struct RenderedImage {
+    pub swapchain: vk::SwapchainKHR,
+    pub image_index: u32,
+    pub image: vk::Image,
+    pub image_view: vk::ImageView,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub rendered_semaphore: vk::Semaphore,
+}
+
+/// Presentation mode of the swapchain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentMode {
+    /// best supported mode for the goal
+    Auto(PresentGoal),
+    Mailbox,
+    Immediate,
+    FifoRelaxed,
+    Fifo,
+}
+
+impl PresentMode {
+    /// Modes to try in order, each ends with FIFO. The default is MAILBOX, then FIFO.
+    pub fn preference(self) -> Vec<vk::PresentModeKHR> {
+        let preferred: &[vk::PresentModeKHR] = match self {
+            PresentMode::Auto(PresentGoal::LowLatency) => &[
+                vk::PRESENT_MODE_MAILBOX_KHR,
+                vk::PRESENT_MODE_IMMEDIATE_KHR,
+                vk::PRESENT_MODE_FIFO_RELAXED_KHR,
+            ],
+            PresentMode::Auto(PresentGoal::NoTearing) => &[vk::PRESENT_MODE_MAILBOX_KHR],
+            PresentMode::Auto(PresentGoal::PowerSaving) => &[],
+            PresentMode::Mailbox => &[vk::PRESENT_MODE_MAILBOX_KHR],
+            PresentMode::Immediate => &[vk::PRESENT_MODE_IMMEDIATE_KHR],
+            PresentMode::FifoRelaxed => &[vk::PRESENT_MODE_FIFO_RELAXED_KHR],
+            PresentMode::Fifo => &[],
+        };
+
+        let mut preference = preferred.to_vec();
+        preference.push(vk::PRESENT_MODE_FIFO_KHR);
+        preference
+    }
+}
+
+impl Default for PresentMode {
+    fn default() -> Self {
+        PresentMode::Auto(PresentGoal::NoTearing)
+    }
+}
+
+/// What `PresentMode::Auto` chooses for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentGoal {
+    /// MAILBOX, IMMEDIATE, FIFO_RELAXED, then FIFO. May tear.
+    LowLatency,
+    /// MAILBOX, then FIFO
+    NoTearing,
+    /// FIFO, renders no more frames than the display shows
+    PowerSaving,
+}
+
+/// `loadOp` of the scene color attachment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadOp {
+    /// clears to the clear value
+    Clear,
+    /// keeps what was rendered to the image before, e.g. for overlays or accumulation. Swapchain
+    /// images can't be prepared for that, so the scene is rendered offscreen and blitted. Scene
+    /// images rotate with the swapchain images, the content is from some frames ago.
+    Load,
+    /// content is undefined, for scenes covering every pixel anyway
+    DontCare,
+}
+
+impl Default for LoadOp {
+    fn default() -> Self {
+        LoadOp::Clear
+    }
+}
+
+impl LoadOp {
+    fn to_vk(self) -> vk::AttachmentLoadOp {
+        match self {
+            LoadOp::Clear => vk::ATTACHMENT_LOAD_OP_CLEAR,
+            LoadOp::Load => vk::ATTACHMENT_LOAD_OP_LOAD,
+            LoadOp::DontCare => vk::ATTACHMENT_LOAD_OP_DONT_CARE,
+        }
+    }
+}
+
+/// `storeOp` of the scene color attachment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreOp {
+    Store,
+    /// the rendered content is undefined afterwards, only when nothing reads the color image
+    DontCare,
+}
+
+impl Default for StoreOp {
+    fn default() -> Self {
+        StoreOp::Store
+    }
+}
+
+impl StoreOp {
+    fn to_vk(self) -> vk::AttachmentStoreOp {
+        match self {
+            StoreOp::Store => vk::ATTACHMENT_STORE_OP_STORE,
+            StoreOp::DontCare => vk::ATTACHMENT_STORE_OP_DONT_CARE,
+        }
+    }
+}
+
+/// Custom presentation, e.g. for compositing into an embedding engine.
+pub type PresentTo = Box<dyn FnMut(&RenderedImage) -> Result<()>>;
+
+/// Execution and memory dependency from work submitted earlier on the graphics queue to the
+/// scene subpass.
+#[derive(Debug, Clone, Copy)]
+pub struct ExternalDependency {
+    pub src_stage_mask: vk::PipelineStageFlags,
+    pub src_access_mask: vk::AccessFlags,
+    pub dst_stage_mask: vk::PipelineStageFlags,
+    pub dst_access_mask: vk::AccessFlags,
+}
+
+impl ExternalDependency {
+    /// compute shader writes consumed as vertex attributes
+    pub const COMPUTE_TO_VERTEX_INPUT: Self = Self {
+        src_stage_mask: vk::PIPELINE_STAGE_COMPUTE_SHADER_BIT,
+        src_access_mask: vk::ACCESS_SHADER_WRITE_BIT,
+        dst_stage_mask: vk::PIPELINE_STAGE_VERTEX_INPUT_BIT,
+        dst_access_mask: vk::ACCESS_VERTEX_ATTRIBUTE_READ_BIT,
+    };
+
+    /// compute shader writes read by vertex or fragment shaders, e.g. storage buffers
+    pub const COMPUTE_TO_SHADER_READ: Self = Self {
+        src_stage_mask: vk::PIPELINE_STAGE_COMPUTE_SHADER_BIT,
+        src_access_mask: vk::ACCESS_SHADER_WRITE_BIT,
+        dst_stage_mask: vk::PIPELINE_STAGE_VERTEX_SHADER_BIT
+            | vk::PIPELINE_STAGE_FRAGMENT_SHADER_BIT,
+        dst_access_mask: vk::ACCESS_SHADER_READ_BIT,
+    };
+}
+
+fn scene_output(settings: &SwapchainSettings) -> SceneOutput {
+    if settings.tonemap.is_some() {
+        // the tonemap pass scales as well
+        SceneOutput::Tonemap
+    } else if settings.render_scale.is_some() || settings.color_load_op == LoadOp::Load {
+        SceneOutput::Blit
+    } else {
+        SceneOutput::Swapchain
+    }
+}
+
+/// Layout the scene color image is left in, the one `scene_output` needs.
+fn scene_final_layout(scene_output: SceneOutput) -> vk::ImageLayout {
+    match scene_output {
+        SceneOutput::Swapchain => vk::IMAGE_LAYOUT_PRESENT_SRC_KHR,
+        SceneOutput::Blit => vk::IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+        SceneOutput::Tonemap => vk::IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+    }
+}
+
+/// Attachments of the scene render pass. A loaded color attachment starts in the layout the
+/// previous frame left it in.
+/// The color attachment, followed by the depth attachment with a `depth_format`.
+fn scene_attachments(
+    format: vk::Format,
+    depth_format: Option<vk::Format>,
+    scene_output: SceneOutput,
+    settings: &SwapchainSettings,
+) -> Vec<vk::AttachmentDescription> {
+    let final_layout = scene_final_layout(scene_output);
+
     let color_attachment_desc = vk::AttachmentDescription {
         flags: 0,
-        format: format.format,
+        format,
         samples: vk::SAMPLE_COUNT_1_BIT,
-        loadOp: vk::ATTACHMENT_LOAD_OP_CLEAR,
-        storeOp: vk::ATTACHMENT_STORE_OP_STORE,
+        loadOp: settings.color_load_op.to_vk(),
+        storeOp: settings.color_store_op.to_vk(),
         stencilLoadOp: vk::ATTACHMENT_LOAD_OP_DONT_CARE,
         stencilStoreOp: vk::ATTACHMENT_STORE_OP_DONT_CARE,
-        initialLayout: vk::IMAGE_LAYOUT_UNDEFINED,
-        finalLayout: vk::IMAGE_LAYOUT_PRESENT_SRC_KHR,
+        initialLayout: match settings.color_load_op {
+            LoadOp::Load => final_layout,
+            LoadOp::Clear | LoadOp::DontCare => vk::IMAGE_LAYOUT_UNDEFINED,
+        },
+        finalLayout: final_layout,
     };
 
+    let mut attachments = vec![color_attachment_desc];
+
+    if let Some(depth_format) = depth_format {
+        // stored for `Vulkan::read_depth_at`
+        attachments.push(vk::AttachmentDescription {
+            flags: 0,
+            format: depth_format,
+            samples: vk::SAMPLE_COUNT_1_BIT,
+            loadOp: vk::ATTACHMENT_LOAD_OP_CLEAR,
+            storeOp: vk::ATTACHMENT_STORE_OP_STORE,
+            stencilLoadOp: vk::ATTACHMENT_LOAD_OP_DONT_CARE,
+            stencilStoreOp: vk::ATTACHMENT_STORE_OP_DONT_CARE,
+            initialLayout: vk::IMAGE_LAYOUT_UNDEFINED,
+            finalLayout: vk::IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        });
+    }
+
+    attachments
+}
+
+/// Clear value per attachment, matching its kind: depth (stencil) attachments are cleared to
+/// `SwapchainSettings::clear_depth`, color (and resolve) attachments to `clear_color`.
+fn clear_values(
+    attachments: &[vk::AttachmentDescription],
+    settings: &SwapchainSettings,
+) -> Vec<vk::ClearValue> {
+    attachments
+        .iter()
+        .map(|attachment| {
+            if is_depth_format(attachment.format) {
+                vk::ClearValue {
+                    depthStencil: vk::ClearDepthStencilValue {
+                        depth: settings.clear_depth.unwrap_or(FAR_DEPTH),
+                        stencil: 0,
+                    },
+                }
+            } else {
+                vk::ClearValue {
+                    color: vk::ClearColorValue {
+                        float32: settings.clear_color,
+                    },
+                }
+            }
+        })
+        .collect()
+}
+
+fn create_render_pass(
+    ctx: &Context,
+    attachments: &[vk::AttachmentDescription],
+    external_dependencies: &[ExternalDependency],
+    scene_output: SceneOutput,
+) -> Result<vk::RenderPass> {
     let color_attachment_ref = vk::AttachmentReference {
         attachment: 0,
         layout: vk::IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
     };
+    let depth_attachment_ref = attachments
+        .iter()
+        .position(|attachment| is_depth_format(attachment.format))
+        .map(|index| vk::AttachmentReference {
+            attachment: index as u32,
+            layout: vk::IMAGE_LAYOUT_DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        });
 
     let subpass_desc = vk::SubpassDescription {
         flags: 0,
@@ -309,31 +1593,81 @@ fn create_render_pass(ctx: &Context, format: &vk::SurfaceFormatKHR) -> Result<vk
         colorAttachmentCount: 1,
         pColorAttachments: &color_attachment_ref,
         pResolveAttachments: std::ptr::null(),
-        pDepthStencilAttachment: std::ptr::null(),
+        pDepthStencilAttachment: depth_attachment_ref
+            .as_ref()
+            .map_or(std::ptr::null(), |reference| reference as *const _),
         preserveAttachmentCount: 0,
         pPreserveAttachments: std::ptr::null(),
     };
 
-    let subpass_dep = vk::SubpassDependency {
+    // the depth buffer is cleared in the early fragment tests
+    let (depth_stage_mask, depth_access_mask) = match depth_attachment_ref {
+        Some(_) => (
+            vk::PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT,
+            vk::ACCESS_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+        ),
+        None => (0, 0),
+    };
+
+    let mut subpass_deps = vec![vk::SubpassDependency {
         srcSubpass: vk::SUBPASS_EXTERNAL,
         dstSubpass: 0,
-        srcStageMask: vk::PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
-        dstStageMask: vk::PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
+        srcStageMask: vk::PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT | depth_stage_mask,
+        dstStageMask: vk::PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT | depth_stage_mask,
         srcAccessMask: 0,
-        dstAccessMask: vk::ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
+        dstAccessMask: vk::ACCESS_COLOR_ATTACHMENT_WRITE_BIT | depth_access_mask,
         dependencyFlags: 0,
+    }];
+
+    subpass_deps.extend(
+        external_dependencies
+            .iter()
+            .map(|dependency| vk::SubpassDependency {
+                srcSubpass: vk::SUBPASS_EXTERNAL,
+                dstSubpass: 0,
+                srcStageMask: dependency.src_stage_mask,
+                dstStageMask: dependency.dst_stage_mask,
+                srcAccessMask: dependency.src_access_mask,
+                dstAccessMask: dependency.dst_access_mask,
+                dependencyFlags: 0,
+            }),
+    );
+
+    // the final pass reads what the subpass wrote
+    let final_pass_read = match scene_output {
+        SceneOutput::Swapchain => None,
+        SceneOutput::Blit => Some((
+            vk::PIPELINE_STAGE_TRANSFER_BIT,
+            vk::ACCESS_TRANSFER_READ_BIT,
+        )),
+        SceneOutput::Tonemap => Some((
+            vk::PIPELINE_STAGE_FRAGMENT_SHADER_BIT,
+            vk::ACCESS_SHADER_READ_BIT,
+        )),
     };
 
+    if let Some((dst_stage_mask, dst_access_mask)) = final_pass_read {
+        subpass_deps.push(vk::SubpassDependency {
+            srcSubpass: 0,
+            dstSubpass: vk::SUBPASS_EXTERNAL,
+            srcStageMask: vk::PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
+            dstStageMask: dst_stage_mask,
+            srcAccessMask: vk::ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
+            dstAccessMask: dst_access_mask,
+            dependencyFlags: 0,
+        });
+    }
+
     let render_pass_info = vk::RenderPassCreateInfo {
         sType: vk::STRUCTURE_TYPE_RENDER_PASS_CREATE_INFO,
         pNext: std::ptr::null(),
         flags: 0,
-        attachmentCount: 1,
-        pAttachments: &color_attachment_desc,
+        attachmentCount: attachments.len() as u32,
+        pAttachments: attachments.as_ptr(),
         subpassCount: 1,
         pSubpasses: &subpass_desc,
-        dependencyCount: 1,
-        pDependencies: &subpass_dep,
+        dependencyCount: subpass_deps.len() as u32,
+        pDependencies: subpass_deps.as_ptr(),
     };
 
     unsafe { ctx.dp.create_render_pass(ctx.device, &render_pass_info) }.map_err(to_vulkan)
@@ -342,6 +1676,8 @@ fn create_render_pass(ctx: &Context, format: &vk::SurfaceFormatKHR) -> Result<vk
 fn create_swapchain(
     ctx: &Context,
     window: &Window,
+    settings: &SwapchainSettings,
+    old_swapchain: vk::SwapchainKHR,
 ) -> Result<(
     vk::SwapchainKHR,
     vk::SurfaceFormatKHR,
@@ -357,18 +1693,21 @@ fn create_swapchain(
         .get_physical_device_surface_present_modes_khr(ctx.physical_device, ctx.surface)
         .map_err(to_vulkan)?;
 
-    let good_format = formats
-        .iter()
-        .find(|format| {
-            format.format == vk::FORMAT_B8G8R8A8_SRGB
-                && format.colorSpace == vk::COLOR_SPACE_SRGB_NONLINEAR_KHR
-        })
-        .or_else(|| formats.iter().next()) // first
-        .ok_or_else(|| to_other(Error::Other("no good format found".to_owned())))?;
-    let good_mode = modes
-        .iter()
-        .find(|mode| **mode == vk::PRESENT_MODE_MAILBOX_KHR)
-        .unwrap_or(&vk::PRESENT_MODE_FIFO_KHR);
+    let good_format = choose_surface_format(&formats, settings.require_srgb_format)?;
+    let present_mode = choose_present_mode(&modes, &settings.present_mode_preference);
+    info!(
+        "present mode {} of preference {:?}, supported {:?}",
+        present_mode_name(present_mode),
+        settings
+            .present_mode_preference
+            .iter()
+            .map(|mode| present_mode_name(*mode))
+            .collect::<Vec<_>>(),
+        modes
+            .iter()
+            .map(|mode| present_mode_name(*mode))
+            .collect::<Vec<_>>()
+    );
 
     let capabilities = ctx
         .ip
@@ -376,7 +1715,19 @@ fn create_swapchain(
         .map_err(to_vulkan)?;
     let extent = choose_swap_extent(&capabilities, window);
 
-    let image_count = (capabilities.minImageCount + 1).min(capabilities.maxImageCount);
+    let image_count = choose_image_count(&capabilities, settings.desired_image_count);
+
+    let mut image_usage = vk::IMAGE_USAGE_COLOR_ATTACHMENT_BIT;
+    if scene_output(settings) == SceneOutput::Blit {
+        if capabilities.supportedUsageFlags & vk::IMAGE_USAGE_TRANSFER_DST_BIT == 0 {
+            return Err(to_other(
+                "rendering offscreen (render scale or loading color op) needs swapchain images \
+                 usable as transfer destination",
+            ));
+        }
+
+        image_usage |= vk::IMAGE_USAGE_TRANSFER_DST_BIT;
+    }
     let (image_sharing_mode, queue_families) =
         if ctx.queue_family_indices.graphics != ctx.queue_family_indices.present {
             (
@@ -400,289 +1751,458 @@ fn create_swapchain(
         imageColorSpace: good_format.colorSpace,
         imageExtent: copy_extent_2d(&extent),
         imageArrayLayers: 1,
-        imageUsage: vk::IMAGE_USAGE_COLOR_ATTACHMENT_BIT,
+        imageUsage: image_usage,
         imageSharingMode: image_sharing_mode,
         queueFamilyIndexCount: queue_families.len() as u32,
         pQueueFamilyIndices: queue_families.as_ptr(),
         preTransform: capabilities.currentTransform,
         compositeAlpha: vk::COMPOSITE_ALPHA_OPAQUE_BIT_KHR,
-        presentMode: *good_mode,
+        presentMode: present_mode,
         clipped: vk::TRUE,
-        oldSwapchain: vk::NULL_HANDLE,
+        oldSwapchain: old_swapchain,
     };
 
-    let swapchain = unsafe { ctx.dp.create_swapchain_khr(ctx.device, &info) }.map_err(to_vulkan)?;
-    let good_format: vk::SurfaceFormatKHR = copy_surface_format_khr(good_format);
+    let swapchain = unsafe { ctx.dp.create_swapchain_khr(ctx.device, &info) }.map_err(to_vulkan)?;
+    let good_format: vk::SurfaceFormatKHR = copy_surface_format_khr(good_format);
+
+    Ok((swapchain, good_format, present_mode, extent))
+}
+
+/// `B8G8R8A8_SRGB` in the sRGB color space, otherwise the first format unless `require_srgb`.
+/// Colors may be off with the fallback (e.g. a linear format), so it is logged.
+fn choose_surface_format(
+    formats: &[vk::SurfaceFormatKHR],
+    require_srgb: bool,
+) -> Result<&vk::SurfaceFormatKHR> {
+    let preferred = formats.iter().find(|format| {
+        format.format == vk::FORMAT_B8G8R8A8_SRGB
+            && format.colorSpace == vk::COLOR_SPACE_SRGB_NONLINEAR_KHR
+    });
+    if let Some(format) = preferred {
+        return Ok(format);
+    }
+
+    let supported = formats
+        .iter()
+        .map(|format| (format.format, format.colorSpace))
+        .collect::<Vec<_>>();
+    if require_srgb {
+        return Err(Error::Other(format!(
+            "surface doesn't support B8G8R8A8_SRGB with sRGB color space, supported (format, \
+             color space) {:?}",
+            supported
+        )));
+    }
+
+    let first = formats
+        .first()
+        .ok_or_else(|| to_other(Error::Other("no good format found".to_owned())))?;
+    warn!(
+        "surface doesn't support B8G8R8A8_SRGB with sRGB color space, falling back to format {} \
+         color space {}, supported (format, color space) {:?}",
+        first.format, first.colorSpace, supported
+    );
+
+    Ok(first)
+}
+
+/// First supported mode of `preference`. FIFO is always supported.
+fn choose_present_mode(
+    supported: &[vk::PresentModeKHR],
+    preference: &[vk::PresentModeKHR],
+) -> vk::PresentModeKHR {
+    preference
+        .iter()
+        .find(|mode| supported.contains(mode))
+        .cloned()
+        .unwrap_or(vk::PRESENT_MODE_FIFO_KHR)
+}
+
+fn present_mode_name(present_mode: vk::PresentModeKHR) -> &'static str {
+    match present_mode {
+        vk::PRESENT_MODE_IMMEDIATE_KHR => "IMMEDIATE",
+        vk::PRESENT_MODE_MAILBOX_KHR => "MAILBOX",
+        vk::PRESENT_MODE_FIFO_KHR => "FIFO",
+        vk::PRESENT_MODE_FIFO_RELAXED_KHR => "FIFO_RELAXED",
+        _ => "unknown",
+    }
+}
+
+fn choose_image_count(caps: &vk::SurfaceCapabilitiesKHR, desired: Option<u32>) -> u32 {
+    let image_count = desired
+        .unwrap_or(caps.minImageCount + 1)
+        .max(caps.minImageCount);
+
+    // 0 means there is no maximum
+    if caps.maxImageCount == 0 {
+        image_count
+    } else {
+        image_count.min(caps.maxImageCount)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum SwapExtentKind {
+    /// surface dictates the extent via `currentExtent`
+    Current,
+    /// `minImageExtent == maxImageExtent`, the surface size cannot change at all
+    Fixed,
+    /// `currentExtent` is the `u32::MAX` sentinel, we choose within min/max
+    Flexible,
+}
+
+fn swap_extent_kind(caps: &vk::SurfaceCapabilitiesKHR) -> SwapExtentKind {
+    if caps.minImageExtent.width == caps.maxImageExtent.width
+        && caps.minImageExtent.height == caps.maxImageExtent.height
+    {
+        SwapExtentKind::Fixed
+    } else if caps.currentExtent.width != u32::MAX {
+        SwapExtentKind::Current
+    } else {
+        SwapExtentKind::Flexible
+    }
+}
+
+fn choose_swap_extent(caps: &vk::SurfaceCapabilitiesKHR, window: &glfw::Window) -> vk::Extent2D {
+    swap_extent(caps, window.get_framebuffer_size())
+}
+
+/// See `choose_swap_extent`, with the window's framebuffer size.
+fn swap_extent(caps: &vk::SurfaceCapabilitiesKHR, framebuffer_size: (i32, i32)) -> vk::Extent2D {
+    match swap_extent_kind(caps) {
+        SwapExtentKind::Current => copy_extent_2d(&caps.currentExtent),
+        // some drivers report the sentinel even for fixed size surfaces, so min (== max) is
+        // the only reliable value here, the framebuffer size is ignored
+        SwapExtentKind::Fixed => copy_extent_2d(&caps.minImageExtent),
+        SwapExtentKind::Flexible => {
+            let (w, h) = framebuffer_size;
+            let w = w as u32;
+            let h = h as u32;
+
+            vk::Extent2D {
+                width: w.clamp(caps.minImageExtent.width, caps.maxImageExtent.width),
+                height: h.clamp(caps.minImageExtent.height, caps.maxImageExtent.height),
+            }
+        }
+    }
+}
+
+/// Texel of the `render_extent` sized scene at `pixel` of the `extent` sized swapchain image,
+/// `None` outside of it.
+fn render_texel(
+    (x, y): (u32, u32),
+    extent: &vk::Extent2D,
+    render_extent: &vk::Extent2D,
+) -> Option<(u32, u32)> {
+    if x >= extent.width || y >= extent.height {
+        return None;
+    }
+
+    let scale = |pixel: u32, size: u32, render_size: u32| {
+        ((pixel as u64 * render_size as u64 / size as u64) as u32).min(render_size - 1)
+    };
+
+    Some((
+        scale(x, extent.width, render_extent.width),
+        scale(y, extent.height, render_extent.height),
+    ))
+}
+
+fn choose_render_area(settings: &SwapchainSettings, extent: &vk::Extent2D) -> Result<vk::Rect2D> {
+    let render_area = match &settings.render_area {
+        None => {
+            return Ok(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: copy_extent_2d(extent),
+            })
+        }
+        Some(render_area) => render_area,
+    };
+
+    check_area_in_bounds("render area", render_area, extent)?;
+
+    Ok(copy_rect_2d(render_area))
+}
+
+fn choose_viewports(
+    settings: &SwapchainSettings,
+    render_area: &vk::Rect2D,
+    extent: &vk::Extent2D,
+) -> Result<Vec<vk::Rect2D>> {
+    if settings.viewports.is_empty() {
+        return Ok(vec![copy_rect_2d(render_area)]);
+    }
+
+    settings
+        .viewports
+        .iter()
+        .map(|viewport| {
+            check_area_in_bounds("viewport", viewport, extent)?;
+            Ok(copy_rect_2d(viewport))
+        })
+        .collect()
+}
+
+fn check_area_in_bounds(name: &str, area: &vk::Rect2D, extent: &vk::Extent2D) -> Result<()> {
+    let in_bounds = area.offset.x >= 0
+        && area.offset.y >= 0
+        && area.offset.x as u64 + area.extent.width as u64 <= extent.width as u64
+        && area.offset.y as u64 + area.extent.height as u64 <= extent.height as u64;
+
+    if !in_bounds {
+        return Err(Error::Other(format!(
+            "{} {}x{}+{}+{} exceeds framebuffer {}x{}",
+            name,
+            area.extent.width,
+            area.extent.height,
+            area.offset.x,
+            area.offset.y,
+            extent.width,
+            extent.height
+        )));
+    }
 
-    Ok((swapchain, good_format, *good_mode, extent))
+    Ok(())
 }
 
-fn choose_swap_extent(caps: &vk::SurfaceCapabilitiesKHR, window: &glfw::Window) -> vk::Extent2D {
-    if caps.currentExtent.width != u32::MAX {
-        return vk::Extent2D {
-            width: caps.currentExtent.width,
-            height: caps.currentExtent.height,
-        };
+pub fn triangle_vertices() -> Vec<Vertex> {
+    vec![
+        Vertex {
+            pos: Vec2::new(0.0, -0.5),
+            color: Vec3::new(1.0, 0.0, 0.0),
+        },
+        Vertex {
+            pos: Vec2::new(0.5, 0.5),
+            color: Vec3::new(0.0, 1.0, 0.0),
+        },
+        Vertex {
+            pos: Vec2::new(-0.5, 0.5),
+            color: Vec3::new(0.0, 0.0, 1.0),
+        },
+    ]
+}
+
+/// `0, 1, 2, ..`, draws `vertex_count` vertices as they are.
+pub fn sequential_indices(vertex_count: usize) -> Vec<u32> {
+    (0..vertex_count as u32).collect()
+}
+
+/// Share of the triangles of `indices` (a triangle list into `vertices`) that are
+/// counter-clockwise on screen and culled as back faces, degenerate ones don't count.
+pub fn back_facing_fraction(vertices: &[Vertex], indices: &[u32]) -> f32 {
+    let triangles = indices.len() / 3;
+    if triangles == 0 {
+        return 0.0;
     }
 
-    let (w, h) = window.get_framebuffer_size();
-    let w = w as u32;
-    let h = h as u32;
+    let back_facing = indices
+        .chunks_exact(3)
+        .filter(|triangle| {
+            let (a, b, c) = (
+                vertices[triangle[0] as usize].pos,
+                vertices[triangle[1] as usize].pos,
+                vertices[triangle[2] as usize].pos,
+            );
+            // y points down in Vulkan, so a negative cross product is counter-clockwise on screen
+            (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x) < 0.0
+        })
+        .count();
+
+    back_facing as f32 / triangles as f32
+}
 
-    vk::Extent2D {
-        width: w.clamp(caps.minImageExtent.width, caps.maxImageExtent.width),
-        height: h.clamp(caps.minImageExtent.height, caps.maxImageExtent.height),
+fn check_winding(vertices: &[Vertex], indices: &[u32]) {
+    let fraction = back_facing_fraction(vertices, indices);
+    if fraction >= BACK_FACING_WARN_FRACTION {
+        warn!(
+            "{:.0}% of the scene triangles face away and would be culled, front faces have to be \
+             clockwise on screen: flip the winding of the geometry or its y axis",
+            fraction * 100.0
+        );
+    } else {
+        info!(
+            "winding check: {:.0}% of the scene triangles face away",
+            fraction * 100.0
+        );
     }
 }
 
-fn create_graphics_pipeline(
-    ctx: &Context,
-    extent: &vk::Extent2D,
-    render_pass: vk::RenderPass,
+/// Host visible vertex buffer with `vertices`, `extra_usage` is added to `VERTEX_BUFFER` (see
+/// `check_vertex_buffer_usage`).
+fn create_vertex_buffer<'a, T>(
+    ctx: &'a Context,
+    vertices: &[T],
+    extra_usage: vk::BufferUsageFlags,
 ) -> Result<(
-    vk::ShaderModule,
-    vk::ShaderModule,
-    vk::PipelineLayout,
-    vk::Pipeline,
+    handle::Scoped<'a, vk::Buffer>,
+    handle::Scoped<'a, vk::DeviceMemory>,
+    bool,
 )> {
-    let vert_shader = include_spirv!("shader/vert.glsl", glsl, vert);
-    let frag_shader = include_spirv!("shader/frag.glsl", glsl, frag);
-
-    let vertex_shader_module = create_shader_module(&ctx.dp, ctx.device, vert_shader)?;
-    let fragment_shader_module = create_shader_module(&ctx.dp, ctx.device, frag_shader)?;
+    check_vertex_buffer_usage(extra_usage)?;
 
-    let name = CString::new("main").map_err(to_other)?;
+    let size = (size_of::<T>() * vertices.len()) as u64;
+    let (buffer, device_memory, coherent) =
+        create_host_visible_buffer(ctx, size, vk::BUFFER_USAGE_VERTEX_BUFFER_BIT | extra_usage)?;
 
-    let vertex_shader_info = vk::PipelineShaderStageCreateInfo {
-        sType: vk::STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
-        pNext: std::ptr::null(),
-        flags: 0,
-        stage: vk::SHADER_STAGE_VERTEX_BIT,
-        module: vertex_shader_module,
-        pName: name.as_ptr(),
-        pSpecializationInfo: std::ptr::null(),
-    };
+    write_host_visible_memory(ctx, *device_memory, coherent, vertices)?;
 
-    let fragment_shader_info = vk::PipelineShaderStageCreateInfo {
-        sType: vk::STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
-        pNext: std::ptr::null(),
-        flags: 0,
-        stage: vk::SHADER_STAGE_FRAGMENT_BIT,
-        module: fragment_shader_module,
-        pName: name.as_ptr(),
-        pSpecializationInfo: std::ptr::null(),
-    };
+    Ok((buffer, device_memory, coherent))
+}
 
-    let shader_stages = [vertex_shader_info, fragment_shader_info];
+/// Host visible index buffer with `indices`, drawn with `INDEX_TYPE_UINT32`.
+fn create_index_buffer<'a>(
+    ctx: &'a Context,
+    indices: &[u32],
+) -> Result<(
+    handle::Scoped<'a, vk::Buffer>,
+    handle::Scoped<'a, vk::DeviceMemory>,
+)> {
+    let size = (size_of::<u32>() * indices.len()) as u64;
+    let (buffer, device_memory, coherent) =
+        create_host_visible_buffer(ctx, size, vk::BUFFER_USAGE_INDEX_BUFFER_BIT)?;
 
-    let binding_description = Vertex::get_binding_description();
-    let attribute_descriptions = Vertex::get_attribute_descriptions();
+    write_host_visible_memory(ctx, *device_memory, coherent, indices)?;
 
-    let vert_input_info = vk::PipelineVertexInputStateCreateInfo {
-        sType: vk::STRUCTURE_TYPE_PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO,
-        pNext: std::ptr::null(),
-        flags: 0,
-        vertexBindingDescriptionCount: 1,
-        pVertexBindingDescriptions: &binding_description,
-        vertexAttributeDescriptionCount: attribute_descriptions.len() as u32,
-        pVertexAttributeDescriptions: attribute_descriptions.as_ptr(),
-    };
+    Ok((buffer, device_memory))
+}
 
-    let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo {
-        sType: vk::STRUCTURE_TYPE_PIPELINE_INPUT_ASSEMBLY_STATE_CREATE_INFO,
-        pNext: std::ptr::null(),
-        flags: 0,
-        topology: vk::PRIMITIVE_TOPOLOGY_TRIANGLE_LIST,
-        primitiveRestartEnable: vk::FALSE,
-    };
+/// Usage a vertex buffer may have besides `VERTEX_BUFFER`: written by compute shaders or
+/// transfers, or read as indices. Texel buffer usages need buffer views, which vertex buffers
+/// don't get.
+pub fn check_vertex_buffer_usage(extra_usage: vk::BufferUsageFlags) -> Result<()> {
+    let allowed = vk::BUFFER_USAGE_STORAGE_BUFFER_BIT
+        | vk::BUFFER_USAGE_TRANSFER_SRC_BIT
+        | vk::BUFFER_USAGE_TRANSFER_DST_BIT
+        | vk::BUFFER_USAGE_INDEX_BUFFER_BIT
+        | vk::BUFFER_USAGE_INDIRECT_BUFFER_BIT;
+
+    if extra_usage & !allowed != 0 {
+        return Err(Error::Other(format!(
+            "vertex buffer usage {:#x} not supported, allowed are {:#x}",
+            extra_usage, allowed
+        )));
+    }
 
-    let viewport = vk::Viewport {
-        x: 0.0,
-        y: 0.0,
-        width: extent.width as f32,
-        height: extent.height as f32,
-        minDepth: 0.0,
-        maxDepth: 1.0,
-    };
+    Ok(())
+}
 
-    let scissor = vk::Rect2D {
-        offset: vk::Offset2D { x: 0, y: 0 },
-        extent: copy_extent_2d(extent),
-    };
+/// Copies `elements` to the start of host visible `memory`, flushing it unless it is `coherent`.
+pub fn write_host_visible_memory<T>(
+    ctx: &Context,
+    memory: vk::DeviceMemory,
+    coherent: bool,
+    elements: &[T],
+) -> Result<()> {
+    let size = (size_of::<T>() * elements.len()) as u64;
 
-    let viewport_state_info = vk::PipelineViewportStateCreateInfo {
-        sType: vk::STRUCTURE_TYPE_PIPELINE_VIEWPORT_STATE_CREATE_INFO,
-        pNext: std::ptr::null(),
-        flags: 0,
-        viewportCount: 1,
-        pViewports: &viewport,
-        scissorCount: 1,
-        pScissors: &scissor,
+    let data = ctx
+        .dp
+        .map_memory(ctx.device, memory, 0, size, 0)
+        .map_err(to_vulkan)?;
+    unsafe {
+        // count of elements, not bytes
+        std::ptr::copy_nonoverlapping(elements.as_ptr(), data as *mut T, elements.len())
     };
 
-    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo {
-        sType: vk::STRUCTURE_TYPE_PIPELINE_RASTERIZATION_STATE_CREATE_INFO,
-        pNext: std::ptr::null(),
-        flags: 0,
-        depthClampEnable: vk::FALSE,
-        rasterizerDiscardEnable: vk::FALSE,
-        polygonMode: vk::POLYGON_MODE_FILL,
-        cullMode: vk::CULL_MODE_BACK_BIT,
-        frontFace: vk::FRONT_FACE_CLOCKWISE,
-        depthBiasEnable: vk::FALSE,
-        depthBiasConstantFactor: 0.0,
-        depthBiasClamp: 0.0,
-        depthBiasSlopeFactor: 0.0,
-        lineWidth: 1.0,
-    };
+    if !coherent {
+        ctx.flush_mapped_range(memory, 0, size)?;
+    }
 
-    let multisample_info = vk::PipelineMultisampleStateCreateInfo {
-        sType: vk::STRUCTURE_TYPE_PIPELINE_MULTISAMPLE_STATE_CREATE_INFO,
-        pNext: std::ptr::null(),
-        flags: 0,
-        rasterizationSamples: vk::SAMPLE_COUNT_1_BIT,
-        sampleShadingEnable: vk::FALSE,
-        minSampleShading: 1.0,
-        pSampleMask: std::ptr::null(),
-        alphaToCoverageEnable: vk::FALSE,
-        alphaToOneEnable: vk::FALSE,
-    };
+    ctx.dp.unmap_memory(ctx.device, memory);
 
-    let color_blend_attach = vk::PipelineColorBlendAttachmentState {
-        blendEnable: vk::FALSE,
-        srcColorBlendFactor: vk::BLEND_FACTOR_ONE,
-        dstColorBlendFactor: vk::BLEND_FACTOR_ZERO,
-        colorBlendOp: vk::BLEND_OP_ADD,
-        srcAlphaBlendFactor: vk::BLEND_FACTOR_ONE,
-        dstAlphaBlendFactor: vk::BLEND_FACTOR_ZERO,
-        alphaBlendOp: vk::BLEND_OP_ADD,
-        colorWriteMask: vk::COLOR_COMPONENT_R_BIT
-            | vk::COLOR_COMPONENT_G_BIT
-            | vk::COLOR_COMPONENT_B_BIT
-            | vk::COLOR_COMPONENT_A_BIT,
-    };
+    Ok(())
+}
 
-    let color_blend = vk::PipelineColorBlendStateCreateInfo {
-        sType: vk::STRUCTURE_TYPE_PIPELINE_COLOR_BLEND_STATE_CREATE_INFO,
-        pNext: std::ptr::null(),
+/// Buffer in host visible memory, preferably coherent. Mapped writes need a flush unless the
+/// returned `coherent` is true.
+pub fn create_host_visible_buffer(
+    ctx: &Context,
+    size: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+) -> Result<(
+    handle::Scoped<vk::Buffer>,
+    handle::Scoped<vk::DeviceMemory>,
+    bool,
+)> {
+    let buffer_info = vk::BufferCreateInfo {
+        sType: vk::STRUCTURE_TYPE_BUFFER_CREATE_INFO,
+        pNext: ptr::null(),
         flags: 0,
-        logicOpEnable: vk::FALSE,
-        logicOp: vk::LOGIC_OP_COPY,
-        attachmentCount: 1,
-        pAttachments: &color_blend_attach,
-        blendConstants: [0.0, 0.0, 0.0, 0.0],
+        size,
+        usage,
+        sharingMode: vk::SHARING_MODE_EXCLUSIVE,
+        queueFamilyIndexCount: 0,
+        pQueueFamilyIndices: ptr::null(),
     };
 
-    // let dynamic_states = [vk::DYNAMIC_STATE_VIEWPORT, vk::DYNAMIC_STATE_LINE_WIDTH];
+    let buffer = handle::buffer(
+        ctx,
+        unsafe { ctx.dp.create_buffer(ctx.device, &buffer_info) }.map_err(to_vulkan)?,
+    );
 
-    // let dynamic_state_info = vk::PipelineDynamicStateCreateInfo {
-    //     sType: vk::STRUCTURE_TYPE_PIPELINE_DYNAMIC_STATE_CREATE_INFO,
-    //     pNext: std::ptr::null(),
-    //     flags: 0,
-    //     dynamicStateCount: dynamic_states.len() as u32,
-    //     pDynamicStates: dynamic_states.as_ptr(),
-    // };
+    let memory_requirements = ctx.dp.get_buffer_memory_requirements(ctx.device, *buffer);
 
-    let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
-        sType: vk::STRUCTURE_TYPE_PIPELINE_LAYOUT_CREATE_INFO,
-        pNext: std::ptr::null(),
-        flags: 0,
-        setLayoutCount: 0,
-        pSetLayouts: std::ptr::null(),
-        pushConstantRangeCount: 0,
-        pPushConstantRanges: std::ptr::null(),
-    };
+    let (memory_type, memory_flags) = find_memory_type_with_fallback(
+        ctx,
+        memory_requirements.memoryTypeBits,
+        &[
+            vk::MEMORY_PROPERTY_HOST_VISIBLE_BIT | vk::MEMORY_PROPERTY_HOST_COHERENT_BIT,
+            vk::MEMORY_PROPERTY_HOST_VISIBLE_BIT,
+        ],
+    )?;
 
-    let pipeline_layout = unsafe {
-        ctx.dp
-            .create_pipeline_layout(ctx.device, &pipeline_layout_info)
-    }
-    .map_err(to_vulkan)?;
+    let coherent = memory_flags & vk::MEMORY_PROPERTY_HOST_COHERENT_BIT != 0;
 
-    let pipeline_info = vk::GraphicsPipelineCreateInfo {
-        sType: vk::STRUCTURE_TYPE_GRAPHICS_PIPELINE_CREATE_INFO,
-        pNext: std::ptr::null(),
-        flags: 0,
-        stageCount: shader_stages.len() as u32,
-        pStages: shader_stages.as_ptr(),
-        pVertexInputState: &vert_input_info,
-        pInputAssemblyState: &input_assembly_info,
-        pTessellationState: std::ptr::null(),
-        pViewportState: &viewport_state_info,
-        pRasterizationState: &rasterizer_info,
-        pMultisampleState: &multisample_info,
-        pDepthStencilState: std::ptr::null(),
-        pColorBlendState: &color_blend,
-        pDynamicState: std::ptr::null(),
-        layout: pipeline_layout,
-        renderPass: render_pass,
-        subpass: 0,
-        basePipelineHandle: vk::NULL_HANDLE,
-        basePipelineIndex: -1,
+    // flushed ranges are rounded to whole atoms, they must stay within the allocation
+    let allocation_size = if coherent {
+        memory_requirements.size
+    } else {
+        align_up(memory_requirements.size, ctx.limits.nonCoherentAtomSize)
     };
 
-    let pipelines = unsafe {
-        ctx.dp
-            .create_graphics_pipelines(ctx.device, vk::NULL_HANDLE, &[pipeline_info])
-    }
-    .map_err(to_vulkan)?;
-    let pipeline: vk::Pipeline = *pipelines.iter().next().unwrap();
+    let allocate_info = vk::MemoryAllocateInfo {
+        sType: vk::STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
+        pNext: ptr::null(),
+        allocationSize: allocation_size,
+        memoryTypeIndex: memory_type,
+    };
 
-    Ok((
-        vertex_shader_module,
-        fragment_shader_module,
-        pipeline_layout,
-        pipeline,
-    ))
-}
+    let device_memory = handle::memory(
+        ctx,
+        unsafe { ctx.dp.allocate_memory(ctx.device, &allocate_info) }.map_err(to_vulkan)?,
+    );
 
-fn create_shader_module(
-    dp: &DevicePointers,
-    device: vk::Device,
-    code: &[u32],
-) -> Result<vk::ShaderModule> {
-    let info = vk::ShaderModuleCreateInfo {
-        sType: vk::STRUCTURE_TYPE_SHADER_MODULE_CREATE_INFO,
-        pNext: std::ptr::null(),
-        flags: 0,
-        codeSize: code.len() * size_of::<u32>(), // not the len, but the size
-        pCode: code.as_ptr(),
-    };
+    ctx.dp
+        .bind_buffer_memory(ctx.device, *buffer, *device_memory, 0)
+        .map_err(to_vulkan)?;
 
-    unsafe { dp.create_shader_module(device, &info) }.map_err(to_vulkan)
+    Ok((buffer, device_memory, coherent))
 }
 
-fn create_vertex_buffer(ctx: &Context) -> Result<(vk::Buffer, vk::DeviceMemory)> {
-    let vertices = [
-        Vertex {
-            pos: Vec2::new(0.0, -0.5),
-            color: Vec3::new(1.0, 0.0, 0.0),
-        },
-        Vertex {
-            pos: Vec2::new(0.5, 0.5),
-            color: Vec3::new(0.0, 1.0, 0.0),
-        },
-        Vertex {
-            pos: Vec2::new(-0.5, 0.5),
-            color: Vec3::new(0.0, 0.0, 1.0),
-        },
-    ];
-
+/// Buffer in device local memory, filled e.g. by `Context::copy_buffer` from a host visible
+/// one.
+pub fn create_device_local_buffer(
+    ctx: &Context,
+    size: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+) -> Result<(handle::Scoped<vk::Buffer>, handle::Scoped<vk::DeviceMemory>)> {
     let buffer_info = vk::BufferCreateInfo {
         sType: vk::STRUCTURE_TYPE_BUFFER_CREATE_INFO,
         pNext: ptr::null(),
         flags: 0,
-        size: (size_of::<Vertex>() * vertices.len()) as u64,
-        usage: vk::BUFFER_USAGE_VERTEX_BUFFER_BIT,
+        size,
+        usage,
         sharingMode: vk::SHARING_MODE_EXCLUSIVE,
         queueFamilyIndexCount: 0,
         pQueueFamilyIndices: ptr::null(),
     };
 
-    let buffer = unsafe { ctx.dp.create_buffer(ctx.device, &buffer_info) }.map_err(to_vulkan)?;
-
-    let memory_requirements = ctx.dp.get_buffer_memory_requirements(ctx.device, buffer);
+    let buffer = handle::buffer(
+        ctx,
+        unsafe { ctx.dp.create_buffer(ctx.device, &buffer_info) }.map_err(to_vulkan)?,
+    );
 
+    let memory_requirements = ctx.dp.get_buffer_memory_requirements(ctx.device, *buffer);
     let allocate_info = vk::MemoryAllocateInfo {
         sType: vk::STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
         pNext: ptr::null(),
@@ -690,81 +2210,275 @@ fn create_vertex_buffer(ctx: &Context) -> Result<(vk::Buffer, vk::DeviceMemory)>
         memoryTypeIndex: find_memory_type(
             ctx,
             memory_requirements.memoryTypeBits,
-            vk::MEMORY_PROPERTY_HOST_VISIBLE_BIT | vk::MEMORY_PROPERTY_HOST_COHERENT_BIT,
+            vk::MEMORY_PROPERTY_DEVICE_LOCAL_BIT,
         )?,
     };
 
-    let device_memory =
-        unsafe { ctx.dp.allocate_memory(ctx.device, &allocate_info) }.map_err(to_vulkan)?;
+    let device_memory = handle::memory(
+        ctx,
+        unsafe { ctx.dp.allocate_memory(ctx.device, &allocate_info) }.map_err(to_vulkan)?,
+    );
 
     ctx.dp
-        .bind_buffer_memory(ctx.device, buffer, device_memory, 0)
-        .map_err(to_vulkan)?;
-
-    let data = ctx
-        .dp
-        .map_memory(ctx.device, device_memory, 0, buffer_info.size, 0)
+        .bind_buffer_memory(ctx.device, *buffer, *device_memory, 0)
         .map_err(to_vulkan)?;
-    unsafe {
-        std::ptr::copy_nonoverlapping(
-            vertices.as_ptr(),
-            data as *mut Vertex,
-            buffer_info.size as usize,
-        )
-    };
-    ctx.dp.unmap_memory(ctx.device, device_memory);
 
     Ok((buffer, device_memory))
 }
 
-fn find_memory_type(
+/// Device local buffer with `data`, copied through a staging buffer on the graphics queue and
+/// waited for. `TRANSFER_DST` is added to `usage`.
+pub fn upload_device_local_buffer<'a, T>(
+    ctx: &'a Context,
+    data: &[T],
+    usage: vk::BufferUsageFlags,
+) -> Result<(
+    handle::Scoped<'a, vk::Buffer>,
+    handle::Scoped<'a, vk::DeviceMemory>,
+)> {
+    if data.is_empty() {
+        return Err(Error::Other("buffers cannot be empty".to_owned()));
+    }
+
+    let size = (size_of::<T>() * data.len()) as vk::DeviceSize;
+
+    // destroyed at the end of the scope, the copy has been waited for by then
+    let (staging_buffer, staging_memory, coherent) =
+        create_host_visible_buffer(ctx, size, vk::BUFFER_USAGE_TRANSFER_SRC_BIT)?;
+    write_host_visible_memory(ctx, *staging_memory, coherent, data)?;
+
+    let (buffer, memory) =
+        create_device_local_buffer(ctx, size, usage | vk::BUFFER_USAGE_TRANSFER_DST_BIT)?;
+    ctx.copy_buffer(*staging_buffer, *buffer, size)?;
+
+    Ok((buffer, memory))
+}
+
+/// Index of the first memory type allowed by `type_filter`, which has all of `flags`.
+pub fn find_memory_type(
     ctx: &Context,
     type_filter: u32,
     flags: vk::MemoryPropertyFlags,
 ) -> Result<u32> {
-    for i in 0..ctx.memory_properties.memoryTypeCount {
-        if (type_filter & (1 << i)) != 0
-            && (ctx.memory_properties.memoryTypes[i as usize].propertyFlags & flags) != 0
-        {
-            return Ok(i);
+    choose_memory_type(&ctx.memory_properties, type_filter, flags).ok_or_else(|| {
+        to_other(format!(
+            "could not find memory type with flags {:#x}",
+            flags
+        ))
+    })
+}
+
+/// Tries `candidates` in order, returns the memory type index and all of its property flags, so
+/// the caller knows e.g. whether it is coherent.
+pub fn find_memory_type_with_fallback(
+    ctx: &Context,
+    type_filter: u32,
+    candidates: &[vk::MemoryPropertyFlags],
+) -> Result<(u32, vk::MemoryPropertyFlags)> {
+    choose_memory_type_with_fallback(&ctx.memory_properties, type_filter, candidates).ok_or_else(
+        || {
+            to_other(format!(
+                "could not find memory type with any of the flags {:x?}",
+                candidates
+            ))
+        },
+    )
+}
+
+fn choose_memory_type_with_fallback(
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    type_filter: u32,
+    candidates: &[vk::MemoryPropertyFlags],
+) -> Option<(u32, vk::MemoryPropertyFlags)> {
+    candidates
+        .iter()
+        .find_map(|flags| choose_memory_type(memory_properties, type_filter, *flags))
+        .map(|index| {
+            (
+                index,
+                memory_properties.memoryTypes[index as usize].propertyFlags,
+            )
+        })
+}
+
+fn choose_memory_type(
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    type_filter: u32,
+    flags: vk::MemoryPropertyFlags,
+) -> Option<u32> {
+    (0..memory_properties.memoryTypeCount).find(|i| {
+        type_filter & (1 << i) != 0
+            && memory_properties.memoryTypes[*i as usize].propertyFlags & flags == flags
+    })
+}
+
+/// pass after the scene render pass, to get the scene onto the swapchain image
+enum FinalPass<'a> {
+    /// the scene is rendered into the swapchain image directly
+    None,
+    /// render target the scene is rendered into and the swapchain image to blit it onto
+    Blit(&'a RenderTarget, vk::Image),
+    /// tonemap pass and bloom added by it
+    Tonemap(
+        &'a TonemapPass,
+        &'a TonemapImage,
+        Option<(&'a PostProcessPass, &'a PostProcessImage)>,
+    ),
+}
+
+/// The pass after the scene render pass, by the images a swapchain image has.
+fn final_pass<'a>(
+    sc_ctx: &'a SwapchainContext,
+    image: vk::Image,
+    render_target: Option<&'a RenderTarget>,
+    tonemap_image: Option<&'a TonemapImage>,
+    post_process_image: Option<&'a PostProcessImage>,
+) -> FinalPass<'a> {
+    let bloom = match (&sc_ctx.post_process_pass, post_process_image) {
+        (Some(post_process_pass), Some(post_process_image)) => {
+            Some((post_process_pass, post_process_image))
         }
-    }
+        _ => None,
+    };
 
-    Err(to_other("could not find memory type"))
+    match (&sc_ctx.tonemap_pass, tonemap_image, render_target) {
+        (Some(tonemap_pass), Some(tonemap_image), _) => {
+            FinalPass::Tonemap(tonemap_pass, tonemap_image, bloom)
+        }
+        (_, _, Some(render_target)) => FinalPass::Blit(render_target, image),
+        _ => FinalPass::None,
+    }
 }
 
 fn create_command_buffer(
     ctx: &Context,
     sc_ctx: &SwapchainContext,
     framebuffer: vk::Framebuffer,
+    scene_uniforms: &SceneUniformImage,
+    final_pass: FinalPass,
 ) -> Result<vk::CommandBuffer> {
-    let command_buffer = ctx.allocate_primary_command_buffer()?;
+    let scoped_command_buffer = handle::command_buffer(ctx, ctx.allocate_primary_command_buffer()?);
+    let command_buffer = *scoped_command_buffer;
     ctx.begin_command_buffer(command_buffer)?;
     ctx.begin_render_pass(sc_ctx, command_buffer, framebuffer);
 
-    ctx.cmd_bind_pipeline(sc_ctx, command_buffer);
+    for (viewport_index, viewport) in sc_ctx.viewports.iter().enumerate() {
+        ctx.cmd_set_viewport(command_buffer, viewport);
+        record_scene(
+            ctx,
+            sc_ctx,
+            command_buffer,
+            scene_uniforms,
+            viewport_index as u32,
+        )?;
+    }
 
-    ctx.dp
-        .cmd_bind_vertex_buffers(command_buffer, 0, &[sc_ctx.vertex_buffer], &[0]);
-    ctx.dp.cmd_draw(command_buffer, 3, 1, 0, 0);
     ctx.dp.cmd_end_render_pass(command_buffer);
 
+    match final_pass {
+        FinalPass::None => {}
+        FinalPass::Blit(render_target, swapchain_image) => {
+            render_target.cmd_blit_to_swapchain_image(
+                ctx,
+                command_buffer,
+                swapchain_image,
+                &sc_ctx.extent,
+            );
+        }
+        FinalPass::Tonemap(tonemap_pass, tonemap_image, bloom) => {
+            if let Some((post_process_pass, post_process_image)) = bloom {
+                post_process_pass.cmd_bloom(ctx, command_buffer, post_process_image);
+            }
+            tonemap_pass.cmd_tonemap(ctx, command_buffer, tonemap_image, &sc_ctx.extent);
+        }
+    }
+
     ctx.dp
         .end_command_buffer(command_buffer)
         .map_err(to_vulkan)?;
 
-    Ok(command_buffer)
+    Ok(scoped_command_buffer.release())
+}
+
+fn record_scene(
+    ctx: &Context,
+    sc_ctx: &SwapchainContext,
+    command_buffer: vk::CommandBuffer,
+    scene_uniforms: &SceneUniformImage,
+    viewport_index: u32,
+) -> Result<()> {
+    ctx.cmd_bind_pipeline(&sc_ctx.pipeline, command_buffer);
+    scene_uniforms.cmd_bind(ctx, command_buffer, sc_ctx.pipeline.layout, viewport_index);
+
+    ctx.cmd_bind_vertex_buffers(command_buffer, &[(sc_ctx.vertex_buffer, 0)]);
+    ctx.dp.cmd_bind_index_buffer(
+        command_buffer,
+        sc_ctx.index_buffer,
+        0,
+        vk::INDEX_TYPE_UINT32,
+    );
+
+    for model in &sc_ctx.models {
+        let model =
+            unsafe { slice::from_raw_parts(model as *const Mat4 as *const u8, size_of::<Mat4>()) };
+        ctx.cmd_push_constants(
+            command_buffer,
+            sc_ctx.pipeline.layout,
+            vk::SHADER_STAGE_VERTEX_BIT,
+            model,
+        )?;
+
+        ctx.dp
+            .cmd_draw_indexed(command_buffer, sc_ctx.index_count, 1, 0, 0, 0);
+    }
+
+    if sc_ctx.particle_count > 0 {
+        ctx.cmd_bind_pipeline(&sc_ctx.particle_pipeline, command_buffer);
+        ctx.cmd_bind_vertex_buffers(command_buffer, &[(sc_ctx.particle_buffer, 0)]);
+        ctx.dp
+            .cmd_draw(command_buffer, sc_ctx.particle_count, 1, 0, 0);
+    }
+
+    // `mesh_draws` are in draw order: opaque ones first, then translucent ones back to front
+    let (opaque, translucent): (Vec<_>, Vec<_>) = sc_ctx
+        .mesh_draws
+        .iter()
+        .partition(|mesh_draw| !mesh_draw.translucent);
+
+    for (pipeline, mesh_draws) in &[
+        (&sc_ctx.mesh_pipeline, opaque),
+        (&sc_ctx.translucent_mesh_pipeline, translucent),
+    ] {
+        if mesh_draws.is_empty() {
+            continue;
+        }
+
+        ctx.cmd_bind_pipeline(pipeline, command_buffer);
+        scene_uniforms.cmd_bind(ctx, command_buffer, pipeline.layout, viewport_index);
+        ctx.dp.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PIPELINE_BIND_POINT_GRAPHICS,
+            pipeline.layout,
+            1,
+            &[sc_ctx.mesh_texture_set],
+            &[],
+        );
+
+        for mesh_draw in mesh_draws {
+            mesh_draw.cmd_draw(ctx, command_buffer, pipeline.layout)?;
+        }
+    }
+
+    Ok(())
 }
 
 pub fn create_framebuffer(
     dp: &DevicePointers,
     device: vk::Device,
     render_pass: vk::RenderPass,
-    image_view: vk::ImageView,
+    attachments: &[vk::ImageView],
     extent: &vk::Extent2D,
 ) -> Result<vk::Framebuffer> {
-    let attachments = [image_view];
-
     let create_info = vk::FramebufferCreateInfo {
         sType: vk::STRUCTURE_TYPE_FRAMEBUFFER_CREATE_INFO,
         pNext: std::ptr::null(),
@@ -810,3 +2524,337 @@ pub fn create_image_view(
 
     unsafe { dp.create_image_view(device, &info) }.map_err(to_vulkan)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::TonemapOperator;
+    use super::*;
+
+    fn extent(width: u32, height: u32) -> vk::Extent2D {
+        vk::Extent2D { width, height }
+    }
+
+    fn surface_caps(
+        current: vk::Extent2D,
+        min: vk::Extent2D,
+        max: vk::Extent2D,
+    ) -> vk::SurfaceCapabilitiesKHR {
+        vk::SurfaceCapabilitiesKHR {
+            minImageCount: 2,
+            maxImageCount: 0,
+            currentExtent: current,
+            minImageExtent: min,
+            maxImageExtent: max,
+            maxImageArrayLayers: 1,
+            supportedTransforms: vk::SURFACE_TRANSFORM_IDENTITY_BIT_KHR,
+            currentTransform: vk::SURFACE_TRANSFORM_IDENTITY_BIT_KHR,
+            supportedCompositeAlpha: vk::COMPOSITE_ALPHA_OPAQUE_BIT_KHR,
+            supportedUsageFlags: vk::IMAGE_USAGE_COLOR_ATTACHMENT_BIT,
+        }
+    }
+
+    fn assert_extent(actual: vk::Extent2D, width: u32, height: u32) {
+        assert_eq!((actual.width, actual.height), (width, height));
+    }
+
+    #[test]
+    fn current_extent_wins_over_the_framebuffer() {
+        let caps = surface_caps(extent(800, 600), extent(1, 1), extent(4096, 4096));
+
+        assert_eq!(swap_extent_kind(&caps), SwapExtentKind::Current);
+        assert_extent(swap_extent(&caps, (1024, 768)), 800, 600);
+    }
+
+    #[test]
+    fn sentinel_extent_takes_the_framebuffer_size() {
+        let caps = surface_caps(extent(u32::MAX, u32::MAX), extent(1, 1), extent(4096, 4096));
+
+        assert_eq!(swap_extent_kind(&caps), SwapExtentKind::Flexible);
+        assert_extent(swap_extent(&caps, (1024, 768)), 1024, 768);
+    }
+
+    #[test]
+    fn sentinel_extent_clamps_to_min_and_max() {
+        let caps = surface_caps(
+            extent(u32::MAX, u32::MAX),
+            extent(64, 64),
+            extent(1920, 1080),
+        );
+
+        assert_extent(swap_extent(&caps, (32, 4000)), 64, 1080);
+        assert_extent(swap_extent(&caps, (2560, 16)), 1920, 64);
+    }
+
+    #[test]
+    fn zero_sized_framebuffer_clamps_to_min() {
+        let caps = surface_caps(extent(u32::MAX, u32::MAX), extent(1, 1), extent(4096, 4096));
+
+        assert_extent(swap_extent(&caps, (0, 0)), 1, 1);
+    }
+
+    #[test]
+    fn fixed_extent_ignores_the_framebuffer() {
+        let caps = surface_caps(
+            extent(u32::MAX, u32::MAX),
+            extent(1280, 720),
+            extent(1280, 720),
+        );
+
+        assert_eq!(swap_extent_kind(&caps), SwapExtentKind::Fixed);
+        assert_extent(swap_extent(&caps, (640, 480)), 1280, 720);
+    }
+
+    #[test]
+    fn scene_output_by_settings() {
+        let mut settings = SwapchainSettings::default();
+        assert_eq!(scene_output(&settings), SceneOutput::Swapchain);
+
+        settings.render_scale = Some(0.5);
+        assert_eq!(scene_output(&settings), SceneOutput::Blit);
+
+        // the tonemap pass scales as well
+        settings.tonemap = Some(Tonemap {
+            operator: TonemapOperator::Reinhard,
+            exposure: 1.0,
+        });
+        assert_eq!(scene_output(&settings), SceneOutput::Tonemap);
+        assert_eq!(
+            scene_final_layout(SceneOutput::Tonemap),
+            vk::IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL
+        );
+    }
+
+    #[test]
+    fn default_scene_draws_every_vertex() {
+        let vertices = triangle_vertices();
+
+        assert_eq!(sequential_indices(vertices.len()), vec![0, 1, 2]);
+        assert!(sequential_indices(0).is_empty());
+    }
+
+    #[test]
+    fn default_triangle_faces_the_camera() {
+        let vertices = triangle_vertices();
+
+        assert_eq!(back_facing_fraction(&vertices, &[0, 1, 2]), 0.0);
+        assert_eq!(back_facing_fraction(&vertices, &[0, 2, 1]), 1.0);
+        assert_eq!(back_facing_fraction(&vertices, &[]), 0.0);
+    }
+
+    #[test]
+    fn clear_values_per_attachment_kind() {
+        let mut settings = SwapchainSettings::default();
+        settings.clear_color = [0.1, 0.2, 0.3, 1.0];
+
+        let attachments = scene_attachments(
+            vk::FORMAT_B8G8R8A8_SRGB,
+            Some(vk::FORMAT_D32_SFLOAT),
+            SceneOutput::Swapchain,
+            &settings,
+        );
+        let values = clear_values(&attachments, &settings);
+
+        assert_eq!(values.len(), 2);
+        assert_eq!(unsafe { values[0].color.float32 }, [0.1, 0.2, 0.3, 1.0]);
+        assert_eq!(unsafe { values[1].depthStencil.depth }, FAR_DEPTH);
+
+        settings.clear_depth = Some(0.0);
+        let values = clear_values(&attachments, &settings);
+        assert_eq!(unsafe { values[1].depthStencil.depth }, 0.0);
+    }
+
+    #[test]
+    fn loaded_color_attachment_starts_in_its_final_layout() {
+        let mut settings = SwapchainSettings::default();
+        let attachments =
+            scene_attachments(vk::FORMAT_B8G8R8A8_SRGB, None, SceneOutput::Blit, &settings);
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].initialLayout, vk::IMAGE_LAYOUT_UNDEFINED);
+
+        settings.color_load_op = LoadOp::Load;
+        let attachments =
+            scene_attachments(vk::FORMAT_B8G8R8A8_SRGB, None, SceneOutput::Blit, &settings);
+        assert_eq!(
+            attachments[0].initialLayout,
+            vk::IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL
+        );
+    }
+
+    #[test]
+    fn host_visible_memory_falls_back_to_non_coherent() {
+        // all zero is a valid value of the plain struct, only the used types are filled in
+        let mut memory_properties: vk::PhysicalDeviceMemoryProperties =
+            unsafe { std::mem::zeroed() };
+        memory_properties.memoryTypeCount = 3;
+        memory_properties.memoryTypes[0].propertyFlags = vk::MEMORY_PROPERTY_DEVICE_LOCAL_BIT;
+        memory_properties.memoryTypes[1].propertyFlags = vk::MEMORY_PROPERTY_HOST_VISIBLE_BIT;
+        memory_properties.memoryTypes[2].propertyFlags =
+            vk::MEMORY_PROPERTY_HOST_VISIBLE_BIT | vk::MEMORY_PROPERTY_HOST_COHERENT_BIT;
+
+        let candidates = [
+            vk::MEMORY_PROPERTY_HOST_VISIBLE_BIT | vk::MEMORY_PROPERTY_HOST_COHERENT_BIT,
+            vk::MEMORY_PROPERTY_HOST_VISIBLE_BIT,
+        ];
+
+        assert_eq!(
+            choose_memory_type_with_fallback(&memory_properties, 0b111, &candidates),
+            Some((2, candidates[0]))
+        );
+        assert_eq!(
+            choose_memory_type_with_fallback(&memory_properties, 0b011, &candidates),
+            Some((1, vk::MEMORY_PROPERTY_HOST_VISIBLE_BIT))
+        );
+        assert_eq!(
+            choose_memory_type_with_fallback(&memory_properties, 0b001, &candidates),
+            None
+        );
+    }
+
+    #[test]
+    fn present_mode_falls_back_to_fifo() {
+        let supported = [vk::PRESENT_MODE_FIFO_KHR];
+
+        for &present_mode in &[
+            PresentMode::Mailbox,
+            PresentMode::Immediate,
+            PresentMode::FifoRelaxed,
+            PresentMode::Auto(PresentGoal::LowLatency),
+            PresentMode::Auto(PresentGoal::NoTearing),
+        ] {
+            assert_eq!(
+                choose_present_mode(&supported, &present_mode.preference()),
+                vk::PRESENT_MODE_FIFO_KHR
+            );
+        }
+
+        assert_eq!(
+            choose_present_mode(&supported, &[]),
+            vk::PRESENT_MODE_FIFO_KHR
+        );
+    }
+
+    #[test]
+    fn present_mode_by_goal() {
+        let supported = [
+            vk::PRESENT_MODE_FIFO_KHR,
+            vk::PRESENT_MODE_IMMEDIATE_KHR,
+            vk::PRESENT_MODE_MAILBOX_KHR,
+        ];
+        let choose =
+            |present_mode: PresentMode| choose_present_mode(&supported, &present_mode.preference());
+
+        assert_eq!(
+            choose(PresentMode::Auto(PresentGoal::NoTearing)),
+            vk::PRESENT_MODE_MAILBOX_KHR
+        );
+        assert_eq!(
+            choose(PresentMode::Auto(PresentGoal::PowerSaving)),
+            vk::PRESENT_MODE_FIFO_KHR
+        );
+        assert_eq!(
+            choose_present_mode(
+                &[vk::PRESENT_MODE_FIFO_KHR, vk::PRESENT_MODE_IMMEDIATE_KHR],
+                &PresentMode::Auto(PresentGoal::LowLatency).preference()
+            ),
+            vk::PRESENT_MODE_IMMEDIATE_KHR
+        );
+        assert_eq!(
+            choose(PresentMode::Immediate),
+            vk::PRESENT_MODE_IMMEDIATE_KHR
+        );
+    }
+
+    #[test]
+    fn first_supported_present_mode_of_the_preference() {
+        let supported = [vk::PRESENT_MODE_FIFO_KHR, vk::PRESENT_MODE_IMMEDIATE_KHR];
+
+        assert_eq!(
+            choose_present_mode(
+                &supported,
+                &[vk::PRESENT_MODE_MAILBOX_KHR, vk::PRESENT_MODE_IMMEDIATE_KHR]
+            ),
+            vk::PRESENT_MODE_IMMEDIATE_KHR
+        );
+        assert_eq!(
+            choose_present_mode(
+                &supported,
+                &[vk::PRESENT_MODE_FIFO_KHR, vk::PRESENT_MODE_IMMEDIATE_KHR]
+            ),
+            vk::PRESENT_MODE_FIFO_KHR
+        );
+        assert_eq!(
+            PresentMode::default().preference(),
+            [vk::PRESENT_MODE_MAILBOX_KHR, vk::PRESENT_MODE_FIFO_KHR]
+        );
+    }
+
+    fn surface_format(format: vk::Format, color_space: vk::ColorSpaceKHR) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format,
+            colorSpace: color_space,
+        }
+    }
+
+    #[test]
+    fn prefers_srgb_surface_format() {
+        let formats = [
+            surface_format(
+                vk::FORMAT_B8G8R8A8_UNORM,
+                vk::COLOR_SPACE_SRGB_NONLINEAR_KHR,
+            ),
+            surface_format(vk::FORMAT_B8G8R8A8_SRGB, vk::COLOR_SPACE_SRGB_NONLINEAR_KHR),
+        ];
+
+        for &require_srgb in &[false, true] {
+            let chosen = choose_surface_format(&formats, require_srgb).unwrap();
+            assert_eq!(chosen.format, vk::FORMAT_B8G8R8A8_SRGB);
+        }
+    }
+
+    #[test]
+    fn surface_format_fallback_unless_srgb_required() {
+        let formats = [
+            surface_format(
+                vk::FORMAT_R8G8B8A8_UNORM,
+                vk::COLOR_SPACE_SRGB_NONLINEAR_KHR,
+            ),
+            surface_format(
+                vk::FORMAT_B8G8R8A8_UNORM,
+                vk::COLOR_SPACE_SRGB_NONLINEAR_KHR,
+            ),
+        ];
+
+        let chosen = choose_surface_format(&formats, false).unwrap();
+        assert_eq!(chosen.format, vk::FORMAT_R8G8B8A8_UNORM);
+        assert!(choose_surface_format(&formats, true).is_err());
+        assert!(choose_surface_format(&[], false).is_err());
+    }
+
+    #[test]
+    fn render_texel_scales_to_the_render_extent() {
+        let extent = vk::Extent2D {
+            width: 800,
+            height: 600,
+        };
+        let half = vk::Extent2D {
+            width: 400,
+            height: 300,
+        };
+
+        assert_eq!(render_texel((0, 0), &extent, &half), Some((0, 0)));
+        assert_eq!(render_texel((401, 301), &extent, &half), Some((200, 150)));
+        assert_eq!(render_texel((799, 599), &extent, &half), Some((399, 299)));
+        assert_eq!(render_texel((799, 599), &extent, &extent), Some((799, 599)));
+    }
+
+    #[test]
+    fn render_texel_outside_of_the_swapchain() {
+        let extent = vk::Extent2D {
+            width: 800,
+            height: 600,
+        };
+
+        assert_eq!(render_texel((800, 0), &extent, &extent), None);
+        assert_eq!(render_texel((0, 600), &extent, &extent), None);
+    }
+}
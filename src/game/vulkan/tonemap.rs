@@ -0,0 +1,396 @@
+//! HDR rendering: the scene is rendered into a float render target, which is tonemapped onto the
+//! swapchain image by a fullscreen pass. The bloom of `post_process` is added to the scene colors
+//! before tonemapping.
+
+use std::{mem::size_of, ptr, slice};
+
+use super::{
+    error::to_vulkan,
+    handle,
+    pipeline::create_fullscreen_pipeline,
+    render_target::RenderTarget,
+    util::{copy_extent_2d, copy_rect_2d},
+    Context, DescriptorWriter, Pipeline, Result,
+};
+use inline_spirv::include_spirv;
+use vk_sys as vk;
+
+/// format of the scene render target with tonemapping
+pub const HDR_FORMAT: vk::Format = vk::FORMAT_R16G16B16A16_SFLOAT;
+
+#[derive(Debug, Clone, Copy)]
+pub enum TonemapOperator {
+    /// `c / (1 + c)`, soft but desaturates highlights
+    Reinhard,
+    /// Narkowicz' fit of the ACES filmic curve, more contrast
+    Aces,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tonemap {
+    pub operator: TonemapOperator,
+    /// scene colors are multiplied by this before tonemapping
+    pub exposure: f32,
+}
+
+/// layout of the push constants in `shader/tonemap_frag.glsl`
+#[repr(C)]
+struct TonemapConstants {
+    exposure: f32,
+    operator: u32,
+    bloom_intensity: f32,
+}
+
+impl TonemapConstants {
+    fn new(tonemap: &Tonemap, bloom_intensity: f32) -> Self {
+        Self {
+            exposure: tonemap.exposure,
+            operator: match tonemap.operator {
+                TonemapOperator::Reinhard => 0,
+                TonemapOperator::Aces => 1,
+            },
+            bloom_intensity,
+        }
+    }
+}
+
+pub struct TonemapPass {
+    pub tonemap: Tonemap,
+    /// bloom is added with this, `0.0` without bloom
+    pub bloom_intensity: f32,
+    pub render_pass: vk::RenderPass,
+    pub pipeline: Pipeline,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    sampler: vk::Sampler,
+}
+
+/// resources of the tonemap pass per swapchain image
+pub struct TonemapImage {
+    pub framebuffer: vk::Framebuffer,
+    descriptor_set: vk::DescriptorSet,
+}
+
+impl TonemapPass {
+    pub fn new(
+        ctx: &Context,
+        tonemap: Tonemap,
+        bloom_intensity: f32,
+        surface_format: vk::Format,
+        image_count: u32,
+    ) -> Result<Self> {
+        let sampler = handle::sampler(ctx, create_sampler(ctx)?);
+        let descriptor_set_layout =
+            handle::descriptor_set_layout(ctx, create_descriptor_set_layout(ctx)?);
+        let descriptor_pool =
+            handle::descriptor_pool(ctx, create_descriptor_pool(ctx, image_count)?);
+        let render_pass = handle::render_pass(ctx, create_render_pass(ctx, surface_format)?);
+
+        let push_constant_range = vk::PushConstantRange {
+            stageFlags: vk::SHADER_STAGE_FRAGMENT_BIT,
+            offset: 0,
+            size: size_of::<TonemapConstants>() as u32,
+        };
+
+        let pipeline = create_fullscreen_pipeline(
+            ctx,
+            *render_pass,
+            include_spirv!("shader/tonemap_frag.glsl", glsl, frag),
+            &[*descriptor_set_layout],
+            &[push_constant_range],
+        )?;
+
+        Ok(Self {
+            tonemap,
+            bloom_intensity,
+            render_pass: render_pass.release(),
+            pipeline,
+            descriptor_set_layout: descriptor_set_layout.release(),
+            descriptor_pool: descriptor_pool.release(),
+            sampler: sampler.release(),
+        })
+    }
+
+    /// Descriptor sets are freed with the pool, only the framebuffer of `TonemapImage` needs to
+    /// be destroyed before.
+    pub fn destroy(&self, ctx: &Context) {
+        self.pipeline.destroy(ctx);
+        ctx.dp.destroy_render_pass(ctx.device, self.render_pass);
+        ctx.dp
+            .destroy_descriptor_pool(ctx.device, self.descriptor_pool);
+        ctx.dp
+            .destroy_descriptor_set_layout(ctx.device, self.descriptor_set_layout);
+        ctx.dp.destroy_sampler(ctx.device, self.sampler);
+    }
+
+    /// `hdr_target` and `bloom` are sampled, `image_view` of the swapchain image is rendered
+    /// into.
+    pub fn create_image(
+        &self,
+        ctx: &Context,
+        hdr_target: &RenderTarget,
+        bloom: Option<&RenderTarget>,
+        image_view: vk::ImageView,
+        extent: &vk::Extent2D,
+    ) -> Result<TonemapImage> {
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            sType: vk::STRUCTURE_TYPE_DESCRIPTOR_SET_ALLOCATE_INFO,
+            pNext: ptr::null(),
+            descriptorPool: self.descriptor_pool,
+            descriptorSetCount: 1,
+            pSetLayouts: &self.descriptor_set_layout,
+        };
+
+        let descriptor_set = unsafe { ctx.dp.allocate_descriptor_sets(ctx.device, &allocate_info) }
+            .map_err(to_vulkan)?[0];
+
+        // without bloom the binding still needs a valid image, it is added with 0 intensity
+        let bloom = bloom.unwrap_or(hdr_target);
+
+        DescriptorWriter::default()
+            .write_image(descriptor_set, 0, hdr_target.image_view, self.sampler)
+            .write_image(descriptor_set, 1, bloom.image_view, self.sampler)
+            .flush(ctx);
+
+        let attachments = [image_view];
+        let framebuffer_info = vk::FramebufferCreateInfo {
+            sType: vk::STRUCTURE_TYPE_FRAMEBUFFER_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            renderPass: self.render_pass,
+            attachmentCount: attachments.len() as u32,
+            pAttachments: attachments.as_ptr(),
+            width: extent.width,
+            height: extent.height,
+            layers: 1,
+        };
+
+        let framebuffer = unsafe { ctx.dp.create_framebuffer(ctx.device, &framebuffer_info) }
+            .map_err(to_vulkan)?;
+
+        Ok(TonemapImage {
+            framebuffer,
+            descriptor_set,
+        })
+    }
+
+    /// Records the whole tonemap render pass, after the scene render pass.
+    pub fn cmd_tonemap(
+        &self,
+        ctx: &Context,
+        command_buffer: vk::CommandBuffer,
+        image: &TonemapImage,
+        extent: &vk::Extent2D,
+    ) {
+        let area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: copy_extent_2d(extent),
+        };
+
+        let info = vk::RenderPassBeginInfo {
+            sType: vk::STRUCTURE_TYPE_RENDER_PASS_BEGIN_INFO,
+            pNext: ptr::null(),
+            renderPass: self.render_pass,
+            framebuffer: image.framebuffer,
+            renderArea: copy_rect_2d(&area),
+            clearValueCount: 0,
+            pClearValues: ptr::null(),
+        };
+
+        unsafe {
+            ctx.dp
+                .cmd_begin_render_pass(command_buffer, &info, vk::SUBPASS_CONTENTS_INLINE)
+        };
+
+        ctx.cmd_set_viewport(command_buffer, &area);
+        ctx.cmd_bind_pipeline(&self.pipeline, command_buffer);
+        ctx.dp.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PIPELINE_BIND_POINT_GRAPHICS,
+            self.pipeline.layout,
+            0,
+            &[image.descriptor_set],
+            &[],
+        );
+
+        let constants = TonemapConstants::new(&self.tonemap, self.bloom_intensity);
+        let constants = unsafe {
+            slice::from_raw_parts(
+                &constants as *const _ as *const u8,
+                size_of::<TonemapConstants>(),
+            )
+        };
+
+        ctx.dp.cmd_push_constants(
+            command_buffer,
+            self.pipeline.layout,
+            vk::SHADER_STAGE_FRAGMENT_BIT,
+            0,
+            constants,
+        );
+
+        ctx.cmd_draw_fullscreen_triangle(command_buffer);
+        ctx.dp.cmd_end_render_pass(command_buffer);
+    }
+}
+
+impl TonemapImage {
+    pub fn destroy(&self, ctx: &Context) {
+        ctx.dp.destroy_framebuffer(ctx.device, self.framebuffer);
+    }
+}
+
+fn create_sampler(ctx: &Context) -> Result<vk::Sampler> {
+    let info = vk::SamplerCreateInfo {
+        sType: vk::STRUCTURE_TYPE_SAMPLER_CREATE_INFO,
+        pNext: ptr::null(),
+        flags: 0,
+        // linear, so a render scale is filtered as well
+        magFilter: vk::FILTER_LINEAR,
+        minFilter: vk::FILTER_LINEAR,
+        mipmapMode: vk::SAMPLER_MIPMAP_MODE_NEAREST,
+        addressModeU: vk::SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE,
+        addressModeV: vk::SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE,
+        addressModeW: vk::SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE,
+        mipLodBias: 0.0,
+        anisotropyEnable: vk::FALSE,
+        maxAnisotropy: 1.0,
+        compareEnable: vk::FALSE,
+        compareOp: vk::COMPARE_OP_ALWAYS,
+        minLod: 0.0,
+        maxLod: 0.0,
+        borderColor: vk::BORDER_COLOR_FLOAT_TRANSPARENT_BLACK,
+        unnormalizedCoordinates: vk::FALSE,
+    };
+
+    unsafe { ctx.dp.create_sampler(ctx.device, &info) }.map_err(to_vulkan)
+}
+
+/// the HDR target and the bloom
+fn create_descriptor_set_layout(ctx: &Context) -> Result<vk::DescriptorSetLayout> {
+    let bindings: Vec<_> = (0..2)
+        .map(|binding| vk::DescriptorSetLayoutBinding {
+            binding,
+            descriptorType: vk::DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+            descriptorCount: 1,
+            stageFlags: vk::SHADER_STAGE_FRAGMENT_BIT,
+            pImmutableSamplers: ptr::null(),
+        })
+        .collect();
+
+    let info = vk::DescriptorSetLayoutCreateInfo {
+        sType: vk::STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+        pNext: ptr::null(),
+        flags: 0,
+        bindingCount: bindings.len() as u32,
+        pBindings: bindings.as_ptr(),
+    };
+
+    unsafe { ctx.dp.create_descriptor_set_layout(ctx.device, &info) }.map_err(to_vulkan)
+}
+
+/// one set with the HDR target and the bloom per swapchain image
+fn create_descriptor_pool(ctx: &Context, image_count: u32) -> Result<vk::DescriptorPool> {
+    let pool_size = vk::DescriptorPoolSize {
+        ty: vk::DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER,
+        descriptorCount: 2 * image_count,
+    };
+
+    let info = vk::DescriptorPoolCreateInfo {
+        sType: vk::STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO,
+        pNext: ptr::null(),
+        flags: 0,
+        maxSets: image_count,
+        poolSizeCount: 1,
+        pPoolSizes: &pool_size,
+    };
+
+    unsafe { ctx.dp.create_descriptor_pool(ctx.device, &info) }.map_err(to_vulkan)
+}
+
+fn create_render_pass(ctx: &Context, format: vk::Format) -> Result<vk::RenderPass> {
+    let color_attachment_desc = vk::AttachmentDescription {
+        flags: 0,
+        format,
+        samples: vk::SAMPLE_COUNT_1_BIT,
+        // the fullscreen triangle overwrites everything
+        loadOp: vk::ATTACHMENT_LOAD_OP_DONT_CARE,
+        storeOp: vk::ATTACHMENT_STORE_OP_STORE,
+        stencilLoadOp: vk::ATTACHMENT_LOAD_OP_DONT_CARE,
+        stencilStoreOp: vk::ATTACHMENT_STORE_OP_DONT_CARE,
+        initialLayout: vk::IMAGE_LAYOUT_UNDEFINED,
+        finalLayout: vk::IMAGE_LAYOUT_PRESENT_SRC_KHR,
+    };
+
+    let color_attachment_ref = vk::AttachmentReference {
+        attachment: 0,
+        layout: vk::IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+    };
+
+    let subpass_desc = vk::SubpassDescription {
+        flags: 0,
+        pipelineBindPoint: vk::PIPELINE_BIND_POINT_GRAPHICS,
+        inputAttachmentCount: 0,
+        pInputAttachments: ptr::null(),
+        colorAttachmentCount: 1,
+        pColorAttachments: &color_attachment_ref,
+        pResolveAttachments: ptr::null(),
+        pDepthStencilAttachment: ptr::null(),
+        preserveAttachmentCount: 0,
+        pPreserveAttachments: ptr::null(),
+    };
+
+    let subpass_dep = vk::SubpassDependency {
+        srcSubpass: vk::SUBPASS_EXTERNAL,
+        dstSubpass: 0,
+        srcStageMask: vk::PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
+        dstStageMask: vk::PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
+        srcAccessMask: 0,
+        dstAccessMask: vk::ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
+        dependencyFlags: 0,
+    };
+
+    let render_pass_info = vk::RenderPassCreateInfo {
+        sType: vk::STRUCTURE_TYPE_RENDER_PASS_CREATE_INFO,
+        pNext: ptr::null(),
+        flags: 0,
+        attachmentCount: 1,
+        pAttachments: &color_attachment_desc,
+        subpassCount: 1,
+        pSubpasses: &subpass_desc,
+        dependencyCount: 1,
+        pDependencies: &subpass_dep,
+    };
+
+    unsafe { ctx.dp.create_render_pass(ctx.device, &render_pass_info) }.map_err(to_vulkan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_match_the_shader() {
+        assert_eq!(size_of::<TonemapConstants>(), 12);
+
+        let constants = TonemapConstants::new(
+            &Tonemap {
+                operator: TonemapOperator::Aces,
+                exposure: 2.0,
+            },
+            0.5,
+        );
+        assert_eq!(constants.exposure, 2.0);
+        assert_eq!(constants.operator, 1);
+        assert_eq!(constants.bloom_intensity, 0.5);
+
+        let constants = TonemapConstants::new(
+            &Tonemap {
+                operator: TonemapOperator::Reinhard,
+                exposure: 1.0,
+            },
+            0.0,
+        );
+        assert_eq!(constants.operator, 0);
+    }
+}
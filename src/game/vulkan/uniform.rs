@@ -0,0 +1,128 @@
+//! Per object uniforms packed into one buffer, selected by dynamic offsets when binding the
+//! descriptor set (`UNIFORM_BUFFER_DYNAMIC` descriptors).
+
+use std::{ffi::c_void, mem::size_of, ptr};
+
+use super::{
+    error::to_vulkan, swapchain::create_host_visible_buffer, util::align_up, Context, Error, Result,
+};
+use vk_sys as vk;
+
+/// `count` elements of `element_size` bytes, each at an offset aligned to
+/// `minUniformBufferOffsetAlignment`. Stays mapped until destroyed.
+pub struct DynamicUniformBuffer {
+    pub buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    mapped: *mut c_void,
+    coherent: bool,
+    element_size: vk::DeviceSize,
+    stride: vk::DeviceSize,
+    count: u32,
+}
+
+impl DynamicUniformBuffer {
+    pub fn new(ctx: &Context, element_size: usize, count: u32) -> Result<Self> {
+        let element_size = element_size as vk::DeviceSize;
+        let stride = align_up(element_size, ctx.limits.minUniformBufferOffsetAlignment);
+
+        if element_size > ctx.limits.maxUniformBufferRange as vk::DeviceSize {
+            return Err(Error::Other(format!(
+                "uniform of {} bytes exceeds the device limit {}",
+                element_size, ctx.limits.maxUniformBufferRange
+            )));
+        }
+
+        let size = stride * count as vk::DeviceSize;
+        let (buffer, memory, coherent) =
+            create_host_visible_buffer(ctx, size, vk::BUFFER_USAGE_UNIFORM_BUFFER_BIT)?;
+
+        let mapped = ctx
+            .dp
+            .map_memory(ctx.device, *memory, 0, size, 0)
+            .map_err(to_vulkan)?;
+
+        Ok(Self {
+            buffer: buffer.release(),
+            memory: memory.release(),
+            mapped,
+            coherent,
+            element_size,
+            stride,
+            count,
+        })
+    }
+
+    pub fn destroy(&self, ctx: &Context) {
+        ctx.dp.unmap_memory(ctx.device, self.memory);
+        ctx.dp.destroy_buffer(ctx.device, self.buffer);
+        ctx.dp.free_memory(ctx.device, self.memory);
+    }
+
+    /// distance of two elements in bytes, at least the element size
+    pub fn stride(&self) -> vk::DeviceSize {
+        self.stride
+    }
+
+    /// dynamic offset selecting element `index` in `cmd_bind_descriptor_sets`
+    pub fn dynamic_offset(&self, index: u32) -> u32 {
+        (self.stride * index as vk::DeviceSize) as u32
+    }
+
+    /// Writes element `index`. The element must not be in use by the GPU, e.g. one buffer per
+    /// frame in flight.
+    pub fn write<T>(&self, ctx: &Context, index: u32, value: &T) -> Result<()> {
+        if index >= self.count || size_of::<T>() as vk::DeviceSize > self.element_size {
+            return Err(Error::Other(format!(
+                "cannot write {} bytes to uniform {} of {}",
+                size_of::<T>(),
+                index,
+                self.count
+            )));
+        }
+
+        unsafe {
+            let dst = (self.mapped as *mut u8).add(self.dynamic_offset(index) as usize);
+            ptr::copy_nonoverlapping(value as *const T, dst as *mut T, 1);
+        }
+
+        if !self.coherent {
+            ctx.flush_mapped_range(
+                self.memory,
+                self.dynamic_offset(index) as vk::DeviceSize,
+                size_of::<T>() as vk::DeviceSize,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// For the `UNIFORM_BUFFER_DYNAMIC` descriptor, the range covers a single element.
+    pub fn descriptor_buffer_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo {
+            buffer: self.buffer,
+            offset: 0,
+            range: self.element_size,
+        }
+    }
+
+    /// Binds `descriptor_set` (with this buffer as its only dynamic descriptor) at `set`,
+    /// selecting element `index`.
+    pub fn cmd_bind(
+        &self,
+        ctx: &Context,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        set: u32,
+        descriptor_set: vk::DescriptorSet,
+        index: u32,
+    ) {
+        ctx.dp.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PIPELINE_BIND_POINT_GRAPHICS,
+            layout,
+            set,
+            &[descriptor_set],
+            &[self.dynamic_offset(index)],
+        );
+    }
+}
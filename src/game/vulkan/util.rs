@@ -38,6 +38,32 @@ pub fn cchar_to_string(c: &[i8]) -> String {
         .collect()
 }
 
+/// Rounds `size` up to the next multiple of `alignment`, which is 0 or a power of two like all
+/// alignments in Vulkan limits. 0 means no alignment.
+pub fn align_up(size: u64, alignment: u64) -> u64 {
+    if alignment == 0 {
+        return size;
+    }
+
+    debug_assert!(alignment.is_power_of_two());
+    (size + alignment - 1) & !(alignment - 1)
+}
+
+/// Widens `(offset, size)` to whole multiples of `atom_size` (`nonCoherentAtomSize`), as flushing
+/// and invalidating mapped memory requires. `WHOLE_SIZE` stays as it is.
+pub fn atom_range(offset: u64, size: u64, atom_size: u64) -> (u64, u64) {
+    if atom_size == 0 {
+        return (offset, size);
+    }
+
+    let start = offset & !(atom_size - 1);
+    if size == vk::WHOLE_SIZE {
+        return (start, size);
+    }
+
+    (start, align_up(offset + size, atom_size) - start)
+}
+
 macro_rules! impl_copy {
     ($t:ty, $fn_name:ident) => {
         pub fn $fn_name(data: &$t) -> $t {
@@ -47,4 +73,54 @@ macro_rules! impl_copy {
 }
 
 impl_copy!(vk::Extent2D, copy_extent_2d);
+impl_copy!(vk::Rect2D, copy_rect_2d);
 impl_copy!(vk::SurfaceFormatKHR, copy_surface_format_khr);
+impl_copy!(vk::PhysicalDeviceLimits, copy_physical_device_limits);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn align_up_to_powers_of_two() {
+        assert_eq!(align_up(0, 256), 0);
+        assert_eq!(align_up(1, 256), 256);
+        assert_eq!(align_up(256, 256), 256);
+        assert_eq!(align_up(257, 256), 512);
+        assert_eq!(align_up(64, 16), 64);
+    }
+
+    #[test]
+    fn align_up_without_alignment() {
+        assert_eq!(align_up(100, 0), 100);
+        assert_eq!(align_up(100, 1), 100);
+    }
+
+    #[test]
+    fn atom_range_widens_to_whole_atoms() {
+        assert_eq!(atom_range(0, 64, 64), (0, 64));
+        assert_eq!(atom_range(10, 20, 64), (0, 64));
+        assert_eq!(atom_range(70, 100, 64), (64, 128));
+    }
+
+    #[test]
+    fn atom_range_keeps_whole_size() {
+        assert_eq!(atom_range(70, vk::WHOLE_SIZE, 64), (64, vk::WHOLE_SIZE));
+        assert_eq!(atom_range(70, 100, 0), (70, 100));
+    }
+
+    #[test]
+    fn copies_device_limits() {
+        let mut limits: vk::PhysicalDeviceLimits = unsafe { std::mem::zeroed() };
+        limits.maxImageDimension2D = 16384;
+        limits.maxPushConstantsSize = 128;
+        limits.maxMemoryAllocationCount = 4096;
+        limits.maxViewportDimensions = [16384, 8192];
+
+        let copy = copy_physical_device_limits(&limits);
+        assert_eq!(copy.maxImageDimension2D, 16384);
+        assert_eq!(copy.maxPushConstantsSize, 128);
+        assert_eq!(copy.maxMemoryAllocationCount, 4096);
+        assert_eq!(copy.maxViewportDimensions, [16384, 8192]);
+    }
+}
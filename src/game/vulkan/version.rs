@@ -53,3 +53,24 @@ fn get_version(compact: u32) -> (u32, u32, u32) {
         compact & VERSION_PATCH_MASK,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_layout() {
+        assert_eq!(
+            VulkanVersion::new(1, 2, 0).get_compact(),
+            (1 << 22) | (2 << 12)
+        );
+    }
+
+    #[test]
+    fn compact_round_trip() {
+        let version = VulkanVersion::from_compact(VulkanVersion::new(1, 3, 275).get_compact());
+
+        assert_eq!((version.major, version.minor, version.patch), (1, 3, 275));
+        assert_eq!(version.to_string(), "1.3.275");
+    }
+}
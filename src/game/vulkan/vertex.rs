@@ -2,6 +2,40 @@ use memoffset::offset_of;
 use std::mem::size_of;
 use vk_sys as vk;
 
+/// Vertex input of a pipeline, one binding per vertex buffer: a single interleaved one, or e.g.
+/// separate position, normal and uv buffers.
+#[derive(Default)]
+pub struct VertexLayout {
+    pub bindings: Vec<vk::VertexInputBindingDescription>,
+    pub attributes: Vec<vk::VertexInputAttributeDescription>,
+}
+
+impl VertexLayout {
+    /// Adds a per vertex binding after the existing ones, with `(location, format, offset)`
+    /// attributes read from it.
+    pub fn binding(mut self, stride: u32, attributes: &[(u32, vk::Format, u32)]) -> Self {
+        let binding = self.bindings.len() as u32;
+
+        self.bindings.push(vk::VertexInputBindingDescription {
+            binding,
+            stride,
+            inputRate: vk::VERTEX_INPUT_RATE_VERTEX,
+        });
+
+        self.attributes
+            .extend(attributes.iter().map(|&(location, format, offset)| {
+                vk::VertexInputAttributeDescription {
+                    location,
+                    binding,
+                    format,
+                    offset,
+                }
+            }));
+
+        self
+    }
+}
+
 #[repr(C)]
 pub struct Vertex {
     pub pos: glm::Vec2,
@@ -9,28 +43,121 @@ pub struct Vertex {
 }
 
 impl Vertex {
-    pub fn get_binding_description() -> vk::VertexInputBindingDescription {
-        vk::VertexInputBindingDescription {
-            binding: 0,
-            stride: size_of::<Self>() as u32,
-            inputRate: vk::VERTEX_INPUT_RATE_VERTEX,
-        }
+    /// single interleaved binding
+    pub fn layout() -> VertexLayout {
+        VertexLayout::default().binding(
+            size_of::<Self>() as u32,
+            &[
+                (0, vk::FORMAT_R32G32_SFLOAT, offset_of!(Self, pos) as u32),
+                (
+                    1,
+                    vk::FORMAT_R32G32B32_SFLOAT,
+                    offset_of!(Self, color) as u32,
+                ),
+            ],
+        )
     }
+}
+
+#[repr(C)]
+pub struct ParticleVertex {
+    pub pos: glm::Vec3,
+    pub color: glm::Vec3,
+    /// point size in pixels
+    pub size: f32,
+}
+
+impl ParticleVertex {
+    /// single interleaved binding
+    pub fn layout() -> VertexLayout {
+        VertexLayout::default().binding(
+            size_of::<Self>() as u32,
+            &[
+                (0, vk::FORMAT_R32G32B32_SFLOAT, offset_of!(Self, pos) as u32),
+                (
+                    1,
+                    vk::FORMAT_R32G32B32_SFLOAT,
+                    offset_of!(Self, color) as u32,
+                ),
+                (2, vk::FORMAT_R32_SFLOAT, offset_of!(Self, size) as u32),
+            ],
+        )
+    }
+}
+
+/// Vertex of lit, textured geometry, e.g. from `primitives`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MeshVertex {
+    pub pos: glm::Vec3,
+    pub normal: glm::Vec3,
+    pub uv: glm::Vec2,
+    /// texture array layer, e.g. grass top and grass side differ per face
+    pub tex_id: u32,
+}
+
+impl MeshVertex {
+    /// single interleaved binding
+    pub fn layout() -> VertexLayout {
+        VertexLayout::default().binding(
+            size_of::<Self>() as u32,
+            &[
+                (0, vk::FORMAT_R32G32B32_SFLOAT, offset_of!(Self, pos) as u32),
+                (
+                    1,
+                    vk::FORMAT_R32G32B32_SFLOAT,
+                    offset_of!(Self, normal) as u32,
+                ),
+                (2, vk::FORMAT_R32G32_SFLOAT, offset_of!(Self, uv) as u32),
+                (3, vk::FORMAT_R32_UINT, offset_of!(Self, tex_id) as u32),
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_are_numbered_in_order() {
+        let layout = VertexLayout::default()
+            .binding(12, &[(0, vk::FORMAT_R32G32B32_SFLOAT, 0)])
+            .binding(
+                8,
+                &[(1, vk::FORMAT_R32_SFLOAT, 0), (2, vk::FORMAT_R32_SFLOAT, 4)],
+            );
+
+        let bindings: Vec<(u32, u32)> = layout
+            .bindings
+            .iter()
+            .map(|binding| (binding.binding, binding.stride))
+            .collect();
+        assert_eq!(bindings, vec![(0, 12), (1, 8)]);
+
+        let attributes: Vec<(u32, u32, u32)> = layout
+            .attributes
+            .iter()
+            .map(|attribute| (attribute.location, attribute.binding, attribute.offset))
+            .collect();
+        assert_eq!(attributes, vec![(0, 0, 0), (1, 1, 0), (2, 1, 4)]);
+    }
+
+    #[test]
+    fn interleaved_layouts_match_the_structs() {
+        let layout = Vertex::layout();
+        assert_eq!(layout.bindings.len(), 1);
+        assert_eq!(layout.bindings[0].stride, 20);
+
+        let layout = MeshVertex::layout();
+        assert_eq!(layout.bindings[0].stride, 36);
+        let offsets: Vec<u32> = layout
+            .attributes
+            .iter()
+            .map(|attribute| attribute.offset)
+            .collect();
+        assert_eq!(offsets, vec![0, 12, 24, 32]);
 
-    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
-        [
-            vk::VertexInputAttributeDescription {
-                location: 0,
-                binding: 0,
-                format: vk::FORMAT_R32G32_SFLOAT,
-                offset: offset_of!(Self, pos) as u32,
-            },
-            vk::VertexInputAttributeDescription {
-                location: 1,
-                binding: 0,
-                format: vk::FORMAT_R32G32B32_SFLOAT,
-                offset: offset_of!(Self, color) as u32,
-            },
-        ]
+        assert_eq!(ParticleVertex::layout().bindings[0].stride, 28);
     }
 }
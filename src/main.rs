@@ -1,6 +1,4 @@
-mod game;
-
-use game::{Game, GameInit};
+use chunklands_rs::game::{Game, GameInit};
 
 fn main() {
     env_logger::builder()
@@ -8,8 +6,12 @@ fn main() {
         .format_module_path(false)
         .init();
 
-    let mut game = Game::new(GameInit { debug: is_debug() }).unwrap();
-    game.make_loop();
+    let mut game = Game::new(GameInit {
+        debug: is_debug(),
+        ..Default::default()
+    })
+    .unwrap();
+    game.make_loop().unwrap();
 }
 
 fn is_debug() -> bool {
@@ -0,0 +1,96 @@
+//! Creates and recreates everything `Vulkan` owns, destroys it and fails if the validation layer
+//! reports objects still alive when the device is destroyed. Its own test binary, so no other
+//! `Vulkan` of the process adds to the error count.
+//!
+//! Needs the same setup as `tests/validation.rs`:
+//!
+//! ```sh
+//! VK_ICD_FILENAMES=/usr/share/vulkan/icd.d/lvp_icd.x86_64.json \
+//!     xvfb-run cargo test --features validation-harness --test leaks
+//! ```
+
+use chunklands_rs::game::{
+    textured_cube, FrameOutcome, ValidationFeatures, Vulkan, VulkanInit, DEFAULT_FENCE_TIMEOUT,
+};
+use glm::{Mat4, Vec4};
+
+/// frames rendered after each change
+const FRAMES: usize = 3;
+
+#[test]
+fn destroys_everything_it_created() {
+    let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
+    glfw.window_hint(glfw::WindowHint::Visible(false));
+    glfw.window_hint(glfw::WindowHint::ClientApi(glfw::ClientApiHint::NoApi));
+
+    let (mut window, _events) = glfw
+        .create_window(320, 240, "leaks", glfw::WindowMode::Windowed)
+        .expect("no display, run with e.g. xvfb-run");
+    assert!(glfw.vulkan_supported(), "no Vulkan loader or ICD found");
+    let required_extensions = glfw.get_required_instance_extensions().unwrap();
+
+    let mut vulkan = Vulkan::new(VulkanInit {
+        debug: true,
+        window: &mut window,
+        req_ext: &required_extensions,
+        req_layers: &vec![],
+        fence_timeout: DEFAULT_FENCE_TIMEOUT,
+        queue_priorities: vec![1.0],
+        sparse_binding: false,
+        validation_features: Default::default(),
+        device_name_filter: None,
+        power_preference: Default::default(),
+        allow_integrated: true,
+        pipeline_cache_path: None,
+    })
+    .unwrap();
+    render_frames(&mut glfw, &mut vulkan, &window);
+
+    // a mesh on the render list and textures replacing the default ones
+    let (vertices, indices) = textured_cube([0, 1, 0, 1, 0, 1]);
+    let mesh = vulkan.upload_mesh(&vertices, &indices).unwrap();
+    vulkan
+        .set_mesh_textures(2, 2, 2, &[u8::MAX; 2 * 2 * 2 * 4])
+        .unwrap();
+    vulkan.set_render_list(vec![(mesh, identity())]).unwrap();
+    render_frames(&mut glfw, &mut vulkan, &window);
+
+    for (width, height) in &[(480, 360), (200, 150)] {
+        window.set_size(*width, *height);
+        glfw.poll_events();
+        vulkan.on_framebuffer_changed().unwrap();
+        render_frames(&mut glfw, &mut vulkan, &window);
+    }
+
+    // freed while frames in flight may still draw it
+    vulkan.free_mesh(mesh).unwrap();
+    render_frames(&mut glfw, &mut vulkan, &window);
+
+    vulkan.destroy().unwrap();
+
+    assert_eq!(
+        Vulkan::process_validation_error_count(),
+        0,
+        "validation errors, leaked objects are reported when the device is destroyed"
+    );
+}
+
+fn render_frames(glfw: &mut glfw::Glfw, vulkan: &mut Vulkan, window: &glfw::Window) {
+    for _ in 0..FRAMES {
+        glfw.poll_events();
+
+        match vulkan.draw_frame(window).unwrap() {
+            FrameOutcome::Presented | FrameOutcome::SwapchainOutdated => {}
+            outcome => panic!("unexpected frame outcome {:?}", outcome),
+        }
+    }
+}
+
+fn identity() -> Mat4 {
+    Mat4::new(
+        Vec4::new(1.0, 0.0, 0.0, 0.0),
+        Vec4::new(0.0, 1.0, 0.0, 0.0),
+        Vec4::new(0.0, 0.0, 1.0, 0.0),
+        Vec4::new(0.0, 0.0, 0.0, 1.0),
+    )
+}
@@ -0,0 +1,89 @@
+//! Renders frames, including a resize, with synchronization validation and fails on any
+//! validation error, as regression test of the barriers and semaphores between frames.
+//!
+//! Needs a Vulkan ICD, the Khronos validation layer and a display. Without a GPU, Mesa's
+//! lavapipe software ICD and a virtual X server do, e.g. on Debian/Ubuntu:
+//!
+//! ```sh
+//! apt install mesa-vulkan-drivers vulkan-validationlayers xvfb
+//! VK_ICD_FILENAMES=/usr/share/vulkan/icd.d/lvp_icd.x86_64.json \
+//!     xvfb-run cargo test --features validation-harness --test validation
+//! ```
+
+use chunklands_rs::game::{
+    triangle_grid, FrameOutcome, ValidationFeatures, Vulkan, VulkanInit, DEFAULT_FENCE_TIMEOUT,
+};
+
+/// frames rendered before and after the resize
+const FRAMES: usize = 10;
+
+#[test]
+fn renders_and_resizes_without_validation_errors() {
+    let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
+    glfw.window_hint(glfw::WindowHint::Visible(false));
+    glfw.window_hint(glfw::WindowHint::ClientApi(glfw::ClientApiHint::NoApi));
+
+    let (mut window, _events) = glfw
+        .create_window(320, 240, "validation", glfw::WindowMode::Windowed)
+        .expect("no display, run with e.g. xvfb-run");
+    assert!(glfw.vulkan_supported(), "no Vulkan loader or ICD found");
+    let required_extensions = glfw.get_required_instance_extensions().unwrap();
+
+    let mut vulkan = Vulkan::new(VulkanInit {
+        debug: true,
+        window: &mut window,
+        req_ext: &required_extensions,
+        req_layers: &vec![],
+        fence_timeout: DEFAULT_FENCE_TIMEOUT,
+        queue_priorities: vec![1.0],
+        sparse_binding: false,
+        validation_features: ValidationFeatures {
+            synchronization: true,
+            ..Default::default()
+        },
+        device_name_filter: None,
+        power_preference: Default::default(),
+        // lavapipe is a CPU device, but some CI runners have an integrated GPU
+        allow_integrated: true,
+        pipeline_cache_path: None,
+    })
+    .unwrap();
+    vulkan.set_scene_vertices(triangle_grid(64)).unwrap();
+
+    let mut presented = render_frames(&mut glfw, &mut vulkan, &window);
+    let retired_before_resize = vulkan.retired_swapchains();
+
+    window.set_size(480, 360);
+    glfw.poll_events();
+    vulkan.on_framebuffer_changed().unwrap();
+    presented += render_frames(&mut glfw, &mut vulkan, &window);
+    let retired_by_resize = vulkan.retired_swapchains() - retired_before_resize;
+
+    let errors = vulkan.validation_error_count();
+    vulkan.destroy().unwrap();
+
+    assert!(presented > 0, "no frame was presented");
+    assert!(
+        retired_by_resize > 0,
+        "the resize did not pass the old swapchain as oldSwapchain"
+    );
+    assert_eq!(errors, 0, "validation errors, see the log above");
+}
+
+/// Draws `FRAMES` frames, returns how many were presented.
+fn render_frames(glfw: &mut glfw::Glfw, vulkan: &mut Vulkan, window: &glfw::Window) -> usize {
+    let mut presented = 0;
+
+    for _ in 0..FRAMES {
+        glfw.poll_events();
+
+        match vulkan.draw_frame(window).unwrap() {
+            FrameOutcome::Presented => presented += 1,
+            // recreated with the next frame
+            FrameOutcome::SwapchainOutdated => {}
+            outcome => panic!("unexpected frame outcome {:?}", outcome),
+        }
+    }
+
+    presented
+}